@@ -0,0 +1,74 @@
+//! Benchmarks the Ogg page checksum on a whole Tonie file, like `check` and `extract` verify it.
+//! Run with `cargo bench --bench crc32`.
+
+#![allow(clippy::needless_return)]
+
+use audio2tonie::ogg_page::crc32;
+use audio2tonie::taf::{audio_offset, OggPageIterator};
+use divan::Bencher;
+
+const TEST_TONIE_FILE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/resources/test/test_1.taf");
+
+fn main() {
+    divan::main();
+}
+
+fn audio_data() -> Vec<u8> {
+    let tonie_data = std::fs::read(TEST_TONIE_FILE).expect("The test file exists");
+    let audio_offset = audio_offset(&tonie_data).expect("The test file has a header");
+    return tonie_data[audio_offset..].to_vec();
+}
+
+// The bytewise implementation the slicing-by-8 checksum replaced, as a baseline
+fn bytewise_crc32(data: &[u8]) -> u32 {
+    const CRC_TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = (i as u32) << 24;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 0x8000_0000 != 0 {
+                    (crc << 1) ^ 0x04C1_1DB7
+                } else {
+                    crc << 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    return data.iter().fold(0u32, |crc, byte| {
+        (crc << 8) ^ CRC_TABLE[(((crc >> 24) as u8) ^ byte) as usize]
+    });
+}
+
+#[divan::bench]
+fn crc32_audio_data(bencher: Bencher) {
+    let audio_data = audio_data();
+    bencher
+        .counter(divan::counter::BytesCount::of_slice(&audio_data))
+        .bench(|| crc32(divan::black_box(&audio_data)));
+}
+
+#[divan::bench]
+fn bytewise_crc32_audio_data(bencher: Bencher) {
+    let audio_data = audio_data();
+    bencher
+        .counter(divan::counter::BytesCount::of_slice(&audio_data))
+        .bench(|| bytewise_crc32(divan::black_box(&audio_data)));
+}
+
+#[divan::bench]
+fn verify_page_checksums(bencher: Bencher) {
+    let audio_data = audio_data();
+    bencher
+        .counter(divan::counter::BytesCount::of_slice(&audio_data))
+        .bench(|| {
+            OggPageIterator::new(divan::black_box(&audio_data))
+                .all(|page| page.is_ok_and(|(_, page)| page.is_checksum_valid()))
+        });
+}
@@ -0,0 +1,525 @@
+use anyhow::{anyhow, Result};
+use audio2tonie::input::InputFile;
+use audio2tonie::limits::Limits;
+use audio2tonie::ogg_page::OggPage;
+use audio2tonie::opus_packet::OpusPacket;
+use audio2tonie::taf::{audio_offset, OggPageReader, TONIEFILE_FRAME_SIZE};
+use audio2tonie::utils::check_input_limits;
+use serde_json::{json, Value};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::i18n::{Language, Message};
+
+/// The structure of a single Ogg page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageAnalysis {
+    /// The offset of the page in the file.
+    pub offset: usize,
+    pub page_sequence_number: u32,
+    pub granule_position: u64,
+    /// The number of lacing values in the segment table.
+    pub segments: usize,
+    /// The size of the page including its header.
+    pub size: usize,
+    /// The sizes of the packets in the page. Packets continued on the next page only count their part in this page.
+    pub packet_sizes: Vec<usize>,
+    /// The padding bytes of the Opus packets, which the encoder adds to fill a 4kb block.
+    pub padding: usize,
+    pub checksum_valid: bool,
+    /// Whether the page stays within a 4kb block. `None` for Ogg files without a Tonie header.
+    pub within_block: Option<bool>,
+}
+
+/// The pages of a Tonie file or an Ogg Opus file and the error which stopped the analysis, if any.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamAnalysis {
+    pub pages: Vec<PageAnalysis>,
+    pub error: Option<String>,
+}
+
+/// Analyzes the Ogg page structure of a Tonie file or a plain Ogg Opus file. Tonie files are recognized by their
+/// header, which is skipped. The analysis stops at the first invalid page.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file or Ogg file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+pub fn analyze_pages(input_file_path: &Path, limits: &Limits) -> Result<StreamAnalysis> {
+    let (reader, audio_offset, is_tonie) = open_ogg_stream(input_file_path, limits)?;
+
+    let mut pages = vec![];
+    let mut error = None;
+    for page in reader {
+        let (page_offset, page) = match page {
+            Ok(page) => page,
+            Err(page_error) => {
+                let offset = pages
+                    .last()
+                    .map(|page: &PageAnalysis| page.offset + page.size)
+                    .unwrap_or(audio_offset);
+                error = Some(format!(
+                    "invalid page at offset {:#x}: {}",
+                    offset, page_error
+                ));
+                break;
+            }
+        };
+        // The header occupies the first block of the file, so audio blocks are aligned relative to the audio data
+        let page_end = page_offset + page.size();
+        let within_block = is_tonie
+            .then(|| page_offset / TONIEFILE_FRAME_SIZE == (page_end - 1) / TONIEFILE_FRAME_SIZE);
+        pages.push(analyze_page(
+            &page,
+            audio_offset + page_offset,
+            within_block,
+        ));
+    }
+
+    return Ok(StreamAnalysis { pages, error });
+}
+
+// Opens a Tonie file or Ogg file at the start of its Ogg stream. Returns the page reader, the offset of the stream
+// in the file and whether the file is a Tonie file.
+fn open_ogg_stream(
+    input_file_path: &Path,
+    limits: &Limits,
+) -> Result<(OggPageReader<BufReader<InputFile>>, usize, bool)> {
+    let mut input_file = InputFile::open(input_file_path)?;
+    let mut capture_pattern = [0u8; 4];
+    let is_ogg = input_file.read_exact(&mut capture_pattern).is_ok() && &capture_pattern == b"OggS";
+    let audio_offset = match is_ogg {
+        true => {
+            limits.check_total_bytes(input_file.len()?)?;
+            0
+        }
+        false => {
+            check_input_limits(&mut input_file, limits)?;
+            let mut length_prefix = [0u8; 4];
+            input_file.read_exact(&mut length_prefix)?;
+            audio_offset(&length_prefix).ok_or_else(|| anyhow!("The Tonie file is too short."))?
+        }
+    };
+    input_file.seek(SeekFrom::Start(audio_offset as u64))?;
+
+    let reader = OggPageReader::with_limits(BufReader::new(input_file), *limits);
+    return Ok((reader, audio_offset, !is_ogg));
+}
+
+fn analyze_page(page: &OggPage, offset: usize, within_block: Option<bool>) -> PageAnalysis {
+    let packets = page.packets();
+    return PageAnalysis {
+        offset,
+        page_sequence_number: page.page_sequence_number,
+        granule_position: page.granule_position,
+        segments: page.segment_table.len(),
+        size: page.size(),
+        packet_sizes: packets.iter().map(|packet| packet.len()).collect(),
+        // Packets continued from the previous page do not start with a TOC byte
+        padding: packets
+            .iter()
+            .skip(page.is_continued() as usize)
+            .map(|packet| opus_padding(packet))
+            .sum(),
+        checksum_valid: page.is_checksum_valid(),
+        within_block,
+    };
+}
+
+/// The number of padding bytes of an Opus packet. Only packets with code 3 in their TOC byte can be padded, the
+/// padding length follows the frame count byte (RFC 6716, section 3.2.5).
+///
+/// # Arguments
+///
+/// * `packet` - The Opus packet.
+pub fn opus_padding(packet: &[u8]) -> usize {
+    // OpusHead and OpusTags are no audio packets
+    if packet.len() < 2 || packet[0] & 0x03 != 3 || packet.starts_with(b"Opus") {
+        return 0;
+    }
+    let has_padding = packet[1] & 0x40 != 0;
+    if !has_padding {
+        return 0;
+    }
+
+    let mut padding = 0;
+    for length in &packet[2..] {
+        if *length < 255 {
+            return padding + *length as usize;
+        }
+        // 255 adds 254 bytes of padding and continues with the next byte
+        padding += 254;
+    }
+    return padding;
+}
+
+/// Prints a table with the structure of every Ogg page and fails if a page is invalid.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file or Ogg file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+/// * `language` - The language of the table headers.
+/// * `json` - Print the pages as JSON object instead of a table.
+pub fn print_page_analysis(
+    input_file_path: &Path,
+    limits: &Limits,
+    language: Language,
+    json: bool,
+) -> Result<()> {
+    let analysis = analyze_pages(input_file_path, limits)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&page_analysis_json(&analysis))?
+        );
+    } else {
+        println!(
+            "{:>10} {:>6} {:>12} {:>9} {:>6} {:>8} {:>6} {:>9}  {}",
+            language.translate(Message::Offset),
+            language.translate(Message::Page),
+            language.translate(Message::Granule),
+            language.translate(Message::Segments),
+            language.translate(Message::Size),
+            language.translate(Message::Padding),
+            language.translate(Message::Checksum),
+            language.translate(Message::Block),
+            language.translate(Message::Packets)
+        );
+        let status = |ok: bool| match ok {
+            true => language.translate(Message::CheckPassed),
+            false => language.translate(Message::CheckFailed),
+        };
+        for page in &analysis.pages {
+            let packet_sizes = page
+                .packet_sizes
+                .iter()
+                .map(|size| size.to_string())
+                .collect::<Vec<_>>();
+            println!(
+                "{:>#10x} {:>6} {:>12} {:>9} {:>6} {:>8} {:>6} {:>9}  {}",
+                page.offset,
+                page.page_sequence_number,
+                page.granule_position,
+                page.segments,
+                page.size,
+                page.padding,
+                status(page.checksum_valid),
+                page.within_block.map(status).unwrap_or("-"),
+                packet_sizes.join(" ")
+            );
+        }
+    }
+
+    if let Some(error) = &analysis.error {
+        return Err(anyhow!("The Ogg stream is invalid: {}", error));
+    }
+    let invalid_pages = analysis
+        .pages
+        .iter()
+        .filter(|page| !page.checksum_valid || page.within_block == Some(false))
+        .count();
+    if invalid_pages > 0 {
+        return Err(anyhow!(
+            "{} pages have an invalid checksum or cross a 4kb block boundary.",
+            invalid_pages
+        ));
+    }
+
+    return Ok(());
+}
+
+/// The structure of all Ogg pages as JSON object.
+///
+/// # Arguments
+///
+/// * `analysis` - The analyzed pages.
+pub fn page_analysis_json(analysis: &StreamAnalysis) -> Value {
+    let pages = analysis
+        .pages
+        .iter()
+        .map(|page| {
+            json!({
+                "offset": page.offset,
+                "page_sequence_number": page.page_sequence_number,
+                "granule_position": page.granule_position,
+                "segments": page.segments,
+                "size": page.size,
+                "packet_sizes": page.packet_sizes,
+                "padding": page.padding,
+                "checksum_valid": page.checksum_valid,
+                "within_block": page.within_block,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    return json!({ "pages": pages, "error": analysis.error });
+}
+
+/// A single Ogg page with its raw header and the packets of its segments.
+#[derive(Clone, Debug)]
+pub struct PageInspection {
+    /// The offset of the page in the file.
+    pub offset: usize,
+    pub page: OggPage,
+    /// The index of the packet every segment belongs to.
+    pub segment_packets: Vec<usize>,
+    /// The decoded Opus packets. `None` for packets continued from the previous page, an error for packets which
+    /// are continued on the next page or are malformed.
+    pub packets: Vec<Option<Result<OpusPacket, String>>>,
+}
+
+impl PageInspection {
+    /// The header of the page including its segment table.
+    pub fn header(&self) -> Vec<u8> {
+        let mut header = self.page.serialize();
+        header.truncate(self.page.size() - self.page.data.len());
+        // Show the stored checksum instead of the recomputed one, so corrupt pages can be spotted
+        header[22..26].copy_from_slice(&self.page.checksum.to_le_bytes());
+        return header;
+    }
+}
+
+/// Reads a single Ogg page of a Tonie file or Ogg file and decodes its packets.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file or Ogg file.
+/// * `page_sequence_number` - The number of the page in the Ogg stream.
+/// * `limits` - Caps for the input size, header size and number of pages.
+pub fn inspect_page(
+    input_file_path: &Path,
+    page_sequence_number: u32,
+    limits: &Limits,
+) -> Result<PageInspection> {
+    let (reader, audio_offset, _) = open_ogg_stream(input_file_path, limits)?;
+    for page in reader {
+        let (page_offset, page) = page?;
+        if page.page_sequence_number != page_sequence_number {
+            continue;
+        }
+
+        let mut segment_packets = vec![];
+        let mut packet_index = 0;
+        for segment_size in &page.segment_table {
+            segment_packets.push(packet_index);
+            if *segment_size < 255 {
+                packet_index += 1;
+            }
+        }
+
+        let packets = page
+            .packets()
+            .iter()
+            .enumerate()
+            .map(|(index, packet)| {
+                if index == 0 && page.is_continued() {
+                    return None;
+                }
+                if packet.starts_with(b"Opus") {
+                    return Some(Err("Opus header".to_string()));
+                }
+                if index == page.packets().len() - 1 && page.has_incomplete_packet() {
+                    return Some(Err("continued on the next page".to_string()));
+                }
+                return Some(OpusPacket::parse(packet).map_err(|error| error.to_string()));
+            })
+            .collect();
+
+        return Ok(PageInspection {
+            offset: audio_offset + page_offset,
+            page,
+            segment_packets,
+            packets,
+        });
+    }
+
+    return Err(anyhow!(
+        "The Ogg stream has no page {}.",
+        page_sequence_number
+    ));
+}
+
+/// Prints the header, segments and packets of a single Ogg page and the frame layout of its first Opus packet.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file or Ogg file.
+/// * `page_sequence_number` - The number of the page in the Ogg stream.
+/// * `limits` - Caps for the input size, header size and number of pages.
+/// * `language` - The language of the table headers.
+/// * `json` - Print the page as JSON object instead of tables.
+pub fn print_page_inspection(
+    input_file_path: &Path,
+    page_sequence_number: u32,
+    limits: &Limits,
+    language: Language,
+    json: bool,
+) -> Result<()> {
+    let inspection = inspect_page(input_file_path, page_sequence_number, limits)?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&page_inspection_json(&inspection))?
+        );
+        return Ok(());
+    }
+
+    let page = &inspection.page;
+    println!(
+        "{} {}, {} {:#x}, {} {}, {} {}",
+        language.translate(Message::Page),
+        page.page_sequence_number,
+        language.translate(Message::Offset),
+        inspection.offset,
+        language.translate(Message::Granule),
+        page.granule_position,
+        language.translate(Message::Size),
+        page.size()
+    );
+
+    println!("\n{}", language.translate(Message::Header));
+    for (line, bytes) in inspection.header().chunks(16).enumerate() {
+        let hex = bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>();
+        let ascii = bytes
+            .iter()
+            .map(|byte| match byte.is_ascii_graphic() {
+                true => *byte as char,
+                false => '.',
+            })
+            .collect::<String>();
+        println!("{:08x}  {:<47}  |{}|", line * 16, hex.join(" "), ascii);
+    }
+
+    println!(
+        "\n{:>4} {:>6} {:>7}",
+        "#",
+        language.translate(Message::Size),
+        language.translate(Message::Packets)
+    );
+    for (index, segment_size) in page.segment_table.iter().enumerate() {
+        println!(
+            "{:>4} {:>6} {:>7}",
+            index, segment_size, inspection.segment_packets[index]
+        );
+    }
+
+    println!(
+        "\n{:>4} {:>6} {:>6} {:>6} {:>9} {:>8} {:>8} {:>5} {:>6} {:>8}",
+        "#",
+        language.translate(Message::Size),
+        language.translate(Message::Config),
+        language.translate(Message::Mode),
+        language.translate(Message::Bandwidth),
+        language.translate(Message::FrameDuration),
+        language.translate(Message::Channels),
+        language.translate(Message::FramePacking),
+        language.translate(Message::Frames),
+        language.translate(Message::Padding)
+    );
+    let packets = page.packets();
+    for (index, packet) in inspection.packets.iter().enumerate() {
+        match packet {
+            Some(Ok(packet)) => println!(
+                "{:>4} {:>6} {:>6} {:>6} {:>9} {:>8} {:>8} {:>5} {:>6} {:>8}",
+                index,
+                packets[index].len(),
+                packet.config,
+                packet.mode(),
+                packet.bandwidth(),
+                format!("{} ms", packet.frame_duration() as f64 / 1000.0),
+                if packet.stereo { "stereo" } else { "mono" },
+                format!("{}{}", packet.code, if packet.vbr { " VBR" } else { "" }),
+                packet.frames.len(),
+                packet.padding
+            ),
+            Some(Err(error)) => println!("{:>4} {:>6}  {}", index, packets[index].len(), error),
+            None => println!(
+                "{:>4} {:>6}  {}",
+                index,
+                packets[index].len(),
+                language.translate(Message::ContinuedPacket)
+            ),
+        }
+    }
+
+    let first_packet = inspection
+        .packets
+        .iter()
+        .enumerate()
+        .find_map(|(index, packet)| Some((index, packet.as_ref()?.as_ref().ok()?)));
+    if let Some((index, packet)) = first_packet {
+        println!("\n{} {}", language.translate(Message::FrameLayout), index);
+        println!(
+            "{:>4} {:>8} {:>6}",
+            "#",
+            language.translate(Message::Offset),
+            language.translate(Message::Size)
+        );
+        for (frame, (offset, size)) in packet.frames.iter().enumerate() {
+            println!("{:>4} {:>8} {:>6}", frame, offset, size);
+        }
+    }
+
+    return Ok(());
+}
+
+/// The header, segments and packets of a single Ogg page as JSON object.
+///
+/// # Arguments
+///
+/// * `inspection` - The inspected page.
+pub fn page_inspection_json(inspection: &PageInspection) -> Value {
+    let page = &inspection.page;
+    let segments = page
+        .segment_table
+        .iter()
+        .zip(&inspection.segment_packets)
+        .map(|(size, packet)| json!({ "size": size, "packet": packet }))
+        .collect::<Vec<_>>();
+    let packets = page
+        .packets()
+        .iter()
+        .zip(&inspection.packets)
+        .map(|(data, packet)| match packet {
+            Some(Ok(packet)) => json!({
+                "size": data.len(),
+                "config": packet.config,
+                "mode": packet.mode(),
+                "bandwidth": packet.bandwidth(),
+                "frame_duration_us": packet.frame_duration(),
+                "stereo": packet.stereo,
+                "code": packet.code,
+                "vbr": packet.vbr,
+                "padding": packet.padding,
+                "frames": packet
+                    .frames
+                    .iter()
+                    .map(|(offset, size)| json!({ "offset": offset, "size": size }))
+                    .collect::<Vec<_>>(),
+            }),
+            Some(Err(error)) => json!({ "size": data.len(), "error": error }),
+            None => json!({ "size": data.len(), "continued": true }),
+        })
+        .collect::<Vec<_>>();
+    let header = inspection
+        .header()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    return json!({
+        "offset": inspection.offset,
+        "page_sequence_number": page.page_sequence_number,
+        "granule_position": page.granule_position,
+        "size": page.size(),
+        "checksum_valid": page.is_checksum_valid(),
+        "header": header,
+        "segments": segments,
+        "packets": packets,
+    });
+}
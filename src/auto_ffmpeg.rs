@@ -0,0 +1,233 @@
+//! Downloads a static ffmpeg build into the tool cache on first use, so ffmpeg does not have to be installed
+//! before the first conversion. The builds are the ones linked on ffmpeg.org: gyan.dev for Windows,
+//! evermeet.cx for macOS and johnvansickle.com for Linux. Every build is pinned to a release and the SHA-256
+//! checksum of its archive, which is verified before anything is extracted.
+
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::utils::{find_executable, tool_cache_dir};
+
+/// The archive formats of the static ffmpeg builds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarXz,
+}
+
+/// A static ffmpeg build for one platform.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FfmpegDownload {
+    /// The URL of a fixed release, so the archive matches its checksum.
+    pub url: &'static str,
+    pub format: ArchiveFormat,
+    /// The SHA-256 checksum of the archive as lowercase hex. `None` until the checksum of the release is pinned,
+    /// the build is not downloaded without one.
+    pub sha256: Option<&'static str>,
+}
+
+/// The static ffmpeg build for the running platform, `None` if there is no build for it.
+pub fn ffmpeg_download() -> Option<FfmpegDownload> {
+    let (url, format) = match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("windows", "x86_64") => (
+            "https://github.com/GyanD/codexffmpeg/releases/download/7.1/ffmpeg-7.1-essentials_build.zip",
+            ArchiveFormat::Zip,
+        ),
+        ("macos", _) => ("https://evermeet.cx/ffmpeg/ffmpeg-7.1.zip", ArchiveFormat::Zip),
+        ("linux", "x86_64") => (
+            "https://johnvansickle.com/ffmpeg/old-releases/ffmpeg-7.0.2-amd64-static.tar.xz",
+            ArchiveFormat::TarXz,
+        ),
+        ("linux", "aarch64") => (
+            "https://johnvansickle.com/ffmpeg/old-releases/ffmpeg-7.0.2-arm64-static.tar.xz",
+            ArchiveFormat::TarXz,
+        ),
+        _ => return None,
+    };
+    return Some(FfmpegDownload {
+        url,
+        format,
+        sha256: None,
+    });
+}
+
+/// Makes sure ffmpeg can be found: returns the installed ffmpeg or the cached download, and downloads
+/// ffmpeg into the [`tool_cache_dir`] if neither exists.
+///
+/// # Arguments
+///
+/// * `on_download` - Called with the URL before the download starts, which can take a while.
+pub fn ensure_ffmpeg<F>(on_download: F) -> Result<PathBuf>
+where
+    F: FnOnce(&str),
+{
+    let ffmpeg = find_executable("ffmpeg");
+    if ffmpeg.is_absolute() {
+        return Ok(ffmpeg);
+    }
+
+    let cache_dir = tool_cache_dir()
+        .ok_or_else(|| anyhow!("There is no cache directory to download ffmpeg to."))?;
+    let no_download = || {
+        anyhow!(
+            "There is no verified ffmpeg download for {} {}, please install ffmpeg.",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        )
+    };
+    let download = ffmpeg_download().ok_or_else(no_download)?;
+    let sha256 = download.sha256.ok_or_else(no_download)?;
+    on_download(download.url);
+    return download_ffmpeg(download.url, download.format, sha256, &cache_dir);
+}
+
+/// Downloads an ffmpeg archive and extracts the ffmpeg executable into the given directory.
+///
+/// # Arguments
+///
+/// * `url` - The URL of the archive.
+/// * `format` - The format of the archive.
+/// * `sha256` - The expected SHA-256 checksum of the archive as hex.
+/// * `cache_dir` - The directory for the ffmpeg executable.
+pub fn download_ffmpeg(
+    url: &str,
+    format: ArchiveFormat,
+    sha256: &str,
+    cache_dir: &Path,
+) -> Result<PathBuf> {
+    std::fs::create_dir_all(cache_dir)?;
+    // The archive is kept on disk instead of memory, the Linux builds are about 40 MB
+    let archive_path = cache_dir.join("ffmpeg-download.tmp");
+    let result = (|| {
+        let response = ureq::get(url)
+            .call()
+            .with_context(|| format!("Failed to download {}", url))?;
+        std::io::copy(
+            &mut response.into_reader(),
+            &mut File::create(&archive_path)?,
+        )?;
+        return install_ffmpeg(&archive_path, format, sha256, cache_dir);
+    })();
+    std::fs::remove_file(&archive_path).ok();
+
+    return result;
+}
+
+/// Verifies the checksum of a downloaded archive and extracts the ffmpeg executable from it into the given
+/// directory, which is created if needed. On a checksum mismatch nothing is extracted, and the archive and any
+/// ffmpeg in the directory are removed.
+///
+/// # Arguments
+///
+/// * `archive_path` - The path of the archive.
+/// * `format` - The format of the archive.
+/// * `sha256` - The expected SHA-256 checksum of the archive as hex.
+/// * `cache_dir` - The directory for the ffmpeg executable.
+pub fn install_ffmpeg(
+    archive_path: &Path,
+    format: ArchiveFormat,
+    sha256: &str,
+    cache_dir: &Path,
+) -> Result<PathBuf> {
+    std::fs::create_dir_all(cache_dir)?;
+    let ffmpeg_path = cache_dir.join(format!("ffmpeg{}", std::env::consts::EXE_SUFFIX));
+    let actual_sha256 = file_sha256(archive_path)?;
+    if !actual_sha256.eq_ignore_ascii_case(sha256) {
+        std::fs::remove_file(archive_path).ok();
+        std::fs::remove_file(&ffmpeg_path).ok();
+        return Err(anyhow!(
+            "The downloaded ffmpeg archive has the SHA-256 checksum {}, but {} was expected.",
+            actual_sha256,
+            sha256
+        ));
+    }
+
+    // Writing to a temporary file first, so an interrupted extraction does not leave a broken ffmpeg
+    let partial_path = cache_dir.join("ffmpeg.partial");
+    let result = (|| {
+        let mut partial_file = File::create(&partial_path)?;
+        return match format {
+            ArchiveFormat::Zip => extract_from_zip(File::open(archive_path)?, &mut partial_file),
+            ArchiveFormat::TarXz => {
+                let tar_path = cache_dir.join("ffmpeg-download.tar");
+                let found = (|| {
+                    lzma_rs::xz_decompress(
+                        &mut BufReader::new(File::open(archive_path)?),
+                        &mut File::create(&tar_path)?,
+                    )
+                    .map_err(|error| {
+                        anyhow!("Failed to decompress the ffmpeg archive: {}", error)
+                    })?;
+                    return extract_from_tar(File::open(&tar_path)?, &mut partial_file);
+                })();
+                std::fs::remove_file(&tar_path).ok();
+                found
+            }
+        };
+    })();
+    match result {
+        Ok(true) => (),
+        Ok(false) => {
+            std::fs::remove_file(&partial_path).ok();
+            return Err(anyhow!("The downloaded archive does not contain ffmpeg."));
+        }
+        Err(error) => {
+            std::fs::remove_file(&partial_path).ok();
+            return Err(error);
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&partial_path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    std::fs::rename(&partial_path, &ffmpeg_path)?;
+    return Ok(ffmpeg_path);
+}
+
+// The SHA-256 checksum of a file as lowercase hex, read in chunks because the archives are about 40 MB
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut sha256 = Sha256::new();
+    std::io::copy(&mut BufReader::new(File::open(path)?), &mut sha256)?;
+    return Ok(sha256
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect());
+}
+
+// The archives contain ffmpeg in a directory named after the version, e.g. `ffmpeg-7.1-essentials_build/bin/`
+fn is_ffmpeg_entry(entry_path: &str) -> bool {
+    let file_name = entry_path.rsplit(['/', '\\']).next().unwrap_or_default();
+    return file_name == "ffmpeg" || file_name == "ffmpeg.exe";
+}
+
+fn extract_from_zip<R: Read + Seek>(archive: R, output: &mut impl Write) -> Result<bool> {
+    let mut archive = zip::ZipArchive::new(archive)?;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        if entry.is_file() && is_ffmpeg_entry(entry.name()) {
+            std::io::copy(&mut entry, output)?;
+            return Ok(true);
+        }
+    }
+    return Ok(false);
+}
+
+fn extract_from_tar<R: Read>(archive: R, output: &mut impl Write) -> Result<bool> {
+    let mut archive = tar::Archive::new(archive);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let is_ffmpeg = entry.header().entry_type().is_file()
+            && is_ffmpeg_entry(&entry.path()?.to_string_lossy());
+        if is_ffmpeg {
+            std::io::copy(&mut entry, output)?;
+            return Ok(true);
+        }
+    }
+    return Ok(false);
+}
@@ -0,0 +1,244 @@
+use anyhow::{anyhow, Result};
+use audio2tonie::extract::{extract_tonie_from_reader, ExtractOptions};
+use audio2tonie::input::InputFile;
+use audio2tonie::sd_card::{TagUid, CONTENT_DIRECTORY};
+use audio2tonie::tonies::{MatchKind, ToniesDatabase, ToniesEntry};
+use audio2tonie::utils::sanitize_file_name;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use crate::i18n::{Language, Message};
+use crate::info::get_header_info;
+use crate::list::find_tonie_files;
+
+/// The name of the manifest in the backup directory.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// A Tonie file of the SD card and the files it was extracted to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupEntry {
+    pub tag_uid: TagUid,
+    /// The path relative to the root of the SD card, e.g. `CONTENT/5634121E/500304E0`.
+    pub content_path: PathBuf,
+    /// The audio id of the header, `None` if the header cannot be read.
+    pub audio_id: Option<u32>,
+    /// The SHA1 hash of the header as hex string, empty if the header cannot be read.
+    pub sha1_hash: String,
+    /// The entry of `tonies.json` the file was matched with, `None` for unknown content.
+    pub tonie: Option<(ToniesEntry, MatchKind)>,
+    /// The directory of the extracted files relative to the backup directory.
+    pub directory: PathBuf,
+    /// The extracted files relative to the backup directory, in chapter order.
+    pub files: Vec<PathBuf>,
+    /// Why the file could not be backed up.
+    pub error: Option<String>,
+}
+
+impl BackupEntry {
+    /// The series and episode of the content, or `Unknown` if it is not in the database.
+    pub fn title(&self) -> String {
+        return match &self.tonie {
+            Some((entry, _)) => entry.display_name(),
+            None => String::from("Unknown"),
+        };
+    }
+}
+
+/// Extracts every Tonie file of a Toniebox SD card into a directory of the backup, named after the series and
+/// episode from `tonies.json` and the tag UID, e.g. `Benjamin Blümchen – Folge 12 (E00403501E123456)`. The
+/// chapters are named after the track titles of the database. A file which cannot be extracted does not stop the
+/// backup, it is recorded with its error instead. The manifest is written last, see [`write_manifest`].
+///
+/// # Arguments
+///
+/// * `sd_root` - The mount point of the SD card or its `CONTENT` directory.
+/// * `backup_dir` - The directory of the backup, which is created if missing.
+/// * `database` - The database of official Tonies.
+/// * `options` - Options controlling the extraction, e.g. the audio format.
+pub fn backup_sd_card(
+    sd_root: &Path,
+    backup_dir: &Path,
+    database: &ToniesDatabase,
+    options: &ExtractOptions,
+) -> Result<Vec<BackupEntry>> {
+    let content_dir = match sd_root.file_name() {
+        Some(name) if name.eq_ignore_ascii_case(CONTENT_DIRECTORY) => sd_root.to_path_buf(),
+        _ => sd_root.join(CONTENT_DIRECTORY),
+    };
+    if !content_dir.is_dir() {
+        return Err(anyhow!(
+            "{} does not contain a {} directory. Is it the SD card of a Toniebox?",
+            sd_root.display(),
+            CONTENT_DIRECTORY
+        ));
+    }
+    let sd_root = content_dir.parent().unwrap_or(Path::new(""));
+    std::fs::create_dir_all(backup_dir)?;
+
+    let mut entries = vec![];
+    for tonie_file in find_tonie_files(&content_dir)? {
+        // Only the files of the content layout belong to tags
+        let Ok(tag_uid) = TagUid::from_content_path(&tonie_file) else {
+            continue;
+        };
+        let mut entry = BackupEntry {
+            tag_uid,
+            content_path: tonie_file
+                .strip_prefix(sd_root)
+                .unwrap_or(&tonie_file)
+                .to_path_buf(),
+            audio_id: None,
+            sha1_hash: String::new(),
+            tonie: None,
+            directory: PathBuf::new(),
+            files: vec![],
+            error: None,
+        };
+        if let Err(error) =
+            backup_tonie_file(&tonie_file, backup_dir, database, options, &mut entry)
+        {
+            entry.error = Some(format!("{:#}", error));
+        }
+        entries.push(entry);
+    }
+
+    write_manifest(&entries, backup_dir)?;
+    return Ok(entries);
+}
+
+fn backup_tonie_file(
+    tonie_file: &Path,
+    backup_dir: &Path,
+    database: &ToniesDatabase,
+    options: &ExtractOptions,
+    entry: &mut BackupEntry,
+) -> Result<()> {
+    let header_info = get_header_info(tonie_file, &options.limits)?;
+    entry.audio_id = Some(header_info.audio_id);
+    entry.sha1_hash = header_info.sha1_hex();
+    entry.tonie = database
+        .identify(header_info.audio_id, &entry.sha1_hash)
+        .map(|(tonie, kind)| (tonie.clone(), kind));
+
+    let title = entry.title();
+    let uid = entry.tag_uid.to_string().replace(':', "");
+    entry.directory = PathBuf::from(sanitize_file_name(
+        &format!("{} ({})", title, uid),
+        options.transliterate,
+    ));
+    let directory = backup_dir.join(&entry.directory);
+    std::fs::create_dir_all(&directory)?;
+
+    let options = ExtractOptions {
+        chapter_titles: entry.tonie.as_ref().map(|(tonie, _)| tonie.tracks.clone()),
+        ..options.clone()
+    };
+    let output_name = sanitize_file_name(
+        &format!("{}.{}", title, options.format.extension()),
+        options.transliterate,
+    );
+    let files = extract_tonie_from_reader(
+        InputFile::open(tonie_file)?,
+        &directory.join(output_name),
+        &options,
+    )?;
+    entry.files = files
+        .iter()
+        .map(|file| file.strip_prefix(backup_dir).unwrap_or(file).to_path_buf())
+        .collect();
+
+    return Ok(());
+}
+
+/// Writes the manifest of a backup, a JSON array describing every Tonie file of the SD card, see
+/// [`manifest_json`].
+///
+/// # Arguments
+///
+/// * `entries` - The result of `backup_sd_card`.
+/// * `backup_dir` - The directory of the backup.
+pub fn write_manifest(entries: &[BackupEntry], backup_dir: &Path) -> Result<()> {
+    std::fs::write(
+        backup_dir.join(MANIFEST_FILE_NAME),
+        serde_json::to_string_pretty(&manifest_json(entries))?,
+    )?;
+    return Ok(());
+}
+
+/// The entries of a backup as JSON array with the tag UID, the content path, the header details, the matched
+/// Tonie and the extracted files of every Tonie file.
+///
+/// # Arguments
+///
+/// * `entries` - The result of `backup_sd_card`.
+pub fn manifest_json(entries: &[BackupEntry]) -> Value {
+    return entries
+        .iter()
+        .map(|entry| {
+            let tonie = entry.tonie.as_ref().map(|(tonie, kind)| {
+                json!({
+                    "match": kind.name(),
+                    "model": tonie.model,
+                    "series": tonie.series,
+                    "episodes": tonie.episodes,
+                    "tracks": tonie.tracks,
+                })
+            });
+            json!({
+                "tag_uid": entry.tag_uid.to_string(),
+                "content_path": entry.content_path,
+                "audio_id": entry.audio_id,
+                "sha1_hash": entry.sha1_hash,
+                "title": entry.title(),
+                "tonie": tonie,
+                "directory": entry.directory,
+                "files": entry.files,
+                "error": entry.error,
+            })
+        })
+        .collect();
+}
+
+/// Prints the tag UID, the title and the backup directory of every Tonie file, and the errors of the files which
+/// could not be backed up below.
+///
+/// # Arguments
+///
+/// * `entries` - The result of `backup_sd_card`.
+/// * `language` - The language of the table headers.
+/// * `json` - Print the manifest instead of a table.
+pub fn print_backup(entries: &[BackupEntry], language: Language, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(&manifest_json(entries))?);
+        return Ok(());
+    }
+
+    let titles = entries.iter().map(BackupEntry::title).collect::<Vec<_>>();
+    let title_width = titles
+        .iter()
+        .map(|title| title.chars().count())
+        .chain([language.translate(Message::Title).chars().count()])
+        .max()
+        .unwrap_or_default();
+    println!(
+        "{:<23} {:<title_width$} {}",
+        language.translate(Message::TagUid),
+        language.translate(Message::Title),
+        language.translate(Message::OutputFile)
+    );
+    for (entry, title) in entries.iter().zip(&titles) {
+        println!(
+            "{:<23} {:<title_width$} {}",
+            entry.tag_uid.to_string(),
+            title,
+            entry.directory.display()
+        );
+    }
+    for entry in entries {
+        if let Some(error) = &entry.error {
+            eprintln!("{}: {}", entry.content_path.display(), error);
+        }
+    }
+
+    return Ok(());
+}
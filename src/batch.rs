@@ -0,0 +1,285 @@
+//! Batch conversions described by a manifest file, e.g. `jobs.yaml`, as a replacement for shell loops around
+//! `convert`. Every job of the manifest converts its inputs into one Tonie file:
+//!
+//! ```yaml
+//! sd_root: /media/toniebox
+//! jobs:
+//!   - inputs: [intro.mp3, stories/]
+//!     output: stories.taf
+//!     bitrate: 64
+//!   - inputs: [songs/]
+//!     title: Kids Songs
+//!     tag_uid: E0:04:03:50:1E:12:34:56
+//! ```
+//!
+//! JSON manifests are supported as well, as JSON is a subset of YAML.
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::convert::{
+    collect_input_files, convert_files_to_tonie, output_exists, resolve_output_path,
+    ConvertOptions, ExistingOutput, FailedTrack, FailedTracks,
+};
+use crate::sd_card::TagUid;
+use crate::utils::{limit_path_length, sanitize_file_name};
+
+/// A conversion of a batch manifest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchJob {
+    /// The input files, directories or glob patterns in chapter order.
+    pub inputs: Vec<PathBuf>,
+    /// The Tonie file to write, a file in the `CONTENT` directory of the SD card for jobs with a tag UID.
+    pub output: PathBuf,
+    /// The Opus bitrate in kbit/s. `None` uses the bitrate of the command line.
+    pub bitrate: Option<u32>,
+    /// The title of the Tonie file, which names the output file of jobs without an output path.
+    pub title: Option<String>,
+    /// The UID of the tag whose Tonie file on the SD card is written.
+    pub tag_uid: Option<TagUid>,
+}
+
+/// What happened to a job of a batch run.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchStatus {
+    Converted,
+    /// The output file exists and was kept, see [`ExistingOutput::Skip`].
+    Skipped,
+    Failed(String),
+    /// The job was not started, because an earlier job failed and the batch stops at the first failure.
+    NotStarted,
+}
+
+/// The outcome of a job of a batch run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchResult {
+    pub status: BatchStatus,
+    /// The input files left out of or truncated in the Tonie file with [`crate::convert::TrackFailure::Skip`].
+    pub failed_tracks: Vec<FailedTrack>,
+    /// The time it took to convert the job.
+    pub duration: Duration,
+}
+
+/// Reads the jobs of a manifest file. Relative paths are relative to the directory of the manifest.
+///
+/// # Arguments
+///
+/// * `manifest_path` - The path to the YAML or JSON manifest.
+/// * `sd_root` - The SD card for jobs with a tag UID. Overrides the `sd_root` of the manifest.
+pub fn read_manifest(manifest_path: &Path, sd_root: Option<&Path>) -> Result<Vec<BatchJob>> {
+    let manifest = std::fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read the manifest {}", manifest_path.display()))?;
+    let base_dir = manifest_path.parent().unwrap_or(Path::new(""));
+
+    return parse_manifest(&manifest, base_dir, sd_root)
+        .with_context(|| format!("Invalid manifest {}", manifest_path.display()));
+}
+
+/// Parses the jobs of a manifest, either a list of jobs or a map with the list in `jobs` and an optional
+/// `sd_root`.
+///
+/// # Arguments
+///
+/// * `manifest` - The YAML or JSON content of the manifest.
+/// * `base_dir` - The directory relative paths are resolved against.
+/// * `sd_root` - The SD card for jobs with a tag UID. Overrides the `sd_root` of the manifest.
+pub fn parse_manifest(
+    manifest: &str,
+    base_dir: &Path,
+    sd_root: Option<&Path>,
+) -> Result<Vec<BatchJob>> {
+    let manifest = serde_yaml::from_str::<Value>(manifest)?;
+    let (jobs, manifest_sd_root) = match &manifest {
+        Value::Array(jobs) => (jobs, None),
+        Value::Object(fields) => match &fields.get("jobs") {
+            Some(Value::Array(jobs)) => (jobs, fields.get("sd_root").and_then(Value::as_str)),
+            _ => return Err(anyhow!("The manifest does not contain a list of jobs.")),
+        },
+        _ => return Err(anyhow!("The manifest does not contain a list of jobs.")),
+    };
+    let sd_root = sd_root
+        .map(Path::to_path_buf)
+        .or_else(|| manifest_sd_root.map(|sd_root| base_dir.join(sd_root)));
+
+    return jobs
+        .iter()
+        .enumerate()
+        .map(|(index, job)| {
+            return parse_job(job, base_dir, sd_root.as_deref())
+                .with_context(|| format!("Job {} is invalid", index + 1));
+        })
+        .collect();
+}
+
+/// Parses a job of a manifest.
+///
+/// # Arguments
+///
+/// * `job` - The job.
+/// * `base_dir` - The directory relative paths are resolved against.
+/// * `sd_root` - The SD card for jobs with a tag UID.
+fn parse_job(job: &Value, base_dir: &Path, sd_root: Option<&Path>) -> Result<BatchJob> {
+    let inputs = match (&job["inputs"], &job["input"]) {
+        (Value::Array(inputs), Value::Null) => inputs
+            .iter()
+            .map(|input| {
+                let input = input
+                    .as_str()
+                    .ok_or_else(|| anyhow!("Every input has to be a path."))?;
+                return Ok(base_dir.join(input));
+            })
+            .collect::<Result<Vec<_>>>()?,
+        (Value::Null, Value::String(input)) => vec![base_dir.join(input)],
+        _ => {
+            return Err(anyhow!(
+                "Expected either a list of inputs or a single input."
+            ))
+        }
+    };
+    if inputs.is_empty() {
+        return Err(anyhow!("The job has no inputs."));
+    }
+    let bitrate = match &job["bitrate"] {
+        Value::Null => None,
+        bitrate => match bitrate.as_u64() {
+            Some(bitrate @ 6..=510) => Some(bitrate as u32),
+            _ => return Err(anyhow!("The bitrate has to be between 6 and 510 kbit/s.")),
+        },
+    };
+    let title = job["title"].as_str().map(str::to_string);
+    let tag_uid = job["tag_uid"].as_str().map(TagUid::parse).transpose()?;
+
+    let output = match (job["output"].as_str(), tag_uid, &title) {
+        (Some(_), Some(_), _) => {
+            return Err(anyhow!(
+                "A job with a tag UID is written to the SD card and cannot have an output."
+            ));
+        }
+        (Some(output), None, _) => base_dir.join(output),
+        (None, Some(tag_uid), _) => {
+            let sd_root = sd_root
+                .ok_or_else(|| anyhow!("A job with a tag UID needs the sd_root of the SD card."))?;
+            tag_uid.content_path(sd_root)
+        }
+        (None, None, Some(title)) => limit_path_length(
+            &base_dir.join(format!("{}.taf", sanitize_file_name(title.trim(), false))),
+        ),
+        (None, None, None) => {
+            return Err(anyhow!(
+                "The job needs an output, a title or a tag UID to name its Tonie file."
+            ));
+        }
+    };
+
+    return Ok(BatchJob {
+        inputs,
+        output,
+        bitrate,
+        title,
+        tag_uid,
+    });
+}
+
+/// Converts the jobs of a batch, several of them in parallel. A failed job does not stop the other jobs, unless
+/// `fail_fast` is set. Returns the results in the order of the jobs.
+///
+/// # Arguments
+///
+/// * `jobs` - The jobs to convert.
+/// * `options` - Options controlling the conversions. The bitrate of a job replaces the bitrate of the options.
+/// * `parallel` - The number of jobs converted at the same time.
+/// * `fail_fast` - Do not start further jobs once a job failed. The running jobs are finished.
+/// * `on_result` - Called with every job and its result once it is done, e.g. to show the progress.
+pub fn run_batch<F>(
+    jobs: &[BatchJob],
+    options: &ConvertOptions,
+    parallel: usize,
+    fail_fast: bool,
+    on_result: F,
+) -> Vec<BatchResult>
+where
+    F: Fn(&BatchJob, &BatchResult) + Sync,
+{
+    let next_job = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results = Mutex::new(vec![None; jobs.len()]);
+    std::thread::scope(|scope| {
+        for _ in 0..parallel.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next_job.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(index) else {
+                    break;
+                };
+                if fail_fast && failed.load(Ordering::Relaxed) {
+                    break;
+                }
+                let result = run_job(job, options);
+                if matches!(result.status, BatchStatus::Failed(_)) {
+                    failed.store(true, Ordering::Relaxed);
+                }
+                on_result(job, &result);
+                results.lock().unwrap_or_else(|error| error.into_inner())[index] = Some(result);
+            });
+        }
+    });
+
+    return results
+        .into_inner()
+        .unwrap_or_else(|error| error.into_inner())
+        .into_iter()
+        .map(|result| {
+            return result.unwrap_or(BatchResult {
+                status: BatchStatus::NotStarted,
+                failed_tracks: vec![],
+                duration: Duration::ZERO,
+            });
+        })
+        .collect();
+}
+
+/// Converts a job of a batch.
+///
+/// # Arguments
+///
+/// * `job` - The job to convert.
+/// * `options` - Options controlling the conversion.
+fn run_job(job: &BatchJob, options: &ConvertOptions) -> BatchResult {
+    let start = Instant::now();
+    if options.existing_output == ExistingOutput::Skip && output_exists(&job.output) {
+        return BatchResult {
+            status: BatchStatus::Skipped,
+            failed_tracks: vec![],
+            duration: start.elapsed(),
+        };
+    }
+
+    let options = ConvertOptions {
+        bitrate: job.bitrate.unwrap_or(options.bitrate),
+        // Parallel jobs must not report the failed tracks of each other
+        failed_tracks: FailedTracks::new(),
+        ..options.clone()
+    };
+    let result = (|| {
+        let input_files = collect_input_files(&job.inputs, &options)?;
+        if job.tag_uid.is_some() {
+            if let Some(content_directory) = resolve_output_path(&job.output).parent() {
+                std::fs::create_dir_all(content_directory)?;
+            }
+        }
+        convert_files_to_tonie(&input_files, &job.output, &options)?;
+        return Ok::<(), anyhow::Error>(());
+    })();
+
+    return BatchResult {
+        status: match result {
+            Ok(()) => BatchStatus::Converted,
+            Err(error) => BatchStatus::Failed(error.to_string()),
+        },
+        failed_tracks: options.failed_tracks.take(),
+        duration: start.elapsed(),
+    };
+}
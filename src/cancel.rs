@@ -0,0 +1,35 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::Audio2TonieError;
+
+/// Cancels a running conversion from another thread, e.g. from the handler of Ctrl-C. The conversion stops
+/// after the current chunk of decoded audio, kills ffmpeg and removes the partially written Tonie file.
+/// Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct Cancellation {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Cancellation {
+    pub fn new() -> Self {
+        return Cancellation::default();
+    }
+
+    /// Cancels every conversion using this flag or one of its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.cancelled.load(Ordering::SeqCst);
+    }
+
+    /// Returns [`Audio2TonieError::Cancelled`] once the conversion is cancelled.
+    pub fn check(&self) -> Result<(), Audio2TonieError> {
+        if self.is_cancelled() {
+            return Err(Audio2TonieError::Cancelled);
+        }
+        return Ok(());
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use audio2tonie::sd_card::TagUid;
+use audio2tonie::taf::{audio_offset, opus_comments, TONIEFILE_FRAME_SIZE};
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::info::{get_audio_info, get_header_info, AudioInfo, HeaderInfo};
+use crate::list::find_tonie_files;
+
+/// The file format of a catalog.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum CatalogFormat {
+    Json,
+    Csv,
+}
+
+impl CatalogFormat {
+    /// The format matching the extension of the output file, i.e. CSV for `.csv` and JSON otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_path` - The path of the catalog.
+    pub fn from_path(output_path: &Path) -> Self {
+        return match output_path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("csv") => CatalogFormat::Csv,
+            _ => CatalogFormat::Json,
+        };
+    }
+}
+
+/// A Tonie file of a catalog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatalogEntry {
+    /// The path relative to the catalogued directory.
+    pub path: PathBuf,
+    /// The UID of the tag for files in the `CONTENT` layout of the SD card.
+    pub tag_uid: Option<TagUid>,
+    /// The file size in bytes.
+    pub size: u64,
+    /// The header and the chapter durations, or the reason they cannot be read.
+    pub info: Result<(HeaderInfo, AudioInfo), String>,
+    /// The user comments of the Opus header, e.g. the name of the first input file or `CHAPTER001NAME=...`.
+    pub comments: Vec<String>,
+}
+
+/// Reads the header details and the Opus comments of every Tonie file in a directory and its subdirectories.
+/// Files which cannot be read are catalogued with the error instead of failing the catalog.
+///
+/// # Arguments
+///
+/// * `directory` - The directory to catalog, e.g. a library folder or the root of the SD card.
+/// * `limits` - Caps for the input size, header size and number of pages of every file.
+pub fn build_catalog(directory: &Path, limits: &Limits) -> Result<Vec<CatalogEntry>> {
+    return find_tonie_files(directory)?
+        .into_iter()
+        .map(|path| {
+            let info = get_header_info(&path, limits)
+                .and_then(|header_info| {
+                    let audio_info = get_audio_info(&path, &header_info, limits)?;
+                    return Ok((header_info, audio_info));
+                })
+                .map_err(|error| error.to_string());
+            return Ok(CatalogEntry {
+                path: path.strip_prefix(directory).unwrap_or(&path).to_path_buf(),
+                tag_uid: TagUid::from_content_path(&path).ok(),
+                size: std::fs::metadata(&path)?.len(),
+                comments: match info {
+                    Ok(_) => read_comments(&path).unwrap_or_default(),
+                    Err(_) => vec![],
+                },
+                info,
+            });
+        })
+        .collect();
+}
+
+/// Reads the user comments of the Opus header, which fills the first block of the audio data. The comment of zeros
+/// padding the header to the block size is left out.
+///
+/// # Arguments
+///
+/// * `tonie_file_path` - The path to the Tonie file.
+fn read_comments(tonie_file_path: &Path) -> Result<Vec<String>> {
+    let mut tonie_file = File::open(tonie_file_path)?;
+    let mut length_prefix = [0u8; 4];
+    tonie_file.read_exact(&mut length_prefix)?;
+    let audio_offset = audio_offset(&length_prefix).unwrap_or_default();
+    tonie_file.seek(SeekFrom::Start(audio_offset as u64))?;
+    let mut first_block = vec![];
+    tonie_file
+        .take(TONIEFILE_FRAME_SIZE as u64)
+        .read_to_end(&mut first_block)?;
+
+    return Ok(opus_comments(&first_block)
+        .into_iter()
+        .filter(|comment| !comment.is_empty() && !comment.bytes().all(|byte| byte == b'0'))
+        .collect());
+}
+
+/// Writes a catalog as JSON array or as CSV table with one row per Tonie file.
+///
+/// # Arguments
+///
+/// * `entries` - The result of `build_catalog`.
+/// * `format` - The file format.
+/// * `writer` - The output, e.g. a file or stdout.
+pub fn write_catalog<W: Write>(
+    entries: &[CatalogEntry],
+    format: CatalogFormat,
+    mut writer: W,
+) -> Result<()> {
+    match format {
+        CatalogFormat::Json => {
+            let catalog = entries.iter().map(catalog_entry_json).collect::<Vec<_>>();
+            serde_json::to_writer_pretty(&mut writer, &catalog)?;
+            writeln!(writer)?;
+        }
+        CatalogFormat::Csv => {
+            writeln!(
+                writer,
+                "path,tag_uid,size,audio_id,sha1_hash,data_length,duration,chapters,comments,error"
+            )?;
+            for entry in entries {
+                let (header_info, audio_info, error) = match &entry.info {
+                    Ok((header_info, audio_info)) => (Some(header_info), Some(audio_info), None),
+                    Err(error) => (None, None, Some(error.as_str())),
+                };
+                let fields = [
+                    entry.path.display().to_string(),
+                    entry.tag_uid.map(|uid| uid.to_string()).unwrap_or_default(),
+                    entry.size.to_string(),
+                    header_info
+                        .map(|info| info.audio_id.to_string())
+                        .unwrap_or_default(),
+                    header_info.map(HeaderInfo::sha1_hex).unwrap_or_default(),
+                    header_info
+                        .map(|info| info.data_length.to_string())
+                        .unwrap_or_default(),
+                    audio_info
+                        .map(|info| format!("{:.3}", info.total_duration()))
+                        .unwrap_or_default(),
+                    audio_info
+                        .map(|info| info.chapter_durations.len().to_string())
+                        .unwrap_or_default(),
+                    entry.comments.join("; "),
+                    error.unwrap_or_default().to_string(),
+                ];
+                let row = fields
+                    .iter()
+                    .map(|field| csv_field(field))
+                    .collect::<Vec<_>>();
+                writeln!(writer, "{}", row.join(","))?;
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+/// A Tonie file of a catalog as JSON object.
+///
+/// # Arguments
+///
+/// * `entry` - The catalogued Tonie file.
+pub fn catalog_entry_json(entry: &CatalogEntry) -> Value {
+    let mut entry_json = json!({
+        "path": entry.path,
+        "tag_uid": entry.tag_uid.map(|uid| uid.to_string()),
+        "size": entry.size,
+        "comments": entry.comments,
+    });
+    match &entry.info {
+        Ok((header_info, audio_info)) => {
+            entry_json["audio_id"] = json!(header_info.audio_id);
+            entry_json["sha1_hash"] = json!(header_info.sha1_hex());
+            entry_json["data_length"] = json!(header_info.data_length);
+            entry_json["duration"] = json!(audio_info.total_duration());
+            let chapters = audio_info
+                .chapter_starts()
+                .iter()
+                .zip(&audio_info.chapter_durations)
+                .enumerate()
+                .map(|(index, (start, duration))| {
+                    json!({
+                        "number": index + 1,
+                        "start": start,
+                        "duration": duration,
+                    })
+                })
+                .collect::<Vec<_>>();
+            entry_json["chapters"] = json!(chapters);
+        }
+        Err(error) => entry_json["error"] = json!(error),
+    }
+
+    return entry_json;
+}
+
+// Fields with separators, quotes or line breaks are quoted, and their quotes are doubled
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", field.replace('"', "\"\""));
+    }
+    return field.to_string();
+}
@@ -0,0 +1,304 @@
+use anyhow::{anyhow, Result};
+use audio2tonie::input::InputFile;
+use audio2tonie::limits::Limits;
+use audio2tonie::ogg_page::{OggPage, PacketAssembler};
+use audio2tonie::opus_packet::OpusPacket;
+use audio2tonie::taf::{audio_offset, OggPageIterator, TONIEFILE_FRAME_SIZE};
+use audio2tonie::utils::check_input_limits;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::path::Path;
+use toniefile::Toniefile;
+
+use crate::i18n::{Language, Message};
+
+/// The individual validations of a Tonie file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Check {
+    /// The SHA1 hash in the header matches the audio data.
+    Sha1Hash,
+    /// The data length in the header matches the audio data.
+    DataLength,
+    /// No Ogg page crosses a 4kb block boundary.
+    PageAlignment,
+    /// The Ogg pages fill every 4kb block completely.
+    PageSizes,
+    /// The checksums of all Ogg pages are valid.
+    Checksums,
+    /// The granule positions increase and match the duration of the audio packets, also across chapters.
+    GranulePositions,
+}
+
+impl Check {
+    /// The identifier of the check in JSON reports.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Check::Sha1Hash => "sha1_hash",
+            Check::DataLength => "data_length",
+            Check::PageAlignment => "page_alignment",
+            Check::PageSizes => "page_sizes",
+            Check::Checksums => "checksums",
+            Check::GranulePositions => "granule_positions",
+        };
+    }
+}
+
+/// The outcome of a single check. `error` describes the problem if the check failed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckResult {
+    pub check: Check,
+    pub error: Option<String>,
+}
+
+impl CheckResult {
+    fn new(check: Check, errors: Vec<String>) -> Self {
+        CheckResult {
+            check,
+            error: (!errors.is_empty()).then(|| errors.join(", ")),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        return self.error.is_none();
+    }
+}
+
+/// Validates the header and the Ogg stream of a Tonie file.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+pub fn check_tonie_file(input_file_path: &Path, limits: &Limits) -> Result<Vec<CheckResult>> {
+    let mut tonie_file = InputFile::open(input_file_path)?;
+    check_input_limits(&mut tonie_file, limits)?;
+
+    let tonie_header = Toniefile::parse_header(&mut tonie_file)?;
+    let tonie_data = tonie_file.read_all()?;
+    let audio_offset =
+        audio_offset(&tonie_data).ok_or_else(|| anyhow!("The Tonie file is too short."))?;
+    let audio_data = &tonie_data[audio_offset..];
+
+    let mut hash_errors = vec![];
+    let sha1_hash = Sha1::digest(audio_data);
+    if sha1_hash.as_slice() != tonie_header.sha1_hash.as_slice() {
+        hash_errors.push(String::from(
+            "the hash in the header does not match the audio data",
+        ));
+    }
+
+    let mut length_errors = vec![];
+    if tonie_header.num_bytes != audio_data.len() as u64 {
+        length_errors.push(format!(
+            "the header announces {} bytes, but the audio data has {} bytes",
+            tonie_header.num_bytes,
+            audio_data.len()
+        ));
+    }
+
+    let mut alignment_errors = vec![];
+    let mut size_errors = vec![];
+    let mut checksum_errors = vec![];
+    let mut granule_positions = GranulePositions::default();
+    let mut end_of_last_page = 0;
+    for page in OggPageIterator::with_limits(audio_data, *limits) {
+        let (page_offset, page) = match page {
+            Ok(page) => page,
+            Err(error) => {
+                size_errors.push(format!(
+                    "invalid page at offset {:#x}: {}",
+                    audio_offset + end_of_last_page,
+                    error
+                ));
+                break;
+            }
+        };
+        let page_end = page_offset + page.size();
+        end_of_last_page = page_end;
+
+        // The header occupies the first block of the file, so audio blocks are aligned relative to the audio data
+        if page_offset / TONIEFILE_FRAME_SIZE != (page_end - 1) / TONIEFILE_FRAME_SIZE {
+            alignment_errors.push(format!(
+                "page {} at offset {:#x} crosses a block boundary",
+                page.page_sequence_number,
+                audio_offset + page_offset
+            ));
+        }
+        if !page.is_checksum_valid() {
+            checksum_errors.push(format!(
+                "page {} at offset {:#x} has an invalid checksum",
+                page.page_sequence_number,
+                audio_offset + page_offset
+            ));
+        }
+        let block = (page_offset / TONIEFILE_FRAME_SIZE) as u32;
+        let chapter = tonie_header
+            .track_page_nums
+            .iter()
+            .rposition(|page_num| *page_num <= block)
+            .unwrap_or_default();
+        granule_positions.push_page(&page, audio_offset + page_offset, chapter);
+    }
+    let granule_errors = granule_positions.errors;
+    if end_of_last_page % TONIEFILE_FRAME_SIZE != 0 {
+        size_errors.push(format!(
+            "the last block is only filled up to {} of {} bytes",
+            end_of_last_page % TONIEFILE_FRAME_SIZE,
+            TONIEFILE_FRAME_SIZE
+        ));
+    }
+
+    return Ok(vec![
+        CheckResult::new(Check::Sha1Hash, hash_errors),
+        CheckResult::new(Check::DataLength, length_errors),
+        CheckResult::new(Check::PageAlignment, alignment_errors),
+        CheckResult::new(Check::PageSizes, size_errors),
+        CheckResult::new(Check::Checksums, checksum_errors),
+        CheckResult::new(Check::GranulePositions, granule_errors),
+    ]);
+}
+
+// Follows the granule positions of the Ogg pages and compares them with the summed duration of the packets
+#[derive(Default)]
+struct GranulePositions {
+    packet_assembler: PacketAssembler,
+    samples: u64,
+    last_granule_position: Option<u64>,
+    drift: Option<i64>,
+    errors: Vec<String>,
+}
+
+impl GranulePositions {
+    fn push_page(&mut self, page: &OggPage, offset: usize, chapter: usize) {
+        for packet in self.packet_assembler.push_page(page) {
+            // OpusHead and OpusTags contain no audio
+            if packet.starts_with(b"Opus") {
+                continue;
+            }
+            match OpusPacket::parse(&packet) {
+                Ok(packet) => self.samples += packet.samples(),
+                Err(error) => self.errors.push(format!(
+                    "page {} at offset {:#x} contains a malformed Opus packet: {}",
+                    page.page_sequence_number,
+                    offset,
+                    error.to_string().to_lowercase()
+                )),
+            }
+        }
+
+        // Pages on which no packet ends have no granule position
+        let granule_position = page.granule_position;
+        if granule_position == u64::MAX {
+            return;
+        }
+        if let Some(last_granule_position) = self.last_granule_position {
+            if granule_position <= last_granule_position && self.samples > 0 {
+                self.errors.push(format!(
+                    "page {} at offset {:#x} in chapter {} has the granule position {}, which does not increase",
+                    page.page_sequence_number,
+                    offset,
+                    chapter + 1,
+                    granule_position
+                ));
+            }
+        }
+        self.last_granule_position = Some(granule_position);
+
+        // Streams may start at any granule position, so the drift of the first audio page is the baseline. Only the
+        // start of a drift is reported, the following pages usually keep it. The last page may end before its
+        // packets to trim the padding of the encoder.
+        if self.samples == 0 {
+            return;
+        }
+        let drift = granule_position as i64 - self.samples as i64;
+        let baseline = *self.drift.get_or_insert(drift);
+        let is_end_trimmed = page.is_end_of_stream() && drift < baseline;
+        if drift != baseline && !is_end_trimmed {
+            self.errors.push(format!(
+                "page {} at offset {:#x} in chapter {} has the granule position {}, but its packets end at sample {} (drift of {} samples)",
+                page.page_sequence_number,
+                offset,
+                chapter + 1,
+                granule_position,
+                self.samples,
+                drift - baseline
+            ));
+            self.drift = Some(drift);
+        }
+    }
+}
+
+/// Prints a report of all checks and fails if any check failed.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+/// * `language` - The language of the report.
+/// * `json` - Print the report as JSON object instead of text.
+pub fn print_check_report(
+    input_file_path: &Path,
+    limits: &Limits,
+    language: Language,
+    json: bool,
+) -> Result<()> {
+    let results = check_tonie_file(input_file_path, limits)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&check_report_json(input_file_path, &results))?
+        );
+    } else {
+        for result in &results {
+            let label = language.translate(match result.check {
+                Check::Sha1Hash => Message::Sha1Hash,
+                Check::DataLength => Message::DataLength,
+                Check::PageAlignment => Message::PageAlignment,
+                Check::PageSizes => Message::PageSizes,
+                Check::Checksums => Message::Checksums,
+                Check::GranulePositions => Message::GranulePositions,
+            });
+            match &result.error {
+                None => println!("{:<18} {}", label, language.translate(Message::CheckPassed)),
+                Some(error) => println!(
+                    "{:<18} {}: {}",
+                    label,
+                    language.translate(Message::CheckFailed),
+                    error
+                ),
+            }
+        }
+    }
+
+    if results.iter().any(|result| !result.is_ok()) {
+        return Err(anyhow!(language.translate(Message::TonieFileInvalid)));
+    }
+
+    return Ok(());
+}
+
+/// The results of all checks of a Tonie file as JSON object.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `results` - The results of `check_tonie_file`.
+pub fn check_report_json(input_file_path: &Path, results: &[CheckResult]) -> Value {
+    let checks = results
+        .iter()
+        .map(|result| {
+            json!({
+                "check": result.check.name(),
+                "ok": result.is_ok(),
+                "error": result.error,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    return json!({
+        "path": input_file_path,
+        "valid": results.iter().all(CheckResult::is_ok),
+        "checks": checks,
+    });
+}
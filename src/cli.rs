@@ -1,31 +1,798 @@
-use clap::{Parser, Subcommand};
+use audio2tonie::limits::{
+    Limits, DEFAULT_MAX_HEADER_SIZE, DEFAULT_MAX_PAGES, DEFAULT_MAX_TOTAL_BYTES,
+};
+use clap::{Args, Parser, Subcommand};
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::catalog::CatalogFormat;
+use crate::i18n::Language;
+use audio2tonie::convert::{
+    is_glob_pattern, is_stdin_input, ChapterOverflow, ExistingOutput, Passthrough, Since,
+    TrackFailure, TrackOrder, DEFAULT_TARGET_LOUDNESS,
+};
+use audio2tonie::download::is_url_input;
+use audio2tonie::encode::OpusApplication;
+use audio2tonie::extract::OutputFormat;
+use audio2tonie::hooks::CoverSource;
+use audio2tonie::sd_card::TagUid;
+use audio2tonie::silence::SilenceTrim;
+use audio2tonie::teddycloud::TeddyCloudClient;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: CLICommands,
+    #[arg(
+        long,
+        global = true,
+        allow_hyphen_values = true,
+        value_parser = clap::value_parser!(i32).range(-20..=19),
+        help = "Run with the given process priority (niceness) from -20 (highest) to 19 (lowest), e.g. to not starve other services."
+    )]
+    pub nice: Option<i32>,
+    #[arg(
+        long,
+        global = true,
+        value_parser = parse_size,
+        help = "Limit reading and writing audio data to the given number of bytes per second. Supports K, M and G suffixes, e.g. 10M."
+    )]
+    pub io_throttle: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        help = "The language of printed messages. Defaults to the system locale."
+    )]
+    pub lang: Option<Language>,
+    #[arg(
+        long,
+        global = true,
+        help = "Print the results of info, check, convert, extract and uid as JSON on stdout instead of text."
+    )]
+    pub json: bool,
+    #[arg(
+        long,
+        global = true,
+        env = "AUDIO2TONIE_TMP_DIR",
+        help = "The directory for intermediate files, e.g. the Tonie file converted before an upload. Defaults to the system temp directory."
+    )]
+    pub tmp_dir: Option<PathBuf>,
+    #[cfg(feature = "auto-ffmpeg")]
+    #[arg(
+        long,
+        global = true,
+        env = "AUDIO2TONIE_AUTO_FFMPEG",
+        help = "Download a static ffmpeg build into the cache directory if ffmpeg is not installed, and use it."
+    )]
+    pub auto_ffmpeg: bool,
 }
 
+// The commands are parsed once, so the size of the convert options does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 pub enum CLICommands {
     #[command(
-        about = "Extract the audio content from a Tonie file and save it as new Ogg Opus file."
+        about = "Extract the audio content from a Tonie file and save it as new Ogg Opus file."
+    )]
+    Extract {
+        #[arg(required=true, help="The input audio file in Tonie format.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[arg(help="The output directory for saving the extracted audio content in, or '-' to write all chapters as one Ogg stream to stdout.", value_parser = validate_output_directory)]
+        output: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Replace non-ASCII characters in output file names with ASCII equivalents, e.g. 'ä' with 'ae'."
+        )]
+        transliterate: bool,
+        #[arg(
+            long,
+            default_value = "ogg",
+            value_parser = parse_output_format,
+            help = "The audio format of the extracted files: ogg, mp3, flac or wav. All formats except ogg are transcoded with ffmpeg."
+        )]
+        format: OutputFormat,
+        #[arg(
+            long,
+            default_value = "ffmpeg",
+            help = "Path to ffmpeg executable on your system."
+        )]
+        ffmpeg: String,
+        #[arg(
+            long,
+            help = "File name of every extracted chapter with the placeholders {number}, {index}, {title} and {name}, e.g. '{number} - {title}'. Defaults to '{number} - {title}' if chapter titles are known."
+        )]
+        name_template: Option<String>,
+        #[arg(
+            long,
+            help = "Extract the audio even if it does not match the SHA1 hash in the header and only print a warning."
+        )]
+        no_verify: bool,
+        #[arg(
+            long,
+            conflicts_with = "name_template",
+            help = "Extract all chapters into a single Ogg file and mark the chapters with CHAPTER001= comments, which players like VLC show as chapters. Only supported for ogg."
+        )]
+        merge_chapters: bool,
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_parser = parse_chapter_range,
+            conflicts_with = "merge_chapters",
+            help = "Only extract these chapters, counting from 1, e.g. '2,5-7'."
+        )]
+        chapters: Vec<RangeInclusive<usize>>,
+        #[arg(
+            long,
+            conflicts_with_all = ["merge_chapters", "chapters", "no_verify"],
+            help = "Recover the audio of a Tonie file with a destroyed header: skip the header, search for the first Ogg page and copy all valid pages into a single Ogg file."
+        )]
+        ignore_header: bool,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Convert audio files or directories of audio files into a Toniebox compatible audio file. Input audio files can be in any audio format that can be handled and converted by ffmpeg."
+    )]
+    Convert {
+        #[arg(required_unless_present = "files_from", num_args = 1.., help="The input audio files, directories of files or quoted glob patterns, e.g. 'Album/Disc*/[0-9]*.mp3'. Their order is the chapter order. Without --output, the last of several paths is the output audio file.")]
+        inputs: Vec<PathBuf>,
+        #[arg(
+            long,
+            value_name = "LIST",
+            help = "Read the input paths from a file with one path per line, or from stdin with '-'. Empty lines and lines starting with '#' are skipped. A single positional path is then the output audio file."
+        )]
+        files_from: Option<PathBuf>,
+        #[arg(
+            short,
+            long,
+            help = "The output audio file, or '-' to write it to stdout. Defaults to 500304E0 for a single input."
+        )]
+        output: Option<PathBuf>,
+        #[arg(
+            long,
+            default_value = "ffmpeg",
+            help = "Path to ffmpeg executable on your system."
+        )]
+        ffmpeg: String,
+        #[arg(
+            long,
+            value_parser = parse_since,
+            help = "Only convert if an input file was modified after the given Unix timestamp (in seconds). Use 'last' to compare against the modification time of an existing output file."
+        )]
+        since: Option<Since>,
+        #[arg(
+            long,
+            conflicts_with = "normalize_album",
+            help = "Normalize the loudness of every track to the target loudness."
+        )]
+        normalize: bool,
+        #[arg(
+            long,
+            help = "Normalize the overall loudness of all tracks with a single gain, preserving loudness differences between chapters."
+        )]
+        normalize_album: bool,
+        #[arg(
+            long,
+            default_value_t = DEFAULT_TARGET_LOUDNESS,
+            allow_hyphen_values = true,
+            help = "The integrated loudness in LUFS used for normalization."
+        )]
+        target_loudness: f64,
+        #[arg(
+            long,
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "",
+            value_name = "THRESHOLD_DB,MIN_MS",
+            value_parser = parse_trim_silence,
+            help = "Remove silence at the start and the end of every track. Audio below the threshold in dBFS (default: -50) counts as silence, pauses shorter than the minimum duration in milliseconds (default: 500) are kept."
+        )]
+        trim_silence: Option<SilenceTrim>,
+        #[arg(
+            long,
+            default_value_t = 0,
+            value_name = "MS",
+            help = "Fade in the start of every track over the given number of milliseconds."
+        )]
+        fade_in: u64,
+        #[arg(
+            long,
+            default_value_t = 0,
+            value_name = "MS",
+            help = "Fade out the end of every track over the given number of milliseconds."
+        )]
+        fade_out: u64,
+        #[arg(
+            long,
+            default_value_t = 1.0,
+            value_parser = parse_speed,
+            help = "Change the playback speed without changing the pitch, e.g. 1.15 to play 15% faster. Supports 0.25 to 4."
+        )]
+        speed: f64,
+        #[arg(
+            long = "post-command",
+            value_name = "COMMAND",
+            help = "Run a shell command after a successful conversion. The output path is passed in AUDIO2TONIE_OUTPUT. Can be repeated."
+        )]
+        post_commands: Vec<String>,
+        #[arg(
+            long,
+            value_name = "URL",
+            help = "Upload the Tonie file to the library of a TeddyCloud server after a successful conversion."
+        )]
+        teddycloud_url: Option<String>,
+        #[arg(
+            long,
+            default_value = "",
+            requires = "teddycloud_url",
+            help = "The directory in the TeddyCloud library to upload to."
+        )]
+        teddycloud_path: String,
+        #[arg(
+            long,
+            help = "Write a JSON file with the conversion metadata next to the Tonie file."
+        )]
+        sidecar: bool,
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Add an entry with the audio id, hash, titles and cover of the Tonie file to a tonies.custom.json file of TeddyCloud, which is created if missing."
+        )]
+        teddycloud_json: Option<PathBuf>,
+        #[arg(
+            long,
+            value_name = "FILE|auto",
+            value_parser = parse_cover,
+            help = "Write a cover image scaled down to 500x500 pixels next to the Tonie file, e.g. 500304E0.jpg, and reference it from --teddycloud-json. Takes an image file or 'auto' to use a cover.jpg, folder.jpg or front.jpg next to the input files or the artwork embedded in them."
+        )]
+        cover: Option<CoverSource>,
+        #[arg(
+            long,
+            help = "Write a TeddyCloud playlist next to the Tonie file, e.g. gruffalo.tap, which lists the input files as chapters."
+        )]
+        tap: bool,
+        #[arg(
+            long,
+            visible_alias = "timestamp",
+            value_parser = parse_audio_id,
+            help = "The audio id stored in the Tonie header as decimal or 0x-prefixed hexadecimal number. Defaults to the current Unix timestamp."
+        )]
+        audio_id: Option<u32>,
+        #[arg(
+            long,
+            help = "Walk the subdirectories of the input directory and create one Tonie file per directory with audio files. The output is used as directory."
+        )]
+        recursive: bool,
+        #[arg(
+            long,
+            requires = "recursive",
+            help = "Convert all directories with --recursive, including the ones which did not change since the last run."
+        )]
+        rebuild: bool,
+        #[arg(
+            long,
+            requires = "tag_uid",
+            conflicts_with = "recursive",
+            value_parser = validate_directory_path,
+            help = "Write the Tonie file into the CONTENT directory of the Toniebox SD card mounted at this path instead of the output file. Requires --tag-uid."
+        )]
+        sd_root: Option<PathBuf>,
+        #[arg(
+            long,
+            requires = "sd_root",
+            value_parser = parse_tag_uid,
+            help = "The UID of the NFC tag of the Tonie, e.g. E0:04:03:50:1E:12:34:56. Selects the directory and file name on the SD card."
+        )]
+        tag_uid: Option<TagUid>,
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u16).range(1..),
+            help = "Decode up to this many input files concurrently. Every track in flight is kept in memory."
+        )]
+        threads: u16,
+        #[arg(
+            long,
+            conflicts_with = "sd_root",
+            value_parser = parse_duration_limit,
+            help = "Split the input files into several Tonie files (output_part1, output_part2, ...) that are each at most this long, e.g. 90m or 1h30m. Input files are not cut."
+        )]
+        max_duration: Option<f64>,
+        #[arg(
+            long,
+            conflicts_with = "sd_root",
+            value_parser = parse_size,
+            help = "Split the input files into several Tonie files (output_part1, output_part2, ...) with an estimated size of at most this many bytes. Supports K, M and G suffixes, e.g. 500M."
+        )]
+        max_size: Option<u64>,
+        #[arg(
+            long,
+            conflicts_with = "sd_root",
+            help = "Split the input files into several Tonie files (output_part1, output_part2, ...) if they exceed the 2 GiB data length of a Tonie file. Input files are not cut."
+        )]
+        split_on_overflow: bool,
+        #[arg(
+            long,
+            help = "Estimate the size of the Tonie files from the duration of the input files before encoding and abort if they exceed the free space at the output location, the data length of a Tonie file or --max-size. Always done when splitting."
+        )]
+        preflight: bool,
+        #[arg(
+            long,
+            conflicts_with_all = ["max_duration", "max_size", "split_on_overflow"],
+            value_parser = parse_size,
+            help = "Select the highest bitrate up to --bitrate for which the Tonie file stays below this size, estimated from the duration of the input files. A larger Tonie file is encoded once more with a lower bitrate. Supports K, M and G suffixes, e.g. 200M."
+        )]
+        target_size: Option<u64>,
+        #[arg(
+            long,
+            default_value = "error",
+            value_parser = parse_chapter_overflow,
+            help = "What to do if the input files result in more than 99 chapters, which the Toniebox cannot navigate: error, merge-adjacent (merge the shortest adjacent tracks into shared chapters) or split-output (create several Tonie files)."
+        )]
+        on_too_many_chapters: ChapterOverflow,
+        #[arg(
+            long,
+            default_value = "auto",
+            value_parser = parse_passthrough,
+            help = "Repackage Opus inputs which the Toniebox can play as they are (stereo, CELT mode) without re-encoding them: auto (if all inputs allow it), always (fail otherwise) or never."
+        )]
+        passthrough: Passthrough,
+        #[arg(
+            long,
+            help = "Pass input files with unknown extensions to ffmpeg instead of rejecting them. Input directories then contain all files except hidden files, CUE sheets, images, playlists and text files."
+        )]
+        any_extension: bool,
+        #[arg(
+            long,
+            default_value = "tags",
+            value_parser = parse_track_order,
+            help = "The chapter order of the files of an input directory: tags (by the disc and track number tags, falling back to the names if tags are missing or conflict) or name."
+        )]
+        order: TrackOrder,
+        #[arg(
+            long,
+            default_value_t = 96,
+            value_parser = clap::value_parser!(u32).range(6..=510),
+            help = "The Opus bitrate in kbit/s. Tonie files are usually encoded with 96 kbit/s, lower bitrates save space for long audiobooks."
+        )]
+        bitrate: u32,
+        #[arg(
+            long,
+            default_value = "audio",
+            value_parser = parse_opus_application,
+            help = "The Opus application profile of the encoder: audio (music and mixed content), voip (speech, compresses speech-only audiobooks better, but uses the SILK mode not every Toniebox firmware plays) or lowdelay."
+        )]
+        opus_application: OpusApplication,
+        #[arg(
+            long,
+            help = "Encode with a constant instead of a variable bitrate, so the size of the Tonie file only depends on its duration."
+        )]
+        cbr: bool,
+        #[arg(
+            long,
+            value_name = "ARGS",
+            allow_hyphen_values = true,
+            help = "Additional ffmpeg arguments for decoding every input file, e.g. \"-af loudnorm\" or \"-ss 10 -t 60\". Can be repeated, quotes group arguments with spaces."
+        )]
+        ffmpeg_args: Vec<String>,
+        #[arg(
+            long,
+            value_name = "FORMAT",
+            help = "The ffmpeg input format of the input files, e.g. mp3. Needed for formats ffmpeg cannot detect when reading from stdin with '-'."
+        )]
+        input_format: Option<String>,
+        #[arg(
+            long,
+            value_name = "COMMAND",
+            help = "Download URL inputs, e.g. videos, streams or playlists, with yt-dlp. Takes 'yt-dlp' or the path to the yt-dlp executable."
+        )]
+        downloader: Option<String>,
+        #[command(flatten)]
+        failure: FailureArgs,
+        #[arg(
+            long,
+            help = "Only list the input files in their final order, the chapters and the estimated duration and size of the Tonie file without converting anything."
+        )]
+        dry_run: bool,
+        #[command(flatten)]
+        existing_output: ExistingOutputArgs,
+    },
+    #[command(
+        about = "Show the header details of a Tonie file, e.g. SHA1 hash, audio id, chapter pages and the duration of every chapter."
+    )]
+    Info {
+        #[arg(required=true, help="The input audio file in Tonie format.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        visible_alias = "ls",
+        about = "List the Tonie files in a directory and its subdirectories, e.g. a library folder or the SD card, with their audio id, duration, number of chapters, size and validity."
+    )]
+    List {
+        #[arg(required=true, help="The directory to search for .taf files and Tonie files in the CONTENT layout of the SD card.", value_parser = validate_directory_path)]
+        directory: PathBuf,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Identify official Tonie files by their audio id and hash with the community database tonies.json and show their series and episode."
+    )]
+    Identify {
+        #[arg(required=true, num_args=1.., help="Tonie files or directories to search for Tonie files, e.g. the CONTENT directory of the SD card.", value_parser = validate_directory_path)]
+        inputs: Vec<PathBuf>,
+        #[arg(
+            long,
+            value_name = "PATH_OR_URL",
+            help = "The tonies.json file or its URL. Defaults to a cached copy of the community database, which is downloaded on first use."
+        )]
+        tonies_json: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "tonies_json",
+            help = "Download the latest community database into the cache before identifying the files."
+        )]
+        update: bool,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Back up a Toniebox SD card: extract every Tonie file into a folder named after its title from tonies.json and write a manifest.json describing the backup."
+    )]
+    Backup {
+        #[arg(required=true, help="The mount point of the SD card or its CONTENT directory.", value_parser = validate_directory_path)]
+        sd_root: PathBuf,
+        #[arg(
+            required = true,
+            help = "The backup directory, which is created if missing."
+        )]
+        output: PathBuf,
+        #[arg(
+            long,
+            default_value = "ogg",
+            value_parser = parse_output_format,
+            help = "The audio format of the extracted files: ogg, mp3, flac or wav. All formats except ogg are transcoded with ffmpeg."
+        )]
+        format: OutputFormat,
+        #[arg(
+            long,
+            help = "Replace non-ASCII characters in output file names with ASCII equivalents, e.g. 'ä' with 'ae'."
+        )]
+        transliterate: bool,
+        #[arg(
+            long,
+            value_name = "PATH_OR_URL",
+            help = "The tonies.json file or its URL. Defaults to a cached copy of the community database, which is downloaded on first use."
+        )]
+        tonies_json: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "tonies_json",
+            help = "Download the latest community database into the cache before the backup."
+        )]
+        update: bool,
+        #[arg(
+            long,
+            default_value = "ffmpeg",
+            help = "Path to ffmpeg executable on your system."
+        )]
+        ffmpeg: String,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Restore a backup onto a Toniebox SD card: write every Tonie file of its manifest.json into the CONTENT layout of its tag, converting the audio files with their original audio id."
+    )]
+    Restore {
+        #[arg(required = true, help = "The backup directory or its manifest.json.")]
+        backup: PathBuf,
+        #[arg(required=true, help="The mount point of the SD card.", value_parser = validate_directory_path)]
+        sd_root: PathBuf,
+        #[arg(
+            long,
+            help = "Replace the content of tags which already have content on the SD card. By default they are skipped."
+        )]
+        overwrite: bool,
+        #[arg(
+            long,
+            default_value = "ffmpeg",
+            help = "Path to ffmpeg executable on your system."
+        )]
+        ffmpeg: String,
+    },
+    #[command(
+        about = "Write a catalog of the Tonie files in a directory and its subdirectories with their header details and Opus comments as JSON or CSV, e.g. for TeddyCloud front-ends or spreadsheets."
+    )]
+    Catalog {
+        #[arg(required=true, help="The directory to search for .taf files and Tonie files in the CONTENT layout of the SD card.", value_parser = validate_directory_path)]
+        directory: PathBuf,
+        #[arg(
+            long,
+            short,
+            required = true,
+            help = "The catalog file to write, e.g. library.json or library.csv, or '-' to write it to stdout."
+        )]
+        output: PathBuf,
+        #[arg(
+            long,
+            value_enum,
+            help = "The format of the catalog. Defaults to csv for .csv files and json otherwise."
+        )]
+        format: Option<CatalogFormat>,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Upload a Tonie file to the library of a TeddyCloud server. Audio files and directories are converted first."
+    )]
+    Upload {
+        #[arg(required=true, help="A Tonie file, an audio file or a directory of audio files.", value_parser = validate_directory_path)]
+        input: PathBuf,
+        #[arg(
+            long,
+            required = true,
+            env = "AUDIO2TONIE_TEDDYCLOUD_URL",
+            help = "The base URL of the TeddyCloud server, e.g. https://teddycloud.local."
+        )]
+        url: String,
+        #[arg(
+            long,
+            default_value = "",
+            help = "The directory in the TeddyCloud library to upload to."
+        )]
+        path: String,
+        #[arg(
+            long,
+            env = "AUDIO2TONIE_TEDDYCLOUD_TOKEN",
+            hide_env_values = true,
+            help = "Token sent as bearer authorization, e.g. when TeddyCloud runs behind an authenticating reverse proxy."
+        )]
+        token: Option<String>,
+        #[arg(
+            long,
+            value_parser = validate_file_path,
+            help = "PEM file with additional trusted certificates, e.g. for a self-signed TeddyCloud certificate."
+        )]
+        ca_cert: Option<PathBuf>,
+        #[arg(
+            long,
+            default_value = "ffmpeg",
+            help = "Path to ffmpeg executable on your system."
+        )]
+        ffmpeg: String,
+    },
+    #[command(
+        about = "Upload audio files to a creative Tonie of the official Tonie cloud (my.tonies.com), one chapter per file. Tonie files and unsupported formats are converted first."
+    )]
+    UploadCloud {
+        #[arg(
+            required_unless_present = "list",
+            help = "Audio files, directories of audio files or Tonie files, in chapter order."
+        )]
+        inputs: Vec<PathBuf>,
+        #[arg(
+            long,
+            help = "The name or id of the creative Tonie. Can be omitted if the account has only one."
+        )]
+        tonie: Option<String>,
+        #[arg(
+            long,
+            conflicts_with_all = ["inputs", "tonie", "replace"],
+            help = "List the creative Tonies of the account instead of uploading."
+        )]
+        list: bool,
+        #[arg(
+            long,
+            help = "Replace the current chapters of the Tonie instead of appending to them."
+        )]
+        replace: bool,
+        #[arg(
+            long,
+            required = true,
+            env = "AUDIO2TONIE_TONIES_USERNAME",
+            help = "The email address of the my.tonies.com account."
+        )]
+        username: String,
+        #[arg(
+            long,
+            required = true,
+            env = "AUDIO2TONIE_TONIES_PASSWORD",
+            hide_env_values = true,
+            help = "The password of the my.tonies.com account."
+        )]
+        password: String,
+        #[arg(
+            long,
+            default_value = "ffmpeg",
+            help = "Path to ffmpeg executable on your system."
+        )]
+        ffmpeg: String,
+    },
+    #[command(
+        about = "Download the episodes of a podcast feed and convert them into a Tonie file with one chapter per episode, named after the episode titles."
+    )]
+    Podcast {
+        #[arg(required = true, help = "The URL of the RSS feed of the podcast.")]
+        feed: String,
+        #[arg(
+            help = "The output file. Defaults to the title of the podcast in the current directory."
+        )]
+        output: Option<PathBuf>,
+        #[arg(
+            long,
+            value_name = "N",
+            conflicts_with = "episode",
+            value_parser = clap::value_parser!(u16).range(1..),
+            help = "Convert the N latest episodes, the oldest of them becomes the first chapter."
+        )]
+        latest: Option<u16>,
+        #[arg(
+            long,
+            value_name = "TITLE",
+            help = "Convert the episodes whose titles contain the text, ignoring case. Can be repeated."
+        )]
+        episode: Vec<String>,
+        #[arg(
+            long,
+            conflicts_with_all = ["latest", "episode"],
+            help = "List the episodes of the feed instead of converting them."
+        )]
+        list: bool,
+        #[arg(
+            long,
+            help = "Write a JSON file with the conversion metadata next to the Tonie file, which keeps the episode titles as chapter titles."
+        )]
+        sidecar: bool,
+        #[arg(
+            long,
+            default_value = "ffmpeg",
+            help = "Path to ffmpeg executable on your system."
+        )]
+        ffmpeg: String,
+        #[command(flatten)]
+        existing_output: ExistingOutputArgs,
+    },
+    #[command(
+        about = "Run the conversions described by a YAML or JSON manifest file, each with its inputs, output, bitrate, title and tag UID, and print a summary."
     )]
-    Extract {
+    Batch {
+        #[arg(required=true, help="The manifest file with the jobs, e.g. jobs.yaml.", value_parser = validate_file_path)]
+        manifest: PathBuf,
+        #[arg(
+            long,
+            default_value_t = 1,
+            value_parser = clap::value_parser!(u16).range(1..),
+            help = "Run up to this many conversions in parallel."
+        )]
+        jobs: u16,
+        #[arg(
+            long,
+            value_parser = validate_directory_path,
+            help = "The mounted SD card of the Toniebox for jobs with a tag UID. Overrides the sd_root of the manifest."
+        )]
+        sd_root: Option<PathBuf>,
+        #[arg(
+            long,
+            default_value_t = 96,
+            value_parser = clap::value_parser!(u32).range(6..=510),
+            help = "The Opus bitrate in kbit/s of the jobs without a bitrate of their own."
+        )]
+        bitrate: u32,
+        #[arg(
+            long,
+            default_value = "ffmpeg",
+            help = "Path to ffmpeg executable on your system."
+        )]
+        ffmpeg: String,
+        #[command(flatten)]
+        failure: FailureArgs,
+        #[command(flatten)]
+        existing_output: ExistingOutputArgs,
+    },
+    #[command(
+        about = "Split a Tonie file with several chapters into standalone Tonie files, one per chapter, without re-encoding the audio."
+    )]
+    Split {
         #[arg(required=true, help="The input audio file in Tonie format.", value_parser = validate_file_path)]
         input: PathBuf,
-        #[arg(help="The output directory for saving the extracted audio content in.", value_parser = validate_directory_path)]
+        #[arg(help="The output directory for the Tonie files of the chapters.", value_parser = validate_directory_path)]
         output: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Replace non-ASCII characters in output file names with ASCII equivalents, e.g. 'ä' with 'ae'."
+        )]
+        transliterate: bool,
+        #[arg(
+            long,
+            help = "File name of every chapter with the placeholders {number}, {index}, {title} and {name}, e.g. '{number} - {title}'. Defaults to '{number} - {title}' if chapter titles are known."
+        )]
+        name_template: Option<String>,
+        #[command(flatten)]
+        limits: LimitArgs,
     },
     #[command(
-        about = "Convert a single audio file or a directory of audio files into a Toniebox compatible audio file. Input audio files can be in any audio format that can be handled and converted by ffmpeg."
+        about = "Re-encode a Tonie file with another Opus bitrate, keeping its chapters, e.g. to save space on the SD card."
     )]
-    Convert {
-        #[arg(required=true, help="The input audio file or a directory of files.", value_parser = validate_directory_path)]
+    Recode {
+        #[arg(required=true, help="The input audio file in Tonie format.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[arg(short, long, required = true, help = "The path of the new Tonie file.")]
+        output: PathBuf,
+        #[arg(
+            long,
+            required = true,
+            value_parser = clap::value_parser!(u32).range(6..=510),
+            help = "The Opus bitrate of the new Tonie file in kbit/s. Tonie files are usually encoded with 96 kbit/s."
+        )]
+        bitrate: u32,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        visible_alias = "verify",
+        about = "Validate a Tonie file before copying it to the Toniebox: SHA1 hash, data length, 4kb page alignment, page sizes and Ogg checksums."
+    )]
+    Check {
+        #[arg(required=true, help="The input audio file in Tonie format.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "List every Ogg page of a Tonie file or Ogg Opus file with its offset, granule position, packet sizes, padding, checksum and 4kb block alignment."
+    )]
+    Analyze {
+        #[arg(required=true, help="The input audio file in Tonie format or an Ogg Opus file.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Show a single Ogg page of a Tonie file or Ogg Opus file: a hex dump of its header, its segments, the TOC byte of every Opus packet and the frame layout of the first packet."
+    )]
+    InspectPage {
+        #[arg(required=true, help="The input audio file in Tonie format or an Ogg Opus file.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[arg(
+            long,
+            help = "The page number in the Ogg stream, as listed by the analyze command."
+        )]
+        page: u32,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Play a Tonie file with ffplay to check the content and the chapter boundaries before copying it to the Toniebox."
+    )]
+    Play {
+        #[arg(required=true, help="The input audio file in Tonie format.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u32).range(1..),
+            help = "The chapter to start with, counting from 1."
+        )]
+        chapter: Option<u32>,
+        #[arg(
+            long,
+            default_value = "ffplay",
+            help = "Path to the ffplay executable, which ships with ffmpeg."
+        )]
+        ffplay: String,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Watch a drop folder and convert every audio file or directory that appears in it into a Tonie file."
+    )]
+    Watch {
+        #[arg(required=true, help="The directory to watch.", value_parser = validate_directory_path)]
         input: PathBuf,
-        #[arg(default_value = "500304E0", help = "The output audio file.")]
+        #[arg(required = true, help = "The output directory for the Tonie files.")]
         output: PathBuf,
         #[arg(
             long,
@@ -33,9 +800,310 @@ pub enum CLICommands {
             help = "Path to ffmpeg executable on your system."
         )]
         ffmpeg: String,
+        #[arg(
+            long,
+            default_value_t = 5,
+            value_name = "SECONDS",
+            help = "Convert a new file or directory once it did not change for the given number of seconds, so it is not read while it is copied."
+        )]
+        debounce: u64,
+        #[arg(
+            long,
+            help = "Convert files with unknown extensions with ffmpeg instead of ignoring them."
+        )]
+        any_extension: bool,
+        #[command(flatten)]
+        existing_output: ExistingOutputArgs,
+    },
+    #[command(
+        about = "Browse a directory in an interactive terminal interface, select and reorder the tracks, set title and bitrate and convert them into a Tonie file."
+    )]
+    Tui {
+        #[arg(default_value = ".", help = "The directory to start browsing in.", value_parser = validate_directory_path)]
+        directory: PathBuf,
+        #[arg(
+            short,
+            long,
+            default_value = ".",
+            help = "The directory for the Tonie file, which is named after the title."
+        )]
+        output_dir: PathBuf,
+        #[arg(
+            long,
+            default_value = "ffmpeg",
+            help = "Path to ffmpeg executable on your system."
+        )]
+        ffmpeg: String,
+    },
+    #[command(
+        about = "Run an HTTP server with a REST API to upload audio files, list the conversion jobs and download the Tonie files, e.g. on a NAS driven from a phone browser."
+    )]
+    Serve {
+        #[arg(
+            long,
+            default_value = "0.0.0.0:8080",
+            value_name = "ADDRESS",
+            help = "The address and port to listen on."
+        )]
+        listen: String,
+        #[arg(
+            long,
+            default_value = "ffmpeg",
+            help = "Path to ffmpeg executable on your system."
+        )]
+        ffmpeg: String,
+        #[arg(
+            long,
+            default_value = "512M",
+            value_name = "SIZE",
+            value_parser = parse_size,
+            help = "The maximum size of an upload, e.g. 100M or 1G."
+        )]
+        max_upload_size: u64,
+    },
+    #[command(
+        about = "Show the SD card path of the Tonie file of an NFC tag UID, or the tag UID of a path on the SD card."
+    )]
+    Uid {
+        #[arg(
+            required = true,
+            help = "A tag UID, e.g. E0:04:03:50:1E:12:34:56, or the path of a Tonie file on the SD card, e.g. CONTENT/5634121E/500304E0."
+        )]
+        uid_or_path: String,
+    },
+    #[command(
+        about = "Decode a Tonie file and show duration, integrated loudness (LUFS) and true peak (dBTP) for every chapter."
+    )]
+    Stats {
+        #[arg(required=true, help="The input audio file in Tonie format.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Give a Tonie file a new audio id in place, e.g. so TeddyCloud downloads it again. The Ogg serial numbers, checksums and the SHA1 hash are updated as well."
+    )]
+    Retimestamp {
+        #[arg(required=true, help="The Tonie file to change in place.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[arg(
+            long,
+            visible_alias = "audio-id",
+            value_parser = parse_audio_id,
+            help = "The new audio id as decimal or 0x-prefixed hexadecimal number. Defaults to the current Unix timestamp."
+        )]
+        timestamp: Option<u32>,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Dump the protobuf header of a Tonie file as JSON or rewrite it from edited JSON, keeping the audio untouched."
+    )]
+    Header {
+        #[command(subcommand)]
+        command: HeaderCommands,
+    },
+    #[command(
+        name = "teddycloud",
+        about = "Manage the content of a TeddyCloud server: list its library and tags, assign Tonie files of the library to tags and delete files."
+    )]
+    TeddyCloud {
+        #[command(subcommand)]
+        command: TeddyCloudCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TeddyCloudCommands {
+    #[command(
+        about = "List a directory of the library with the audio id and title of every Tonie file, or the tags known to the server with their assigned content."
+    )]
+    List {
+        #[arg(
+            default_value = "",
+            help = "The directory in the library. Defaults to the root of the library."
+        )]
+        path: String,
+        #[arg(
+            long,
+            conflicts_with = "path",
+            help = "List the tags known to the server instead of the library."
+        )]
+        tags: bool,
+        #[arg(
+            long,
+            requires = "tags",
+            help = "The ID of the Toniebox whose settings overlay is used for the tags."
+        )]
+        overlay: Option<String>,
+        #[command(flatten)]
+        server: TeddyCloudArgs,
+    },
+    #[command(
+        about = "Assign a Tonie file of the library to a tag, so the Toniebox plays it when the tag is placed on it."
+    )]
+    Assign {
+        #[arg(required = true, value_parser = parse_tag_uid, help = "The UID of the tag, e.g. E0:04:03:50:1E:12:34:56.")]
+        tag_uid: TagUid,
+        #[arg(
+            required = true,
+            help = "The path of the Tonie file in the library, e.g. audiobooks/gruffalo.taf."
+        )]
+        library_path: String,
+        #[arg(
+            long,
+            help = "The ID of the Toniebox whose settings overlay is changed. Defaults to the settings of all Tonieboxes."
+        )]
+        overlay: Option<String>,
+        #[command(flatten)]
+        server: TeddyCloudArgs,
+    },
+    #[command(about = "Delete a file from the library.")]
+    Delete {
+        #[arg(
+            required = true,
+            help = "The path of the file in the library, e.g. audiobooks/gruffalo.taf."
+        )]
+        library_path: String,
+        #[command(flatten)]
+        server: TeddyCloudArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct TeddyCloudArgs {
+    #[arg(
+        long,
+        required = true,
+        env = "AUDIO2TONIE_TEDDYCLOUD_URL",
+        help = "The base URL of the TeddyCloud server, e.g. https://teddycloud.local."
+    )]
+    pub url: String,
+    #[arg(
+        long,
+        env = "AUDIO2TONIE_TEDDYCLOUD_TOKEN",
+        hide_env_values = true,
+        help = "Token sent as bearer authorization, e.g. when TeddyCloud runs behind an authenticating reverse proxy."
+    )]
+    pub token: Option<String>,
+    #[arg(
+        long,
+        value_parser = validate_file_path,
+        help = "PEM file with additional trusted certificates, e.g. for a self-signed TeddyCloud certificate."
+    )]
+    pub ca_cert: Option<PathBuf>,
+}
+
+impl From<TeddyCloudArgs> for TeddyCloudClient {
+    fn from(args: TeddyCloudArgs) -> Self {
+        TeddyCloudClient::new(&args.url)
+            .with_token(args.token)
+            .with_ca_cert(args.ca_cert)
+    }
+}
+
+#[derive(Subcommand)]
+pub enum HeaderCommands {
+    #[command(
+        about = "Print all header fields as JSON: SHA1 hash, data length, audio ID (timestamp), chapter pages and fill size."
+    )]
+    Dump {
+        #[arg(required=true, help="The input audio file in Tonie format.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    #[command(
+        about = "Rewrite the header with the fields of a JSON file in the format of header dump. The fill size is recomputed."
+    )]
+    Apply {
+        #[arg(required=true, help="The Tonie file to change in place.", value_parser = validate_file_path)]
+        input: PathBuf,
+        #[arg(required=true, help="A JSON file with the header fields to change.", value_parser = validate_file_path)]
+        patch: PathBuf,
+        #[command(flatten)]
+        limits: LimitArgs,
     },
 }
 
+#[derive(Args)]
+pub struct LimitArgs {
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_PAGES,
+        help = "Maximum number of Ogg pages to parse from an input file."
+    )]
+    pub max_pages: usize,
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_TOTAL_BYTES,
+        help = "Maximum size of an input file in bytes."
+    )]
+    pub max_input_size: u64,
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_HEADER_SIZE,
+        help = "Maximum size of the Tonie header in bytes."
+    )]
+    pub max_header_size: usize,
+}
+
+impl From<LimitArgs> for Limits {
+    fn from(args: LimitArgs) -> Self {
+        Limits {
+            max_pages: args.max_pages,
+            max_total_bytes: args.max_input_size,
+            max_header_size: args.max_header_size,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct FailureArgs {
+    #[arg(
+        long,
+        conflicts_with = "fail_fast",
+        help = "Leave input files that fail to decode out of the Tonie file instead of failing its conversion. The skipped files are listed at the end."
+    )]
+    pub keep_going: bool,
+    #[arg(
+        long,
+        help = "Stop at the first failed conversion. By default the other conversions of the run continue."
+    )]
+    pub fail_fast: bool,
+}
+
+impl From<&FailureArgs> for TrackFailure {
+    fn from(args: &FailureArgs) -> Self {
+        match args.keep_going {
+            true => TrackFailure::Skip,
+            false => TrackFailure::Fail,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct ExistingOutputArgs {
+    #[arg(
+        long,
+        conflicts_with = "skip_existing",
+        help = "Overwrite existing output files. By default existing files are kept and the conversion fails."
+    )]
+    pub force: bool,
+    #[arg(long, help = "Skip the conversion if the output file already exists.")]
+    pub skip_existing: bool,
+}
+
+impl From<ExistingOutputArgs> for ExistingOutput {
+    fn from(args: ExistingOutputArgs) -> Self {
+        match (args.force, args.skip_existing) {
+            (true, _) => ExistingOutput::Overwrite,
+            (_, true) => ExistingOutput::Skip,
+            _ => ExistingOutput::Fail,
+        }
+    }
+}
+
 fn validate_file_path(s: &str) -> Result<PathBuf, String> {
     let path = PathBuf::from(s);
     if path.exists() && path.is_file() {
@@ -54,6 +1122,302 @@ fn validate_directory_path(s: &str) -> Result<PathBuf, String> {
     }
 }
 
+// Stdout is written to with "-"
+fn validate_output_directory(s: &str) -> Result<PathBuf, String> {
+    if s == "-" {
+        return Ok(PathBuf::from(s));
+    }
+    return validate_directory_path(s);
+}
+
+fn validate_input_path(s: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(s);
+    if path.exists() || is_glob_pattern(s) {
+        Ok(path)
+    } else {
+        Err(format!("The path '{}' does not exist.", s))
+    }
+}
+
+/// Separates the positional paths of the convert command into the inputs and the output. Without an explicit
+/// output, the last of several paths is the output like in `convert input.mp3 output.taf`, and a single input is
+/// converted into "500304E0". Inputs read with `--files-from` replace the positional inputs, so only the output
+/// may be given as path. Every input has to exist, be a glob pattern or a URL to download.
+pub fn split_convert_paths(
+    mut paths: Vec<PathBuf>,
+    listed_inputs: Option<Vec<PathBuf>>,
+    output: Option<PathBuf>,
+) -> Result<(Vec<PathBuf>, PathBuf), String> {
+    let (inputs, output) = match (listed_inputs, output) {
+        (Some(listed_inputs), None) if paths.len() <= 1 => {
+            let output = paths.pop().unwrap_or_else(|| PathBuf::from("500304E0"));
+            (listed_inputs, output)
+        }
+        (Some(listed_inputs), Some(output)) if paths.is_empty() => (listed_inputs, output),
+        (Some(_), _) => {
+            return Err(
+                "Input paths cannot be combined with --files-from, only the output audio file."
+                    .to_string(),
+            );
+        }
+        (None, Some(output)) => (paths, output),
+        (None, None) if paths.len() > 1 => {
+            let output = paths.pop().unwrap_or_default();
+            (paths, output)
+        }
+        (None, None) => (paths, PathBuf::from("500304E0")),
+    };
+    if inputs.len() > 1 && inputs.iter().any(|input| is_stdin_input(input)) {
+        return Err("The audio from stdin cannot be combined with other input paths.".to_string());
+    }
+    for input in inputs
+        .iter()
+        .filter(|input| !is_stdin_input(input) && !is_url_input(input))
+    {
+        validate_input_path(&input.to_string_lossy())?;
+    }
+    Ok((inputs, output))
+}
+
+/// Parses a size in bytes with an optional binary K, M or G suffix, e.g. "500M".
+/// Splits a command line into arguments at whitespace. Single or double quotes group arguments with spaces,
+/// e.g. `-metadata "title=Der Räuber"`.
+///
+/// # Arguments
+///
+/// * `s` - The command line.
+pub fn split_arguments(s: &str) -> Result<Vec<String>, String> {
+    let mut arguments = vec![];
+    let mut argument: Option<String> = None;
+    let mut quote = None;
+    for c in s.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => argument.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                argument.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => arguments.extend(argument.take()),
+            (None, c) => argument.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("Unterminated quote in '{}'", s));
+    }
+    arguments.extend(argument);
+
+    return Ok(arguments);
+}
+
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (number, multiplier) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len() - 1], 1024),
+        Some('M') => (&s[..s.len() - 1], 1024 * 1024),
+        Some('G') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s, 1),
+    };
+
+    let number = number
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| format!("'{}' is not a valid size, e.g. 500M.", s))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("'{}' is too large.", s))
+}
+
+/// Parses a duration in seconds with optional h, m and s units, e.g. "90m", "1h30m" or "5400".
+pub fn parse_duration_limit(s: &str) -> Result<f64, String> {
+    let error = || format!("'{}' is not a valid duration, e.g. 90m or 1h30m.", s);
+    let s = s.trim();
+    if let Ok(seconds) = s.parse::<f64>() {
+        return Some(seconds)
+            .filter(|seconds| *seconds > 0.0)
+            .ok_or_else(error);
+    }
+
+    let mut seconds = 0.0;
+    let mut number = String::new();
+    for c in s.chars() {
+        let unit = match c {
+            'h' => 3600.0,
+            'm' => 60.0,
+            's' => 1.0,
+            _ => {
+                number.push(c);
+                continue;
+            }
+        };
+        seconds += number.parse::<f64>().map_err(|_| error())? * unit;
+        number.clear();
+    }
+    if !number.is_empty() || seconds <= 0.0 {
+        return Err(error());
+    }
+
+    return Ok(seconds);
+}
+
+fn parse_since(s: &str) -> Result<Since, String> {
+    if s == "last" {
+        return Ok(Since::LastRun);
+    }
+
+    s.parse::<u64>()
+        .map(|seconds| Since::Timestamp(UNIX_EPOCH + Duration::from_secs(seconds)))
+        .map_err(|_| format!("'{}' is neither a Unix timestamp in seconds nor 'last'.", s))
+}
+
+/// Parses a chapter number or an inclusive range of chapter numbers counting from 1, e.g. "5" or "5-7".
+pub fn parse_chapter_range(s: &str) -> Result<RangeInclusive<usize>, String> {
+    let error = || format!("'{}' is not a chapter number or range, e.g. 2 or 5-7.", s);
+    let (start, end) = s.trim().split_once('-').unwrap_or((s.trim(), s.trim()));
+    let start = start.trim().parse::<usize>().map_err(|_| error())?;
+    let end = end.trim().parse::<usize>().map_err(|_| error())?;
+    if start == 0 || end < start {
+        return Err(error());
+    }
+
+    return Ok(start..=end);
+}
+
+fn parse_output_format(s: &str) -> Result<OutputFormat, String> {
+    return match s.to_ascii_lowercase().as_str() {
+        "ogg" => Ok(OutputFormat::Ogg),
+        "mp3" => Ok(OutputFormat::Mp3),
+        "flac" => Ok(OutputFormat::Flac),
+        "wav" => Ok(OutputFormat::Wav),
+        _ => Err(format!(
+            "'{}' is not a supported format. Expected ogg, mp3, flac or wav.",
+            s
+        )),
+    };
+}
+
+/// Parses the source of a cover image, either "auto" or the path of an existing image file.
+fn parse_cover(s: &str) -> Result<CoverSource, String> {
+    if s.eq_ignore_ascii_case("auto") {
+        return Ok(CoverSource::Auto);
+    }
+    return validate_file_path(s).map(CoverSource::File);
+}
+
+/// Parses an audio id given as decimal number or as hexadecimal number with a 0x prefix, e.g. "0x12345678".
+pub fn parse_audio_id(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    let audio_id = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse::<u32>(),
+    };
+
+    audio_id.map_err(|_| {
+        format!(
+            "'{}' is not a valid audio id. Expected a 32 bit decimal or 0x-prefixed hexadecimal number.",
+            s
+        )
+    })
+}
+
+/// Parses the silence threshold in dBFS and the minimum duration in milliseconds, e.g. "-40,300".
+/// Missing values are taken from the defaults.
+pub fn parse_trim_silence(s: &str) -> Result<SilenceTrim, String> {
+    let mut trim = SilenceTrim::default();
+    let mut parts = s.split(',').map(str::trim);
+    let error = || {
+        format!(
+            "'{}' is not a valid silence threshold and duration, e.g. -50,500.",
+            s
+        )
+    };
+
+    if let Some(threshold) = parts.next().filter(|part| !part.is_empty()) {
+        trim.threshold = threshold
+            .trim_end_matches("dB")
+            .parse::<f64>()
+            .ok()
+            .filter(|threshold| *threshold <= 0.0)
+            .ok_or_else(error)?;
+    }
+    if let Some(min_duration) = parts.next() {
+        trim.min_duration = min_duration
+            .trim_end_matches("ms")
+            .parse::<u64>()
+            .map_err(|_| error())?;
+    }
+    if parts.next().is_some() {
+        return Err(error());
+    }
+
+    return Ok(trim);
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    return s
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|speed| (0.25..=4.0).contains(speed))
+        .ok_or_else(|| {
+            format!(
+                "'{}' is not a valid speed. Expected a factor from 0.25 to 4, e.g. 1.15.",
+                s
+            )
+        });
+}
+
+fn parse_chapter_overflow(s: &str) -> Result<ChapterOverflow, String> {
+    return match s.to_ascii_lowercase().as_str() {
+        "error" => Ok(ChapterOverflow::Error),
+        "merge-adjacent" => Ok(ChapterOverflow::MergeAdjacent),
+        "split-output" => Ok(ChapterOverflow::SplitOutput),
+        _ => Err(format!(
+            "'{}' is not a supported strategy. Expected error, merge-adjacent or split-output.",
+            s
+        )),
+    };
+}
+
+fn parse_passthrough(s: &str) -> Result<Passthrough, String> {
+    return match s.to_ascii_lowercase().as_str() {
+        "auto" => Ok(Passthrough::Auto),
+        "always" => Ok(Passthrough::Always),
+        "never" => Ok(Passthrough::Never),
+        _ => Err(format!(
+            "'{}' is not a supported passthrough mode. Expected auto, always or never.",
+            s
+        )),
+    };
+}
+
+fn parse_opus_application(s: &str) -> Result<OpusApplication, String> {
+    return match s.to_ascii_lowercase().as_str() {
+        "audio" => Ok(OpusApplication::Audio),
+        "voip" => Ok(OpusApplication::Voip),
+        "lowdelay" => Ok(OpusApplication::LowDelay),
+        _ => Err(format!(
+            "'{}' is not a supported Opus application. Expected audio, voip or lowdelay.",
+            s
+        )),
+    };
+}
+
+fn parse_track_order(s: &str) -> Result<TrackOrder, String> {
+    return match s.to_ascii_lowercase().as_str() {
+        "name" => Ok(TrackOrder::Name),
+        "tags" => Ok(TrackOrder::Tags),
+        _ => Err(format!(
+            "'{}' is not a supported order. Expected tags or name.",
+            s
+        )),
+    };
+}
+
+fn parse_tag_uid(s: &str) -> Result<TagUid, String> {
+    return TagUid::parse(s).map_err(|error| error.to_string());
+}
+
 pub fn get_cli() -> Cli {
     Cli::parse()
 }
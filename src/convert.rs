@@ -1,67 +1,1713 @@
-use anyhow::{anyhow, Result};
+use crate::cancel::Cancellation;
+use crate::cue::{find_cue_sheet, parse_cue_sheet};
+use crate::encode::{OpusApplication, TafEncoder, DEFAULT_BITRATE, MIN_BITRATE};
+use crate::error::Audio2TonieError;
+use crate::fade::Fader;
+use crate::loudness::{apply_gain, gated_loudness, normalization_gain, LoudnessMeter};
+use anyhow::{anyhow, Context, Result};
 use human_sort::compare;
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use toniefile::Toniefile;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Stdio};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::utils::vec_u8_to_i16;
+use crate::ogg_page::OGG_PAGE_HEADER_SIZE;
+use crate::passthrough::{check_passthrough, pass_through_opus};
+use crate::progress::ProgressBar;
+use crate::silence::{SilenceTrim, SilenceTrimmer};
+use crate::taf::{MAX_AUDIO_LENGTH, MAX_CHAPTERS, TONIEFILE_FRAME_SIZE, TONIEFILE_HEADER_SIZE};
+use crate::tap::{is_tap_file, TapPlaylist};
+use crate::throttle::ThrottledIo;
+use crate::utils::{available_space, limit_path_length, sanitize_file_name, tool_command};
 
-const SUPPORTED_FILE_EXTENSIONS: [&str; 6] = ["mp3", "aac", "wav", "ogg", "webm", "opus"];
+const SUPPORTED_FILE_EXTENSIONS: [&str; 14] = [
+    "mp3", "aac", "wav", "ogg", "webm", "opus", "flac", "m4a", "m4b", "mka", "aiff", "aif", "aifc",
+    "wma",
+];
+// Files next to audio files which are never passed to ffmpeg, even if any extension is accepted
+const NON_AUDIO_FILE_EXTENSIONS: [&str; 16] = [
+    "cue", "jpg", "jpeg", "png", "gif", "bmp", "webp", "txt", "nfo", "json", "log", "m3u", "m3u8",
+    "pdf", "taf", "tap",
+];
+// Containers that may carry embedded chapter markers, e.g. audio books
+const CHAPTER_FILE_EXTENSIONS: [&str; 3] = ["m4a", "m4b", "mka"];
+const DEFAULT_OUTPUT_FILE_NAME: &str = "500304E0";
+// Stereo 16 bit PCM at 48 kHz as requested from ffmpeg
+const PCM_SAMPLE_RATE: u64 = 48000;
+const PCM_CHANNELS: usize = 2;
+const PCM_BYTES_PER_SECOND: usize = PCM_SAMPLE_RATE as usize * PCM_CHANNELS * 2;
+const PCM_CHUNK_SIZE: usize = 64 * 1024;
+// The number of lines ffmpeg printed last which are reported when it fails
+const FFMPEG_STDERR_LINES: usize = 10;
+pub const DEFAULT_TARGET_LOUDNESS: f64 = -16.0;
+/// The input path that reads the audio from stdin. It can only be decoded once, so it cannot be normalized or
+/// planned upfront, and ffmpeg may need the [`ConvertOptions::input_format`] to detect the format.
+pub const STDIN_INPUT: &str = "-";
+// Leave some headroom for the lossy Opus encoding
+const TRUE_PEAK_CEILING: f64 = -1.0;
+
+/// Loudness normalization applied to the decoded audio before encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Normalization {
+    #[default]
+    None,
+    /// Every track is normalized to the target loudness on its own.
+    Track,
+    /// All tracks get the same gain, preserving loudness differences between chapters.
+    Album,
+}
+
+/// What to do if the input files result in more chapters than the Toniebox supports, see [`MAX_CHAPTERS`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChapterOverflow {
+    /// Fail the conversion.
+    #[default]
+    Error,
+    /// Merge the shortest adjacent chapters into shared chapters.
+    MergeAdjacent,
+    /// Distribute the input files across several Tonie files. Needs to be handled by the caller with
+    /// [`split_conversion_plan`], for a single Tonie file it behaves like `Error`.
+    SplitOutput,
+}
+
+/// What to do if the output file already exists.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ExistingOutput {
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Keep the existing file and skip the conversion. Needs to be handled by the caller with
+    /// [`output_exists`], the conversion itself fails like with `Fail`.
+    Skip,
+    /// Keep the existing file and fail the conversion.
+    Fail,
+}
+
+/// Whether Opus inputs the Toniebox can play as they are are repackaged without re-encoding them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Passthrough {
+    /// Pass the inputs through if all of them allow it, see [`check_passthrough`], and re-encode them otherwise.
+    Auto,
+    /// Pass the inputs through and fail the conversion if they do not allow it.
+    Always,
+    /// Always decode and re-encode the inputs.
+    #[default]
+    Never,
+}
+
+/// What to do if an input file fails to decode, e.g. because it is corrupt.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TrackFailure {
+    /// Fail the conversion with [`Audio2TonieError::TrackFailed`].
+    #[default]
+    Fail,
+    /// Leave the file out of the Tonie file and record it in [`ConvertOptions::failed_tracks`]. Audio decoded
+    /// before a failure in the middle of the file is kept and the file is recorded as truncated, see
+    /// [`FailedTrack::truncated`]. The conversion still fails if none of the files produce any audio.
+    Skip,
+}
+
+/// An input file that failed to decode and was left out of the Tonie file or cut short.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailedTrack {
+    pub input_file: PathBuf,
+    /// The reason of the failure.
+    pub error: String,
+    /// Whether the file failed in the middle and the audio decoded before the failure is in the Tonie file.
+    pub truncated: bool,
+}
+
+/// The input files left out of or truncated in conversions with [`TrackFailure::Skip`], so they can be reported once the
+/// conversion is done. Clones share the same list.
+#[derive(Clone, Debug, Default)]
+pub struct FailedTracks {
+    tracks: Arc<Mutex<Vec<FailedTrack>>>,
+}
+
+impl FailedTracks {
+    pub fn new() -> Self {
+        return FailedTracks::default();
+    }
+
+    fn record(&self, input_file: &Path, error: &Audio2TonieError, truncated: bool) {
+        let track = FailedTrack {
+            input_file: input_file.to_path_buf(),
+            error: error.to_string(),
+            truncated,
+        };
+        self.tracks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(track);
+    }
+
+    /// Removes and returns the recorded input files, e.g. to report the failures of every conversion of a run
+    /// separately.
+    pub fn take(&self) -> Vec<FailedTrack> {
+        return std::mem::take(&mut *self.tracks.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+}
+
+/// The order of the audio files of an input directory, which becomes the chapter order.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TrackOrder {
+    /// Sort the files naturally by their names.
+    #[default]
+    Name,
+    /// Sort the files by their disc and track number tags, e.g. ID3 `TRCK` or Vorbis `TRACKNUMBER`. Falls back to
+    /// the order of the names if a file has no track number or two files have the same numbers.
+    Tags,
+}
+
+/// Reference point in time for incremental conversions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Since {
+    /// An absolute point in time, e.g. parsed from a Unix timestamp.
+    Timestamp(SystemTime),
+    /// The last recorded run, i.e. the modification time of an existing output file.
+    LastRun,
+}
+
+/// Options controlling how input audio files are converted into a Tonie file.
+#[derive(Clone, Debug)]
+pub struct ConvertOptions {
+    /// The path to the ffmpeg executable.
+    pub ffmpeg: String,
+    /// Limits reading decoded audio and writing the output to the given number of bytes per second.
+    pub io_throttle: Option<u64>,
+    /// Show the decoding progress of every track on stderr.
+    pub show_progress: bool,
+    /// Loudness normalization mode.
+    pub normalization: Normalization,
+    /// The integrated loudness in LUFS to normalize to.
+    pub target_loudness: f64,
+    /// The number of tracks decoded concurrently. Every track in flight is kept in memory.
+    pub threads: usize,
+    /// The audio id stored in the Tonie header. `None` uses the current Unix timestamp.
+    pub audio_id: Option<u32>,
+    /// Removes the silence at the start and the end of every track.
+    pub trim_silence: Option<SilenceTrim>,
+    /// The length of the fade-in at the start of every track.
+    pub fade_in: Duration,
+    /// The length of the fade-out at the end of every track.
+    pub fade_out: Duration,
+    /// The playback speed, e.g. 1.15 plays 15% faster. The pitch is kept.
+    pub speed: f64,
+    /// What to do if the input files result in more chapters than the Toniebox supports.
+    pub on_too_many_chapters: ChapterOverflow,
+    /// What to do if the output file already exists.
+    pub existing_output: ExistingOutput,
+    /// Whether Opus inputs are repackaged without re-encoding them.
+    pub passthrough: Passthrough,
+    /// Pass files with unknown extensions to ffmpeg instead of ignoring or rejecting them, see
+    /// [`filter_input_files`].
+    pub any_extension: bool,
+    /// The order of the audio files of input directories.
+    pub track_order: TrackOrder,
+    /// The Opus bitrate in kbit/s. Tonie files are usually encoded with [`DEFAULT_BITRATE`].
+    pub bitrate: u32,
+    /// The Opus application profile of the encoder, e.g. [`OpusApplication::Voip`] for speech.
+    pub opus_application: OpusApplication,
+    /// Encode with a constant instead of a variable bitrate. The size of the output then only depends on the
+    /// duration, at the cost of quality for the same size.
+    pub cbr: bool,
+    /// Write the Tonie header. Without it, the output is a plain Ogg Opus file with the 4kb blocks of a Tonie
+    /// file, e.g. to add the header later or to play the audio elsewhere.
+    pub tonie_header: bool,
+    /// The largest audio data length in bytes, e.g. to leave room on a small medium. Defaults to
+    /// [`MAX_AUDIO_LENGTH`]; the Ogg pages always fill the 4kb blocks, so their size is not configurable.
+    pub max_audio_length: u64,
+    /// Additional user comments of the Opus header, e.g. `TITLE=...`. The first comment is always the name of the
+    /// first input file.
+    pub comments: Vec<String>,
+    /// Additional ffmpeg arguments for decoding every input file, e.g. `-af loudnorm` or `-ss 10`.
+    pub ffmpeg_args: Vec<String>,
+    /// The ffmpeg input format of every input file, e.g. `mp3`. `None` lets ffmpeg detect it, which is not
+    /// possible for every format when reading from stdin, see [`STDIN_INPUT`].
+    pub input_format: Option<String>,
+    /// What to do if an input file fails to decode.
+    pub on_track_failure: TrackFailure,
+    /// Collects the input files left out with [`TrackFailure::Skip`].
+    pub failed_tracks: FailedTracks,
+    /// Stops the conversion when it is cancelled, e.g. on Ctrl-C.
+    pub cancellation: Cancellation,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            ffmpeg: String::from("ffmpeg"),
+            io_throttle: None,
+            show_progress: false,
+            normalization: Normalization::None,
+            target_loudness: DEFAULT_TARGET_LOUDNESS,
+            threads: 1,
+            audio_id: None,
+            trim_silence: None,
+            fade_in: Duration::ZERO,
+            fade_out: Duration::ZERO,
+            speed: 1.0,
+            on_too_many_chapters: ChapterOverflow::Error,
+            existing_output: ExistingOutput::Overwrite,
+            passthrough: Passthrough::Never,
+            any_extension: false,
+            track_order: TrackOrder::Name,
+            bitrate: DEFAULT_BITRATE,
+            opus_application: OpusApplication::Audio,
+            cbr: false,
+            tonie_header: true,
+            max_audio_length: MAX_AUDIO_LENGTH,
+            comments: vec![],
+            ffmpeg_args: vec![],
+            input_format: None,
+            on_track_failure: TrackFailure::Fail,
+            failed_tracks: FailedTracks::new(),
+            cancellation: Cancellation::new(),
+        }
+    }
+}
+
+impl ConvertOptions {
+    /// Starts building options from the defaults, e.g.
+    /// `ConvertOptions::builder().bitrate(64).cbr(true).build()`. Unlike a struct literal, the builder keeps
+    /// compiling when new options are added.
+    pub fn builder() -> ConvertOptionsBuilder {
+        return ConvertOptionsBuilder::default();
+    }
+}
+
+/// Builds [`ConvertOptions`], see [`ConvertOptions::builder`]. Options which are not set keep their defaults.
+#[derive(Clone, Debug, Default)]
+pub struct ConvertOptionsBuilder {
+    options: ConvertOptions,
+}
+
+impl ConvertOptionsBuilder {
+    /// Sets [`ConvertOptions::ffmpeg`].
+    pub fn ffmpeg(mut self, ffmpeg: impl Into<String>) -> Self {
+        self.options.ffmpeg = ffmpeg.into();
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::io_throttle`].
+    pub fn io_throttle(mut self, io_throttle: u64) -> Self {
+        self.options.io_throttle = Some(io_throttle);
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::show_progress`].
+    pub fn show_progress(mut self, show_progress: bool) -> Self {
+        self.options.show_progress = show_progress;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::normalization`].
+    pub fn normalization(mut self, normalization: Normalization) -> Self {
+        self.options.normalization = normalization;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::target_loudness`].
+    pub fn target_loudness(mut self, target_loudness: f64) -> Self {
+        self.options.target_loudness = target_loudness;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::threads`].
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = threads;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::audio_id`].
+    pub fn audio_id(mut self, audio_id: u32) -> Self {
+        self.options.audio_id = Some(audio_id);
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::trim_silence`].
+    pub fn trim_silence(mut self, trim_silence: SilenceTrim) -> Self {
+        self.options.trim_silence = Some(trim_silence);
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::fade_in`].
+    pub fn fade_in(mut self, fade_in: Duration) -> Self {
+        self.options.fade_in = fade_in;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::fade_out`].
+    pub fn fade_out(mut self, fade_out: Duration) -> Self {
+        self.options.fade_out = fade_out;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::speed`].
+    pub fn speed(mut self, speed: f64) -> Self {
+        self.options.speed = speed;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::on_too_many_chapters`].
+    pub fn on_too_many_chapters(mut self, on_too_many_chapters: ChapterOverflow) -> Self {
+        self.options.on_too_many_chapters = on_too_many_chapters;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::existing_output`].
+    pub fn existing_output(mut self, existing_output: ExistingOutput) -> Self {
+        self.options.existing_output = existing_output;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::passthrough`].
+    pub fn passthrough(mut self, passthrough: Passthrough) -> Self {
+        self.options.passthrough = passthrough;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::any_extension`].
+    pub fn any_extension(mut self, any_extension: bool) -> Self {
+        self.options.any_extension = any_extension;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::track_order`].
+    pub fn track_order(mut self, track_order: TrackOrder) -> Self {
+        self.options.track_order = track_order;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::bitrate`].
+    pub fn bitrate(mut self, bitrate: u32) -> Self {
+        self.options.bitrate = bitrate;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::opus_application`].
+    pub fn opus_application(mut self, opus_application: OpusApplication) -> Self {
+        self.options.opus_application = opus_application;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::cbr`].
+    pub fn cbr(mut self, cbr: bool) -> Self {
+        self.options.cbr = cbr;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::tonie_header`].
+    pub fn tonie_header(mut self, tonie_header: bool) -> Self {
+        self.options.tonie_header = tonie_header;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::max_audio_length`].
+    pub fn max_audio_length(mut self, max_audio_length: u64) -> Self {
+        self.options.max_audio_length = max_audio_length;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::comments`].
+    pub fn comments(mut self, comments: Vec<String>) -> Self {
+        self.options.comments = comments;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::ffmpeg_args`].
+    pub fn ffmpeg_args(mut self, ffmpeg_args: Vec<String>) -> Self {
+        self.options.ffmpeg_args = ffmpeg_args;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::input_format`].
+    pub fn input_format(mut self, input_format: impl Into<String>) -> Self {
+        self.options.input_format = Some(input_format.into());
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::on_track_failure`].
+    pub fn on_track_failure(mut self, on_track_failure: TrackFailure) -> Self {
+        self.options.on_track_failure = on_track_failure;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::failed_tracks`].
+    pub fn failed_tracks(mut self, failed_tracks: FailedTracks) -> Self {
+        self.options.failed_tracks = failed_tracks;
+        return self;
+    }
+
+    /// Sets [`ConvertOptions::cancellation`].
+    pub fn cancellation(mut self, cancellation: Cancellation) -> Self {
+        self.options.cancellation = cancellation;
+        return self;
+    }
+
+    /// Returns the options.
+    pub fn build(self) -> ConvertOptions {
+        return self.options;
+    }
+}
 
 /// Converts an input file into a Tonie compatible Ogg Opus audio file with the custom Tonie header and correctly sized 4kb opus content blocks.
 /// If the input is a directory then all files will be converted into a single Tonie file with multiple chapters.
+/// Audio files with a CUE sheet next to them, e.g. `album.flac` and `album.cue`, are split into one chapter per CUE track.
+/// Embedded chapter markers of m4b/m4a/mka files are kept as chapters as well.
+/// Silence trimming and fades are applied per chapter, i.e. for every CUE track or chapter marker as well.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the input file or a directory.
+/// * `output_file_path` - The path to the output file.
+/// * `options` - Options controlling the conversion.
+pub fn convert_to_tonie(
+    input_file_path: &PathBuf,
+    output_file_path: &Path,
+    options: &ConvertOptions,
+) -> Result<File, Audio2TonieError> {
+    let input_files = collect_input_files(std::slice::from_ref(input_file_path), options)?;
+    return convert_files_to_tonie(&input_files, output_file_path, options);
+}
+
+/// Converts an input file or directory into a Tonie file written to any seekable writer, e.g. an in-memory
+/// buffer, see [`convert_to_tonie`]. Returns the writer.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the input file or a directory.
+/// * `writer` - The output. The Tonie file is written from its start.
+/// * `options` - Options controlling the conversion.
+pub fn convert_to_writer<W: Write + Seek>(
+    input_file_path: &PathBuf,
+    writer: W,
+    options: &ConvertOptions,
+) -> Result<W, Audio2TonieError> {
+    let input_files = collect_input_files(std::slice::from_ref(input_file_path), options)?;
+    return convert_files_to_writer(&input_files, writer, options);
+}
+
+/// Converts the given audio files into a single Tonie file with one chapter per file, see [`convert_to_tonie`].
+///
+/// # Arguments
+///
+/// * `input_files` - The input audio files in the order of the chapters.
+/// * `output_file_path` - The path to the output file.
+/// * `options` - Options controlling the conversion.
+pub fn convert_files_to_tonie(
+    input_files: &[PathBuf],
+    output_file_path: &Path,
+    options: &ConvertOptions,
+) -> Result<File, Audio2TonieError> {
+    let output_file_path = resolve_output_path(output_file_path);
+    // Fail before encoding, the output is checked again when the finished file is moved into place
+    if options.existing_output != ExistingOutput::Overwrite && output_file_path.exists() {
+        return Err(Audio2TonieError::OutputExists(output_file_path));
+    }
+    let output = PartialOutput::create(&output_file_path)?;
+    convert_files_to_writer(input_files, output.file(), options)?;
+    return output.complete(&output_file_path, options.existing_output);
+}
+
+/// Converts the given audio files into a Tonie file written to any seekable writer, e.g. an in-memory buffer,
+/// see [`convert_files_to_tonie`]. Returns the writer.
+///
+/// # Arguments
+///
+/// * `input_files` - The input audio files in the order of the chapters.
+/// * `writer` - The output. The Tonie file is written from its start.
+/// * `options` - Options controlling the conversion.
+pub fn convert_files_to_writer<W: Write + Seek>(
+    input_files: &[PathBuf],
+    writer: W,
+    options: &ConvertOptions,
+) -> Result<W, Audio2TonieError> {
+    // Use the input file name as a Opus header metadata comment
+    // Make it easier to identify already encoded files without listening to them
+    let comments = input_files
+        .first()
+        .and_then(|file_path| file_path.file_name())
+        .and_then(|os_str| os_str.to_str())
+        .map(|file_name| vec![file_name.to_string()])
+        .unwrap_or_default()
+        .into_iter()
+        .chain(options.comments.iter().cloned())
+        .collect::<Vec<_>>();
+
+    let cue_points = input_files
+        .iter()
+        .map(|input_file| read_cue_points(input_file, options))
+        .collect::<Result<Vec<_>>>()?;
+    // The index of the first chapter of every track among all planned chapters
+    let first_chapters = cue_points
+        .iter()
+        .scan(0, |chapter, track_cue_points| {
+            let first_chapter = *chapter;
+            *chapter += track_cue_points.len() + 1;
+            return Some(first_chapter);
+        })
+        .collect::<Vec<_>>();
+    let chapter_count = cue_points
+        .iter()
+        .map(|points| points.len() + 1)
+        .sum::<usize>();
+    let merged = match (chapter_count > MAX_CHAPTERS, options.on_too_many_chapters) {
+        (false, _) => vec![],
+        (true, ChapterOverflow::MergeAdjacent) => {
+            let durations = plan_files(input_files.to_vec(), options)?
+                .chapters
+                .iter()
+                .map(|chapter| chapter.duration.unwrap_or_default())
+                .collect::<Vec<_>>();
+            merge_shortest_chapters(&durations, MAX_CHAPTERS)
+        }
+        (true, _) => {
+            return Err(Audio2TonieError::TooManyChapters {
+                chapters: chapter_count,
+                max: MAX_CHAPTERS,
+            });
+        }
+    };
+
+    let passthrough = match options.passthrough {
+        Passthrough::Auto => check_passthrough(input_files, options, chapter_count).is_ok(),
+        Passthrough::Always => {
+            check_passthrough(input_files, options, chapter_count)?;
+            true
+        }
+        Passthrough::Never => false,
+    };
+
+    let writer = HeaderlessWriter::new(
+        ThrottledIo::new(writer, options.io_throttle),
+        !options.tonie_header,
+    );
+    let audio_id = options.audio_id.unwrap_or_else(current_timestamp);
+    if passthrough {
+        let writer = pass_through_opus(
+            input_files,
+            writer,
+            audio_id,
+            &comments,
+            options.max_audio_length,
+        )?;
+        return Ok(writer.into_inner().into_inner());
+    }
+    let mut toniefile = TafEncoder::with_settings(
+        writer,
+        audio_id,
+        options.bitrate,
+        options.opus_application,
+        options.cbr,
+        &comments,
+    )?;
+    toniefile.set_max_audio_length(options.max_audio_length);
+    let mut encoder = ChapterEncoder::new(toniefile, options, merged);
+    let mut has_failed_tracks = false;
+
+    // Album normalization needs the loudness of all tracks upfront, which requires an additional decoding pass
+    let album_gain = match options.normalization {
+        Normalization::Album => Some(measure_album_gain(input_files, options)?),
+        _ => None,
+    };
+
+    if options.threads > 1 {
+        // Concurrently decoded tracks are buffered in memory until it is their turn to be encoded
+        let mut tracks = input_files
+            .iter()
+            .zip(first_chapters.into_iter().zip(cue_points));
+        decode_tracks(input_files, options, read_samples, |buffer| {
+            options.cancellation.check()?;
+            let Some((input_file, (first_chapter, track_cue_points))) = tracks.next() else {
+                return Ok(());
+            };
+            let buffer = match buffer {
+                Ok(buffer) => buffer,
+                Err(error) => {
+                    has_failed_tracks = true;
+                    return Ok(handle_track_failure(input_file, error, false, options)?);
+                }
+            };
+            encoder.start_track(first_chapter, track_cue_points);
+
+            let gain = match options.normalization {
+                Normalization::None => 0.0,
+                Normalization::Track => track_gain(&measure_loudness(&buffer), options),
+                Normalization::Album => album_gain.unwrap_or_default(),
+            };
+            encoder.encode(&buffer, gain)?;
+            encoder.finish_track()?;
+            return Ok(());
+        })?;
+    } else {
+        let tracks = first_chapters.into_iter().zip(cue_points);
+        for (input_file, (first_chapter, track_cue_points)) in input_files.iter().zip(tracks) {
+            options.cancellation.check()?;
+            let gain = match options.normalization {
+                Normalization::None => 0.0,
+                Normalization::Track => match measure_track(input_file, options) {
+                    Ok(meter) => track_gain(&meter, options),
+                    Err(error) => {
+                        has_failed_tracks = true;
+                        handle_track_failure(input_file, error, false, options)?;
+                        continue;
+                    }
+                },
+                Normalization::Album => album_gain.unwrap_or_default(),
+            };
+
+            // Stream the decoded samples into the encoder, so only a small chunk of PCM is kept in memory
+            encoder.start_track(first_chapter, track_cue_points);
+            let mut encoder_error = None;
+            let result = stream_pcm(input_file, options, |samples| {
+                // Only decoding errors are failures of the track, encoder errors fail the conversion
+                if let Err(error) = encoder.encode(samples, gain) {
+                    encoder_error = Some(error);
+                    return Err(anyhow!("The encoder failed."));
+                }
+                return Ok(());
+            });
+            if let Some(error) = encoder_error {
+                return Err(error.into());
+            }
+            // Audio decoded before a failure in the middle of the file is already encoded
+            let truncated = encoder.track_position > 0;
+            encoder.finish_track()?;
+            if let Err(error) = result {
+                has_failed_tracks = true;
+                handle_track_failure(input_file, error.into(), truncated, options)?;
+            }
+        }
+    }
+
+    // Skipped tracks might hide a track stopped by the cancellation, which is only noticed here
+    options.cancellation.check()?;
+    // An empty Tonie file of skipped tracks is useless, unlike one of inputs without any audio
+    if has_failed_tracks && !encoder.writer.has_samples {
+        return Err(Audio2TonieError::NoAudio);
+    }
+    let writer = encoder.writer.toniefile.finalize()?;
+
+    return Ok(writer.into_inner().into_inner());
+}
+
+/// Fails the conversion for an input file that failed to decode or records it as skipped, depending on
+/// [`ConvertOptions::on_track_failure`].
+///
+/// # Arguments
+///
+/// * `input_file` - The input file that failed to decode.
+/// * `error` - The reason of the failure.
+/// * `truncated` - Whether audio of the file was encoded before the failure.
+/// * `options` - Options controlling the conversion.
+fn handle_track_failure(
+    input_file: &Path,
+    error: anyhow::Error,
+    truncated: bool,
+    options: &ConvertOptions,
+) -> Result<(), Audio2TonieError> {
+    // A track stopped by the cancellation did not fail on its own
+    options.cancellation.check()?;
+    // The input files exist, so a missing file means that ffmpeg is not installed, which fails every track
+    let error = Audio2TonieError::from(error);
+    if matches!(&error, Audio2TonieError::Io(error) if error.kind() == std::io::ErrorKind::NotFound)
+    {
+        return Err(error);
+    }
+
+    return match options.on_track_failure {
+        TrackFailure::Fail => Err(Audio2TonieError::TrackFailed {
+            input_file: input_file.to_path_buf(),
+            error: Box::new(error),
+        }),
+        TrackFailure::Skip => {
+            options.failed_tracks.record(input_file, &error, truncated);
+            Ok(())
+        }
+    };
+}
+
+/// The path the Tonie file is written to before it is complete, e.g. `500304E0.part` for `500304E0`.
+/// It is in the same directory, so the complete file can be renamed atomically.
+///
+/// # Arguments
+///
+/// * `output_file_path` - The path to the output file.
+pub fn partial_file_path(output_file_path: &Path) -> PathBuf {
+    let mut file_name = output_file_path
+        .file_name()
+        .unwrap_or_default()
+        .to_os_string();
+    file_name.push(".part");
+    return output_file_path.with_file_name(file_name);
+}
+
+/// The Tonie file being written next to the output file. It is renamed to the output file once it is complete,
+/// so watchers, TeddyCloud and the Toniebox never see a truncated Tonie file. If the conversion fails or is
+/// cancelled, it is removed again.
+pub(crate) struct PartialOutput {
+    file: Option<File>,
+    path: PathBuf,
+    completed: bool,
+}
+
+impl PartialOutput {
+    /// Creates the partial file of an output file, see [`partial_file_path`].
+    ///
+    /// # Arguments
+    ///
+    /// * `output_file_path` - The path to the output file.
+    pub(crate) fn create(output_file_path: &Path) -> Result<Self, Audio2TonieError> {
+        let path = partial_file_path(output_file_path);
+        return Ok(PartialOutput {
+            file: Some(File::create(&path)?),
+            path,
+            completed: false,
+        });
+    }
+
+    pub(crate) fn file(&self) -> &File {
+        return self
+            .file
+            .as_ref()
+            .expect("The file is only closed on completion.");
+    }
+
+    /// Moves the complete Tonie file into place and returns it.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_file_path` - The path to the output file.
+    /// * `existing_output` - Whether an existing output file is replaced.
+    pub(crate) fn complete(
+        mut self,
+        output_file_path: &Path,
+        existing_output: ExistingOutput,
+    ) -> Result<File, Audio2TonieError> {
+        // The file has to be on the disk before it replaces the output, and an open file cannot be renamed on Windows
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        match existing_output {
+            ExistingOutput::Overwrite => std::fs::rename(&self.path, output_file_path)?,
+            _ => rename_new(&self.path, output_file_path)?,
+        }
+        self.completed = true;
+
+        return Ok(File::open(output_file_path)?);
+    }
+}
+
+impl Drop for PartialOutput {
+    fn drop(&mut self) {
+        if !self.completed {
+            // The file is closed first, an open file cannot be removed on Windows
+            self.file.take();
+            std::fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+// Renames a file without replacing an existing one. Creating a hard link fails if the target exists, so it cannot
+// appear between a check and the rename. File systems without hard links, e.g. FAT on SD cards, are checked first.
+fn rename_new(from: &Path, to: &Path) -> Result<(), Audio2TonieError> {
+    match std::fs::hard_link(from, to) {
+        Ok(()) => std::fs::remove_file(from)?,
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(Audio2TonieError::OutputExists(to.to_path_buf()));
+        }
+        Err(_) if to.exists() => return Err(Audio2TonieError::OutputExists(to.to_path_buf())),
+        Err(_) => std::fs::rename(from, to)?,
+    }
+    return Ok(());
+}
+
+// Drops the Tonie header at the start of the output if enabled, so the output starts with the Ogg pages. The
+// positions of the writer are shifted by the header size, the encoder still sees the positions of a Tonie file.
+struct HeaderlessWriter<W: Write + Seek> {
+    writer: W,
+    skip_header: bool,
+    position: u64,
+}
+
+impl<W: Write + Seek> HeaderlessWriter<W> {
+    fn new(writer: W, skip_header: bool) -> Self {
+        return HeaderlessWriter {
+            writer,
+            skip_header,
+            position: 0,
+        };
+    }
+
+    fn into_inner(self) -> W {
+        return self.writer;
+    }
+}
+
+impl<W: Write + Seek> Write for HeaderlessWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if !self.skip_header {
+            return self.writer.write(buf);
+        }
+        let header_size = TONIEFILE_HEADER_SIZE as u64;
+        if self.position < header_size {
+            let skipped = buf.len().min((header_size - self.position) as usize);
+            self.position += skipped as u64;
+            return Ok(skipped);
+        }
+        let written = self.writer.write(buf)?;
+        self.position += written as u64;
+        return Ok(written);
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.writer.flush();
+    }
+}
+
+impl<W: Write + Seek> Seek for HeaderlessWriter<W> {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        if !self.skip_header {
+            return self.writer.seek(position);
+        }
+        let header_size = TONIEFILE_HEADER_SIZE as u64;
+        let position = match position {
+            SeekFrom::Start(offset) => offset,
+            SeekFrom::Current(offset) => {
+                self.position.checked_add_signed(offset).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid seek position.")
+                })?
+            }
+            SeekFrom::End(offset) => self.writer.seek(SeekFrom::End(offset))? + header_size,
+        };
+        self.writer
+            .seek(SeekFrom::Start(position.saturating_sub(header_size)))?;
+        self.position = position;
+        return Ok(position);
+    }
+}
+
+/// The current Unix timestamp, which Boxine uses as audio id for its Tonie files.
+pub fn current_timestamp() -> u32 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as u32)
+        .unwrap_or_default();
+}
+
+/// Encodes consecutive tracks as chapters of a Tonie file.
+struct ChapterEncoder<W: Write + Seek> {
+    writer: ChapterWriter<W>,
+    /// The position within the current track in samples per channel.
+    track_position: u64,
+    /// Positions within the current track that start a new chapter, in descending order.
+    cue_points: Vec<u64>,
+    trimmer: Option<SilenceTrimmer>,
+    fader: Option<Fader>,
+}
+
+/// Writes the processed samples into the Tonie file and starts a new chapter before the first samples of
+/// every chapter, so chapters without any audio, e.g. of tracks that failed to decode, are left out.
+struct ChapterWriter<W: Write + Seek> {
+    toniefile: TafEncoder<W>,
+    has_samples: bool,
+    new_chapter_pending: bool,
+    /// The index of the current chapter among all planned chapters.
+    chapter: usize,
+    /// Whether a planned chapter is merged into the previous one, see [`merge_shortest_chapters`].
+    merged: Vec<bool>,
+}
+
+impl<W: Write + Seek> ChapterWriter<W> {
+    fn write(&mut self, samples: &[i16]) -> Result<()> {
+        if self.new_chapter_pending && !self.merged.get(self.chapter).copied().unwrap_or(false) {
+            self.toniefile.new_chapter()?;
+        }
+        self.new_chapter_pending = false;
+        self.has_samples = true;
+        self.toniefile.encode(samples)?;
+        return Ok(());
+    }
+
+    /// A callback for the silence trimmer and the fader, which cannot fail. The first error of the encoder is
+    /// stored in `result` and the samples after it are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `result` - Receives the first error of the encoder.
+    fn writer<'a>(&'a mut self, result: &'a mut Result<()>) -> impl FnMut(&[i16]) + 'a {
+        return move |samples| {
+            if result.is_ok() {
+                *result = self.write(samples);
+            }
+        };
+    }
+}
+
+impl<W: Write + Seek> ChapterEncoder<W> {
+    /// Creates an encoder for the chapters of a Tonie file.
+    ///
+    /// # Arguments
+    ///
+    /// * `toniefile` - The Tonie file to write.
+    /// * `options` - Options controlling the conversion, e.g. the fades.
+    /// * `merged` - Whether a planned chapter is merged into the previous one. Empty if no chapters are merged.
+    fn new(toniefile: TafEncoder<W>, options: &ConvertOptions, merged: Vec<bool>) -> Self {
+        let has_fades = !options.fade_in.is_zero() || !options.fade_out.is_zero();
+        ChapterEncoder {
+            writer: ChapterWriter {
+                toniefile,
+                has_samples: false,
+                new_chapter_pending: false,
+                chapter: 0,
+                merged,
+            },
+            track_position: 0,
+            cue_points: vec![],
+            trimmer: options
+                .trim_silence
+                .map(|trim| SilenceTrimmer::new(PCM_CHANNELS, &trim)),
+            fader: has_fades.then(|| Fader::new(PCM_CHANNELS, options.fade_in, options.fade_out)),
+        }
+    }
+
+    /// Prepares the encoder for the next track.
+    ///
+    /// # Arguments
+    ///
+    /// * `first_chapter` - The index of the first chapter of the track among all planned chapters.
+    /// * `cue_points` - Positions in samples per channel where the track is split into further chapters.
+    fn start_track(&mut self, first_chapter: usize, mut cue_points: Vec<u64>) {
+        cue_points.sort_unstable_by(|a, b| b.cmp(a));
+        self.cue_points = cue_points;
+        self.track_position = 0;
+        self.writer.chapter = first_chapter;
+    }
+
+    fn encode(&mut self, mut samples: &[i16], gain: f64) -> Result<()> {
+        while let Some(&cue_point) = self.cue_points.last() {
+            let split = ((cue_point.saturating_sub(self.track_position)) as usize)
+                .saturating_mul(PCM_CHANNELS);
+            if split >= samples.len() {
+                break;
+            }
+
+            let (head, tail) = samples.split_at(split);
+            self.encode_samples(head, gain)?;
+            self.finish_chapter()?;
+            self.writer.chapter += 1;
+            self.cue_points.pop();
+            samples = tail;
+        }
+        return self.encode_samples(samples, gain);
+    }
+
+    fn encode_samples(&mut self, samples: &[i16], gain: f64) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        // The cue points refer to the position in the decoded track, including trimmed silence
+        self.track_position += (samples.len() / PCM_CHANNELS) as u64;
+
+        let mut gained_samples;
+        let mut samples = samples;
+        if gain != 0.0 {
+            gained_samples = samples.to_vec();
+            apply_gain(&mut gained_samples, gain);
+            samples = &gained_samples;
+        }
+
+        // The silence is trimmed first, so the fades apply to the audible part of the chapter
+        let mut result = Ok(());
+        {
+            let mut write = self.writer.writer(&mut result);
+            let fader = &mut self.fader;
+            let mut fade = |samples: &[i16]| match fader {
+                Some(fader) => fader.process(samples, &mut write),
+                None => write(samples),
+            };
+            match &mut self.trimmer {
+                Some(trimmer) => trimmer.process(samples, &mut fade),
+                None => fade(samples),
+            }
+        }
+        return result;
+    }
+
+    /// Drops the trailing silence of the current chapter, applies the fade-out and resets the trimmer and the
+    /// fader for the next chapter.
+    fn finish_chapter(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        {
+            let mut write = self.writer.writer(&mut result);
+            let fader = &mut self.fader;
+            let mut fade = |samples: &[i16]| match fader {
+                Some(fader) => fader.process(samples, &mut write),
+                None => write(samples),
+            };
+            if let Some(trimmer) = &mut self.trimmer {
+                trimmer.finish(&mut fade);
+            }
+            if let Some(fader) = &mut self.fader {
+                fader.finish(&mut write);
+            }
+        }
+        result?;
+
+        // The next samples start a new chapter, unless nothing was written at all yet
+        self.writer.new_chapter_pending = self.writer.has_samples;
+        return Ok(());
+    }
+
+    fn finish_track(&mut self) -> Result<()> {
+        self.finish_chapter()?;
+        self.cue_points.clear();
+        return Ok(());
+    }
+}
+
+/// Measures the loudness of all input files together and computes a single gain for all of them.
+///
+/// # Arguments
+///
+/// * `input_files` - The input audio files.
+/// * `options` - Options controlling the conversion, e.g. the target loudness.
+fn measure_album_gain(input_files: &[PathBuf], options: &ConvertOptions) -> Result<f64> {
+    let mut block_powers = vec![];
+    let mut true_peak = f64::NEG_INFINITY;
+
+    decode_tracks(input_files, options, measure_track, |meter| {
+        // Skipped tracks are recorded once they fail to decode again for the encoding
+        let meter = match (meter, options.on_track_failure) {
+            (Ok(meter), _) => meter,
+            (Err(_), TrackFailure::Skip) => return Ok(()),
+            (Err(error), TrackFailure::Fail) => return Err(error),
+        };
+        block_powers.extend_from_slice(meter.block_powers());
+        true_peak = true_peak.max(meter.true_peak());
+        return Ok(());
+    })?;
+
+    return Ok(normalization_gain(
+        gated_loudness(&block_powers),
+        true_peak,
+        options.target_loudness,
+        TRUE_PEAK_CEILING,
+    ));
+}
+
+fn track_gain(meter: &LoudnessMeter, options: &ConvertOptions) -> f64 {
+    return normalization_gain(
+        meter.integrated_loudness(),
+        meter.true_peak(),
+        options.target_loudness,
+        TRUE_PEAK_CEILING,
+    );
+}
+
+/// Runs a function for every input file with up to `options.threads` concurrent workers and passes
+/// the results to the callback in the original order. The Opus encoding itself stays sequential,
+/// because the pages of all chapters form one continuous stream. The workers run ahead of the callback,
+/// so the next tracks are decoded while a track is encoded. At most `options.threads` results are kept
+/// in memory besides the one passed to the callback.
+///
+/// # Arguments
+///
+/// * `input_files` - The input audio files.
+/// * `options` - Options controlling the conversion, e.g. the number of threads.
+/// * `worker` - Processes a single input file, e.g. decodes or measures it.
+/// * `on_track` - Called with the result of every track.
+fn decode_tracks<T, W, F>(
+    input_files: &[PathBuf],
+    options: &ConvertOptions,
+    worker: W,
+    mut on_track: F,
+) -> Result<()>
+where
+    T: Send,
+    W: Fn(&Path, &ConvertOptions) -> Result<T> + Sync,
+    F: FnMut(Result<T>) -> Result<()>,
+{
+    let threads = options.threads.max(1);
+    // Progress bars of concurrently decoded tracks would overwrite each other
+    let worker_options = ConvertOptions {
+        show_progress: options.show_progress && threads == 1,
+        ..options.clone()
+    };
+
+    return std::thread::scope(|scope| {
+        // The indices of the tracks to process. Dropping the sender on an error stops the workers.
+        let (job_sender, job_receiver) = mpsc::channel::<usize>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (result_sender, result_receiver) = mpsc::channel::<(usize, Result<T>)>();
+        for _ in 0..threads.min(input_files.len()) {
+            let job_receiver = Arc::clone(&job_receiver);
+            let result_sender = result_sender.clone();
+            let (worker, worker_options) = (&worker, &worker_options);
+            scope.spawn(move || loop {
+                // The lock is released before the track is processed, so the workers run concurrently
+                let job = job_receiver
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .recv();
+                let Ok(index) = job else {
+                    return;
+                };
+                let result = catch_unwind(AssertUnwindSafe(|| {
+                    worker(&input_files[index], worker_options)
+                }))
+                .unwrap_or_else(|_| Err(anyhow!("Decoding thread panicked.")));
+                if result_sender.send((index, result)).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(result_sender);
+
+        let mut next_job = 0;
+        while next_job < threads.min(input_files.len()) {
+            job_sender.send(next_job).ok();
+            next_job += 1;
+        }
+
+        // Tracks finish in any order, the ones ahead of the next track wait until it is their turn
+        let mut finished = HashMap::new();
+        for index in 0..input_files.len() {
+            let result = loop {
+                if let Some(result) = finished.remove(&index) {
+                    break result;
+                }
+                let (finished_index, result) = result_receiver
+                    .recv()
+                    .map_err(|_| anyhow!("Decoding thread panicked."))?;
+                finished.insert(finished_index, result);
+            };
+            if next_job < input_files.len() {
+                job_sender.send(next_job).ok();
+                next_job += 1;
+            }
+            on_track(result)?;
+        }
+
+        return Ok(());
+    });
+}
+
+/// Returns the positions in samples per channel at which an audio file is split into chapters.
+/// These are taken from a CUE sheet next to the audio file or otherwise from the chapter markers
+/// embedded in the file. Files without either are not split. The positions refer to the decoded
+/// audio, i.e. they are adjusted to the playback speed.
+///
+/// # Arguments
+///
+/// * `audio_file_path` - The path to the audio file.
+/// * `options` - Options controlling the conversion, e.g. the path to ffmpeg.
+fn read_cue_points(audio_file_path: &Path, options: &ConvertOptions) -> Result<Vec<u64>> {
+    if is_stdin_input(audio_file_path) {
+        return Ok(vec![]);
+    }
+    let Some(cue_file_path) = find_cue_sheet(audio_file_path) else {
+        let has_chapters = audio_file_path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| CHAPTER_FILE_EXTENSIONS.contains(&ext));
+        if !has_chapters {
+            return Ok(vec![]);
+        }
+
+        return Ok(probe_chapters(audio_file_path, &options.ffmpeg)?
+            .into_iter()
+            .map(|start| (start / options.speed * PCM_SAMPLE_RATE as f64).round() as u64)
+            .filter(|&start| start > 0)
+            .collect());
+    };
+
+    let cue_sheet = std::fs::read_to_string(&cue_file_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| parse_cue_sheet(&content))
+        .with_context(|| format!("Failed to read {}", cue_file_path.display()))?;
+
+    return Ok(cue_sheet
+        .tracks
+        .iter()
+        .map(|track| (track.start_sample(PCM_SAMPLE_RATE) as f64 / options.speed).round() as u64)
+        .filter(|&start| start > 0)
+        .collect());
+}
+
+/// The number of chapters of a Tonie file converted from the given input files, including the
+/// chapters of CUE sheets and embedded chapter markers.
+///
+/// # Arguments
+///
+/// * `input_files` - The input audio files.
+/// * `options` - Options controlling the conversion, e.g. the path to ffmpeg.
+pub fn count_chapters(input_files: &[PathBuf], options: &ConvertOptions) -> Result<usize> {
+    let mut chapters = 0;
+    for input_file in input_files {
+        chapters += read_cue_points(input_file, options)?.len() + 1;
+    }
+    return Ok(chapters);
+}
+
+/// A chapter of a planned conversion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlannedChapter {
+    /// The audio file the chapter is taken from.
+    pub input_file: PathBuf,
+    /// The start of the chapter in the audio file in seconds, adjusted to the playback speed.
+    pub start: f64,
+    /// The duration of the chapter in seconds. `None` if the duration of the audio file is unknown.
+    pub duration: Option<f64>,
+}
+
+/// The outcome of a conversion without encoding anything, see [`plan_conversion`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConversionPlan {
+    /// The input audio files in the order of the chapters.
+    pub input_files: Vec<PathBuf>,
+    /// The chapters of the Tonie file.
+    pub chapters: Vec<PlannedChapter>,
+    /// The Opus bitrate in kbit/s the size is estimated for.
+    pub bitrate: u32,
+}
+
+impl ConversionPlan {
+    /// The total duration in seconds of all chapters with a known duration.
+    pub fn duration(&self) -> f64 {
+        return self
+            .chapters
+            .iter()
+            .filter_map(|chapter| chapter.duration)
+            .sum();
+    }
+
+    /// The estimated size of the Tonie file in bytes, see [`estimate_tonie_size`].
+    pub fn estimated_size(&self) -> u64 {
+        return estimate_tonie_size(self.duration(), self.bitrate);
+    }
+}
+
+/// Resolves the input files and chapters of a conversion and probes the duration of every input file,
+/// without decoding the audio. Used for dry runs.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the input file or a directory.
+/// * `options` - Options controlling the conversion, e.g. the path to ffmpeg.
+pub fn plan_conversion(
+    input_file_path: &PathBuf,
+    options: &ConvertOptions,
+) -> Result<ConversionPlan> {
+    return plan_files(
+        collect_input_files(std::slice::from_ref(input_file_path), options)?,
+        options,
+    );
+}
+
+/// Probes the duration of the given audio files and plans their chapters in the given order, see
+/// [`plan_conversion`].
+///
+/// # Arguments
+///
+/// * `input_files` - The audio files in chapter order.
+/// * `options` - Options controlling the conversion, e.g. the path to ffmpeg.
+pub fn plan_files(input_files: Vec<PathBuf>, options: &ConvertOptions) -> Result<ConversionPlan> {
+    let mut chapters = vec![];
+    for input_file in &input_files {
+        let duration =
+            probe_duration(input_file, &options.ffmpeg)?.map(|duration| duration / options.speed);
+        let mut starts = vec![0.0];
+        starts.extend(
+            read_cue_points(input_file, options)?
+                .into_iter()
+                .map(|start| start as f64 / PCM_SAMPLE_RATE as f64),
+        );
+
+        for (index, start) in starts.iter().enumerate() {
+            let end = starts.get(index + 1).copied().or(duration);
+            chapters.push(PlannedChapter {
+                input_file: input_file.clone(),
+                start: *start,
+                duration: end.map(|end| (end - start).max(0.0)),
+            });
+        }
+    }
+
+    return Ok(ConversionPlan {
+        input_files,
+        chapters,
+        bitrate: options.bitrate,
+    });
+}
+
+/// Reduces the number of chapters by repeatedly merging the two adjacent chapters with the shortest combined
+/// duration into a shared chapter, so long chapters stay separate. Returns whether every chapter is merged into
+/// the previous one.
+///
+/// # Arguments
+///
+/// * `durations` - The durations of all chapters in seconds.
+/// * `max_chapters` - The maximum number of chapters after merging.
+pub fn merge_shortest_chapters(durations: &[f64], max_chapters: usize) -> Vec<bool> {
+    let mut merged = vec![false; durations.len()];
+    // The first chapter and the duration of every shared chapter
+    let mut groups = durations
+        .iter()
+        .enumerate()
+        .map(|(index, duration)| (index, *duration))
+        .collect::<Vec<_>>();
+
+    while groups.len() > max_chapters.max(1) {
+        let shortest = (0..groups.len() - 1)
+            .min_by(|a, b| {
+                let a = groups[*a].1 + groups[*a + 1].1;
+                let b = groups[*b].1 + groups[*b + 1].1;
+                return a.total_cmp(&b);
+            })
+            .expect("There are at least two groups");
+        let (first_chapter, duration) = groups.remove(shortest + 1);
+        merged[first_chapter] = true;
+        groups[shortest].1 += duration;
+    }
+
+    return merged;
+}
+
+/// The estimated size of the Tonie files of a conversion split on overflow. The estimate leaves a tenth of the
+/// largest data length for the variable bitrate of the encoder, see [`MAX_AUDIO_LENGTH`].
+pub const OVERFLOW_SPLIT_SIZE: u64 = MAX_AUDIO_LENGTH / 10 * 9;
+
+/// Caps for a single Tonie file. Conversions exceeding them are split into several Tonie files.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SplitLimits {
+    /// The maximum duration of a Tonie file in seconds.
+    pub max_duration: Option<f64>,
+    /// The maximum estimated size of a Tonie file in bytes, see [`estimate_tonie_size`].
+    pub max_size: Option<u64>,
+    /// The maximum number of chapters of a Tonie file.
+    pub max_chapters: Option<usize>,
+}
+
+/// Distributes the input files of a planned conversion across several sequential Tonie files, so none of them
+/// exceeds the limits. Input files are not cut, so a single file exceeding the limits gets a Tonie file on its own.
+/// Files with an unknown duration count as empty.
+///
+/// # Arguments
+///
+/// * `plan` - The planned conversion of all input files.
+/// * `limits` - The caps for every Tonie file.
+pub fn split_conversion_plan(plan: &ConversionPlan, limits: &SplitLimits) -> Vec<ConversionPlan> {
+    let mut parts: Vec<ConversionPlan> = vec![];
+    for input_file in &plan.input_files {
+        let chapters = plan
+            .chapters
+            .iter()
+            .filter(|chapter| chapter.input_file == *input_file)
+            .cloned()
+            .collect::<Vec<_>>();
+        let file_duration = chapters
+            .iter()
+            .filter_map(|chapter| chapter.duration)
+            .sum::<f64>();
+
+        let fits = parts.last().is_some_and(|part| {
+            let duration = part.duration() + file_duration;
+            limits.max_duration.is_none_or(|max| duration <= max)
+                && limits
+                    .max_size
+                    .is_none_or(|max| estimate_tonie_size(duration, plan.bitrate) <= max)
+                && limits
+                    .max_chapters
+                    .is_none_or(|max| part.chapters.len() + chapters.len() <= max)
+        });
+        if !fits {
+            parts.push(ConversionPlan {
+                input_files: vec![],
+                chapters: vec![],
+                bitrate: plan.bitrate,
+            });
+        }
+
+        let part = parts.last_mut().expect("A part was added above");
+        part.input_files.push(input_file.clone());
+        part.chapters.extend(chapters);
+    }
+
+    return parts;
+}
+
+/// Checks the planned Tonie files of a conversion before encoding, so it fails early instead of after most of the
+/// audio is encoded: no Tonie file may exceed `max_size` or the data length of a Tonie file, and the Tonie files
+/// must fit into the free space at their output locations. The sizes are estimated, see [`estimate_tonie_size`].
+/// Input files with an unknown duration count as empty.
 ///
 /// # Arguments
 ///
-/// * `input_file_path` - The path to the input file or a directory.
-/// * `output_file_path` - The path to the output file.
-/// * `ffmpeg` - The path to the ffmpeg executable.
-pub fn convert_to_tonie(
-    input_file_path: &PathBuf,
-    output_file_path: &PathBuf,
-    ffmpeg: String,
-) -> Result<File> {
-    let input_files = filter_input_files(input_file_path)?;
+/// * `plans` - The planned Tonie files, see [`plan_files`] and [`split_conversion_plan`].
+/// * `output_paths` - The output path of every planned Tonie file.
+/// * `max_size` - The maximum estimated size of a Tonie file in bytes.
+pub fn preflight_check(
+    plans: &[ConversionPlan],
+    output_paths: &[PathBuf],
+    max_size: Option<u64>,
+) -> Result<(), Audio2TonieError> {
+    for (plan, output_path) in plans.iter().zip(output_paths) {
+        let estimated = plan.estimated_size();
+        // The data length does not include the header block
+        if estimated.saturating_sub(TONIEFILE_HEADER_SIZE as u64) > MAX_AUDIO_LENGTH {
+            return Err(Audio2TonieError::DataLengthOverflow);
+        }
+        if let Some(max) = max_size.filter(|max| estimated > *max) {
+            return Err(Audio2TonieError::SizeLimitExceeded {
+                output: output_path.clone(),
+                estimated,
+                max,
+            });
+        }
+    }
 
-    // Use the input file name as a Opus header metadata comment
-    // Make it easier to identify already encoded files without listening to them
-    let user_comments = input_files
-        .first()
-        .and_then(|file_path| file_path.file_name())
-        .and_then(|os_str| os_str.to_str())
-        .map(|file_name| vec![file_name]);
+    // The output directories may not exist yet, so the free space of their closest existing ancestors is used.
+    // Outputs on different file systems are checked separately.
+    let mut required_by_directory: Vec<(&Path, u64)> = vec![];
+    for (plan, output_path) in plans.iter().zip(output_paths) {
+        let Some(directory) = output_path
+            .ancestors()
+            .skip(1)
+            .map(|directory| match directory.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => directory,
+            })
+            .find(|directory| directory.is_dir())
+        else {
+            continue;
+        };
+        match required_by_directory
+            .iter_mut()
+            .find(|(existing, _)| *existing == directory)
+        {
+            Some((_, required)) => *required += plan.estimated_size(),
+            None => required_by_directory.push((directory, plan.estimated_size())),
+        }
+    }
+    for (directory, required) in required_by_directory {
+        if let Some(available) =
+            available_space(directory).filter(|available| *available < required)
+        {
+            return Err(Audio2TonieError::InsufficientSpace {
+                required,
+                available,
+            });
+        }
+    }
 
-    let output_file_path_validated = if output_file_path.is_dir() {
-        &output_file_path.join("500304E0")
-    } else {
-        output_file_path
+    return Ok(());
+}
+
+/// The path of a part of a split conversion, e.g. `output_part2.taf` for `output.taf`.
+///
+/// # Arguments
+///
+/// * `output_file_path` - The path of the Tonie file if it was not split.
+/// * `part` - The number of the part, starting at 1.
+pub fn part_output_path(output_file_path: &Path, part: usize) -> PathBuf {
+    let mut file_name = output_file_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_os_string();
+    file_name.push(format!("_part{}", part));
+    if let Some(extension) = output_file_path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+
+    return output_file_path.with_file_name(file_name);
+}
+
+/// Selects the highest Opus bitrate up to `max_bitrate` for which a Tonie file with the given duration stays below
+/// `target_size`, according to [`estimate_tonie_size`]. A twentieth of the target is left for the variable bitrate
+/// of the encoder. Returns `None` if the audio does not fit even at the lowest bitrate.
+///
+/// # Arguments
+///
+/// * `duration` - The duration of the audio in seconds.
+/// * `target_size` - The maximum size of the Tonie file in bytes.
+/// * `max_bitrate` - The highest bitrate to select in kbit/s.
+pub fn bitrate_for_size(duration: f64, target_size: u64, max_bitrate: u32) -> Option<u32> {
+    let size_limit = target_size / 20 * 19;
+    return (MIN_BITRATE..=max_bitrate)
+        .rev()
+        .find(|bitrate| estimate_tonie_size(duration, *bitrate) <= size_limit);
+}
+
+/// Converts the given audio files with the bitrate selected by [`bitrate_for_size`] and checks the size of the
+/// Tonie file, which exceeds the estimate for audio that is hard to encode. A file above `target_size` is encoded
+/// once more with the bitrate lowered by the excess. If it still does not fit, it is removed and the conversion
+/// fails with [`Audio2TonieError::SizeLimitExceeded`]. The size depends on the bitrate, so the inputs are never
+/// passed through and [`Passthrough::Always`] is rejected.
+///
+/// # Arguments
+///
+/// * `input_files` - The input audio files in the order of the chapters.
+/// * `output_file_path` - The path to the output file.
+/// * `target_size` - The maximum size of the Tonie file in bytes.
+/// * `options` - Options controlling the conversion, with the bitrate selected for the target size.
+pub fn convert_files_to_size(
+    input_files: &[PathBuf],
+    output_file_path: &Path,
+    target_size: u64,
+    options: &ConvertOptions,
+) -> Result<File, Audio2TonieError> {
+    if options.passthrough == Passthrough::Always {
+        return Err(anyhow!(
+            "A target size selects the bitrate, so the inputs cannot be passed through."
+        )
+        .into());
+    }
+    let options = &ConvertOptions {
+        passthrough: Passthrough::Never,
+        ..options.clone()
+    };
+    let output_file_path = resolve_output_path(output_file_path);
+    let file = convert_files_to_tonie(input_files, &output_file_path, options)?;
+    let size = file.metadata()?.len();
+    if size <= target_size {
+        return Ok(file);
+    }
+
+    let bitrate = (options.bitrate as u64 * target_size / size) as u32;
+    let bitrate = bitrate.min(options.bitrate - 1);
+    let size = match bitrate >= MIN_BITRATE {
+        true => {
+            drop(file);
+            // The skipped tracks are recorded again by the second encoding
+            options.failed_tracks.take();
+            let options = ConvertOptions {
+                bitrate,
+                existing_output: ExistingOutput::Overwrite,
+                ..options.clone()
+            };
+            let file = convert_files_to_tonie(input_files, &output_file_path, &options)?;
+            let size = file.metadata()?.len();
+            if size <= target_size {
+                return Ok(file);
+            }
+            size
+        }
+        false => size,
     };
 
-    let output_file = File::create(output_file_path_validated)?;
-    let mut toniefile = Toniefile::new(&output_file, 0x12345678, user_comments).unwrap();
+    std::fs::remove_file(&output_file_path)?;
+    return Err(Audio2TonieError::SizeLimitExceeded {
+        output: output_file_path,
+        estimated: size,
+        max: target_size,
+    });
+}
 
-    input_files
-        .iter()
-        .filter_map(|input_file| {
-            audiofile_to_wav(input_file, &ffmpeg)
-                .and_then(vec_u8_to_i16)
-                .ok()
-        })
-        .enumerate()
-        .for_each(|(index, buffer)| {
-            toniefile.encode(&buffer[..]).ok();
+/// Estimates the size of a Tonie file in bytes from the duration of the audio and the Opus bitrate.
+/// The actual size varies with the content, because the audio is encoded with a variable bitrate.
+///
+/// # Arguments
+///
+/// * `duration` - The duration of the audio in seconds.
+/// * `bitrate` - The Opus bitrate in kbit/s.
+pub fn estimate_tonie_size(duration: f64, bitrate: u32) -> u64 {
+    // Every block holds a page with about five 60ms packets, each with a segment table entry of three bytes
+    let block_payload = (TONIEFILE_FRAME_SIZE - OGG_PAGE_HEADER_SIZE - 16) as f64;
+    let audio_bytes = duration * bitrate as f64 * 1000.0 / 8.0;
+    let audio_blocks = (audio_bytes / block_payload).ceil() as u64;
+
+    // The Tonie header and the block with the Opus headers
+    return TONIEFILE_HEADER_SIZE as u64 + (audio_blocks + 1) * TONIEFILE_FRAME_SIZE as u64;
+}
+
+fn read_samples(file_path: &Path, options: &ConvertOptions) -> Result<Vec<i16>> {
+    let mut buffer = vec![];
+    stream_pcm(file_path, options, |samples| {
+        buffer.extend_from_slice(samples);
+        return Ok(());
+    })?;
+    return Ok(buffer);
+}
+
+fn measure_track(file_path: &Path, options: &ConvertOptions) -> Result<LoudnessMeter> {
+    let mut meter = LoudnessMeter::new(2);
+    stream_pcm(file_path, options, |samples| {
+        meter.add_samples(samples);
+        return Ok(());
+    })?;
+    return Ok(meter);
+}
+
+fn measure_loudness(samples: &[i16]) -> LoudnessMeter {
+    let mut meter = LoudnessMeter::new(2);
+    meter.add_samples(samples);
+    return meter;
+}
+
+/// Resolves the final output file path. Directories get the default Tonie file name appended.
+///
+/// # Arguments
+///
+/// * `output_file_path` - The path to the output file or a directory.
+pub fn resolve_output_path(output_file_path: &Path) -> PathBuf {
+    if output_file_path.is_dir() {
+        return output_file_path.join(DEFAULT_OUTPUT_FILE_NAME);
+    }
+
+    return output_file_path.to_path_buf();
+}
+
+/// Checks whether the output file of a conversion exists, e.g. to skip it with [`ExistingOutput::Skip`].
+///
+/// # Arguments
+///
+/// * `output_file_path` - The path to the output file or a directory.
+pub fn output_exists(output_file_path: &Path) -> bool {
+    return resolve_output_path(output_file_path).is_file();
+}
 
-            if input_files.len() > 1 && index < input_files.len() - 1 {
-                // When providing several input files, when encode them as one audio file with separate chapters
-                // Skip this if there is only one file and for the last file in a collection
-                toniefile.new_chapter().ok();
+/// Checks whether any of the input files was modified after the given point in time.
+/// Used to skip conversions of unchanged inputs in scheduled batch runs.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the input file or a directory.
+/// * `output_file_path` - The path to the output file. Its modification time marks the last run.
+/// * `since` - The point in time to compare the input files against.
+/// * `any_extension` - Consider files with unknown extensions as input files, see [`filter_input_files`].
+pub fn inputs_modified_since(
+    input_file_path: &PathBuf,
+    output_file_path: &Path,
+    since: &Since,
+    any_extension: bool,
+) -> Result<bool> {
+    let since = match since {
+        Since::Timestamp(timestamp) => *timestamp,
+        Since::LastRun => {
+            let output_file_path = resolve_output_path(output_file_path);
+            if !output_file_path.is_file() {
+                // Nothing was recorded yet, so everything counts as modified
+                return Ok(true);
             }
-        });
+            std::fs::metadata(output_file_path)?.modified()?
+        }
+    };
 
-    toniefile.finalize_no_consume()?;
+    for input_file in filter_input_files(input_file_path, any_extension)? {
+        if std::fs::metadata(input_file)?.modified()? > since {
+            return Ok(true);
+        }
+    }
 
-    return Ok(output_file);
+    return Ok(false);
 }
 
 /// Converts an audio file to a WAV file using ffmpeg.
@@ -69,17 +1715,89 @@ pub fn convert_to_tonie(
 /// # Arguments
 ///
 /// * `file_path` - The path to the input audio file.
-/// * `ffmpeg` - The path to the ffmpeg executable.
-pub fn audiofile_to_wav(file_path: &PathBuf, ffmpeg: &str) -> Result<Vec<u8>> {
-    let ffmpeg_process = Command::new(ffmpeg)
+/// * `options` - Options controlling the conversion, e.g. the path to the ffmpeg executable.
+pub fn audiofile_to_wav(
+    file_path: &Path,
+    options: &ConvertOptions,
+) -> Result<Vec<u8>, Audio2TonieError> {
+    let mut wav_buffer = vec![];
+    run_ffmpeg(file_path, "wav", options, |chunk| {
+        wav_buffer.extend_from_slice(chunk);
+        return Ok(());
+    })?;
+
+    return Ok(wav_buffer);
+}
+
+/// Decodes an audio file with ffmpeg and passes the interleaved 16 bit stereo samples at 48 kHz
+/// to the callback in fixed size chunks as they are produced, without buffering the whole file.
+///
+/// # Arguments
+///
+/// * `file_path` - The path to the input audio file.
+/// * `options` - Options controlling the conversion, e.g. the path to the ffmpeg executable.
+/// * `on_samples` - Called with every chunk of decoded samples.
+pub fn stream_pcm<F>(
+    file_path: &Path,
+    options: &ConvertOptions,
+    mut on_samples: F,
+) -> Result<(), Audio2TonieError>
+where
+    F: FnMut(&[i16]) -> Result<()>,
+{
+    let mut samples = Vec::with_capacity(PCM_CHUNK_SIZE / 2);
+    // A read may end in the middle of a sample
+    let mut leftover: Option<u8> = None;
+
+    run_ffmpeg(file_path, "s16le", options, |chunk| {
+        samples.clear();
+        let mut bytes = chunk;
+        if let Some(low_byte) = leftover.take() {
+            samples.push(i16::from_le_bytes([low_byte, bytes[0]]));
+            bytes = &bytes[1..];
+        }
+        let sample_bytes = bytes.chunks_exact(2);
+        leftover = sample_bytes.remainder().first().copied();
+        samples.extend(sample_bytes.map(|pair| i16::from_le_bytes([pair[0], pair[1]])));
+
+        return on_samples(&samples);
+    })?;
+
+    return Ok(());
+}
+
+/// Runs ffmpeg to decode an audio file into 16 bit stereo PCM at 48 kHz and passes its output in chunks to the callback.
+/// The playback speed is changed with ffmpeg's atempo filter.
+///
+/// # Arguments
+///
+/// * `file_path` - The path to the input audio file.
+/// * `format` - The ffmpeg output format, e.g. `wav` or `s16le` for raw samples.
+/// * `options` - Options controlling the conversion, e.g. the path to the ffmpeg executable.
+/// * `on_chunk` - Called with every non-empty chunk of ffmpeg's output.
+fn run_ffmpeg<F>(
+    file_path: &Path,
+    format: &str,
+    options: &ConvertOptions,
+    mut on_chunk: F,
+) -> Result<()>
+where
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    let mut ffmpeg_command = tool_command(&options.ffmpeg);
+    ffmpeg_command.args(["-hide_banner", "-loglevel", "warning"]);
+    if let Some(input_format) = &options.input_format {
+        ffmpeg_command.args(["-f", input_format]);
+    }
+    // ffmpeg inherits stdin, so it reads the audio of the stdin input itself
+    ffmpeg_command.args(["-i", file_path.to_str().unwrap()]);
+    // The output format comes last, so additional arguments cannot change the decoded PCM format. Forcing two
+    // channels upmixes mono sources, which the stereo encoder could not take otherwise
+    ffmpeg_command.args(ffmpeg_output_args(options));
+    let mut ffmpeg_process = ffmpeg_command
         .args([
-            "-hide_banner",
-            "-loglevel",
-            "warning",
-            "-i",
-            file_path.to_str().unwrap(),
             "-f",
-            "wav",
+            format,
             "-ar",
             "48000",
             "-acodec",
@@ -89,33 +1807,379 @@ pub fn audiofile_to_wav(file_path: &PathBuf, ffmpeg: &str) -> Result<Vec<u8>> {
             "-",
         ])
         .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .spawn()?;
+    let ffmpeg_stderr = forward_stderr(&mut ffmpeg_process);
+
+    let mut progress_bar = options.show_progress.then(|| {
+        // Probing stdin would consume the audio
+        let duration = match is_stdin_input(file_path) {
+            true => None,
+            false => probe_duration(file_path, &options.ffmpeg).unwrap_or_default(),
+        };
+        ProgressBar::new(
+            &file_path.file_name().unwrap_or_default().to_string_lossy(),
+            duration,
+        )
+    });
+
+    // Reading the decoded audio slowly makes ffmpeg block, which bounds its CPU and disk usage as well
+    let ffmpeg_stdout = ffmpeg_process
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow!("Failed to read the output of ffmpeg."))?;
+    let mut ffmpeg_stdout = ThrottledIo::new(ffmpeg_stdout, options.io_throttle);
+    let mut chunk = vec![0u8; PCM_CHUNK_SIZE];
+    let mut total_bytes = 0;
+    loop {
+        let bytes_read = ffmpeg_stdout.read(&mut chunk)?;
+        if bytes_read == 0 {
+            break;
+        }
+        total_bytes += bytes_read;
+        let result =
+            on_chunk(&chunk[..bytes_read]).and_then(|_| Ok(options.cancellation.check()?));
+        if let Err(error) = result {
+            ffmpeg_process.kill().ok();
+            ffmpeg_process.wait().ok();
+            return Err(error);
+        }
+
+        if let Some(progress_bar) = progress_bar.as_mut() {
+            // The decoded position is derived from the amount of PCM data ffmpeg produced so far
+            progress_bar.update(total_bytes as f64 / PCM_BYTES_PER_SECOND as f64 * options.speed);
+        }
+    }
+    if let Some(progress_bar) = progress_bar.as_mut() {
+        progress_bar.finish();
+    }
 
     // Await processes to finish
-    let ffmpeg_status = ffmpeg_process.wait_with_output()?;
-    if !ffmpeg_status.status.success() {
-        return Err(anyhow!(
-            "Conversion with ffmpeg failed: {}",
-            ffmpeg_status.status
-        ));
+    let ffmpeg_status = ffmpeg_process.wait()?;
+    // ffmpeg receives Ctrl-C as well and stops early
+    options.cancellation.check()?;
+    if !ffmpeg_status.success() {
+        let stderr = ffmpeg_stderr
+            .and_then(|stderr| stderr.join().ok())
+            .unwrap_or_default();
+        return Err(Audio2TonieError::FfmpegFailed {
+            status: ffmpeg_status.to_string(),
+            stderr,
+        }
+        .into());
+    }
+
+    return Ok(());
+}
+
+// Forwards the warnings ffmpeg prints while decoding and keeps the last lines to report them if ffmpeg fails
+fn forward_stderr(process: &mut Child) -> Option<JoinHandle<String>> {
+    let stderr = process.stderr.take()?;
+    return Some(std::thread::spawn(move || {
+        let mut last_lines = VecDeque::with_capacity(FFMPEG_STDERR_LINES);
+        for line in BufReader::new(stderr).lines().map_while(|line| line.ok()) {
+            eprintln!("{}", line);
+            if last_lines.len() == FFMPEG_STDERR_LINES {
+                last_lines.pop_front();
+            }
+            last_lines.push_back(line);
+        }
+        return Vec::from(last_lines).join("\n");
+    }));
+}
+
+/// The ffmpeg arguments between the input file and the output format: the additional arguments of the options
+/// and the atempo filter for the playback speed. The atempo filter is appended to an audio filter of the
+/// additional arguments, because ffmpeg only applies the last one.
+///
+/// # Arguments
+///
+/// * `options` - Options controlling the conversion, e.g. the additional arguments and the speed.
+pub fn ffmpeg_output_args(options: &ConvertOptions) -> Vec<String> {
+    let mut args = options.ffmpeg_args.clone();
+    if options.speed == 1.0 {
+        return args;
+    }
+
+    let atempo = atempo_filter(options.speed);
+    let filter_index = args
+        .iter()
+        .rposition(|arg| arg == "-af" || arg == "-filter:a")
+        .map(|index| index + 1)
+        .filter(|&index| index < args.len());
+    match filter_index {
+        Some(index) => args[index] = format!("{},{}", args[index], atempo),
+        None => args.extend(["-af".to_string(), atempo]),
+    }
+    return args;
+}
+
+/// Builds the ffmpeg filter changing the playback speed without changing the pitch. A single atempo filter
+/// only supports factors from 0.5 to 2, so larger changes are chained, e.g. `atempo=2,atempo=1.5` for 3.
+///
+/// # Arguments
+///
+/// * `speed` - The playback speed, e.g. 1.15 to play 15% faster.
+pub fn atempo_filter(speed: f64) -> String {
+    let mut factors = vec![];
+    let mut remaining = speed;
+    while remaining > 2.0 {
+        factors.push(2.0);
+        remaining /= 2.0;
+    }
+    while remaining < 0.5 {
+        factors.push(0.5);
+        remaining /= 0.5;
+    }
+    factors.push(remaining);
+
+    return factors
+        .iter()
+        .map(|factor| format!("atempo={}", factor))
+        .collect::<Vec<_>>()
+        .join(",");
+}
+
+/// Probes the duration of an audio file in seconds using ffmpeg. Returns `None` if the duration is unknown.
+///
+/// # Arguments
+///
+/// * `file_path` - The path to the input audio file.
+/// * `ffmpeg` - The path to the ffmpeg executable.
+pub fn probe_duration(file_path: &Path, ffmpeg: &str) -> Result<Option<f64>> {
+    // Without an output file ffmpeg only prints the input stream information and exits with an error
+    let ffmpeg_output = tool_command(ffmpeg)
+        .args(["-hide_banner", "-i"])
+        .arg(file_path)
+        .stdin(Stdio::null())
+        .output()?;
+
+    return Ok(parse_ffmpeg_duration(&String::from_utf8_lossy(
+        &ffmpeg_output.stderr,
+    )));
+}
+
+/// Parses the duration from ffmpeg's input information, e.g. `Duration: 00:03:28.03, start: ...`.
+///
+/// # Arguments
+///
+/// * `ffmpeg_output` - The stderr output of ffmpeg.
+pub fn parse_ffmpeg_duration(ffmpeg_output: &str) -> Option<f64> {
+    let duration = ffmpeg_output
+        .split("Duration: ")
+        .nth(1)?
+        .split(',')
+        .next()?
+        .trim();
+
+    return duration
+        .split(':')
+        .map(|part| part.parse::<f64>())
+        .try_fold(0.0, |total, part| part.map(|part| total * 60.0 + part))
+        .ok();
+}
+
+/// Probes the start times in seconds of the chapters embedded in an audio file, e.g. an m4b audio book.
+///
+/// # Arguments
+///
+/// * `file_path` - The path to the audio file.
+/// * `ffmpeg` - The path to the ffmpeg executable.
+pub fn probe_chapters(file_path: &Path, ffmpeg: &str) -> Result<Vec<f64>> {
+    let ffmpeg_output = tool_command(ffmpeg)
+        .args(["-hide_banner", "-i"])
+        .arg(file_path)
+        .stdin(Stdio::null())
+        .output()?;
+
+    return Ok(parse_ffmpeg_chapters(&String::from_utf8_lossy(
+        &ffmpeg_output.stderr,
+    )));
+}
+
+/// Parses the chapter start times from ffmpeg's input information, e.g. `Chapter #0:1: start 600.000000, end 1200.000000`.
+///
+/// # Arguments
+///
+/// * `ffmpeg_output` - The stderr output of ffmpeg.
+pub fn parse_ffmpeg_chapters(ffmpeg_output: &str) -> Vec<f64> {
+    return ffmpeg_output
+        .lines()
+        .filter_map(|line| {
+            line.trim()
+                .strip_prefix("Chapter #")?
+                .split_once("start ")?
+                .1
+                .split(',')
+                .next()?
+                .trim()
+                .parse::<f64>()
+                .ok()
+        })
+        .collect();
+}
+
+/// Probes the metadata tags of an audio file using ffmpeg, see [`parse_ffmpeg_metadata`].
+///
+/// # Arguments
+///
+/// * `file_path` - The path to the audio file.
+/// * `ffmpeg` - The path to the ffmpeg executable.
+pub fn probe_metadata(file_path: &Path, ffmpeg: &str) -> Result<HashMap<String, String>> {
+    let ffmpeg_output = tool_command(ffmpeg)
+        .args(["-hide_banner", "-i"])
+        .arg(file_path)
+        .stdin(Stdio::null())
+        .output()?;
+
+    return Ok(parse_ffmpeg_metadata(&String::from_utf8_lossy(
+        &ffmpeg_output.stderr,
+    )));
+}
+
+/// Parses the metadata tags from ffmpeg's input information, e.g. `title : The End` and `album : Stories`.
+/// The keys are lowercase and the first value of a key wins, so the tags of the container take precedence over
+/// the tags of its streams.
+///
+/// # Arguments
+///
+/// * `ffmpeg_output` - The stderr output of ffmpeg.
+pub fn parse_ffmpeg_metadata(ffmpeg_output: &str) -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+    for line in ffmpeg_output.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        metadata
+            .entry(key.trim().to_ascii_lowercase())
+            .or_insert_with(|| value.trim().to_string());
+    }
+    return metadata;
+}
+
+/// Probes the disc and track number tags of an audio file using ffmpeg. Returns `None` without a track number,
+/// a missing disc number counts as the first disc.
+///
+/// # Arguments
+///
+/// * `file_path` - The path to the audio file.
+/// * `ffmpeg` - The path to the ffmpeg executable.
+pub fn probe_track_number(file_path: &Path, ffmpeg: &str) -> Result<Option<(u32, u32)>> {
+    let ffmpeg_output = tool_command(ffmpeg)
+        .args(["-hide_banner", "-i"])
+        .arg(file_path)
+        .stdin(Stdio::null())
+        .output()?;
+
+    return Ok(parse_ffmpeg_track_number(&String::from_utf8_lossy(
+        &ffmpeg_output.stderr,
+    )));
+}
+
+/// Parses the disc and track number from the metadata in ffmpeg's input information, e.g. `track : 3/12` and
+/// `disc : 1/2`. ffmpeg maps ID3 `TRCK`/`TPOS` and Vorbis `TRACKNUMBER`/`DISCNUMBER` to these keys.
+///
+/// # Arguments
+///
+/// * `ffmpeg_output` - The stderr output of ffmpeg.
+pub fn parse_ffmpeg_track_number(ffmpeg_output: &str) -> Option<(u32, u32)> {
+    let metadata = parse_ffmpeg_metadata(ffmpeg_output);
+    let metadata_number = |keys: [&str; 2]| {
+        let value = keys.iter().find_map(|key| metadata.get(*key))?;
+        // Numbers may include the total, e.g. 3/12
+        return value.split('/').next()?.trim().parse::<u32>().ok();
+    };
+
+    let track = metadata_number(["track", "tracknumber"])?;
+    let disc = metadata_number(["disc", "discnumber"]).unwrap_or(1);
+    return Some((disc, track));
+}
+
+// Sorts the files of a directory by their disc and track numbers, keeping the given order without usable tags
+fn order_by_track_number(input_files: Vec<PathBuf>, ffmpeg: &str) -> Vec<PathBuf> {
+    let mut numbered_files = vec![];
+    for input_file in &input_files {
+        // Files ffmpeg cannot probe have no usable tags either
+        match probe_track_number(input_file, ffmpeg).ok().flatten() {
+            Some(number) => numbered_files.push((number, input_file.clone())),
+            None => return input_files,
+        }
     }
 
-    return Ok(ffmpeg_status.stdout);
+    let mut numbers = numbered_files
+        .iter()
+        .map(|(number, _)| *number)
+        .collect::<Vec<_>>();
+    numbers.sort();
+    numbers.dedup();
+    if numbers.len() != numbered_files.len() {
+        return input_files;
+    }
+
+    numbered_files.sort_by_key(|(number, _)| *number);
+    return numbered_files
+        .into_iter()
+        .map(|(_, input_file)| input_file)
+        .collect();
+}
+
+/// Collects the audio files of several inputs, keeping the order of the inputs. Every input is expanded like in
+/// [`filter_input_files`], so only the files of a directory or a glob pattern are sorted. The files of a
+/// directory are sorted by their track number tags with [`TrackOrder::Tags`].
+///
+/// # Arguments
+///
+/// * `input_paths` - The paths to input files, directories or glob patterns in chapter order.
+/// * `options` - Options controlling the conversion, e.g. the order of the files and the path to ffmpeg.
+pub fn collect_input_files(
+    input_paths: &[PathBuf],
+    options: &ConvertOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut input_files = vec![];
+    for input_path in input_paths {
+        let files = filter_input_files(input_path, options.any_extension)?;
+        match options.track_order == TrackOrder::Tags && input_path.is_dir() {
+            true => input_files.extend(order_by_track_number(files, &options.ffmpeg)),
+            false => input_files.extend(files),
+        }
+    }
+    return Ok(input_files);
 }
 
 /// Filters the input files based on whether they are a supported file or a directory containing supported files.
+/// With `any_extension` a single input file is accepted with any extension and passed to ffmpeg, which fails if
+/// it cannot decode it. Directories then contain all files except hidden files and known companions of audio
+/// files like CUE sheets, cover images and playlists.
+///
+/// An input path that does not exist but contains glob wildcards, e.g. `Album/Disc*/[0-9]*.mp3`, is expanded
+/// to the matching audio files in natural order. A TeddyCloud playlist (`.tap`) is expanded to the files it
+/// references, in playlist order.
 ///
 /// # Arguments
 ///
-/// * `input_file` - The path to the input file or a directory.
-pub fn filter_input_files(input_file: &PathBuf) -> Result<Vec<PathBuf>> {
-    if input_file.is_file() && is_file_extension_supported(&input_file) {
+/// * `input_file` - The path to the input file, a directory or a glob pattern.
+/// * `any_extension` - Accept files with unknown extensions.
+pub fn filter_input_files(input_file: &PathBuf, any_extension: bool) -> Result<Vec<PathBuf>> {
+    if is_stdin_input(input_file) {
+        return Ok(vec![input_file.to_path_buf()]);
+    }
+    if !input_file.exists() && is_glob_pattern(&input_file.to_string_lossy()) {
+        return expand_glob_pattern(&input_file.to_string_lossy(), any_extension);
+    }
+
+    if input_file.is_file() && is_tap_file(input_file) {
+        return read_tap_files(input_file);
+    }
+    if input_file.is_file() && (any_extension || is_file_extension_supported(input_file)) {
         return Ok(vec![input_file.to_path_buf()]);
     } else if input_file.is_dir() {
         let mut paths = std::fs::read_dir(input_file)?
             .filter_map(|res| res.ok())
             .map(|dir_entry| dir_entry.path())
-            .filter(is_file_extension_supported)
+            .filter(|path| match any_extension {
+                true => path.is_file() && is_possibly_audio_file(path),
+                false => is_file_extension_supported(path),
+            })
             .collect::<Vec<_>>();
 
         paths.sort_by(|a, b| {
@@ -131,8 +2195,163 @@ pub fn filter_input_files(input_file: &PathBuf) -> Result<Vec<PathBuf>> {
 
         return Ok(paths);
     } else {
-        return Err(anyhow!["Could not process the provided input files. Expected the input file to end in one of the follow extensions: {:?}", SUPPORTED_FILE_EXTENSIONS]);
+        return Err(anyhow!["Could not process the provided input files. Expected the input file to end in one of the follow extensions: {:?}. Use --any-extension to pass other files to ffmpeg.", SUPPORTED_FILE_EXTENSIONS]);
+    }
+}
+
+// The files of a TeddyCloud playlist, which all have to exist
+fn read_tap_files(tap_path: &Path) -> Result<Vec<PathBuf>> {
+    let base_dir = tap_path.parent().unwrap_or(Path::new(""));
+    let files = TapPlaylist::read(tap_path)?.resolve_files(base_dir);
+    if files.is_empty() {
+        return Err(anyhow!(
+            "The playlist {} does not contain any files.",
+            tap_path.display()
+        ));
+    }
+    if let Some(missing) = files.iter().find(|file| !file.is_file()) {
+        return Err(anyhow!(
+            "The playlist {} references {}, which does not exist.",
+            tap_path.display(),
+            missing.display()
+        ));
+    }
+
+    return Ok(files);
+}
+
+/// Checks whether an input path stands for stdin, see [`STDIN_INPUT`].
+///
+/// # Arguments
+///
+/// * `input_path` - The path to an input file.
+pub fn is_stdin_input(input_path: &Path) -> bool {
+    return input_path == Path::new(STDIN_INPUT);
+}
+
+/// Reads a list of input paths with one path per line, e.g. the track list of another tool. Empty lines and
+/// lines starting with `#` are skipped, so the paths of M3U playlists can be read as well. Relative paths are
+/// kept relative to the current directory.
+///
+/// # Arguments
+///
+/// * `reader` - The list, e.g. a file or stdin.
+pub fn read_input_list<R: BufRead>(reader: R) -> Result<Vec<PathBuf>> {
+    let mut input_paths = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        input_paths.push(PathBuf::from(line));
+    }
+
+    if input_paths.is_empty() {
+        return Err(anyhow!("The input list does not contain any paths."));
+    }
+    return Ok(input_paths);
+}
+
+/// Checks if an input path is a glob pattern, i.e. it contains one of the wildcards `*`, `?` or `[`.
+///
+/// # Arguments
+///
+/// * `input` - The input path.
+pub fn is_glob_pattern(input: &str) -> bool {
+    return input.contains(['*', '?', '[']);
+}
+
+// Expands a glob pattern to the matching audio files, sorted naturally by their whole path so the files of
+// `Disc 2` follow the files of `Disc 1`
+fn expand_glob_pattern(pattern: &str, any_extension: bool) -> Result<Vec<PathBuf>> {
+    let mut paths = glob::glob(pattern)
+        .map_err(|error| anyhow!("'{}' is not a valid glob pattern: {}", pattern, error))?
+        .filter_map(|path| path.ok())
+        .filter(|path| {
+            path.is_file()
+                && match any_extension {
+                    true => is_possibly_audio_file(path),
+                    false => is_file_extension_supported(path),
+                }
+        })
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        return Err(anyhow!(
+            "The pattern '{}' does not match any audio files.",
+            pattern
+        ));
+    }
+
+    paths.sort_by(|a, b| compare(&a.to_string_lossy(), &b.to_string_lossy()));
+    return Ok(paths);
+}
+
+/// Recursively finds all directories below the given directory that directly contain supported audio files,
+/// e.g. the album folders of a music library. Each of them is converted into a separate Tonie file.
+///
+/// # Arguments
+///
+/// * `root_directory` - The directory to walk.
+/// * `any_extension` - Consider files with unknown extensions as audio files, see [`filter_input_files`].
+pub fn find_album_directories(root_directory: &Path, any_extension: bool) -> Result<Vec<PathBuf>> {
+    let mut album_directories = vec![];
+    let mut pending_directories = vec![root_directory.to_path_buf()];
+
+    while let Some(directory) = pending_directories.pop() {
+        let mut has_audio_files = false;
+        for entry in std::fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending_directories.push(path);
+            } else if is_file_extension_supported(&path)
+                || (any_extension && is_possibly_audio_file(&path))
+            {
+                has_audio_files = true;
+            }
+        }
+
+        if has_audio_files {
+            album_directories.push(directory);
+        }
+    }
+
+    album_directories.sort_by(|a, b| compare(&a.to_string_lossy(), &b.to_string_lossy()));
+    return Ok(album_directories);
+}
+
+/// Derives the output path of an album from its path relative to the library root, e.g.
+/// `Artist/Album` becomes `Artist - Album.taf` to avoid clashes between equally named folders.
+///
+/// # Arguments
+///
+/// * `root_directory` - The root directory of the library.
+/// * `album_directory` - The album directory below the root directory.
+/// * `output_directory` - The directory for the Tonie files.
+pub fn album_output_path(
+    root_directory: &Path,
+    album_directory: &Path,
+    output_directory: &Path,
+) -> PathBuf {
+    let relative_path = album_directory
+        .strip_prefix(root_directory)
+        .unwrap_or(album_directory);
+    let mut name = relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" - ");
+    if name.is_empty() {
+        // The root directory itself contains audio files
+        name = root_directory
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from(DEFAULT_OUTPUT_FILE_NAME));
     }
+
+    return limit_path_length(
+        &output_directory.join(format!("{}.taf", sanitize_file_name(&name, false))),
+    );
 }
 
 /// Checks if the file extension is supported.
@@ -140,9 +2359,29 @@ pub fn filter_input_files(input_file: &PathBuf) -> Result<Vec<PathBuf>> {
 /// # Arguments
 ///
 /// * `input_file_path` - The path to the input file.
-fn is_file_extension_supported(input_file_path: &PathBuf) -> bool {
-    return input_file_path.extension().map_or(false, |ext| {
-        SUPPORTED_FILE_EXTENSIONS
-            .contains(&ext.to_str().expect("Could not identify file extension."))
+pub(crate) fn is_file_extension_supported(input_file_path: &Path) -> bool {
+    return input_file_path.extension().is_some_and(|ext| {
+        SUPPORTED_FILE_EXTENSIONS.contains(
+            &ext.to_str()
+                .expect("Could not identify file extension.")
+                .to_ascii_lowercase()
+                .as_str(),
+        )
+    });
+}
+
+/// Checks if a file in an input directory might be an audio file for ffmpeg, i.e. it is neither hidden nor a
+/// known companion of audio files.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the file.
+fn is_possibly_audio_file(input_file_path: &Path) -> bool {
+    let is_hidden = input_file_path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    let is_companion = input_file_path.extension().is_some_and(|ext| {
+        NON_AUDIO_FILE_EXTENSIONS.contains(&ext.to_string_lossy().to_ascii_lowercase().as_str())
     });
+    return !is_hidden && !is_companion;
 }
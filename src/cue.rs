@@ -0,0 +1,153 @@
+//! Parser for CUE sheets describing the tracks of a single audio file, e.g. a ripped audio CD.
+
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+// Positions in CUE sheets are given as mm:ss:ff with 75 frames per second
+const CUE_FRAMES_PER_SECOND: u64 = 75;
+
+/// A single track of a CUE sheet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CueTrack {
+    pub number: u32,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// The start of the track (`INDEX 01`) in CUE frames of 1/75 second.
+    pub start: u64,
+}
+
+impl CueTrack {
+    /// The start of the track in samples per channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - The sample rate of the audio.
+    pub fn start_sample(&self, sample_rate: u64) -> u64 {
+        return self.start * sample_rate / CUE_FRAMES_PER_SECOND;
+    }
+}
+
+/// The tracks of a single audio file described by a CUE sheet.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueSheet {
+    /// The audio file referenced by the `FILE` command.
+    pub file: Option<String>,
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    pub tracks: Vec<CueTrack>,
+}
+
+/// Parses a CUE sheet. Only sheets referencing a single audio file are supported.
+///
+/// # Arguments
+///
+/// * `content` - The content of the CUE sheet.
+pub fn parse_cue_sheet(content: &str) -> Result<CueSheet> {
+    let mut cue_sheet = CueSheet::default();
+
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim().trim_start_matches('\u{feff}');
+        let (command, arguments) = line.split_once(' ').unwrap_or((line, ""));
+        let arguments = arguments.trim();
+        let invalid_line = || anyhow!("Invalid CUE sheet line {}: {}", line_number + 1, line);
+
+        match command.to_ascii_uppercase().as_str() {
+            "FILE" => {
+                if cue_sheet.file.is_some() {
+                    return Err(anyhow!(
+                        "CUE sheets referencing several audio files are not supported."
+                    ));
+                }
+                let (file, _file_type) = split_quoted(arguments).ok_or_else(invalid_line)?;
+                cue_sheet.file = Some(file);
+            }
+            "TRACK" => {
+                let number = arguments
+                    .split_whitespace()
+                    .next()
+                    .and_then(|number| number.parse::<u32>().ok())
+                    .ok_or_else(invalid_line)?;
+                cue_sheet.tracks.push(CueTrack {
+                    number,
+                    title: None,
+                    performer: None,
+                    start: 0,
+                });
+            }
+            "TITLE" | "PERFORMER" => {
+                let (value, _) = split_quoted(arguments).ok_or_else(invalid_line)?;
+                let target = match cue_sheet.tracks.last_mut() {
+                    Some(track) if command.eq_ignore_ascii_case("TITLE") => &mut track.title,
+                    Some(track) => &mut track.performer,
+                    None if command.eq_ignore_ascii_case("TITLE") => &mut cue_sheet.title,
+                    None => &mut cue_sheet.performer,
+                };
+                *target = Some(value);
+            }
+            "INDEX" => {
+                let mut parts = arguments.split_whitespace();
+                let index = parts.next().ok_or_else(invalid_line)?;
+                let position = parts
+                    .next()
+                    .and_then(parse_cue_time)
+                    .ok_or_else(invalid_line)?;
+                // INDEX 00 marks the pregap, the track itself starts at INDEX 01
+                if index == "01" {
+                    let track = cue_sheet.tracks.last_mut().ok_or_else(invalid_line)?;
+                    track.start = position;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if cue_sheet.tracks.is_empty() {
+        return Err(anyhow!("The CUE sheet does not contain any tracks."));
+    }
+
+    return Ok(cue_sheet);
+}
+
+/// Looks for a CUE sheet next to an audio file, i.e. `album.cue` or `album.flac.cue` for `album.flac`.
+///
+/// # Arguments
+///
+/// * `audio_file_path` - The path to the audio file.
+pub fn find_cue_sheet(audio_file_path: &Path) -> Option<PathBuf> {
+    let mut appended_extension = audio_file_path.as_os_str().to_os_string();
+    appended_extension.push(".cue");
+
+    return [
+        audio_file_path.with_extension("cue"),
+        PathBuf::from(appended_extension),
+    ]
+    .into_iter()
+    .find(|cue_file_path| cue_file_path.is_file());
+}
+
+/// Parses a position given as `mm:ss:ff` into CUE frames.
+fn parse_cue_time(time: &str) -> Option<u64> {
+    let mut parts = time.split(':').map(|part| part.parse::<u64>().ok());
+    let minutes = parts.next()??;
+    let seconds = parts.next()??;
+    let frames = parts.next()??;
+    if parts.next().is_some() || seconds >= 60 || frames >= CUE_FRAMES_PER_SECOND {
+        return None;
+    }
+
+    return Some((minutes * 60 + seconds) * CUE_FRAMES_PER_SECOND + frames);
+}
+
+/// Splits a possibly quoted value from the remaining arguments, e.g. `"My Album.flac" WAVE`.
+fn split_quoted(arguments: &str) -> Option<(String, &str)> {
+    if let Some(quoted) = arguments.strip_prefix('"') {
+        let end = quoted.find('"')?;
+        return Some((quoted[..end].to_string(), quoted[end + 1..].trim()));
+    }
+
+    let (value, rest) = arguments.split_once(' ').unwrap_or((arguments, ""));
+    if value.is_empty() {
+        return None;
+    }
+    return Some((value.to_string(), rest.trim()));
+}
@@ -0,0 +1,115 @@
+use crate::error::Audio2TonieError;
+use crate::limits::Limits;
+use crate::ogg_page::PacketAssembler;
+use crate::taf::{audio_offset, OggPageReader, TONIEFILE_FRAME_SIZE};
+use anyhow::{anyhow, Result};
+use audiopus::{coder::Decoder, Channels, SampleRate};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+use toniefile::Toniefile;
+
+use crate::utils::check_input_limits;
+
+const OPUS_CHANNELS: usize = 2;
+const TONIEFILE_SAMPLE_RATE: u32 = 48000;
+// Maximum duration of an Opus packet is 120ms
+const MAX_PACKET_SAMPLES: usize = 48000 * 120 / 1000;
+
+/// Decodes the Opus audio stream of a Tonie file and passes the PCM samples of every packet
+/// to the given callback together with the index of the chapter the packet belongs to. The pages are read one
+/// at a time, so the memory usage does not depend on the length of the file.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+/// * `on_samples` - Called with the chapter index and interleaved stereo samples.
+pub fn decode_tonie_chapters<F>(
+    input_file_path: &Path,
+    limits: &Limits,
+    on_samples: F,
+) -> Result<()>
+where
+    F: FnMut(usize, &[i16]) -> Result<()>,
+{
+    return decode_tonie_reader(File::open(input_file_path)?, limits, on_samples);
+}
+
+/// Decodes the Opus audio stream of a Tonie file from any seekable reader, see [`decode_tonie_chapters`].
+///
+/// # Arguments
+///
+/// * `tonie_file` - The Tonie file, e.g. an in-memory buffer.
+/// * `limits` - Caps for the input size, header size and number of pages.
+/// * `on_samples` - Called with the chapter index and interleaved stereo samples.
+pub fn decode_tonie_reader<R, F>(
+    mut tonie_file: R,
+    limits: &Limits,
+    mut on_samples: F,
+) -> Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(usize, &[i16]) -> Result<()>,
+{
+    check_input_limits(&mut tonie_file, limits)?;
+
+    let tonie_header = Toniefile::parse_header(&mut tonie_file)?;
+    let mut length_prefix = [0u8; 4];
+    tonie_file.rewind()?;
+    tonie_file.read_exact(&mut length_prefix)?;
+    let audio_offset =
+        audio_offset(&length_prefix).ok_or_else(|| anyhow!("The Tonie file is too short."))?;
+    tonie_file.seek(SeekFrom::Start(audio_offset as u64))?;
+
+    let mut decoder = Decoder::new(SampleRate::Hz48000, Channels::Stereo)?;
+    let mut output = vec![0i16; MAX_PACKET_SAMPLES * OPUS_CHANNELS];
+    let mut pre_skip = 0;
+    let mut packet_count = 0;
+    let mut packet_assembler = PacketAssembler::new();
+
+    for page in OggPageReader::with_limits(BufReader::new(tonie_file), *limits) {
+        let (page_offset, page) = page?;
+        let block = (page_offset / TONIEFILE_FRAME_SIZE) as u32;
+        let chapter = tonie_header
+            .track_page_nums
+            .iter()
+            .rposition(|page_num| *page_num <= block)
+            .unwrap_or_default();
+
+        for packet in packet_assembler.push_page(&page) {
+            packet_count += 1;
+            match packet_count {
+                // OpusHead with the number of samples to skip at the beginning of the stream
+                1 => {
+                    if packet.len() < 16 || !packet.starts_with(b"OpusHead") {
+                        return Err(anyhow!("The Tonie file does not contain an Opus stream."));
+                    }
+                    // The Toniebox only plays Tonie files of 48 kHz input audio
+                    let sample_rate =
+                        u32::from_le_bytes([packet[12], packet[13], packet[14], packet[15]]);
+                    if sample_rate != TONIEFILE_SAMPLE_RATE {
+                        return Err(Audio2TonieError::UnsupportedSampleRate(sample_rate).into());
+                    }
+                    pre_skip = u16::from_le_bytes([packet[10], packet[11]]);
+                }
+                // OpusTags
+                2 => (),
+                _ => {
+                    let samples = decoder.decode(Some(&packet[..]), &mut output[..], false)?;
+                    let skipped = samples.min(pre_skip as usize);
+                    pre_skip -= skipped as u16;
+
+                    on_samples(
+                        chapter,
+                        &output[skipped * OPUS_CHANNELS..samples * OPUS_CHANNELS],
+                    )?;
+                }
+            }
+        }
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,143 @@
+//! Online audio sources as input of a conversion. URLs of videos, streams or playlists are downloaded with
+//! yt-dlp, and the downloaded files are converted like local input files.
+
+use anyhow::{anyhow, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// The temporary directory for the downloaded audio of a conversion. It is removed when it is dropped, i.e. after
+/// the conversion or if it fails.
+pub struct DownloadDir {
+    path: PathBuf,
+}
+
+impl DownloadDir {
+    /// # Arguments
+    ///
+    /// * `temp_root` - The directory to create the download directory in.
+    pub fn new(temp_root: &Path) -> Self {
+        DownloadDir {
+            path: temp_root.join(format!("audio2tonie-download-{}", std::process::id())),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+}
+
+impl Drop for DownloadDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.path).ok();
+    }
+}
+
+/// Checks whether an input path is an http or https URL to download, e.g. `https://www.youtube.com/watch?v=...`.
+///
+/// # Arguments
+///
+/// * `input_path` - The input path given on the command line.
+pub fn is_url_input(input_path: &Path) -> bool {
+    let input = input_path.to_string_lossy();
+    return ["http://", "https://"].iter().any(|scheme| {
+        input
+            .get(..scheme.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+    });
+}
+
+/// Replaces the URL inputs with the files downloaded with yt-dlp, keeping the order of the inputs. The entries
+/// of a playlist become separate inputs, i.e. chapters, in the order of the playlist.
+///
+/// # Arguments
+///
+/// * `inputs` - The input paths, some of which might be URLs, see [`is_url_input`].
+/// * `downloader` - The name or the path of the yt-dlp executable. URL inputs are rejected without it.
+/// * `download_dir` - The directory for the downloaded files.
+/// * `on_download` - Called with the URL before it is downloaded, e.g. to show the progress.
+pub fn download_url_inputs<F>(
+    inputs: Vec<PathBuf>,
+    downloader: Option<&str>,
+    download_dir: &DownloadDir,
+    mut on_download: F,
+) -> Result<Vec<PathBuf>>
+where
+    F: FnMut(&str),
+{
+    let mut downloaded_inputs = Vec::with_capacity(inputs.len());
+    for (index, input) in inputs.into_iter().enumerate() {
+        if !is_url_input(&input) {
+            downloaded_inputs.push(input);
+            continue;
+        }
+        let Some(downloader) = downloader else {
+            return Err(anyhow!(
+                "The input {} is a URL. Use --downloader yt-dlp to download it.",
+                input.display()
+            ));
+        };
+        let url = input.to_string_lossy();
+        on_download(&url);
+        let url_dir = download_dir.path().join(format!("{:03}", index + 1));
+        downloaded_inputs.extend(download_audio(downloader, &url, &url_dir)?);
+    }
+
+    return Ok(downloaded_inputs);
+}
+
+/// Downloads the best available audio of a URL with yt-dlp. Returns the paths of the downloaded files, several
+/// for a playlist. Every file is stored in a numbered sub directory and named after its title, so the titles
+/// become the chapter names, e.g. in the sidecar file of the conversion.
+///
+/// # Arguments
+///
+/// * `downloader` - The name or the path of the yt-dlp executable.
+/// * `url` - The URL of a video, a stream or a playlist.
+/// * `download_dir` - The directory for the downloaded files, which is created if needed.
+pub fn download_audio(downloader: &str, url: &str, download_dir: &Path) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(download_dir)?;
+    let output_template = download_dir
+        .join("%(autonumber)03d")
+        .join("%(title)s.%(ext)s");
+    // The audio is decoded by ffmpeg anyway, so it is downloaded as it is instead of being extracted by yt-dlp
+    let output = Command::new(downloader)
+        .args(["--format", "bestaudio/best", "--no-simulate"])
+        .args(["--print", "after_move:filepath"])
+        .arg("--output")
+        .arg(&output_template)
+        .arg("--")
+        .arg(url)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| {
+            format!(
+                "Failed to run {}. Install yt-dlp or pass its path with --downloader.",
+                downloader
+            )
+        })?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed to download {}: {}",
+            downloader,
+            url,
+            output.status
+        ));
+    }
+
+    let downloaded_files = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    if downloaded_files.is_empty() {
+        return Err(anyhow!(
+            "{} did not download any audio from {}.",
+            downloader,
+            url
+        ));
+    }
+
+    return Ok(downloaded_files);
+}
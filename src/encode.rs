@@ -0,0 +1,590 @@
+//! Opus encoder writing Tonie files, used for every conversion. The toniefile crate always encodes with
+//! 96 kbit/s, so this encoder builds the 4kb aligned Ogg pages on its own: every block holds exactly one page
+//! whose last packet is padded until the page fills the block.
+
+use anyhow::{anyhow, Result};
+use audiopus::{coder::Encoder, ffi, Application, Bitrate, Channels, SampleRate};
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::error::Audio2TonieError;
+use crate::hashing::HashingWriter;
+use crate::ogg_page::{
+    OggPage, HEADER_TYPE_BEGIN_OF_STREAM, HEADER_TYPE_END_OF_STREAM, OGG_MAX_SEGMENT_SIZE,
+    OGG_PAGE_HEADER_SIZE,
+};
+use crate::taf::{
+    encode_header, MAX_AUDIO_LENGTH, MAX_CHAPTERS, TONIEFILE_FRAME_SIZE, TONIEFILE_HEADER_SIZE,
+};
+
+/// The bitrate of the Tonie files of Boxine in kbit/s, used unless another bitrate is requested.
+pub const DEFAULT_BITRATE: u32 = 96;
+
+/// The lowest Opus bitrate in kbit/s the encoder accepts.
+pub const MIN_BITRATE: u32 = 6;
+
+/// The Opus application profile, which tunes the encoder for the kind of audio.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OpusApplication {
+    /// Music and mixed content, the profile of the Tonie files of Boxine.
+    #[default]
+    Audio,
+    /// Speech, which compresses better with the SILK coding mode of Opus. Not every Toniebox firmware plays SILK
+    /// packets, so test the files before converting a library.
+    Voip,
+    /// The lowest encoder delay, which disables the speech optimizations.
+    LowDelay,
+}
+
+impl From<OpusApplication> for Application {
+    fn from(application: OpusApplication) -> Self {
+        return match application {
+            OpusApplication::Audio => Application::Audio,
+            OpusApplication::Voip => Application::Voip,
+            OpusApplication::LowDelay => Application::LowDelay,
+        };
+    }
+}
+
+const OPUS_SAMPLE_RATE: u32 = 48000;
+const OPUS_CHANNELS: usize = 2;
+// Tonie files use 60ms frames
+const OPUS_FRAME_SIZE: usize = OPUS_SAMPLE_RATE as usize * 60 / 1000;
+// Opus packets need some space to encode 60ms of audio at all
+const OPUS_PACKET_MINSIZE: usize = 64;
+const MAX_SEGMENTS: usize = 255;
+
+/// Encodes interleaved stereo 16 bit PCM at 48 kHz into a Tonie file. Mirrors the API of the toniefile encoder,
+/// but takes the Opus bitrate as a parameter.
+pub struct TafEncoder<W: Write + Seek> {
+    // Hashes the audio data following the header
+    writer: HashingWriter<W>,
+    encoder: Encoder,
+    serial_number: u32,
+    audio_id: u32,
+    pre_skip: u64,
+    track_page_nums: Vec<u32>,
+    // Samples of the current frame which are not encoded yet
+    frame: Vec<i16>,
+    // The number of samples per channel passed to `encode`
+    sample_count: u64,
+    granule_position: u64,
+    page_sequence_number: u32,
+    // The packets of the page of the current block
+    packets: Vec<Vec<u8>>,
+    // The page of the current block and its serialization, reused for all pages to avoid allocations
+    page: OggPage,
+    page_buffer: Vec<u8>,
+    blocks_written: u32,
+    // Packets are passed through with `write_packet`, so there is no encoder delay to flush
+    passthrough: bool,
+    // The encoder uses a constant instead of a variable bitrate
+    cbr: bool,
+    max_audio_length: u64,
+}
+
+impl<W: Write + Seek> TafEncoder<W> {
+    /// Creates a new encoder and writes the Opus headers, which fill the first block of the audio data.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The output, e.g. a file. The Tonie header is written at its start on `finalize`.
+    /// * `audio_id` - The audio id of the Tonie file, usually the creation timestamp.
+    /// * `bitrate` - The Opus bitrate in kbit/s.
+    /// * `comments` - User comments of the Opus header, e.g. `TITLE=...`.
+    pub fn new(writer: W, audio_id: u32, bitrate: u32, comments: &[String]) -> Result<Self> {
+        return TafEncoder::with_application(
+            writer,
+            audio_id,
+            bitrate,
+            OpusApplication::Audio,
+            comments,
+        );
+    }
+
+    /// Creates a new encoder with the given Opus application profile, see [`TafEncoder::new`].
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The output, e.g. a file. The Tonie header is written at its start on `finalize`.
+    /// * `audio_id` - The audio id of the Tonie file, usually the creation timestamp.
+    /// * `bitrate` - The Opus bitrate in kbit/s.
+    /// * `application` - The Opus application profile.
+    /// * `comments` - User comments of the Opus header, e.g. `TITLE=...`.
+    pub fn with_application(
+        writer: W,
+        audio_id: u32,
+        bitrate: u32,
+        application: OpusApplication,
+        comments: &[String],
+    ) -> Result<Self> {
+        return TafEncoder::with_settings(writer, audio_id, bitrate, application, false, comments);
+    }
+
+    /// Creates a new encoder with the given Opus application profile and bitrate mode, see [`TafEncoder::new`].
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The output, e.g. a file. The Tonie header is written at its start on `finalize`.
+    /// * `audio_id` - The audio id of the Tonie file, usually the creation timestamp.
+    /// * `bitrate` - The Opus bitrate in kbit/s.
+    /// * `application` - The Opus application profile.
+    /// * `cbr` - Encode every packet with the same size instead of a variable bitrate around `bitrate`.
+    /// * `comments` - User comments of the Opus header, e.g. `TITLE=...`.
+    pub fn with_settings(
+        writer: W,
+        audio_id: u32,
+        bitrate: u32,
+        application: OpusApplication,
+        cbr: bool,
+        comments: &[String],
+    ) -> Result<Self> {
+        let mut encoder = Encoder::new(SampleRate::Hz48000, Channels::Stereo, application.into())?;
+        encoder.set_bitrate(Bitrate::BitsPerSecond(bitrate as i32 * 1000))?;
+        encoder.set_vbr(!cbr)?;
+        encoder.set_encoder_ctl_request(
+            ffi::OPUS_SET_EXPERT_FRAME_DURATION_REQUEST,
+            ffi::OPUS_FRAMESIZE_60_MS,
+        )?;
+        let pre_skip = encoder.lookahead()? as u64;
+
+        let mut taf_encoder =
+            TafEncoder::with_encoder(writer, encoder, audio_id, pre_skip, comments, false)?;
+        taf_encoder.cbr = cbr;
+        return Ok(taf_encoder);
+    }
+
+    /// Creates an encoder for already encoded Opus packets, which are added with `write_packet`.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The output, e.g. a file. The Tonie header is written at its start on `finalize`.
+    /// * `audio_id` - The audio id of the Tonie file, usually the creation timestamp.
+    /// * `pre_skip` - The number of samples to skip at the start, taken from the OpusHead of the input.
+    /// * `comments` - User comments of the Opus header, e.g. `TITLE=...`.
+    pub fn passthrough(
+        writer: W,
+        audio_id: u32,
+        pre_skip: u16,
+        comments: &[String],
+    ) -> Result<Self> {
+        let encoder = Encoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Audio)?;
+        return TafEncoder::with_encoder(
+            writer,
+            encoder,
+            audio_id,
+            pre_skip as u64,
+            comments,
+            true,
+        );
+    }
+
+    fn with_encoder(
+        mut writer: W,
+        encoder: Encoder,
+        audio_id: u32,
+        pre_skip: u64,
+        comments: &[String],
+        passthrough: bool,
+    ) -> Result<Self> {
+        #[rustfmt::skip]
+        let mut opus_head = vec![
+            b'O', b'p', b'u', b's', b'H', b'e', b'a', b'd', // magic
+            0x01,                                           // version
+            OPUS_CHANNELS as u8,                            // channel count
+        ];
+        opus_head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+        opus_head.extend_from_slice(&OPUS_SAMPLE_RATE.to_le_bytes());
+        opus_head.extend_from_slice(&[0x00, 0x00, 0x00]); // output gain and channel map
+
+        let header_pages = opus_header_pages(&opus_head, comments, audio_id)?;
+        // The header is written with the final hash and length when the encoder is finalized
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&[0; TONIEFILE_HEADER_SIZE])?;
+        let mut writer = HashingWriter::new(writer);
+        writer.write_all(&header_pages)?;
+
+        return Ok(TafEncoder {
+            writer,
+            encoder,
+            serial_number: audio_id,
+            audio_id,
+            pre_skip,
+            track_page_nums: vec![0],
+            frame: Vec::with_capacity(OPUS_FRAME_SIZE * OPUS_CHANNELS),
+            sample_count: 0,
+            granule_position: 0,
+            page_sequence_number: 2,
+            packets: vec![],
+            page: OggPage {
+                version: 0,
+                header_type: 0,
+                granule_position: 0,
+                serial_number: audio_id,
+                page_sequence_number: 0,
+                checksum: 0,
+                segment_table: Vec::with_capacity(MAX_SEGMENTS),
+                data: Vec::with_capacity(TONIEFILE_FRAME_SIZE),
+            },
+            page_buffer: Vec::with_capacity(TONIEFILE_FRAME_SIZE),
+            blocks_written: 1,
+            passthrough,
+            cbr: false,
+            max_audio_length: MAX_AUDIO_LENGTH,
+        });
+    }
+
+    /// Whether the encoder uses a constant bitrate, see [`TafEncoder::with_settings`].
+    pub fn cbr(&self) -> bool {
+        return self.cbr;
+    }
+
+    /// Lowers the largest audio data length, e.g. to leave room on a small medium. Writing a block beyond it fails
+    /// with [`Audio2TonieError::DataLengthOverflow`]. Defaults to [`MAX_AUDIO_LENGTH`].
+    ///
+    /// # Arguments
+    ///
+    /// * `max_audio_length` - The largest audio data length in bytes, at most [`MAX_AUDIO_LENGTH`].
+    pub fn set_max_audio_length(&mut self, max_audio_length: u64) {
+        self.max_audio_length = max_audio_length.min(MAX_AUDIO_LENGTH);
+    }
+
+    /// Starts a new chapter. The page of the current block is completed, so the chapter starts at the next
+    /// block. Samples of an incomplete frame are encoded as part of the new chapter.
+    pub fn new_chapter(&mut self) -> Result<()> {
+        if self.track_page_nums.len() >= MAX_CHAPTERS {
+            return Err(Audio2TonieError::TooManyChapters {
+                chapters: self.track_page_nums.len() + 1,
+                max: MAX_CHAPTERS,
+            }
+            .into());
+        }
+
+        self.write_page(false)?;
+        self.track_page_nums.push(self.blocks_written);
+        return Ok(());
+    }
+
+    /// Encodes interleaved stereo samples. Can be called several times, the samples are appended.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Interleaved 16 bit samples in the order left, right, left, right, ...
+    pub fn encode(&mut self, samples: &[i16]) -> Result<()> {
+        self.sample_count += (samples.len() / OPUS_CHANNELS) as u64;
+        return self.push_samples(samples);
+    }
+
+    /// Appends an already encoded Opus packet, e.g. of a stream that is passed through without re-encoding.
+    /// The page of the current block is completed first if the packet does not fit into it anymore.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - A stereo Opus packet.
+    pub fn write_packet(&mut self, packet: &[u8]) -> Result<()> {
+        if !fits_into_block(packet.len()) {
+            return Err(Audio2TonieError::PageTooLarge(packet.len()).into());
+        }
+        // Safety: the pointer and the length describe the packet
+        let samples = unsafe {
+            ffi::opus_packet_get_nb_samples(
+                packet.as_ptr(),
+                packet.len() as i32,
+                OPUS_SAMPLE_RATE as i32,
+            )
+        };
+        if samples < 0 {
+            return Err(anyhow!("Invalid Opus packet: error {}", samples));
+        }
+
+        let segments = self
+            .packets
+            .iter()
+            .map(|packet| lacing_size(packet.len()))
+            .sum::<usize>();
+        if lacing_size(packet.len()) + packet.len() > self.remaining_page_space()
+            || segments + lacing_size(packet.len()) > MAX_SEGMENTS
+        {
+            self.write_page(false)?;
+        }
+
+        self.packets.push(packet.to_vec());
+        self.granule_position += samples as u64;
+        return Ok(());
+    }
+
+    /// Encodes the remaining samples, completes the last page and writes the Tonie header with the hash and
+    /// length of the audio data. Returns the writer.
+    pub fn finalize(mut self) -> Result<W> {
+        if !self.passthrough {
+            // Flush the delay of the encoder with silence, which is trimmed by the granule position of the last page
+            self.push_samples(&vec![0; self.pre_skip as usize * OPUS_CHANNELS])?;
+            if !self.frame.is_empty() {
+                self.frame.resize(OPUS_FRAME_SIZE * OPUS_CHANNELS, 0);
+                self.encode_frame()?;
+            }
+            self.granule_position = self.pre_skip + self.sample_count;
+        }
+        self.write_page(true)?;
+
+        let (mut writer, sha1_hash, audio_length) = self.writer.finish();
+        let header = encode_header(
+            &sha1_hash,
+            audio_length,
+            self.audio_id,
+            &self.track_page_nums,
+        )
+        .ok_or_else(|| anyhow!("Too many chapters for the Tonie header."))?;
+        writer.seek(SeekFrom::Start(0))?;
+        writer.write_all(&header)?;
+        writer.flush()?;
+
+        return Ok(writer);
+    }
+
+    fn push_samples(&mut self, mut samples: &[i16]) -> Result<()> {
+        while !samples.is_empty() {
+            let count = (OPUS_FRAME_SIZE * OPUS_CHANNELS - self.frame.len()).min(samples.len());
+            self.frame.extend_from_slice(&samples[..count]);
+            samples = &samples[count..];
+            if self.frame.len() == OPUS_FRAME_SIZE * OPUS_CHANNELS {
+                self.encode_frame()?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn encode_frame(&mut self) -> Result<()> {
+        let remaining = self.remaining_page_space();
+        let max_packet_length = (0..=remaining)
+            .rev()
+            .find(|length| lacing_size(*length) + length <= remaining)
+            .filter(|length| *length >= OPUS_PACKET_MINSIZE)
+            .ok_or_else(|| anyhow!("Not enough space left in the page for an Opus packet."))?;
+
+        let mut packet = vec![0; max_packet_length];
+        let length = self.encoder.encode(&self.frame, &mut packet)?;
+        packet.truncate(length);
+        self.frame.clear();
+
+        self.granule_position += OPUS_FRAME_SIZE as u64;
+        self.packets.push(packet);
+
+        // Complete the page if the next packet might not fit anymore
+        let remaining = self.remaining_page_space();
+        let segments = self.packets.iter().map(|packet| lacing_size(packet.len()));
+        if remaining < OPUS_PACKET_MINSIZE + 2
+            || segments.sum::<usize>() + remaining / OGG_MAX_SEGMENT_SIZE + 3 > MAX_SEGMENTS
+        {
+            self.write_page(false)?;
+        }
+
+        return Ok(());
+    }
+
+    fn remaining_page_space(&self) -> usize {
+        let used = self
+            .packets
+            .iter()
+            .map(|packet| lacing_size(packet.len()) + packet.len())
+            .sum::<usize>();
+        return TONIEFILE_FRAME_SIZE - OGG_PAGE_HEADER_SIZE - used;
+    }
+
+    // Writes the packets of the current block as one page, padding the last packets to fill the block
+    fn write_page(&mut self, end_of_stream: bool) -> Result<()> {
+        if self.packets.is_empty() {
+            return Ok(());
+        }
+
+        if self.remaining_page_space() > 0 && !self.fill_page()? {
+            // No packet can grow by exactly the remaining space, because its segment table would need one entry
+            // more. Growing another packet by a byte shifts the remaining space.
+            let length = self.packets[0].len() + 1;
+            if lacing_size(length) + length - lacing_size(length - 1) - (length - 1)
+                > self.remaining_page_space()
+            {
+                return Err(anyhow!("Failed to fill the block with the Opus packets."));
+            }
+            pad_packet(&mut self.packets[0], length)?;
+            if !self.fill_page()? {
+                return Err(anyhow!("Failed to fill the block with the Opus packets."));
+            }
+        }
+
+        let page = &mut self.page;
+        page.segment_table.clear();
+        page.segment_table.extend(
+            self.packets
+                .iter()
+                .flat_map(|packet| lacing_values(packet.len())),
+        );
+        if page.segment_table.len() > MAX_SEGMENTS {
+            return Err(anyhow!("Too many Opus packets in one page."));
+        }
+        page.data.clear();
+        for packet in &self.packets {
+            page.data.extend_from_slice(packet);
+        }
+        page.header_type = if end_of_stream {
+            HEADER_TYPE_END_OF_STREAM
+        } else {
+            0
+        };
+        page.granule_position = self.granule_position;
+        page.serial_number = self.serial_number;
+        page.page_sequence_number = self.page_sequence_number;
+
+        self.page_buffer.clear();
+        page.serialize_into(&mut self.page_buffer);
+        debug_assert_eq!(self.page_buffer.len(), TONIEFILE_FRAME_SIZE);
+        if self.writer.bytes_written() + self.page_buffer.len() as u64 > self.max_audio_length {
+            return Err(Audio2TonieError::DataLengthOverflow.into());
+        }
+
+        // The page fills the whole block, so it is written with a single call
+        self.writer.write_all(&self.page_buffer)?;
+        self.page_sequence_number += 1;
+        self.blocks_written += 1;
+        self.packets.clear();
+        return Ok(());
+    }
+
+    // Pads one of the packets, preferably the last one, so the page fills the rest of the block
+    fn fill_page(&mut self) -> Result<bool> {
+        let remaining = self.remaining_page_space();
+        for packet in self.packets.iter_mut().rev() {
+            if let Some(length) = fill_length(packet.len(), remaining) {
+                pad_packet(packet, length)?;
+                return Ok(true);
+            }
+        }
+
+        return Ok(false);
+    }
+}
+
+/// Creates the pages of the OpusHead and OpusTags packets that fill the first 4kb block of the audio data.
+/// The OpusTags packet is padded with a comment of zeros, like in the files of the toniefile encoder.
+///
+/// # Arguments
+///
+/// * `opus_head` - The OpusHead packet.
+/// * `comments` - The user comments of the Opus header.
+/// * `serial_number` - The serial number of the Ogg stream.
+pub(crate) fn opus_header_pages(
+    opus_head: &[u8],
+    comments: &[String],
+    serial_number: u32,
+) -> Result<Vec<u8>> {
+    let mut data =
+        single_packet_page(opus_head, 0, serial_number, HEADER_TYPE_BEGIN_OF_STREAM).serialize();
+
+    let mut opus_tags = opus_tags_packet(comments, true);
+
+    // The page of the OpusTags packet with its segment table has to fill the rest of the block
+    let available = TONIEFILE_FRAME_SIZE
+        .checked_sub(data.len() + OGG_PAGE_HEADER_SIZE)
+        .ok_or_else(|| anyhow!("The Opus header does not fit into the first block."))?;
+    let packet_length = (0..=available)
+        .rev()
+        .find(|length| length + lacing_size(*length) == available)
+        .filter(|length| *length >= opus_tags.len() + 4)
+        .ok_or_else(|| anyhow!("The Opus comments do not fit into the first block."))?;
+    let padding_length = packet_length - opus_tags.len() - 4;
+    opus_tags.extend_from_slice(&(padding_length as u32).to_le_bytes());
+    opus_tags.resize(packet_length, b'0');
+
+    data.extend_from_slice(&single_packet_page(&opus_tags, 1, serial_number, 0).serialize());
+    return Ok(data);
+}
+
+/// Creates an OpusTags packet with the given user comments. Empty comments and the zero padding of Tonie files
+/// are left out.
+///
+/// # Arguments
+///
+/// * `comments` - The user comments, e.g. `TITLE=...`.
+/// * `padded` - Count one more comment for the padding the caller appends to the packet.
+pub(crate) fn opus_tags_packet(comments: &[String], padded: bool) -> Vec<u8> {
+    let vendor = "audio2tonie";
+    let comments = comments
+        .iter()
+        .filter(|comment| !comment.is_empty() && !comment.bytes().all(|byte| byte == b'0'))
+        .collect::<Vec<_>>();
+    let mut opus_tags = b"OpusTags".to_vec();
+    opus_tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    opus_tags.extend_from_slice(vendor.as_bytes());
+    opus_tags.extend_from_slice(&(comments.len() as u32 + padded as u32).to_le_bytes());
+    for comment in comments {
+        opus_tags.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        opus_tags.extend_from_slice(comment.as_bytes());
+    }
+    return opus_tags;
+}
+
+// The segment table entries of a packet: full segments of 255 bytes followed by the remainder, which is 0 when
+// the packet size is a multiple of 255
+fn lacing_values(packet_len: usize) -> impl Iterator<Item = u8> {
+    return std::iter::repeat_n(
+        OGG_MAX_SEGMENT_SIZE as u8,
+        packet_len / OGG_MAX_SEGMENT_SIZE,
+    )
+    .chain(std::iter::once((packet_len % OGG_MAX_SEGMENT_SIZE) as u8));
+}
+
+pub(crate) fn single_packet_page(
+    packet: &[u8],
+    page_sequence_number: u32,
+    serial_number: u32,
+    header_type: u8,
+) -> OggPage {
+    let segment_table = lacing_values(packet.len()).collect();
+
+    return OggPage {
+        version: 0,
+        header_type,
+        granule_position: 0,
+        serial_number,
+        page_sequence_number,
+        checksum: 0,
+        segment_table,
+        data: packet.to_vec(),
+    };
+}
+
+/// Whether a packet fits into the page of a single block, which is required to pass it through.
+///
+/// # Arguments
+///
+/// * `packet_length` - The length of the packet in bytes.
+pub(crate) fn fits_into_block(packet_length: usize) -> bool {
+    return OGG_PAGE_HEADER_SIZE + lacing_size(packet_length) + packet_length
+        <= TONIEFILE_FRAME_SIZE;
+}
+
+// The number of segment table entries of a packet
+fn lacing_size(packet_length: usize) -> usize {
+    return packet_length / OGG_MAX_SEGMENT_SIZE + 1;
+}
+
+// The length a packet has to be padded to, so it grows by `space` bytes including its segment table
+fn fill_length(packet_length: usize, space: usize) -> Option<usize> {
+    let target = lacing_size(packet_length) + packet_length + space;
+    return (packet_length..=packet_length + space)
+        .rev()
+        .find(|length| lacing_size(*length) + length == target);
+}
+
+fn pad_packet(packet: &mut Vec<u8>, length: usize) -> Result<()> {
+    let packet_length = packet.len();
+    packet.resize(length, 0);
+    // Safety: the buffer holds `length` bytes, of which the first `packet_length` bytes are the packet
+    let result =
+        unsafe { ffi::opus_packet_pad(packet.as_mut_ptr(), packet_length as i32, length as i32) };
+    if result != ffi::OPUS_OK {
+        return Err(anyhow!("Failed to pad the Opus packet: error {}", result));
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,210 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use crate::limits::LimitError;
+use crate::ogg_page::OggPageError;
+use crate::taf::MAX_AUDIO_LENGTH;
+
+/// Errors returned by the conversion and extraction functions, so applications embedding the library can react
+/// to the kind of a failure. Failures without a variant of their own are reported as [`Audio2TonieError::Other`].
+#[derive(Debug)]
+pub enum Audio2TonieError {
+    /// Reading or writing a file failed, e.g. because it does not exist.
+    Io(std::io::Error),
+    /// ffmpeg exited with an error. Contains the exit status and the last lines ffmpeg printed.
+    FfmpegFailed {
+        status: String,
+        stderr: String,
+    },
+    /// The file does not start with an OpusHead packet, so it is not an Ogg Opus stream.
+    InvalidOpusHead(PathBuf),
+    /// The protobuf header of a Tonie file is malformed or does not match the audio data.
+    HeaderCorrupt(String),
+    /// The audio data does not match the SHA1 hash in the header. Contains the offset of the first page with an
+    /// invalid checksum, if there is one.
+    HashMismatch(Option<usize>),
+    /// The OpusHead of a Tonie file states another input sample rate than the 48 kHz of the Toniebox. Contains the
+    /// sample rate in Hz.
+    UnsupportedSampleRate(u32),
+    /// An Opus packet does not fit into a 4kb block. Contains the size of the packet.
+    PageTooLarge(usize),
+    /// An Ogg page is malformed.
+    InvalidPage(OggPageError),
+    /// The input exceeds one of the configured [`crate::Limits`].
+    LimitExceeded(LimitError),
+    /// The inputs result in more chapters than the Toniebox supports.
+    TooManyChapters {
+        chapters: usize,
+        max: usize,
+    },
+    /// The audio data exceeds [`crate::taf::MAX_AUDIO_LENGTH`].
+    DataLengthOverflow,
+    /// The estimated or the encoded size of a Tonie file exceeds the requested maximum, see
+    /// [`crate::convert::preflight_check`] and [`crate::convert::convert_files_to_size`].
+    SizeLimitExceeded {
+        output: PathBuf,
+        estimated: u64,
+        max: u64,
+    },
+    /// The estimated size of the Tonie files exceeds the free space at the output location.
+    InsufficientSpace {
+        required: u64,
+        available: u64,
+    },
+    /// The output file exists and must not be overwritten.
+    OutputExists(PathBuf),
+    /// An input file failed to decode, see [`crate::convert::TrackFailure`].
+    TrackFailed {
+        input_file: PathBuf,
+        error: Box<Audio2TonieError>,
+    },
+    /// The skipped input files were the only ones with audio, so the Tonie file would be empty, see
+    /// [`crate::convert::TrackFailure::Skip`].
+    NoAudio,
+    /// The conversion was cancelled with a [`crate::cancel::Cancellation`].
+    Cancelled,
+    Other(anyhow::Error),
+}
+
+impl Display for Audio2TonieError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Audio2TonieError::Io(error) => error.fmt(f),
+            Audio2TonieError::FfmpegFailed { status, stderr } => match stderr.trim() {
+                "" => write!(f, "ffmpeg failed: {}", status),
+                stderr => write!(f, "ffmpeg failed: {}\n{}", status, stderr),
+            },
+            Audio2TonieError::InvalidOpusHead(path) => {
+                write!(f, "{} is not an Ogg Opus stream.", path.display())
+            }
+            Audio2TonieError::HeaderCorrupt(reason) => {
+                write!(f, "The Tonie header is corrupt: {}", reason)
+            }
+            Audio2TonieError::HashMismatch(Some(offset)) => write!(
+                f,
+                "The audio data does not match the SHA1 hash in the header. The first corrupt page is at offset {:#x}.",
+                offset
+            ),
+            Audio2TonieError::HashMismatch(None) => write!(
+                f,
+                "The audio data does not match the SHA1 hash in the header, although all pages have valid checksums. The header might be corrupt."
+            ),
+            Audio2TonieError::UnsupportedSampleRate(sample_rate) => write!(
+                f,
+                "The Tonie file has a sample rate of {} Hz, but the Toniebox only plays 48000 Hz.",
+                sample_rate
+            ),
+            Audio2TonieError::PageTooLarge(size) => {
+                write!(f, "The Opus packet of {} bytes does not fit into a 4kb block.", size)
+            }
+            Audio2TonieError::InvalidPage(error) => error.fmt(f),
+            Audio2TonieError::LimitExceeded(error) => error.fmt(f),
+            Audio2TonieError::TooManyChapters { chapters, max } => write!(
+                f,
+                "The input files result in {} chapters, but the Toniebox supports at most {}.",
+                chapters, max
+            ),
+            Audio2TonieError::DataLengthOverflow => write!(
+                f,
+                "The audio data exceeds the data length of {} bytes a Tonie file supports. Split the input files into several Tonie files, e.g. with --split-on-overflow.",
+                MAX_AUDIO_LENGTH
+            ),
+            Audio2TonieError::SizeLimitExceeded {
+                output,
+                estimated,
+                max,
+            } => write!(
+                f,
+                "{} would be about {:.1} MB, but at most {:.1} MB are allowed.",
+                output.display(),
+                *estimated as f64 / 1_000_000.0,
+                *max as f64 / 1_000_000.0
+            ),
+            Audio2TonieError::InsufficientSpace {
+                required,
+                available,
+            } => write!(
+                f,
+                "The Tonie files need about {:.1} MB, but only {:.1} MB are free at the output location.",
+                *required as f64 / 1_000_000.0,
+                *available as f64 / 1_000_000.0
+            ),
+            Audio2TonieError::OutputExists(path) => {
+                write!(f, "The output file {} already exists.", path.display())
+            }
+            Audio2TonieError::TrackFailed { input_file, error } => {
+                write!(f, "Failed to convert {}: {}", input_file.display(), error)
+            }
+            Audio2TonieError::NoAudio => {
+                write!(f, "None of the input files contain any audio that could be decoded.")
+            }
+            Audio2TonieError::Cancelled => write!(f, "The conversion was cancelled."),
+            Audio2TonieError::Other(error) => error.fmt(f),
+        }
+    }
+}
+
+// The wrapped errors are the sources, so error reporters can walk the chain down to e.g. the I/O error
+impl std::error::Error for Audio2TonieError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            Audio2TonieError::Io(error) => Some(error),
+            Audio2TonieError::InvalidPage(error) => Some(error),
+            Audio2TonieError::LimitExceeded(error) => Some(error),
+            Audio2TonieError::TrackFailed { error, .. } => Some(error.as_ref()),
+            Audio2TonieError::Other(error) => Some(error.as_ref()),
+            _ => None,
+        };
+    }
+}
+
+impl From<std::io::Error> for Audio2TonieError {
+    fn from(error: std::io::Error) -> Self {
+        // Page readers report malformed pages as I/O errors
+        let page_error = error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<OggPageError>())
+            .cloned();
+        return match page_error {
+            Some(page_error) => Audio2TonieError::from(page_error),
+            None => Audio2TonieError::Io(error),
+        };
+    }
+}
+
+impl From<OggPageError> for Audio2TonieError {
+    fn from(error: OggPageError) -> Self {
+        return match error {
+            OggPageError::LimitExceeded(error) => Audio2TonieError::LimitExceeded(error),
+            error => Audio2TonieError::InvalidPage(error),
+        };
+    }
+}
+
+impl From<LimitError> for Audio2TonieError {
+    fn from(error: LimitError) -> Self {
+        return Audio2TonieError::LimitExceeded(error);
+    }
+}
+
+impl From<anyhow::Error> for Audio2TonieError {
+    /// Recovers the typed error of the failure, which the internal functions pass on as [`anyhow::Error`].
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<Audio2TonieError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<std::io::Error>() {
+            Ok(error) => return Audio2TonieError::from(error),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<OggPageError>() {
+            Ok(error) => return Audio2TonieError::from(error),
+            Err(error) => error,
+        };
+        return match error.downcast::<LimitError>() {
+            Ok(error) => Audio2TonieError::LimitExceeded(error),
+            Err(error) => Audio2TonieError::Other(error),
+        };
+    }
+}
@@ -1,76 +1,845 @@
+use crate::decode::decode_tonie_reader;
+use crate::encode::{opus_tags_packet, single_packet_page};
+use crate::error::Audio2TonieError;
+use crate::hooks::SidecarFile;
+use crate::input::InputFile;
+use crate::limits::Limits;
+use crate::ogg_page::{OggPage, OGG_MAX_SEGMENT_SIZE};
+use crate::taf::{
+    audio_offset, find_capture_pattern, opus_comments, OggPageReader, TONIEFILE_FRAME_SIZE,
+    TONIEFILE_HEADER_SIZE,
+};
 use anyhow::{anyhow, Result};
-use std::{ffi::OsStr, fs::File, io::Write, path::PathBuf};
-use toniefile::Toniefile;
+use sha1::{Digest, Sha1};
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process::{Child, Stdio},
+};
+use toniefile::{Toniefile, ToniefileError};
 
-const TONIEFILE_FRAME_SIZE: usize = 4096;
+use crate::throttle::ThrottledIo;
+use crate::utils::{check_input_limits, limit_path_length, sanitize_file_name, tool_command};
 
+/// The audio format of extracted chapters.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// The Ogg Opus stream of the Tonie file as is.
+    #[default]
+    Ogg,
+    Mp3,
+    Flac,
+    Wav,
+}
+
+impl OutputFormat {
+    /// The file extension of the format, which also selects the encoder of ffmpeg.
+    pub fn extension(&self) -> &'static str {
+        return match self {
+            OutputFormat::Ogg => "ogg",
+            OutputFormat::Mp3 => "mp3",
+            OutputFormat::Flac => "flac",
+            OutputFormat::Wav => "wav",
+        };
+    }
+}
+
+/// Options controlling how the audio content of a Tonie file is extracted.
+#[derive(Clone, Debug)]
+pub struct ExtractOptions {
+    /// Caps for the input size, header size and number of pages.
+    pub limits: Limits,
+    /// Replace non-ASCII characters in derived output file names with ASCII equivalents.
+    pub transliterate: bool,
+    /// Limits reading the Tonie file and writing the extracted audio to the given number of bytes per second.
+    pub io_throttle: Option<u64>,
+    /// The audio format of the extracted files. All formats except Ogg are decoded and transcoded with ffmpeg.
+    pub format: OutputFormat,
+    /// The path to the ffmpeg executable.
+    pub ffmpeg: String,
+    /// The file name of every chapter, e.g. `{number} - {title}`. See [`chapter_file_name`] for the placeholders.
+    /// `None` uses `{number} - {title}` if the chapter titles are known and `<index>_<file name>` otherwise.
+    pub name_template: Option<String>,
+    /// Fail if the audio data does not match the SHA1 hash in the header, e.g. after a corrupt read from an SD card.
+    pub verify: bool,
+    /// Extract all chapters into a single Ogg file and mark the chapters with `CHAPTER001=` comments.
+    pub merge_chapters: bool,
+    /// The indices of the chapters to extract, starting at 0. `None` extracts all chapters.
+    pub chapters: Option<Vec<usize>>,
+    /// Skip the protobuf header and recover all valid Ogg pages following the first `OggS` capture pattern into a
+    /// single Ogg file, e.g. when the header is destroyed. The audio cannot be verified or split into chapters.
+    pub ignore_header: bool,
+    /// The titles of the chapters, e.g. the track titles of `tonies.json`, used instead of the titles read from
+    /// the Tonie file. Ignored unless there is a title for every chapter.
+    pub chapter_titles: Option<Vec<String>>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        ExtractOptions {
+            limits: Limits::default(),
+            transliterate: false,
+            io_throttle: None,
+            format: OutputFormat::Ogg,
+            ffmpeg: String::from("ffmpeg"),
+            name_template: None,
+            verify: true,
+            merge_chapters: false,
+            chapters: None,
+            ignore_header: false,
+            chapter_titles: None,
+        }
+    }
+}
+
+/// Extracts the audio content of a Tonie file into Ogg Opus files, one per chapter. Other output formats are
+/// transcoded from the decoded audio with ffmpeg. Returns the paths of the written files in chapter order.
+/// Resource limits are enforced on the untrusted input before parsing it. The audio data is streamed block by
+/// block into the files and checked against the SHA1 hash in the header unless verification is disabled. On a
+/// mismatch the written files are removed again. With merged chapters a single Ogg file is written instead.
+/// With a chapter selection only the files of the selected chapters are written, the whole audio data is still
+/// verified.
+/// With an ignored header the valid Ogg pages are recovered into a single Ogg file without verification.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `output_file_path` - The output file or directory. Defaults to the current directory.
+/// * `options` - Options controlling the extraction.
 pub fn extract_tonie_to_opus(
     input_file_path: &PathBuf,
     output_file_path: Option<PathBuf>,
-) -> Result<()> {
-    let mut tonie_file = File::open(input_file_path)?;
-    let tonie_header = Toniefile::parse_header(&mut tonie_file)?;
-    let audio_data = Toniefile::extract_audio(&mut tonie_file)?;
+    options: &ExtractOptions,
+) -> Result<Vec<PathBuf>, Audio2TonieError> {
+    let mut tonie_file = ThrottledIo::new(InputFile::open(input_file_path)?, options.io_throttle);
+    // Output file names derived from the input must be valid on all platforms, e.g. when writing to SMB shares
+    let default_file_name = sanitize_file_name(
+        &input_file_path
+            .with_extension(options.format.extension())
+            .file_name()
+            .expect("Input file path must have a file name")
+            .to_string_lossy(),
+        options.transliterate,
+    );
 
     let output_file_path = output_file_path
         .map(|path| {
             if path.is_file() {
                 path
             } else {
-                path.join(
-                    input_file_path
-                        .with_extension("ogg")
-                        .file_name()
-                        .expect("Input file path must have a file name"),
-                )
+                limit_path_length(&path.join(&default_file_name))
             }
         })
         .unwrap_or_else(|| {
-            std::env::current_dir()
-                .expect("Failed to get current directory")
-                .join(
-                    input_file_path
-                        .with_extension("ogg")
-                        .file_name()
-                        .expect("Input file path must have a file name"),
-                )
+            limit_path_length(
+                &std::env::current_dir()
+                    .expect("Failed to get current directory")
+                    .join(&default_file_name),
+            )
         });
 
-    return match tonie_header.track_page_nums.len() {
-        1 => {
-            let mut audio_file = File::create(output_file_path)?;
-            audio_file.write_all(&audio_data)?;
+    return extract_tonie(
+        &mut tonie_file,
+        Some(input_file_path),
+        output_file_path,
+        options,
+    );
+}
 
-            return Ok(());
+/// Extracts the audio content of a Tonie file from any seekable reader, e.g. a download kept in memory, see
+/// [`extract_tonie_to_opus`]. Returns the paths of the written files in chapter order. Without the path of the
+/// Tonie file the chapter titles are only taken from the Opus header.
+///
+/// # Arguments
+///
+/// * `tonie_file` - The Tonie file.
+/// * `output_file_path` - The output file, e.g. `story.ogg`. The files of several chapters are named after it.
+/// * `options` - Options controlling the extraction.
+pub fn extract_tonie_from_reader<R: Read + Seek>(
+    tonie_file: R,
+    output_file_path: &Path,
+    options: &ExtractOptions,
+) -> Result<Vec<PathBuf>, Audio2TonieError> {
+    let mut tonie_file = ThrottledIo::new(tonie_file, options.io_throttle);
+    return extract_tonie(
+        &mut tonie_file,
+        None,
+        output_file_path.to_path_buf(),
+        options,
+    );
+}
+
+/// Writes the audio content of a Tonie file as a single Ogg stream with all chapters to any writer, e.g. stdout.
+/// The chapters are marked like with [`ExtractOptions::merge_chapters`], their titles are only taken from the Opus
+/// header. As the audio data is written while it is verified, a hash mismatch is only reported afterwards.
+///
+/// # Arguments
+///
+/// * `tonie_file` - The Tonie file.
+/// * `writer` - The output of the Ogg stream.
+/// * `options` - Options controlling the extraction. Only the Ogg format without a chapter selection is supported.
+pub fn extract_tonie_to_writer<R: Read + Seek, W: Write>(
+    tonie_file: R,
+    writer: W,
+    options: &ExtractOptions,
+) -> Result<(), Audio2TonieError> {
+    if options.format != OutputFormat::Ogg || options.chapters.is_some() || options.ignore_header {
+        return Err(anyhow!(
+            "Only the Ogg stream of all chapters can be written to a stream, without transcoding, a chapter selection or an ignored header."
+        )
+        .into());
+    }
+
+    let mut tonie_file = ThrottledIo::new(tonie_file, options.io_throttle);
+    check_input_limits(&mut tonie_file, &options.limits)?;
+    let tonie_header = Toniefile::parse_header(&mut tonie_file).map_err(header_corrupt)?;
+    let (audio_offset, audio_size) = seek_audio_data(&mut tonie_file)?;
+    options
+        .limits
+        .check_pages(audio_size as usize / TONIEFILE_FRAME_SIZE)?;
+    if tonie_header.track_page_nums.is_empty() {
+        return Err(Audio2TonieError::HeaderCorrupt(String::from(
+            "it does not contain any chapters.",
+        )));
+    }
+
+    let mut writer = ThrottledIo::new(writer, options.io_throttle);
+    extract_merged_chapters(
+        None,
+        &mut tonie_file,
+        audio_offset,
+        &tonie_header.sha1_hash,
+        &tonie_header.track_page_nums,
+        &mut writer,
+        options,
+    )?;
+    return Ok(());
+}
+
+fn extract_tonie<R: Read + Seek>(
+    tonie_file: &mut R,
+    input_file_path: Option<&Path>,
+    output_file_path: PathBuf,
+    options: &ExtractOptions,
+) -> Result<Vec<PathBuf>, Audio2TonieError> {
+    if options.ignore_header {
+        recover_ogg_stream(tonie_file, &output_file_path, options)?;
+        return Ok(vec![output_file_path]);
+    }
+
+    check_input_limits(tonie_file, &options.limits)?;
+    let tonie_header = Toniefile::parse_header(tonie_file).map_err(header_corrupt)?;
+    let (audio_offset, audio_size) = seek_audio_data(tonie_file)?;
+    options
+        .limits
+        .check_pages(audio_size as usize / TONIEFILE_FRAME_SIZE)?;
+
+    let chapter_count = tonie_header.track_page_nums.len();
+    if chapter_count == 0 {
+        return Err(Audio2TonieError::HeaderCorrupt(String::from(
+            "it does not contain any chapters.",
+        )));
+    }
+    if let Some(chapter) = options
+        .chapters
+        .iter()
+        .flatten()
+        .find(|chapter| **chapter >= chapter_count)
+    {
+        return Err(anyhow!(
+            "Chapter {} does not exist, the Tonie file has {} chapters.",
+            chapter + 1,
+            chapter_count
+        )
+        .into());
+    }
+    if options.merge_chapters {
+        if options.format != OutputFormat::Ogg {
+            return Err(anyhow!("Merged chapters can only be extracted into an Ogg file.").into());
+        }
+        if options.chapters.is_some() {
+            return Err(anyhow!(
+                "Merged chapters always contain all chapters, they cannot be combined with a chapter selection."
+            )
+            .into());
+        }
+
+        let mut audio_file =
+            ThrottledIo::new(File::create(&output_file_path)?, options.io_throttle);
+        let result = extract_merged_chapters(
+            input_file_path,
+            tonie_file,
+            audio_offset,
+            &tonie_header.sha1_hash,
+            &tonie_header.track_page_nums,
+            &mut audio_file,
+            options,
+        );
+        if result.is_err() {
+            drop(audio_file);
+            let _ = std::fs::remove_file(&output_file_path);
         }
-        x if x > 1 => {
-            // Split Toniefile per chapter into separate audio files
-            let mut page_start: usize = 0;
-            let mut page_offsets = tonie_header.track_page_nums;
+        result?;
+        return Ok(vec![output_file_path]);
+    }
+    let chapter_file_paths = if chapter_count > 1 {
+        // The Opus headers with the chapter names fill the first block of the audio data
+        let mut first_block = vec![];
+        (&mut *tonie_file)
+            .take(TONIEFILE_FRAME_SIZE as u64)
+            .read_to_end(&mut first_block)?;
+        tonie_file.seek(SeekFrom::Start(audio_offset))?;
 
-            // Add final page offset, i.e. end of file
-            page_offsets.push((audio_data.len() / TONIEFILE_FRAME_SIZE) as u32);
+        let titles = chapter_titles(input_file_path, &first_block, chapter_count, options);
+        (0..chapter_count)
+            .map(|chapter| {
+                limit_path_length(&output_file_path.with_file_name(chapter_file_name(
+                    &output_file_path,
+                    chapter,
+                    titles.get(chapter).map(String::as_str),
+                    options,
+                )))
+            })
+            .collect::<Vec<_>>()
+    } else {
+        vec![output_file_path.clone()]
+    };
 
-            for (i, page_offset) in page_offsets.into_iter().skip(1).enumerate() {
-                let enumerated_output_file_path = output_file_path.with_file_name(format!(
-                    "{}_{}",
-                    i,
-                    output_file_path
-                        .file_name()
-                        .and_then(OsStr::to_str)
-                        .expect("Expected to have a file name for output path."),
-                ));
+    if options.format != OutputFormat::Ogg {
+        if options.verify {
+            verify_tonie_data(tonie_file, &options.limits)?;
+        }
+        transcode_chapters(tonie_file, &chapter_file_paths, options)?;
+        return Ok(selected_chapter_files(chapter_file_paths, options));
+    }
 
-                let page_end = page_offset as usize * TONIEFILE_FRAME_SIZE;
+    // The chapters end at the first block of the next chapter, the last one at the end of the audio data
+    let mut chapter_ends = tonie_header.track_page_nums[1..]
+        .iter()
+        .map(|page_num| *page_num as u64 * TONIEFILE_FRAME_SIZE as u64)
+        .collect::<Vec<_>>();
+    chapter_ends.push(audio_size);
 
-                let mut audio_file = File::create(enumerated_output_file_path)?;
-                audio_file.write_all(&audio_data[page_start..page_end])?;
+    // Every chapter is copied block by block into its file while the audio data is hashed
+    let mut hasher = Sha1::new();
+    let mut block = vec![0u8; TONIEFILE_FRAME_SIZE];
+    let mut chapter_start = 0;
+    for (chapter, chapter_end) in chapter_ends.into_iter().enumerate() {
+        if chapter_end < chapter_start || chapter_end > audio_size {
+            return Err(Audio2TonieError::HeaderCorrupt(format!(
+                "chapter {} points outside of the audio data.",
+                chapter
+            )));
+        }
 
-                page_start = page_end;
+        // The chapters which are not extracted are still read for the hash
+        let mut audio_file = match is_selected(chapter, options) {
+            true => Some(ThrottledIo::new(
+                File::create(&chapter_file_paths[chapter])?,
+                options.io_throttle,
+            )),
+            false => None,
+        };
+        let mut remaining = chapter_end - chapter_start;
+        while remaining > 0 {
+            let block_size = remaining.min(TONIEFILE_FRAME_SIZE as u64) as usize;
+            tonie_file.read_exact(&mut block[..block_size])?;
+            hasher.update(&block[..block_size]);
+            if let Some(audio_file) = audio_file.as_mut() {
+                audio_file.write_all(&block[..block_size])?;
             }
+            remaining -= block_size as u64;
+        }
+        if let Some(mut audio_file) = audio_file {
+            audio_file.flush()?;
+        }
 
-            return Ok(());
+        chapter_start = chapter_end;
+    }
+
+    if options.verify && hasher.finalize().as_slice() != tonie_header.sha1_hash.as_slice() {
+        for chapter_file_path in &chapter_file_paths {
+            let _ = std::fs::remove_file(chapter_file_path);
+        }
+        tonie_file.seek(SeekFrom::Start(audio_offset))?;
+        return Err(hash_mismatch_error(
+            BufReader::new(tonie_file),
+            &options.limits,
+        ));
+    }
+
+    return Ok(selected_chapter_files(chapter_file_paths, options));
+}
+
+// Copies all valid Ogg pages following the first capture pattern into the output file. Pages which cannot be
+// parsed or have an invalid checksum are skipped by searching for the next capture pattern.
+fn recover_ogg_stream<R: Read + Seek>(
+    tonie_file: &mut R,
+    output_file_path: &Path,
+    options: &ExtractOptions,
+) -> Result<()> {
+    if options.format != OutputFormat::Ogg {
+        return Err(anyhow!(
+            "Audio without a header can only be recovered into an Ogg file."
+        ));
+    }
+    if options.merge_chapters || options.chapters.is_some() {
+        return Err(anyhow!(
+            "The chapters are stored in the header, they cannot be selected when the header is ignored."
+        ));
+    }
+
+    let total_bytes = tonie_file.seek(SeekFrom::End(0))?;
+    options.limits.check_total_bytes(total_bytes)?;
+    let mut tonie_data = vec![];
+    tonie_file.rewind()?;
+    tonie_file.read_to_end(&mut tonie_data)?;
+
+    let mut pages = vec![];
+    let mut position = 0;
+    while let Some(page_offset) = find_capture_pattern(&tonie_data[position..]) {
+        let page_start = position + page_offset;
+        match OggPage::parse(&tonie_data[page_start..]) {
+            Ok((page, page_size)) if page.is_checksum_valid() => {
+                pages.push(page_start..page_start + page_size);
+                options.limits.check_pages(pages.len())?;
+                position = page_start + page_size;
+            }
+            _ => position = page_start + 1,
+        }
+    }
+    if pages.is_empty() {
+        return Err(anyhow!("The file does not contain a valid Ogg page."));
+    }
+
+    let mut audio_file = ThrottledIo::new(File::create(output_file_path)?, options.io_throttle);
+    for page in pages {
+        audio_file.write_all(&tonie_data[page])?;
+    }
+    audio_file.flush()?;
+    return Ok(());
+}
+
+fn is_selected(chapter: usize, options: &ExtractOptions) -> bool {
+    return options
+        .chapters
+        .as_ref()
+        .is_none_or(|chapters| chapters.contains(&chapter));
+}
+
+fn selected_chapter_files(
+    chapter_file_paths: Vec<PathBuf>,
+    options: &ExtractOptions,
+) -> Vec<PathBuf> {
+    return chapter_file_paths
+        .into_iter()
+        .enumerate()
+        .filter(|(chapter, _)| is_selected(*chapter, options))
+        .map(|(_, chapter_file_path)| chapter_file_path)
+        .collect();
+}
+
+// Copies the audio data into a single Ogg file. Only the OpusTags page is replaced to add the chapter marks of the
+// Vorbis comment chapter extension, e.g. `CHAPTER002=00:03:28.030` and `CHAPTER002NAME=...`
+fn extract_merged_chapters<R: Read + Seek, W: Write>(
+    input_file_path: Option<&Path>,
+    tonie_file: &mut R,
+    audio_offset: u64,
+    sha1_hash: &[u8],
+    track_page_nums: &[u32],
+    audio_file: &mut W,
+    options: &ExtractOptions,
+) -> Result<()> {
+    let chapter_starts = chapter_start_times(
+        BufReader::new(&mut *tonie_file),
+        track_page_nums,
+        &options.limits,
+    )?;
+    tonie_file.seek(SeekFrom::Start(audio_offset))?;
+    let mut first_block = vec![];
+    (&mut *tonie_file)
+        .take(TONIEFILE_FRAME_SIZE as u64)
+        .read_to_end(&mut first_block)?;
+    let titles = chapter_titles(
+        input_file_path,
+        &first_block,
+        track_page_nums.len(),
+        options,
+    );
+
+    // Existing chapter marks are replaced
+    let mut comments = opus_comments(&first_block)
+        .into_iter()
+        .filter(|comment| !comment.to_ascii_uppercase().starts_with("CHAPTER"))
+        .collect::<Vec<_>>();
+    for (index, start) in chapter_starts.iter().enumerate() {
+        comments.push(format!(
+            "CHAPTER{:03}={}",
+            index + 1,
+            format_chapter_time(*start)
+        ));
+        if let Some(title) = titles.get(index) {
+            comments.push(format!("CHAPTER{:03}NAME={}", index + 1, title));
+        }
+    }
+
+    // The OpusHead and OpusTags packets occupy a page each
+    let (_, opus_head_size) = OggPage::parse(&first_block)?;
+    let (tags_page, tags_size) = OggPage::parse(&first_block[opus_head_size..])?;
+    let opus_tags = opus_tags_packet(&comments, false);
+    if opus_tags.len() >= OGG_MAX_SEGMENT_SIZE * OGG_MAX_SEGMENT_SIZE {
+        return Err(anyhow!(
+            "The chapter marks do not fit into the Opus header."
+        ));
+    }
+    let tags_page = single_packet_page(
+        &opus_tags,
+        tags_page.page_sequence_number,
+        tags_page.serial_number,
+        tags_page.header_type,
+    );
+
+    audio_file.write_all(&first_block[..opus_head_size])?;
+    audio_file.write_all(&tags_page.serialize())?;
+    audio_file.write_all(&first_block[opus_head_size + tags_size..])?;
+
+    let mut hasher = Sha1::new_with_prefix(&first_block);
+    let mut block = vec![0u8; TONIEFILE_FRAME_SIZE];
+    loop {
+        let block_size = tonie_file.read(&mut block)?;
+        if block_size == 0 {
+            break;
+        }
+        hasher.update(&block[..block_size]);
+        audio_file.write_all(&block[..block_size])?;
+    }
+    audio_file.flush()?;
+
+    if options.verify && hasher.finalize().as_slice() != sha1_hash {
+        tonie_file.seek(SeekFrom::Start(audio_offset))?;
+        return Err(hash_mismatch_error(BufReader::new(tonie_file), &options.limits).into());
+    }
+
+    return Ok(());
+}
+
+// The start time of every chapter in seconds, which is the last granule position before its first block
+fn chapter_start_times<R: Read>(
+    audio_data: R,
+    track_page_nums: &[u32],
+    limits: &Limits,
+) -> Result<Vec<f64>> {
+    let mut start_granules = vec![0u64; track_page_nums.len()];
+    let mut pre_skip = 0;
+    for page in OggPageReader::with_limits(audio_data, *limits) {
+        let (page_offset, page) = page?;
+        if page.is_begin_of_stream() && page.data.starts_with(b"OpusHead") && page.data.len() >= 12
+        {
+            pre_skip = u16::from_le_bytes([page.data[10], page.data[11]]) as u64;
         }
-        _ => Err(anyhow!("Something went wrong extracting the Tonie file.")),
+
+        let block = (page_offset / TONIEFILE_FRAME_SIZE) as u32;
+        for (start_granule, page_num) in start_granules.iter_mut().zip(track_page_nums) {
+            if block < *page_num {
+                *start_granule = (*start_granule).max(page.granule_position);
+            }
+        }
+    }
+
+    return Ok(start_granules
+        .into_iter()
+        .map(|granule| granule.saturating_sub(pre_skip) as f64 / 48000.0)
+        .collect());
+}
+
+// Formats a chapter start like the Vorbis comment chapter extension, e.g. `00:03:28.030`
+fn format_chapter_time(seconds: f64) -> String {
+    let milliseconds = (seconds * 1000.0).round() as u64;
+    return format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        milliseconds / 3_600_000,
+        (milliseconds / 60_000) % 60,
+        (milliseconds / 1000) % 60,
+        milliseconds % 1000
+    );
+}
+
+/// Checks the audio data of a Tonie file against the SHA1 hash in its header. On a mismatch the error reports the
+/// file offset of the first page with an invalid checksum, which is usually where a corrupt read starts.
+///
+/// # Arguments
+///
+/// * `sha1_hash` - The SHA1 hash stored in the header.
+/// * `audio_data` - The audio data following the header.
+/// * `limits` - Caps for the number of pages searched for the first corrupt page.
+pub fn verify_audio_data(sha1_hash: &[u8], audio_data: &[u8], limits: &Limits) -> Result<()> {
+    if Sha1::digest(audio_data).as_slice() == sha1_hash {
+        return Ok(());
+    }
+
+    return Err(hash_mismatch_error(audio_data, limits).into());
+}
+
+/// Checks the audio data of a Tonie file against the SHA1 hash in its header, see [`verify_audio_data`].
+/// The file is read block by block.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+pub fn verify_tonie_file(input_file_path: &Path, limits: &Limits) -> Result<(), Audio2TonieError> {
+    return verify_tonie_data(&mut InputFile::open(input_file_path)?, limits);
+}
+
+fn verify_tonie_data<R: Read + Seek>(
+    tonie_file: &mut R,
+    limits: &Limits,
+) -> Result<(), Audio2TonieError> {
+    check_input_limits(tonie_file, limits)?;
+
+    let tonie_header = Toniefile::parse_header(tonie_file).map_err(header_corrupt)?;
+    let (audio_offset, _) = seek_audio_data(tonie_file)?;
+    let mut hasher = Sha1::new();
+    std::io::copy(&mut BufReader::new(&mut *tonie_file), &mut hasher)?;
+    if hasher.finalize().as_slice() == tonie_header.sha1_hash.as_slice() {
+        return Ok(());
+    }
+
+    tonie_file.seek(SeekFrom::Start(audio_offset))?;
+    return Err(hash_mismatch_error(BufReader::new(tonie_file), limits));
+}
+
+// The protobuf header cannot be parsed if it is malformed or longer than the file
+fn header_corrupt(error: ToniefileError) -> Audio2TonieError {
+    return Audio2TonieError::HeaderCorrupt(error.to_string());
+}
+
+// Moves the reader to the start of the audio data and returns its offset and size
+fn seek_audio_data<R: Read + Seek>(tonie_file: &mut R) -> Result<(u64, u64)> {
+    let total_bytes = tonie_file.seek(SeekFrom::End(0))?;
+    let mut length_prefix = [0u8; 4];
+    tonie_file.rewind()?;
+    tonie_file.read_exact(&mut length_prefix)?;
+    let audio_offset = audio_offset(&length_prefix)
+        .filter(|audio_offset| *audio_offset as u64 <= total_bytes)
+        .ok_or_else(|| {
+            Audio2TonieError::HeaderCorrupt(String::from("the file is shorter than the header."))
+        })? as u64;
+
+    tonie_file.seek(SeekFrom::Start(audio_offset))?;
+    return Ok((audio_offset, total_bytes - audio_offset));
+}
+
+// Searches the audio data for the first page with an invalid checksum to report where the corruption starts
+fn hash_mismatch_error<R: Read>(audio_data: R, limits: &Limits) -> Audio2TonieError {
+    let mut end_of_last_page = 0;
+    for page in OggPageReader::with_limits(audio_data, *limits) {
+        let first_bad_offset = match page {
+            Ok((page_offset, page)) => {
+                end_of_last_page = page_offset + page.size();
+                if page.is_checksum_valid() {
+                    continue;
+                }
+                page_offset
+            }
+            Err(_) => end_of_last_page,
+        };
+        return Audio2TonieError::HashMismatch(Some(TONIEFILE_HEADER_SIZE + first_bad_offset));
+    }
+    return Audio2TonieError::HashMismatch(None);
+}
+
+/// The file name of an extracted chapter. Without a name template and without a title the chapter index is
+/// prepended to the output file name, e.g. `1_file.ogg` for the second chapter of `file.ogg`.
+///
+/// The name template supports the placeholders `{number}` (the chapter number starting at 01), `{index}`
+/// (the chapter index starting at 0), `{title}` (the chapter title, or the output file name if it is unknown)
+/// and `{name}` (the output file name without extension). The extension of the output file is appended.
+///
+/// # Arguments
+///
+/// * `output_file_path` - The output file.
+/// * `chapter` - The index of the chapter.
+/// * `title` - The title of the chapter, if known.
+/// * `options` - Options controlling the extraction, e.g. the name template.
+pub fn chapter_file_name(
+    output_file_path: &Path,
+    chapter: usize,
+    title: Option<&str>,
+    options: &ExtractOptions,
+) -> String {
+    let file_name = output_file_path
+        .file_name()
+        .and_then(OsStr::to_str)
+        .expect("Expected to have a file name for output path.");
+    let template = match (&options.name_template, title) {
+        (Some(template), _) => template.as_str(),
+        (None, Some(_)) => "{number} - {title}",
+        (None, None) => {
+            return sanitize_file_name(&format!("{}_{}", chapter, file_name), options.transliterate)
+        }
+    };
+
+    let name = output_file_path
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .unwrap_or(file_name);
+    let mut chapter_name = template
+        .replace("{number}", &format!("{:02}", chapter + 1))
+        .replace("{index}", &chapter.to_string())
+        .replace("{title}", title.unwrap_or(name))
+        .replace("{name}", name);
+    if let Some(extension) = output_file_path.extension().and_then(OsStr::to_str) {
+        chapter_name = format!("{}.{}", chapter_name, extension);
+    }
+
+    // Titles may contain characters that are invalid in file names, e.g. slashes
+    return sanitize_file_name(&chapter_name, options.transliterate);
+}
+
+/// Reads the chapter titles of a Tonie file. They are taken from the input file names in the sidecar JSON file
+/// written during conversion, e.g. `500304E0.json`, or from `CHAPTER001NAME=...` comments in the Opus header.
+/// Returns an empty list unless every chapter has a title.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file, if it is known.
+/// * `audio_data` - The Ogg Opus stream of the Tonie file.
+/// * `chapter_count` - The number of chapters in the Tonie file.
+pub(crate) fn read_chapter_titles(
+    input_file_path: Option<&Path>,
+    audio_data: &[u8],
+    chapter_count: usize,
+) -> Vec<String> {
+    let sidecar_titles = input_file_path
+        .and_then(|input_file_path| {
+            std::fs::read_to_string(SidecarFile::path(input_file_path)).ok()
+        })
+        .and_then(|sidecar| serde_json::from_str::<serde_json::Value>(&sidecar).ok())
+        .and_then(|sidecar| {
+            sidecar["inputs"]
+                .as_array()?
+                .iter()
+                .map(|input| {
+                    let stem = Path::new(input.as_str()?).file_stem()?;
+                    return Some(stem.to_string_lossy().to_string());
+                })
+                .collect::<Option<Vec<_>>>()
+        });
+    if let Some(titles) = sidecar_titles.filter(|titles| titles.len() == chapter_count) {
+        return titles;
+    }
+
+    // Chapter names following the Vorbis comment chapter extension, numbered from 001
+    let comments = opus_comments(audio_data);
+    return (1..=chapter_count)
+        .map(|number| {
+            let key = format!("CHAPTER{:03}NAME=", number);
+            return comments.iter().find_map(|comment| {
+                let (comment_key, title) = comment.split_at_checked(key.len())?;
+                return comment_key
+                    .eq_ignore_ascii_case(&key)
+                    .then(|| title.to_string());
+            });
+        })
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default();
+}
+
+// The given chapter titles if there is one for every chapter, else the titles of the Tonie file
+fn chapter_titles(
+    input_file_path: Option<&Path>,
+    audio_data: &[u8],
+    chapter_count: usize,
+    options: &ExtractOptions,
+) -> Vec<String> {
+    return match &options.chapter_titles {
+        Some(titles) if titles.len() == chapter_count => titles.clone(),
+        _ => read_chapter_titles(input_file_path, audio_data, chapter_count),
     };
 }
+
+/// Decodes the chapters of a Tonie file and encodes every chapter into a separate file with ffmpeg.
+///
+/// # Arguments
+///
+/// * `tonie_file` - The Tonie file.
+/// * `chapter_file_paths` - The output file of every chapter.
+/// * `options` - Options controlling the extraction, e.g. the output format.
+fn transcode_chapters<R: Read + Seek>(
+    tonie_file: &mut R,
+    chapter_file_paths: &[PathBuf],
+    options: &ExtractOptions,
+) -> Result<()> {
+    let mut encoder: Option<(usize, Child)> = None;
+
+    let result = decode_tonie_reader(tonie_file, &options.limits, |chapter, samples| {
+        if !is_selected(chapter, options) {
+            if let Some((_, ffmpeg)) = encoder.take() {
+                finish_encoder(ffmpeg)?;
+            }
+            return Ok(());
+        }
+        if encoder.as_ref().map(|(current, _)| *current) != Some(chapter) {
+            if let Some((_, ffmpeg)) = encoder.take() {
+                finish_encoder(ffmpeg)?;
+            }
+            let chapter_file_path = chapter_file_paths
+                .get(chapter)
+                .ok_or_else(|| anyhow!("Chapter {} is missing in the Tonie header.", chapter))?;
+            encoder = Some((chapter, spawn_encoder(chapter_file_path, options)?));
+        }
+
+        let (_, ffmpeg) = encoder.as_mut().expect("The encoder was just started.");
+        let pcm = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect::<Vec<u8>>();
+        ffmpeg
+            .stdin
+            .as_mut()
+            .ok_or_else(|| anyhow!("Failed to write to ffmpeg."))?
+            .write_all(&pcm)?;
+        return Ok(());
+    });
+
+    match (result, encoder) {
+        (Ok(()), Some((_, ffmpeg))) => return finish_encoder(ffmpeg),
+        (Ok(()), None) => return Ok(()),
+        (Err(error), encoder) => {
+            if let Some((_, mut ffmpeg)) = encoder {
+                ffmpeg.kill().ok();
+                ffmpeg.wait().ok();
+            }
+            return Err(error);
+        }
+    }
+}
+
+/// Starts ffmpeg encoding stereo 16 bit PCM at 48 kHz from stdin into the given file.
+fn spawn_encoder(output_file_path: &Path, options: &ExtractOptions) -> Result<Child> {
+    return Ok(tool_command(&options.ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-f", "s16le", "-ar", "48000", "-ac", "2", "-i", "-"])
+        .arg(output_file_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?);
+}
+
+fn finish_encoder(ffmpeg: Child) -> Result<()> {
+    // Closing stdin signals the end of the audio to ffmpeg, its few error lines are read while waiting
+    let output = ffmpeg.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Audio2TonieError::FfmpegFailed {
+            status: output.status.to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+        .into());
+    }
+
+    return Ok(());
+}
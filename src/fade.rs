@@ -0,0 +1,95 @@
+//! Linear fade-in and fade-out of a track of interleaved 16 bit PCM while it is streamed.
+
+use std::time::Duration;
+
+const SAMPLE_RATE: u64 = 48000;
+
+/// Streaming fader for the start and the end of a track. The samples of the fade-out are held back until the
+/// end of the track is known.
+#[derive(Clone, Debug)]
+pub struct Fader {
+    channels: usize,
+    fade_in_frames: usize,
+    fade_out_frames: usize,
+    // The number of frames per channel passed to `process` since the start of the track
+    position: usize,
+    // The last samples of the track, which might be part of the fade-out
+    pending: Vec<i16>,
+}
+
+impl Fader {
+    /// Creates a fader for a new track.
+    ///
+    /// # Arguments
+    ///
+    /// * `channels` - The number of interleaved channels.
+    /// * `fade_in` - The length of the fade-in.
+    /// * `fade_out` - The length of the fade-out.
+    pub fn new(channels: usize, fade_in: Duration, fade_out: Duration) -> Self {
+        Fader {
+            channels,
+            fade_in_frames: (fade_in.as_millis() as u64 * SAMPLE_RATE / 1000) as usize,
+            fade_out_frames: (fade_out.as_millis() as u64 * SAMPLE_RATE / 1000) as usize,
+            position: 0,
+            pending: vec![],
+        }
+    }
+
+    /// Applies the fade-in and passes the samples to the callback, except for the samples of a possible fade-out.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Interleaved samples, following the order: left, right, left, right, ...
+    /// * `on_samples` - Called with the faded samples.
+    pub fn process<F>(&mut self, samples: &[i16], mut on_samples: F)
+    where
+        F: FnMut(&[i16]),
+    {
+        let start = self.pending.len();
+        self.pending.extend_from_slice(samples);
+        for frame in self.pending[start..].chunks_exact_mut(self.channels) {
+            if self.position < self.fade_in_frames {
+                let factor = self.position as f64 / self.fade_in_frames as f64;
+                scale(frame, factor);
+            }
+            self.position += 1;
+        }
+
+        let fade_out_length = self.fade_out_frames * self.channels;
+        if self.pending.len() > fade_out_length {
+            let ready = self.pending.len() - fade_out_length;
+            on_samples(&self.pending[..ready]);
+            self.pending.drain(..ready);
+        }
+    }
+
+    /// Ends the track, applies the fade-out to the held back samples and passes them to the callback.
+    /// The fader is reset for the next track.
+    ///
+    /// # Arguments
+    ///
+    /// * `on_samples` - Called with the faded samples.
+    pub fn finish<F>(&mut self, mut on_samples: F)
+    where
+        F: FnMut(&[i16]),
+    {
+        let frame_count = self.pending.len() / self.channels;
+        for (index, frame) in self.pending.chunks_exact_mut(self.channels).enumerate() {
+            // Tracks shorter than the fade-out only fade out over their remaining length
+            let factor = (frame_count - index - 1) as f64 / self.fade_out_frames as f64;
+            scale(frame, factor.min(1.0));
+        }
+        if !self.pending.is_empty() {
+            on_samples(&self.pending);
+        }
+
+        self.pending.clear();
+        self.position = 0;
+    }
+}
+
+fn scale(frame: &mut [i16], factor: f64) {
+    for sample in frame.iter_mut() {
+        *sample = (*sample as f64 * factor).round() as i16;
+    }
+}
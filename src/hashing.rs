@@ -0,0 +1,51 @@
+use sha1::{Digest, Sha1};
+use std::io::Write;
+
+/// Wraps a writer and computes the SHA1 hash and the length of every byte written through it, so the hash in the
+/// header of a Tonie file always covers exactly the audio data in the file.
+pub struct HashingWriter<W> {
+    inner: W,
+    sha1: Sha1,
+    bytes_written: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    /// # Arguments
+    ///
+    /// * `inner` - The writer to wrap.
+    pub fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            sha1: Sha1::new(),
+            bytes_written: 0,
+        }
+    }
+
+    /// The number of bytes written so far.
+    pub fn bytes_written(&self) -> u64 {
+        return self.bytes_written;
+    }
+
+    /// Returns the wrapped writer together with the SHA1 hash and the number of all written bytes.
+    pub fn finish(self) -> (W, Vec<u8>, u64) {
+        return (
+            self.inner,
+            self.sha1.finalize().to_vec(),
+            self.bytes_written,
+        );
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Only the bytes accepted by the inner writer are hashed, the rest is passed again by the caller
+        let bytes = self.inner.write(buf)?;
+        self.sha1.update(&buf[..bytes]);
+        self.bytes_written += bytes as u64;
+        return Ok(bytes);
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.inner.flush();
+    }
+}
@@ -0,0 +1,211 @@
+//! Dumps the protobuf header of a Tonie file as JSON and rewrites it from edited JSON, keeping the audio untouched.
+//! Also gives a Tonie file a new audio id in place, including the serial number of its Ogg stream.
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Map, Value};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::convert::{ExistingOutput, PartialOutput};
+use crate::hashing::HashingWriter;
+use crate::limits::Limits;
+use crate::taf::{
+    decode_header, encode_header, header_length, OggPageReader, TonieHeader, TONIEFILE_HEADER_SIZE,
+};
+use crate::utils::check_input_limits;
+
+/// Reads and decodes the protobuf header of a Tonie file.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `limits` - Caps for the header size.
+pub fn read_tonie_header(input_file_path: &Path, limits: &Limits) -> Result<TonieHeader> {
+    let header_block = read_header_block(input_file_path, limits)?;
+    return decode_header(&header_block).ok_or_else(|| anyhow!("The Tonie header is malformed."));
+}
+
+// Reads the length prefix and the protobuf header
+fn read_header_block(input_file_path: &Path, limits: &Limits) -> Result<Vec<u8>> {
+    let mut tonie_file = File::open(input_file_path)?;
+    let mut buffer = vec![0u8; 4];
+    tonie_file.read_exact(&mut buffer)?;
+    let header_size = header_length(&buffer).unwrap_or_default();
+    limits.check_header_size(header_size)?;
+
+    buffer.resize(4 + header_size, 0);
+    tonie_file.read_exact(&mut buffer[4..])?;
+    return Ok(buffer);
+}
+
+/// All fields of the protobuf header as JSON object. The SHA1 hash is written as lowercase hex string.
+///
+/// # Arguments
+///
+/// * `header` - The decoded header.
+pub fn header_json(header: &TonieHeader) -> Value {
+    let sha1_hash = header
+        .sha1_hash
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    return json!({
+        "sha1_hash": sha1_hash,
+        "num_bytes": header.num_bytes,
+        "audio_id": header.audio_id,
+        "track_page_nums": header.track_page_nums,
+        "fill_size": header.fill_size,
+    });
+}
+
+/// Applies the fields of a JSON object to a header. Missing fields are kept, the fill size is ignored because
+/// it is recomputed when the header is encoded.
+///
+/// # Arguments
+///
+/// * `header` - The header to change.
+/// * `patch` - A JSON object with the fields of [`header_json`].
+pub fn patch_header(header: &mut TonieHeader, patch: &Map<String, Value>) -> Result<()> {
+    for (field, value) in patch {
+        let invalid = || anyhow!("Invalid value for the header field {}: {}", field, value);
+        match field.as_str() {
+            "sha1_hash" => {
+                let hex = value.as_str().ok_or_else(invalid)?;
+                if hex.len() != 40 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(invalid());
+                }
+                header.sha1_hash = (0..hex.len())
+                    .step_by(2)
+                    .map(|index| u8::from_str_radix(&hex[index..index + 2], 16))
+                    .collect::<Result<_, _>>()?;
+            }
+            "num_bytes" => header.num_bytes = value.as_u64().ok_or_else(invalid)?,
+            "audio_id" => {
+                header.audio_id = value
+                    .as_u64()
+                    .and_then(|audio_id| u32::try_from(audio_id).ok())
+                    .ok_or_else(invalid)?;
+            }
+            "track_page_nums" => {
+                header.track_page_nums = value
+                    .as_array()
+                    .ok_or_else(invalid)?
+                    .iter()
+                    .map(|page_num| {
+                        page_num
+                            .as_u64()
+                            .and_then(|page_num| u32::try_from(page_num).ok())
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?;
+            }
+            "fill_size" => (),
+            _ => return Err(anyhow!("Unknown header field: {}", field)),
+        }
+    }
+
+    return Ok(());
+}
+
+/// Rewrites the header block of a Tonie file with the fields of a JSON patch. The fill field is resized so the
+/// header occupies the first 4kb block again, the audio data is not touched. Returns the new header.
+///
+/// # Arguments
+///
+/// * `tonie_file_path` - The path to the Tonie file, which is changed in place.
+/// * `patch` - A JSON object with the fields of [`header_json`] to change.
+/// * `limits` - Caps for the header size.
+pub fn apply_header_patch(
+    tonie_file_path: &Path,
+    patch: &Value,
+    limits: &Limits,
+) -> Result<TonieHeader> {
+    let patch = patch
+        .as_object()
+        .ok_or_else(|| anyhow!("The header patch has to be a JSON object."))?;
+    let mut header = decode_header_block(&read_header_block(tonie_file_path, limits)?)?;
+    patch_header(&mut header, patch)?;
+
+    let encoded_header = encode_header(
+        &header.sha1_hash,
+        header.num_bytes,
+        header.audio_id,
+        &header.track_page_nums,
+    )
+    .ok_or_else(|| anyhow!("The header fields do not fit into the first 4kb block."))?;
+
+    let mut tonie_file = OpenOptions::new().write(true).open(tonie_file_path)?;
+    tonie_file.seek(SeekFrom::Start(0))?;
+    tonie_file.write_all(&encoded_header)?;
+    tonie_file.flush()?;
+
+    return decode_header(&encoded_header)
+        .ok_or_else(|| anyhow!("The rewritten Tonie header is malformed."));
+}
+
+/// Gives a Tonie file a new audio id in place. The Ogg stream uses the audio id as serial number, so the serial
+/// number and the checksum of every page are rewritten as well and the SHA1 hash in the header is recomputed.
+/// The pages are streamed into a partial file next to the Tonie file, which replaces it once it is complete, so
+/// an interrupted run leaves the original file intact. Returns the new header.
+///
+/// # Arguments
+///
+/// * `tonie_file_path` - The path to the Tonie file, which is replaced.
+/// * `audio_id` - The new audio id, usually the current Unix timestamp.
+/// * `limits` - Caps for the input size and the number of pages.
+pub fn retimestamp_tonie_file(
+    tonie_file_path: &Path,
+    audio_id: u32,
+    limits: &Limits,
+) -> Result<TonieHeader> {
+    let mut tonie_file = File::open(tonie_file_path)?;
+    check_input_limits(&mut tonie_file, limits)?;
+    let mut header = decode_header_block(&read_header_block(tonie_file_path, limits)?)?;
+    tonie_file.seek(SeekFrom::Start(TONIEFILE_HEADER_SIZE as u64))?;
+
+    let output = PartialOutput::create(tonie_file_path)?;
+    let mut writer = BufWriter::new(output.file());
+    // The header is written with the new hash once all pages are rewritten
+    writer.write_all(&[0; TONIEFILE_HEADER_SIZE])?;
+    let mut writer = HashingWriter::new(writer);
+    let mut page_buffer = vec![];
+    for page in OggPageReader::with_limits(BufReader::new(tonie_file), *limits) {
+        let (_, mut page) = page?;
+        // The page keeps its size, only the serial number and the checksum change
+        page.serial_number = audio_id;
+        page_buffer.clear();
+        page.serialize_into(&mut page_buffer);
+        writer.write_all(&page_buffer)?;
+    }
+    let (mut writer, sha1_hash, _) = writer.finish();
+
+    header.audio_id = audio_id;
+    header.sha1_hash = sha1_hash;
+    let encoded_header = encode_header(
+        &header.sha1_hash,
+        header.num_bytes,
+        header.audio_id,
+        &header.track_page_nums,
+    )
+    .ok_or_else(|| anyhow!("The header fields do not fit into the first 4kb block."))?;
+    writer.seek(SeekFrom::Start(0))?;
+    writer.write_all(&encoded_header)?;
+    writer.flush()?;
+    drop(writer);
+    output.complete(tonie_file_path, ExistingOutput::Overwrite)?;
+
+    return decode_header(&encoded_header)
+        .ok_or_else(|| anyhow!("The rewritten Tonie header is malformed."));
+}
+
+// Decodes a header which is rewritten in place, so it has to occupy exactly the first 4kb block
+fn decode_header_block(header_block: &[u8]) -> Result<TonieHeader> {
+    if header_length(header_block).map(|size| size + 4) != Some(TONIEFILE_HEADER_SIZE) {
+        return Err(anyhow!(
+            "The header does not occupy exactly the first 4kb block, so it cannot be rewritten in place."
+        ));
+    }
+    return decode_header(header_block).ok_or_else(|| anyhow!("The Tonie header is malformed."));
+}
@@ -0,0 +1,557 @@
+//! Post-processing hooks that run after a successful conversion, e.g. to upload the Tonie file
+//! or notify other services. Custom steps can be added by implementing [`PostProcessor`].
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::convert::probe_metadata;
+use crate::header::read_tonie_header;
+use crate::limits::Limits;
+use crate::tap::{playlist_path, TapFile, TapPlaylist};
+use crate::teddycloud::TeddyCloudClient;
+use crate::utils::{multipart_form, tool_command};
+
+// Cover images next to the input files, in the order they are looked for
+const COVER_FILE_NAMES: [&str; 6] = [
+    "cover.jpg",
+    "cover.png",
+    "folder.jpg",
+    "folder.png",
+    "front.jpg",
+    "front.png",
+];
+
+/// The edge length in pixels cover images are scaled down to, which is plenty for the TeddyCloud web interface.
+const COVER_SIZE: u32 = 500;
+
+/// Information about a finished conversion passed to every post processor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConversionMetadata {
+    /// The path of the written Tonie file.
+    pub output_path: PathBuf,
+    /// The converted input files in chapter order.
+    pub input_files: Vec<PathBuf>,
+    /// The number of chapters in the Tonie file.
+    pub chapters: usize,
+}
+
+/// A step that runs after each successful conversion.
+pub trait PostProcessor {
+    /// A short name used in error messages.
+    fn name(&self) -> &str;
+
+    /// Processes the converted Tonie file.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - Information about the finished conversion.
+    fn process(&self, metadata: &ConversionMetadata) -> Result<()>;
+}
+
+/// Runs a shell command. The conversion metadata is passed in the environment variables
+/// `AUDIO2TONIE_OUTPUT`, `AUDIO2TONIE_INPUTS` (one path per line) and `AUDIO2TONIE_CHAPTERS`.
+pub struct ShellCommand {
+    command: String,
+}
+
+impl ShellCommand {
+    /// # Arguments
+    ///
+    /// * `command` - The command line, interpreted by `sh` (or `cmd` on Windows).
+    pub fn new(command: &str) -> Self {
+        ShellCommand {
+            command: command.to_string(),
+        }
+    }
+}
+
+impl PostProcessor for ShellCommand {
+    fn name(&self) -> &str {
+        return &self.command;
+    }
+
+    fn process(&self, metadata: &ConversionMetadata) -> Result<()> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+
+        let input_files = metadata
+            .input_files
+            .iter()
+            .map(|input_file| input_file.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let status = command
+            .arg(&self.command)
+            .env("AUDIO2TONIE_OUTPUT", &metadata.output_path)
+            .env("AUDIO2TONIE_INPUTS", input_files)
+            .env("AUDIO2TONIE_CHAPTERS", metadata.chapters.to_string())
+            .status()?;
+        if !status.success() {
+            return Err(anyhow!("The command failed: {}", status));
+        }
+
+        return Ok(());
+    }
+}
+
+/// Uploads the Tonie file to the library of a TeddyCloud server.
+pub struct TeddyCloudUpload {
+    client: TeddyCloudClient,
+    path: String,
+}
+
+impl TeddyCloudUpload {
+    /// # Arguments
+    ///
+    /// * `url` - The base URL of the TeddyCloud server, e.g. `http://teddycloud.local`.
+    /// * `path` - The directory in the TeddyCloud library to upload to.
+    pub fn new(url: &str, path: &str) -> Self {
+        TeddyCloudUpload {
+            client: TeddyCloudClient::new(url),
+            path: path.to_string(),
+        }
+    }
+
+    /// Authenticates with the given token, sent as `Authorization: Bearer <token>` header.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.client = self.client.with_token(token);
+        return self;
+    }
+
+    /// Trusts the certificates of the given PEM file in addition to the default root certificates,
+    /// e.g. for a TeddyCloud server with a self-signed certificate.
+    pub fn with_ca_cert(mut self, ca_cert: Option<PathBuf>) -> Self {
+        self.client = self.client.with_ca_cert(ca_cert);
+        return self;
+    }
+}
+
+impl PostProcessor for TeddyCloudUpload {
+    fn name(&self) -> &str {
+        return "teddycloud upload";
+    }
+
+    fn process(&self, metadata: &ConversionMetadata) -> Result<()> {
+        let file_name = metadata
+            .output_path
+            .file_name()
+            .ok_or_else(|| anyhow!("The output path has no file name."))?
+            .to_string_lossy();
+        let mut content = vec![];
+        File::open(&metadata.output_path)?.read_to_end(&mut content)?;
+
+        let (content_type, body) = multipart_form(&[], &file_name, &content);
+        self.client
+            .request("POST", "/api/fileUpload")?
+            .query("path", &self.path)
+            .query("special", "library")
+            .set("Content-Type", &content_type)
+            .send_bytes(&body)?;
+
+        return Ok(());
+    }
+}
+
+/// Writes a JSON file with the conversion metadata next to the Tonie file, e.g. `500304E0.json`.
+pub struct SidecarFile;
+
+impl SidecarFile {
+    /// The path of the sidecar file for the given Tonie file.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_path` - The path of the Tonie file.
+    pub fn path(output_path: &Path) -> PathBuf {
+        let mut file_name = output_path.as_os_str().to_os_string();
+        file_name.push(".json");
+        return PathBuf::from(file_name);
+    }
+}
+
+impl PostProcessor for SidecarFile {
+    fn name(&self) -> &str {
+        return "sidecar";
+    }
+
+    fn process(&self, metadata: &ConversionMetadata) -> Result<()> {
+        let sidecar = json!({
+            "output": metadata.output_path.to_string_lossy(),
+            "inputs": metadata
+                .input_files
+                .iter()
+                .map(|input_file| input_file.to_string_lossy())
+                .collect::<Vec<_>>(),
+            "chapters": metadata.chapters,
+        });
+        let json = serde_json::to_string_pretty(&sidecar)? + "\n";
+        std::fs::write(SidecarFile::path(&metadata.output_path), json)?;
+
+        return Ok(());
+    }
+}
+
+/// Where the cover image of a conversion comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CoverSource {
+    /// An image file, e.g. `cover.jpg`.
+    File(PathBuf),
+    /// A cover image next to the input files, e.g. `folder.jpg`, or else the artwork embedded in the input files,
+    /// e.g. the `APIC` frame of an ID3 tag.
+    Auto,
+}
+
+/// Writes the cover image of the content as JPEG file next to the Tonie file, e.g. `500304E0.jpg`, scaled down to
+/// fit into 500x500 pixels. ffmpeg reads and scales the image, so every image format and the artwork embedded in
+/// audio files are supported.
+pub struct CoverArt {
+    source: CoverSource,
+    ffmpeg: String,
+}
+
+impl CoverArt {
+    /// # Arguments
+    ///
+    /// * `source` - Where the cover image comes from.
+    /// * `ffmpeg` - The path to the ffmpeg executable.
+    pub fn new(source: CoverSource, ffmpeg: &str) -> Self {
+        CoverArt {
+            source,
+            ffmpeg: ffmpeg.to_string(),
+        }
+    }
+
+    /// The path of the cover image for the given Tonie file.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_path` - The path of the Tonie file.
+    pub fn path(output_path: &Path) -> PathBuf {
+        let mut file_name = output_path.as_os_str().to_os_string();
+        file_name.push(".jpg");
+        return PathBuf::from(file_name);
+    }
+
+    /// The files to take the cover from, in the order they are tried.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - Information about the finished conversion.
+    fn candidates(&self, metadata: &ConversionMetadata) -> Vec<PathBuf> {
+        return match &self.source {
+            CoverSource::File(cover_path) => vec![cover_path.clone()],
+            CoverSource::Auto => {
+                let input_directory = metadata
+                    .input_files
+                    .first()
+                    .and_then(|input_file| input_file.parent())
+                    .unwrap_or(Path::new(""));
+                COVER_FILE_NAMES
+                    .iter()
+                    .map(|file_name| input_directory.join(file_name))
+                    .filter(|cover_path| cover_path.is_file())
+                    .chain(metadata.input_files.iter().cloned())
+                    .collect()
+            }
+        };
+    }
+
+    /// Converts the first picture of an image or audio file into the scaled down JPEG cover.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_path` - The image file or the audio file with embedded artwork.
+    /// * `cover_path` - The path of the JPEG file to write.
+    fn write_cover(&self, source_path: &Path, cover_path: &Path) -> Result<()> {
+        let scale = format!(
+            "scale={size}:{size}:force_original_aspect_ratio=decrease",
+            size = COVER_SIZE
+        );
+        let output = tool_command(&self.ffmpeg)
+            .args(["-hide_banner", "-v", "error", "-i"])
+            .arg(source_path)
+            .args(["-map", "0:v:0", "-frames:v", "1", "-vf", &scale, "-y"])
+            .arg(cover_path)
+            .stdin(Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            std::fs::remove_file(cover_path).ok();
+            return Err(anyhow!(
+                "ffmpeg failed to read a picture from {}: {}",
+                source_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        return Ok(());
+    }
+}
+
+impl PostProcessor for CoverArt {
+    fn name(&self) -> &str {
+        return "cover";
+    }
+
+    fn process(&self, metadata: &ConversionMetadata) -> Result<()> {
+        let cover_path = CoverArt::path(&metadata.output_path);
+        let mut last_error = None;
+        for candidate in self.candidates(metadata) {
+            match self.write_cover(&candidate, &cover_path) {
+                Ok(()) => return Ok(()),
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        // Content without any artwork is fine when the cover is detected
+        return match (&self.source, last_error) {
+            (CoverSource::File(_), Some(error)) => Err(error),
+            _ => Ok(()),
+        };
+    }
+}
+
+/// Adds an entry for the Tonie file to a `tonies.custom.json` file of TeddyCloud, so TeddyCloud shows the title
+/// and the cover of custom content. The file is created if it does not exist. The series, episode and track
+/// titles are read from the artist, album and title tags of the input files, falling back to the names of the
+/// input directory and files. The cover written by [`CoverArt`] is used as artwork, or else a cover image next to
+/// the input files.
+pub struct TeddyCloudCustomJson {
+    path: PathBuf,
+    ffmpeg: String,
+}
+
+impl TeddyCloudCustomJson {
+    /// # Arguments
+    ///
+    /// * `path` - The path of the `tonies.custom.json` file.
+    /// * `ffmpeg` - The path to the ffmpeg executable, which reads the tags of the input files.
+    pub fn new(path: &Path, ffmpeg: &str) -> Self {
+        TeddyCloudCustomJson {
+            path: path.to_path_buf(),
+            ffmpeg: ffmpeg.to_string(),
+        }
+    }
+
+    /// The entry describing a converted Tonie file.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - Information about the finished conversion.
+    /// * `number` - The number of the entry in the file.
+    pub fn entry(&self, metadata: &ConversionMetadata, number: usize) -> Result<Value> {
+        let header = read_tonie_header(&metadata.output_path, &Limits::default())?;
+        let sha1_hash = header
+            .sha1_hash
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+
+        // Files ffmpeg cannot probe are described by their names
+        let tags = metadata
+            .input_files
+            .iter()
+            .map(|input_file| probe_metadata(input_file, &self.ffmpeg).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let first_tag = |keys: &[&str]| {
+            tags.first()
+                .and_then(|tags| keys.iter().find_map(|key| tags.get(*key)))
+                .filter(|value| !value.is_empty())
+                .cloned()
+        };
+        let input_directory = metadata
+            .input_files
+            .first()
+            .and_then(|input_file| input_file.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let directory_name = input_directory
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string());
+
+        let series = first_tag(&["album_artist", "artist"]).unwrap_or_default();
+        let episodes = first_tag(&["album"]).or(directory_name).unwrap_or_default();
+        let title = match (series.is_empty(), episodes.is_empty()) {
+            (false, false) => format!("{} - {}", series, episodes),
+            (false, true) => series.clone(),
+            _ => episodes.clone(),
+        };
+        let tracks = metadata
+            .input_files
+            .iter()
+            .zip(&tags)
+            .map(|(input_file, tags)| match tags.get("title") {
+                Some(title) if !title.is_empty() => title.clone(),
+                _ => input_file
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        let pic = std::iter::once(CoverArt::path(&metadata.output_path))
+            .chain(
+                COVER_FILE_NAMES
+                    .iter()
+                    .map(|file_name| input_directory.join(file_name)),
+            )
+            .find(|cover_path| cover_path.is_file())
+            .map(|cover_path| cover_path.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        return Ok(json!({
+            "no": number.to_string(),
+            "model": "",
+            "audio_id": [header.audio_id.to_string()],
+            "hash": [sha1_hash],
+            "title": title,
+            "series": series,
+            "episodes": episodes,
+            "tracks": tracks,
+            "release": "0",
+            "language": "",
+            "category": "custom",
+            "pic": pic,
+        }));
+    }
+}
+
+impl PostProcessor for TeddyCloudCustomJson {
+    fn name(&self) -> &str {
+        return "tonies.custom.json";
+    }
+
+    fn process(&self, metadata: &ConversionMetadata) -> Result<()> {
+        let mut entries = match self.path.is_file() {
+            true => {
+                let content = std::fs::read_to_string(&self.path)?;
+                match serde_json::from_str::<Value>(&content)? {
+                    Value::Array(entries) => entries,
+                    _ => return Err(anyhow!("{} is not a JSON array.", self.path.display())),
+                }
+            }
+            false => vec![],
+        };
+
+        entries.push(self.entry(metadata, entries.len())?);
+        std::fs::write(&self.path, serde_json::to_string_pretty(&entries)?)?;
+
+        return Ok(());
+    }
+}
+
+/// Writes a TeddyCloud playlist (`.tap`) next to the Tonie file, e.g. `gruffalo.tap` for `gruffalo.taf`, which lists
+/// the input files as chapters. Files inside the directory of the Tonie file are referenced as library paths, so
+/// TeddyCloud can encode the playlist again when the directory is copied into its library. The chapters are named
+/// after the title tags of the input files, falling back to the file names.
+pub struct TeddyCloudPlaylist {
+    ffmpeg: String,
+}
+
+impl TeddyCloudPlaylist {
+    /// # Arguments
+    ///
+    /// * `ffmpeg` - The path to the ffmpeg executable, which reads the tags of the input files.
+    pub fn new(ffmpeg: &str) -> Self {
+        TeddyCloudPlaylist {
+            ffmpeg: ffmpeg.to_string(),
+        }
+    }
+
+    /// The path of the playlist for the given Tonie file.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_path` - The path of the Tonie file.
+    pub fn path(output_path: &Path) -> PathBuf {
+        if output_path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("taf"))
+        {
+            return output_path.with_extension("tap");
+        }
+        let mut file_name = output_path.as_os_str().to_os_string();
+        file_name.push(".tap");
+        return PathBuf::from(file_name);
+    }
+
+    /// The playlist describing a converted Tonie file.
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` - Information about the finished conversion.
+    pub fn playlist(&self, metadata: &ConversionMetadata) -> Result<TapPlaylist> {
+        let header = read_tonie_header(&metadata.output_path, &Limits::default())?;
+        let base_dir = metadata.output_path.parent().unwrap_or(Path::new(""));
+        let stem = |path: &Path| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+        let files = metadata
+            .input_files
+            .iter()
+            .map(|input_file| {
+                let title = probe_metadata(input_file, &self.ffmpeg)
+                    .unwrap_or_default()
+                    .get("title")
+                    .filter(|title| !title.is_empty())
+                    .cloned();
+                TapFile {
+                    filepath: playlist_path(input_file, base_dir),
+                    name: title.unwrap_or_else(|| stem(input_file)),
+                }
+            })
+            .collect();
+
+        return Ok(TapPlaylist {
+            name: stem(&metadata.output_path),
+            audio_id: Some(header.audio_id),
+            filepath: playlist_path(&metadata.output_path, base_dir),
+            files,
+        });
+    }
+}
+
+impl PostProcessor for TeddyCloudPlaylist {
+    fn name(&self) -> &str {
+        return "tap";
+    }
+
+    fn process(&self, metadata: &ConversionMetadata) -> Result<()> {
+        let playlist = self.playlist(metadata)?;
+        std::fs::write(
+            TeddyCloudPlaylist::path(&metadata.output_path),
+            serde_json::to_string_pretty(&playlist.to_json())?,
+        )?;
+
+        return Ok(());
+    }
+}
+
+/// Runs all post processors in order and stops at the first failure.
+///
+/// # Arguments
+///
+/// * `post_processors` - The post processors to run.
+/// * `metadata` - Information about the finished conversion.
+pub fn run_post_processors(
+    post_processors: &[Box<dyn PostProcessor>],
+    metadata: &ConversionMetadata,
+) -> Result<()> {
+    for post_processor in post_processors {
+        post_processor
+            .process(metadata)
+            .with_context(|| format!("Post-processing hook '{}' failed", post_processor.name()))?;
+    }
+
+    return Ok(());
+}
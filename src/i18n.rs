@@ -0,0 +1,323 @@
+use clap::ValueEnum;
+
+/// Languages available for user-facing messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum Language {
+    #[default]
+    En,
+    De,
+    Fr,
+}
+
+/// User-facing messages printed by the CLI.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Message {
+    InputsNotModified,
+    OutputExists,
+    Cancelled,
+    Chapter,
+    Start,
+    Duration,
+    Size,
+    Bitrate,
+    Pages,
+    Loudness,
+    TruePeak,
+    HeaderSize,
+    Sha1Hash,
+    DataLength,
+    AudioId,
+    ChapterPages,
+    TotalDuration,
+    PageAlignment,
+    PageSizes,
+    Checksums,
+    GranulePositions,
+    CheckPassed,
+    CheckFailed,
+    TonieFileInvalid,
+    OutputFile,
+    InputFile,
+    EstimatedSize,
+    UnknownDuration,
+    TagUid,
+    ContentPath,
+    Offset,
+    Page,
+    Granule,
+    Segments,
+    Padding,
+    Checksum,
+    Block,
+    Packets,
+    Header,
+    Config,
+    Mode,
+    Bandwidth,
+    FrameDuration,
+    Channels,
+    FramePacking,
+    Frames,
+    ContinuedPacket,
+    FrameLayout,
+    Converted,
+    Skipped,
+    Failed,
+    FailedInputs,
+    Truncated,
+    NotStarted,
+    Name,
+    Chapters,
+    Valid,
+    Title,
+    AudioIdMatch,
+    UnknownTonie,
+    Source,
+    Remaining,
+    Id,
+}
+
+impl Language {
+    /// Detects the language from the `LC_ALL`, `LC_MESSAGES` and `LANG` environment variables, e.g. `de_DE.UTF-8`.
+    /// Falls back to English for unset or unsupported locales.
+    pub fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+
+        return Language::from_locale(&locale);
+    }
+
+    /// Parses the language from a POSIX locale name, e.g. `fr_FR.UTF-8`.
+    ///
+    /// # Arguments
+    ///
+    /// * `locale` - The locale name.
+    pub fn from_locale(locale: &str) -> Self {
+        let language = locale
+            .split(['_', '.', '@', '-'])
+            .next()
+            .unwrap_or_default();
+
+        return Language::from_str(language, true).unwrap_or_default();
+    }
+
+    /// Translates a message into this language.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message to translate.
+    pub fn translate(self, message: Message) -> &'static str {
+        return match (self, message) {
+            (Language::En, Message::InputsNotModified) => {
+                "No input files were modified. Skipping conversion."
+            }
+            (Language::De, Message::InputsNotModified) => {
+                "Keine Eingabedateien wurden geändert. Die Konvertierung wird übersprungen."
+            }
+            (Language::Fr, Message::InputsNotModified) => {
+                "Aucun fichier d'entrée n'a été modifié. La conversion est ignorée."
+            }
+            (Language::En, Message::OutputExists) => {
+                "The output file already exists. Skipping conversion."
+            }
+            (Language::De, Message::OutputExists) => {
+                "Die Ausgabedatei existiert bereits. Die Konvertierung wird übersprungen."
+            }
+            (Language::Fr, Message::OutputExists) => {
+                "Le fichier de sortie existe déjà. La conversion est ignorée."
+            }
+            (Language::En, Message::Cancelled) => {
+                "The conversion was cancelled. The partially written output file was removed."
+            }
+            (Language::De, Message::Cancelled) => {
+                "Die Konvertierung wurde abgebrochen. Die unvollständige Ausgabedatei wurde gelöscht."
+            }
+            (Language::Fr, Message::Cancelled) => {
+                "La conversion a été annulée. Le fichier de sortie incomplet a été supprimé."
+            }
+            (Language::En, Message::Chapter) => "Chapter",
+            (Language::De, Message::Chapter) => "Kapitel",
+            (Language::Fr, Message::Chapter) => "Chapitre",
+            (Language::En, Message::Start) => "Start",
+            (Language::De, Message::Start) => "Start",
+            (Language::Fr, Message::Start) => "Début",
+            (Language::En, Message::Size) => "Size",
+            (Language::De, Message::Size) => "Größe",
+            (Language::Fr, Message::Size) => "Taille",
+            (Language::En, Message::Bitrate) => "Bitrate",
+            (Language::De, Message::Bitrate) => "Bitrate",
+            (Language::Fr, Message::Bitrate) => "Débit",
+            (Language::En, Message::Pages) => "Pages",
+            (Language::De, Message::Pages) => "Seiten",
+            (Language::Fr, Message::Pages) => "Pages",
+            (Language::En, Message::Duration) => "Duration",
+            (Language::De, Message::Duration) => "Dauer",
+            (Language::Fr, Message::Duration) => "Durée",
+            (Language::En, Message::Loudness) => "Loudness",
+            (Language::De, Message::Loudness) => "Lautheit",
+            (Language::Fr, Message::Loudness) => "Sonie",
+            (Language::En, Message::TruePeak) => "True Peak",
+            (Language::De, Message::TruePeak) => "Spitzenpegel",
+            (Language::Fr, Message::TruePeak) => "Crête",
+            (Language::En, Message::HeaderSize) => "Header size",
+            (Language::De, Message::HeaderSize) => "Headergröße",
+            (Language::Fr, Message::HeaderSize) => "Taille d'en-tête",
+            (Language::En, Message::Sha1Hash) => "SHA1 hash",
+            (Language::De, Message::Sha1Hash) => "SHA1-Hash",
+            (Language::Fr, Message::Sha1Hash) => "Hachage SHA1",
+            (Language::En, Message::DataLength) => "Data length",
+            (Language::De, Message::DataLength) => "Datenlänge",
+            (Language::Fr, Message::DataLength) => "Taille données",
+            (Language::En, Message::AudioId) => "Audio ID",
+            (Language::De, Message::AudioId) => "Audio-ID",
+            (Language::Fr, Message::AudioId) => "ID audio",
+            (Language::En, Message::ChapterPages) => "Chapter pages",
+            (Language::De, Message::ChapterPages) => "Kapitelseiten",
+            (Language::Fr, Message::ChapterPages) => "Pages chapitres",
+            (Language::En, Message::TotalDuration) => "Total duration",
+            (Language::De, Message::TotalDuration) => "Gesamtdauer",
+            (Language::Fr, Message::TotalDuration) => "Durée totale",
+            (Language::En, Message::PageAlignment) => "Page alignment",
+            (Language::De, Message::PageAlignment) => "Seitenausrichtung",
+            (Language::Fr, Message::PageAlignment) => "Alignement pages",
+            (Language::En, Message::PageSizes) => "Page sizes",
+            (Language::De, Message::PageSizes) => "Seitengrößen",
+            (Language::Fr, Message::PageSizes) => "Taille pages",
+            (Language::En, Message::Checksums) => "Ogg checksums",
+            (Language::De, Message::Checksums) => "Ogg-Prüfsummen",
+            (Language::Fr, Message::Checksums) => "Sommes Ogg",
+            (Language::En, Message::GranulePositions) => "Granule positions",
+            (Language::De, Message::GranulePositions) => "Granule-Positionen",
+            (Language::Fr, Message::GranulePositions) => "Positions granule",
+            (Language::En, Message::CheckPassed) => "OK",
+            (Language::De, Message::CheckPassed) => "OK",
+            (Language::Fr, Message::CheckPassed) => "OK",
+            (Language::En, Message::CheckFailed) => "FAILED",
+            (Language::De, Message::CheckFailed) => "FEHLER",
+            (Language::Fr, Message::CheckFailed) => "ÉCHEC",
+            (Language::En, Message::TonieFileInvalid) => "The Tonie file is invalid.",
+            (Language::De, Message::TonieFileInvalid) => "Die Tonie-Datei ist ungültig.",
+            (Language::Fr, Message::TonieFileInvalid) => "Le fichier Tonie n'est pas valide.",
+            (Language::En, Message::OutputFile) => "Output file",
+            (Language::De, Message::OutputFile) => "Ausgabedatei",
+            (Language::Fr, Message::OutputFile) => "Fichier sortie",
+            (Language::En, Message::InputFile) => "Input file",
+            (Language::De, Message::InputFile) => "Eingabedatei",
+            (Language::Fr, Message::InputFile) => "Fichier d'entrée",
+            (Language::En, Message::EstimatedSize) => "Estimated size",
+            (Language::De, Message::EstimatedSize) => "Geschätzte Größe",
+            (Language::Fr, Message::EstimatedSize) => "Taille estimée",
+            (Language::En, Message::UnknownDuration) => "unknown",
+            (Language::De, Message::UnknownDuration) => "unbekannt",
+            (Language::Fr, Message::UnknownDuration) => "inconnue",
+            (Language::En, Message::TagUid) => "Tag UID",
+            (Language::De, Message::TagUid) => "Tag-UID",
+            (Language::Fr, Message::TagUid) => "UID du tag",
+            (Language::En, Message::ContentPath) => "Content path",
+            (Language::De, Message::ContentPath) => "Inhaltspfad",
+            (Language::Fr, Message::ContentPath) => "Chemin contenu",
+            (Language::En, Message::Offset) => "Offset",
+            (Language::De, Message::Offset) => "Offset",
+            (Language::Fr, Message::Offset) => "Position",
+            (Language::En, Message::Page) => "Page",
+            (Language::De, Message::Page) => "Seite",
+            (Language::Fr, Message::Page) => "Page",
+            (Language::En, Message::Granule) => "Granule",
+            (Language::De, Message::Granule) => "Granule",
+            (Language::Fr, Message::Granule) => "Granule",
+            (Language::En, Message::Segments) => "Segments",
+            (Language::De, Message::Segments) => "Segmente",
+            (Language::Fr, Message::Segments) => "Segments",
+            (Language::En, Message::Padding) => "Padding",
+            (Language::De, Message::Padding) => "Füllung",
+            (Language::Fr, Message::Padding) => "Remplissage",
+            (Language::En, Message::Checksum) => "CRC",
+            (Language::De, Message::Checksum) => "CRC",
+            (Language::Fr, Message::Checksum) => "CRC",
+            (Language::En, Message::Block) => "4kb block",
+            (Language::De, Message::Block) => "4kb-Block",
+            (Language::Fr, Message::Block) => "Bloc 4 ko",
+            (Language::En, Message::Packets) => "Packets",
+            (Language::De, Message::Packets) => "Pakete",
+            (Language::Fr, Message::Packets) => "Paquets",
+            (Language::En, Message::Header) => "Header",
+            (Language::De, Message::Header) => "Header",
+            (Language::Fr, Message::Header) => "En-tête",
+            (Language::En, Message::Config) => "Config",
+            (Language::De, Message::Config) => "Konfig",
+            (Language::Fr, Message::Config) => "Config",
+            (Language::En, Message::Mode) => "Mode",
+            (Language::De, Message::Mode) => "Modus",
+            (Language::Fr, Message::Mode) => "Mode",
+            (Language::En, Message::Bandwidth) => "Bandwidth",
+            (Language::De, Message::Bandwidth) => "Bandbreite",
+            (Language::Fr, Message::Bandwidth) => "Bande",
+            (Language::En, Message::FrameDuration) => "Frame",
+            (Language::De, Message::FrameDuration) => "Frame",
+            (Language::Fr, Message::FrameDuration) => "Trame",
+            (Language::En, Message::Channels) => "Channels",
+            (Language::De, Message::Channels) => "Kanäle",
+            (Language::Fr, Message::Channels) => "Canaux",
+            (Language::En, Message::FramePacking) => "Code",
+            (Language::De, Message::FramePacking) => "Code",
+            (Language::Fr, Message::FramePacking) => "Code",
+            (Language::En, Message::Frames) => "Frames",
+            (Language::De, Message::Frames) => "Frames",
+            (Language::Fr, Message::Frames) => "Trames",
+            (Language::En, Message::ContinuedPacket) => "continued from the previous page",
+            (Language::De, Message::ContinuedPacket) => "Fortsetzung der vorherigen Seite",
+            (Language::Fr, Message::ContinuedPacket) => "suite de la page précédente",
+            (Language::En, Message::FrameLayout) => "Frames of packet",
+            (Language::De, Message::FrameLayout) => "Frames von Paket",
+            (Language::Fr, Message::FrameLayout) => "Trames du paquet",
+            (Language::En, Message::Converted) => "Converted",
+            (Language::De, Message::Converted) => "Konvertiert",
+            (Language::Fr, Message::Converted) => "Converti",
+            (Language::En, Message::Skipped) => "Skipped",
+            (Language::De, Message::Skipped) => "Übersprungen",
+            (Language::Fr, Message::Skipped) => "Ignoré",
+            (Language::En, Message::Failed) => "Failed",
+            (Language::De, Message::Failed) => "Fehlgeschlagen",
+            (Language::Fr, Message::Failed) => "Échoué",
+            (Language::En, Message::FailedInputs) => "Failed inputs",
+            (Language::De, Message::FailedInputs) => "Fehlgeschlagene Eingaben",
+            (Language::Fr, Message::FailedInputs) => "Entrées en échec",
+            (Language::En, Message::Truncated) => "truncated",
+            (Language::De, Message::Truncated) => "gekürzt",
+            (Language::Fr, Message::Truncated) => "tronqué",
+            (Language::En, Message::NotStarted) => "Not started",
+            (Language::De, Message::NotStarted) => "Nicht gestartet",
+            (Language::Fr, Message::NotStarted) => "Non démarré",
+            (Language::En, Message::Name) => "Name",
+            (Language::De, Message::Name) => "Name",
+            (Language::Fr, Message::Name) => "Nom",
+            (Language::En, Message::Chapters) => "Chapters",
+            (Language::De, Message::Chapters) => "Kapitel",
+            (Language::Fr, Message::Chapters) => "Chapitres",
+            (Language::En, Message::Valid) => "Valid",
+            (Language::De, Message::Valid) => "Gültig",
+            (Language::Fr, Message::Valid) => "Valide",
+            (Language::En, Message::Title) => "Title",
+            (Language::De, Message::Title) => "Titel",
+            (Language::Fr, Message::Title) => "Titre",
+            (Language::En, Message::AudioIdMatch) => "audio id only",
+            (Language::De, Message::AudioIdMatch) => "nur Audio-ID",
+            (Language::Fr, Message::AudioIdMatch) => "ID audio seulement",
+            (Language::En, Message::UnknownTonie) => "unknown",
+            (Language::De, Message::UnknownTonie) => "unbekannt",
+            (Language::Fr, Message::UnknownTonie) => "inconnu",
+            (Language::En, Message::Source) => "Content",
+            (Language::De, Message::Source) => "Inhalt",
+            (Language::Fr, Message::Source) => "Contenu",
+            (Language::En, Message::Remaining) => "Remaining",
+            (Language::De, Message::Remaining) => "Frei",
+            (Language::Fr, Message::Remaining) => "Restant",
+            (Language::En, Message::Id) => "ID",
+            (Language::De, Message::Id) => "ID",
+            (Language::Fr, Message::Id) => "ID",
+        };
+    }
+}
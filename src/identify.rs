@@ -0,0 +1,141 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use audio2tonie::tonies::{MatchKind, ToniesDatabase, ToniesEntry};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use crate::i18n::{Language, Message};
+use crate::info::get_header_info;
+use crate::list::find_tonie_files;
+
+/// The entry of the database a Tonie file was matched with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identification {
+    pub path: PathBuf,
+    pub audio_id: u32,
+    /// The matching entry, `None` for unknown content, e.g. custom Tonie files.
+    pub tonie: Option<(ToniesEntry, MatchKind)>,
+}
+
+/// Identifies Tonie files with the `tonies.json` database. Directories are searched for Tonie files like with
+/// `list`, so a copy of the `CONTENT` directory of the SD card can be identified at once.
+///
+/// # Arguments
+///
+/// * `inputs` - Tonie files or directories.
+/// * `database` - The database of official Tonies.
+/// * `limits` - Caps for the input size, header size and number of pages of every file.
+pub fn identify_tonie_files(
+    inputs: &[PathBuf],
+    database: &ToniesDatabase,
+    limits: &Limits,
+) -> Result<Vec<Identification>> {
+    let mut identifications = vec![];
+    for input in inputs {
+        let (base_dir, tonie_files) = match input.is_dir() {
+            true => (input.as_path(), find_tonie_files(input)?),
+            false => (Path::new(""), vec![input.clone()]),
+        };
+        for tonie_file in tonie_files {
+            let header_info = get_header_info(&tonie_file, limits)?;
+            identifications.push(Identification {
+                path: tonie_file
+                    .strip_prefix(base_dir)
+                    .unwrap_or(&tonie_file)
+                    .to_path_buf(),
+                audio_id: header_info.audio_id,
+                tonie: database
+                    .identify(header_info.audio_id, &header_info.sha1_hex())
+                    .map(|(entry, kind)| (entry.clone(), kind)),
+            });
+        }
+    }
+
+    return Ok(identifications);
+}
+
+/// Prints the series and episode of every Tonie file, or that it is unknown.
+///
+/// # Arguments
+///
+/// * `identifications` - The result of `identify_tonie_files`.
+/// * `language` - The language of the table headers.
+/// * `json` - Print the identifications as JSON array instead of a table.
+pub fn print_identifications(
+    identifications: &[Identification],
+    language: Language,
+    json: bool,
+) -> Result<()> {
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&identifications_json(identifications))?
+        );
+        return Ok(());
+    }
+
+    let names = identifications
+        .iter()
+        .map(|identification| identification.path.display().to_string())
+        .collect::<Vec<_>>();
+    let name_width = names
+        .iter()
+        .map(|name| name.chars().count())
+        .chain([language.translate(Message::Name).chars().count()])
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "{:<name_width$} {:>10} {}",
+        language.translate(Message::Name),
+        language.translate(Message::AudioId),
+        language.translate(Message::Title)
+    );
+    for (identification, name) in identifications.iter().zip(&names) {
+        let title = match &identification.tonie {
+            Some((entry, MatchKind::Hash)) => entry.display_name(),
+            Some((entry, MatchKind::AudioId)) => format!(
+                "{} ({})",
+                entry.display_name(),
+                language.translate(Message::AudioIdMatch)
+            ),
+            None => language.translate(Message::UnknownTonie).to_string(),
+        };
+        println!(
+            "{:<name_width$} {:>10} {}",
+            name, identification.audio_id, title
+        );
+    }
+
+    return Ok(());
+}
+
+/// The identifications of Tonie files as JSON array.
+///
+/// # Arguments
+///
+/// * `identifications` - The result of `identify_tonie_files`.
+pub fn identifications_json(identifications: &[Identification]) -> Value {
+    return identifications
+        .iter()
+        .map(|identification| {
+            let tonie = identification.tonie.as_ref().map(|(entry, kind)| {
+                json!({
+                    "match": kind.name(),
+                    "model": entry.model,
+                    "series": entry.series,
+                    "episodes": entry.episodes,
+                    "title": entry.title,
+                    "language": entry.language,
+                    "tracks": entry.tracks,
+                    "pic": entry.pic,
+                })
+            });
+            json!({
+                "path": identification.path,
+                "audio_id": identification.audio_id,
+                "tonie": tonie,
+            })
+        })
+        .collect();
+}
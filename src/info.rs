@@ -0,0 +1,297 @@
+use anyhow::{anyhow, Result};
+use audio2tonie::limits::Limits;
+use audio2tonie::taf::{audio_offset, header_length, OggPageIterator, TONIEFILE_FRAME_SIZE};
+use audio2tonie::utils::{check_input_limits, format_duration, format_timestamp};
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+use toniefile::Toniefile;
+
+use crate::i18n::{Language, Message};
+
+const OPUS_SAMPLE_RATE: f64 = 48000.0;
+
+/// The contents of the protobuf header of a Tonie file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderInfo {
+    /// The size of the protobuf header in bytes, excluding the length prefix.
+    pub header_size: usize,
+    /// The SHA1 hash of the audio data.
+    pub sha1_hash: Vec<u8>,
+    /// The length of the audio data in bytes.
+    pub data_length: u64,
+    /// The audio id, which is the Unix timestamp of the creation for Tonie files created by Boxine.
+    pub audio_id: u32,
+    /// The 4kb block index of the first page of every chapter.
+    pub track_page_nums: Vec<u32>,
+}
+
+impl HeaderInfo {
+    /// The SHA1 hash as lowercase hex string.
+    pub fn sha1_hex(&self) -> String {
+        return self
+            .sha1_hash
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+    }
+}
+
+/// Durations derived from the granule positions of the Ogg pages of a Tonie file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudioInfo {
+    /// The granule position at the start of every chapter, after the pre-skip of the stream.
+    pub chapter_start_granules: Vec<u64>,
+    /// The duration of every chapter in seconds.
+    pub chapter_durations: Vec<f64>,
+    /// The encoded size of every chapter in bytes, including the Ogg page headers.
+    pub chapter_sizes: Vec<u64>,
+    /// The number of Ogg pages of every chapter.
+    pub chapter_page_counts: Vec<usize>,
+}
+
+impl AudioInfo {
+    /// The start time of every chapter in seconds.
+    pub fn chapter_starts(&self) -> Vec<f64> {
+        let first_granule = self
+            .chapter_start_granules
+            .first()
+            .copied()
+            .unwrap_or_default();
+        return self
+            .chapter_start_granules
+            .iter()
+            .map(|granule| (granule - first_granule) as f64 / OPUS_SAMPLE_RATE)
+            .collect();
+    }
+
+    /// The average bitrate of every chapter in kbit/s, including the Ogg page headers.
+    pub fn chapter_bitrates(&self) -> Vec<f64> {
+        return self
+            .chapter_sizes
+            .iter()
+            .zip(&self.chapter_durations)
+            .map(|(size, duration)| match *duration > 0.0 {
+                true => *size as f64 * 8.0 / duration / 1000.0,
+                false => 0.0,
+            })
+            .collect();
+    }
+
+    /// The total duration in seconds.
+    pub fn total_duration(&self) -> f64 {
+        return self.chapter_durations.iter().sum();
+    }
+}
+
+/// Reads the header of a Tonie file.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+pub fn get_header_info(input_file_path: &Path, limits: &Limits) -> Result<HeaderInfo> {
+    let mut tonie_file = File::open(input_file_path)?;
+    check_input_limits(&mut tonie_file, limits)?;
+
+    let mut length_prefix = [0u8; 4];
+    tonie_file.read_exact(&mut length_prefix)?;
+    tonie_file.rewind()?;
+    let tonie_header = Toniefile::parse_header(&mut tonie_file)?;
+
+    return Ok(HeaderInfo {
+        header_size: header_length(&length_prefix).unwrap_or_default(),
+        sha1_hash: tonie_header.sha1_hash,
+        data_length: tonie_header.num_bytes,
+        audio_id: tonie_header.audio_id,
+        track_page_nums: tonie_header.track_page_nums,
+    });
+}
+
+/// Computes the chapter durations of a Tonie file without decoding the audio.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `header_info` - The header of the Tonie file with the chapter page numbers.
+/// * `limits` - Caps for the input size, header size and number of pages.
+pub fn get_audio_info(
+    input_file_path: &Path,
+    header_info: &HeaderInfo,
+    limits: &Limits,
+) -> Result<AudioInfo> {
+    let mut tonie_file = File::open(input_file_path)?;
+    check_input_limits(&mut tonie_file, limits)?;
+    let mut tonie_data = vec![];
+    tonie_file.read_to_end(&mut tonie_data)?;
+    let audio_offset =
+        audio_offset(&tonie_data).ok_or_else(|| anyhow!("The Tonie file is too short."))?;
+
+    let chapter_count = header_info.track_page_nums.len().max(1);
+    let mut chapter_end_granules = vec![0u64; chapter_count];
+    let mut chapter_sizes = vec![0u64; chapter_count];
+    let mut chapter_page_counts = vec![0usize; chapter_count];
+    let mut pre_skip = 0;
+
+    for page in OggPageIterator::with_limits(&tonie_data[audio_offset..], *limits) {
+        let (page_offset, page) = page?;
+        if page.is_begin_of_stream() && page.data.starts_with(b"OpusHead") && page.data.len() >= 12
+        {
+            pre_skip = u16::from_le_bytes([page.data[10], page.data[11]]) as u64;
+        }
+
+        let block = (page_offset / TONIEFILE_FRAME_SIZE) as u32;
+        let chapter = header_info
+            .track_page_nums
+            .iter()
+            .rposition(|page_num| *page_num <= block)
+            .unwrap_or_default();
+        chapter_end_granules[chapter] = chapter_end_granules[chapter].max(page.granule_position);
+        chapter_sizes[chapter] += page.size() as u64;
+        chapter_page_counts[chapter] += 1;
+    }
+
+    let mut chapter_start = pre_skip;
+    let mut chapter_start_granules = vec![];
+    let chapter_durations = chapter_end_granules
+        .into_iter()
+        .map(|chapter_end| {
+            chapter_start_granules.push(chapter_start);
+            let samples = chapter_end.saturating_sub(chapter_start);
+            chapter_start = chapter_start.max(chapter_end);
+            samples as f64 / OPUS_SAMPLE_RATE
+        })
+        .collect();
+
+    return Ok(AudioInfo {
+        chapter_start_granules,
+        chapter_durations,
+        chapter_sizes,
+        chapter_page_counts,
+    });
+}
+
+/// Prints the header details and the chapter durations of a Tonie file.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+/// * `language` - The language of the labels.
+/// * `json` - Print the details as JSON object instead of text.
+pub fn print_info(
+    input_file_path: &Path,
+    limits: &Limits,
+    language: Language,
+    json: bool,
+) -> Result<()> {
+    if json {
+        let info = info_json(input_file_path, limits)?;
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    let header_info = get_header_info(input_file_path, limits)?;
+    let audio_info = get_audio_info(input_file_path, &header_info, limits)?;
+
+    let sha1_hash = header_info.sha1_hex();
+    let rows = [
+        (
+            Message::HeaderSize,
+            format!("{} bytes", header_info.header_size),
+        ),
+        (Message::Sha1Hash, sha1_hash),
+        (
+            Message::DataLength,
+            format!("{} bytes", header_info.data_length),
+        ),
+        (
+            Message::AudioId,
+            format!(
+                "{} (0x{:08X}, {})",
+                header_info.audio_id,
+                header_info.audio_id,
+                format_timestamp(header_info.audio_id as u64)
+            ),
+        ),
+        (
+            Message::ChapterPages,
+            format!("{:?}", header_info.track_page_nums),
+        ),
+        (
+            Message::TotalDuration,
+            format_duration(audio_info.total_duration()),
+        ),
+    ];
+    for (label, value) in rows {
+        println!("{:<16} {}", language.translate(label), value);
+    }
+
+    println!();
+    println!(
+        "{:<8} {:>9} {:>9} {:>9} {:>12} {:>6}",
+        language.translate(Message::Chapter),
+        language.translate(Message::Start),
+        language.translate(Message::Duration),
+        language.translate(Message::Size),
+        language.translate(Message::Bitrate),
+        language.translate(Message::Pages)
+    );
+    let chapter_starts = audio_info.chapter_starts();
+    let chapter_bitrates = audio_info.chapter_bitrates();
+    for index in 0..audio_info.chapter_durations.len() {
+        println!(
+            "{:<8} {:>9} {:>9} {:>9} {:>12} {:>6}",
+            format!("{:02}", index + 1),
+            format_duration(chapter_starts[index]),
+            format_duration(audio_info.chapter_durations[index]),
+            format!(
+                "{:.1} MB",
+                audio_info.chapter_sizes[index] as f64 / 1_000_000.0
+            ),
+            format!("{:.0} kbit/s", chapter_bitrates[index]),
+            audio_info.chapter_page_counts[index]
+        );
+    }
+
+    return Ok(());
+}
+
+/// Collects the header details and the chapter table of a Tonie file as JSON object.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+pub fn info_json(input_file_path: &Path, limits: &Limits) -> Result<Value> {
+    let header_info = get_header_info(input_file_path, limits)?;
+    let audio_info = get_audio_info(input_file_path, &header_info, limits)?;
+
+    let chapter_starts = audio_info.chapter_starts();
+    let chapter_bitrates = audio_info.chapter_bitrates();
+    let chapters = (0..audio_info.chapter_durations.len())
+        .map(|index| {
+            json!({
+                "number": index + 1,
+                "start": chapter_starts[index],
+                "start_granule": audio_info.chapter_start_granules[index],
+                "duration": audio_info.chapter_durations[index],
+                "size": audio_info.chapter_sizes[index],
+                "bitrate": chapter_bitrates[index],
+                "pages": audio_info.chapter_page_counts[index],
+            })
+        })
+        .collect::<Vec<_>>();
+
+    return Ok(json!({
+        "path": input_file_path,
+        "header_size": header_info.header_size,
+        "sha1_hash": header_info.sha1_hex(),
+        "data_length": header_info.data_length,
+        "audio_id": header_info.audio_id,
+        "track_page_nums": header_info.track_page_nums,
+        "duration": audio_info.total_duration(),
+        "chapters": chapters,
+    }));
+}
@@ -0,0 +1,83 @@
+//! Input files of the extraction, validation and analysis. With the `mmap` feature they are memory-mapped, so the
+//! pages are parsed directly from the mapping and seeking to a chapter does not read the data in between.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// A Tonie file or Ogg file opened for reading, memory-mapped with the `mmap` feature.
+pub enum InputFile {
+    File(File),
+    #[cfg(feature = "mmap")]
+    Mapped(std::io::Cursor<memmap2::Mmap>),
+}
+
+impl InputFile {
+    /// Opens the file, with the `mmap` feature by mapping it into memory. The file must not be modified while it
+    /// is mapped.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the file.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        #[cfg(feature = "mmap")]
+        {
+            // SAFETY: The mapping is read-only. Modifying the file while it is read is not supported, just like
+            // with buffered reading
+            let mapping = unsafe { memmap2::Mmap::map(&file)? };
+            return Ok(InputFile::Mapped(std::io::Cursor::new(mapping)));
+        }
+        #[cfg(not(feature = "mmap"))]
+        return Ok(InputFile::File(file));
+    }
+
+    /// The size of the file in bytes.
+    pub fn len(&self) -> std::io::Result<u64> {
+        return match self {
+            InputFile::File(file) => Ok(file.metadata()?.len()),
+            #[cfg(feature = "mmap")]
+            InputFile::Mapped(mapping) => Ok(mapping.get_ref().len() as u64),
+        };
+    }
+
+    /// Whether the file is empty.
+    pub fn is_empty(&self) -> std::io::Result<bool> {
+        return Ok(self.len()? == 0);
+    }
+
+    /// The complete content of the file. A mapped file is borrowed, other files are read into memory.
+    pub fn read_all(&mut self) -> std::io::Result<Cow<'_, [u8]>> {
+        return match self {
+            InputFile::File(file) => {
+                let mut data = vec![];
+                file.rewind()?;
+                file.read_to_end(&mut data)?;
+                Ok(Cow::Owned(data))
+            }
+            #[cfg(feature = "mmap")]
+            InputFile::Mapped(mapping) => Ok(Cow::Borrowed(&mapping.get_ref()[..])),
+        };
+    }
+}
+
+impl Read for InputFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        return match self {
+            InputFile::File(file) => file.read(buf),
+            #[cfg(feature = "mmap")]
+            InputFile::Mapped(mapping) => mapping.read(buf),
+        };
+    }
+}
+
+impl Seek for InputFile {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        return match self {
+            InputFile::File(file) => file.seek(position),
+            #[cfg(feature = "mmap")]
+            InputFile::Mapped(mapping) => mapping.seek(position),
+        };
+    }
+}
@@ -0,0 +1,126 @@
+//! Core of the audio2tonie converter. The Ogg page, Opus packet and Tonie file framing logic only depends on `core`
+//! and `alloc`, so it can be reused without the standard library (e.g. on embedded firmware) by disabling
+//! the default `std` feature.
+//!
+//! With the `std` feature the complete conversion pipeline used by the command line tool is available,
+//! so other applications can embed it without shelling out to the binary:
+//!
+//! ```no_run
+//! use audio2tonie::{convert_to_tonie, ConvertOptions};
+//! use std::path::{Path, PathBuf};
+//!
+//! convert_to_tonie(
+//!     &PathBuf::from("my_audio_files/"),
+//!     Path::new("500304E0"),
+//!     &ConvertOptions::default(),
+//! )?;
+//! # Ok::<(), audio2tonie::Audio2TonieError>(())
+//! ```
+//!
+//! All settings of a conversion are part of [`ConvertOptions`]. Build them with [`ConvertOptions::builder`], which
+//! sets the ones you need and takes the defaults for the rest, so new options do not break existing callers:
+//!
+//! ```no_run
+//! use audio2tonie::{convert_to_tonie, ConvertOptions};
+//! use std::path::{Path, PathBuf};
+//!
+//! let options = ConvertOptions::builder()
+//!     .bitrate(64)
+//!     .cbr(true)
+//!     .audio_id(0x12345678)
+//!     .comments(vec![String::from("TITLE=Bedtime stories")])
+//!     .ffmpeg("/opt/ffmpeg/bin/ffmpeg")
+//!     .build();
+//! convert_to_tonie(&PathBuf::from("story.mp3"), Path::new("500304E0"), &options)?;
+//! # Ok::<(), audio2tonie::Audio2TonieError>(())
+//! ```
+
+//! There is no `Converter` object: a conversion is a single call of [`convert_to_tonie`] or
+//! [`convert_to_writer`] with its [`ConvertOptions`], which hold everything a converter instance would.
+//! The Ogg pages and the Opus packets of a Tonie file can be parsed with [`OggPage`] and [`OpusPacket`].
+
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::needless_return)]
+
+extern crate alloc;
+
+#[cfg(feature = "auto-ffmpeg")]
+pub mod auto_ffmpeg;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod cancel;
+#[cfg(feature = "std")]
+pub mod convert;
+#[cfg(feature = "std")]
+pub mod cue;
+#[cfg(feature = "std")]
+pub mod decode;
+#[cfg(feature = "std")]
+pub mod download;
+#[cfg(feature = "std")]
+pub mod encode;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
+pub mod extract;
+#[cfg(feature = "std")]
+pub mod fade;
+#[cfg(feature = "std")]
+pub mod hashing;
+#[cfg(feature = "std")]
+pub mod header;
+#[cfg(feature = "std")]
+pub mod hooks;
+#[cfg(feature = "std")]
+pub mod input;
+pub mod limits;
+#[cfg(feature = "std")]
+pub mod loudness;
+pub mod ogg_page;
+pub mod opus_packet;
+#[cfg(feature = "std")]
+pub mod passthrough;
+#[cfg(feature = "std")]
+pub mod play;
+#[cfg(feature = "std")]
+pub mod podcast;
+#[cfg(feature = "std")]
+pub mod progress;
+#[cfg(feature = "std")]
+pub mod recode;
+#[cfg(feature = "std")]
+pub mod sd_card;
+#[cfg(feature = "std")]
+pub mod serve;
+#[cfg(feature = "std")]
+pub mod silence;
+#[cfg(feature = "std")]
+pub mod split;
+#[cfg(feature = "std")]
+pub mod state;
+pub mod taf;
+#[cfg(feature = "std")]
+pub mod tap;
+#[cfg(feature = "std")]
+pub mod teddycloud;
+#[cfg(feature = "std")]
+pub mod throttle;
+#[cfg(feature = "std")]
+pub mod tonie_cloud;
+#[cfg(feature = "std")]
+pub mod tonies;
+#[cfg(feature = "std")]
+pub mod utils;
+#[cfg(feature = "std")]
+pub mod watch;
+
+#[cfg(feature = "std")]
+pub use convert::{convert_to_tonie, convert_to_writer, ConvertOptions, ConvertOptionsBuilder};
+#[cfg(feature = "std")]
+pub use error::Audio2TonieError;
+#[cfg(feature = "std")]
+pub use extract::{extract_tonie_to_opus, ExtractOptions};
+pub use limits::Limits;
+pub use ogg_page::OggPage;
+pub use opus_packet::OpusPacket;
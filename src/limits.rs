@@ -0,0 +1,85 @@
+use core::fmt::{Display, Formatter};
+
+use crate::taf::{MAX_AUDIO_LENGTH, TONIEFILE_FRAME_SIZE, TONIEFILE_HEADER_SIZE};
+
+/// The size of the largest Tonie file: the header block and the longest audio data, see [`MAX_AUDIO_LENGTH`].
+pub const DEFAULT_MAX_TOTAL_BYTES: u64 = TONIEFILE_HEADER_SIZE as u64 + MAX_AUDIO_LENGTH;
+pub const DEFAULT_MAX_PAGES: usize = DEFAULT_MAX_TOTAL_BYTES as usize / TONIEFILE_FRAME_SIZE;
+pub const DEFAULT_MAX_HEADER_SIZE: usize = TONIEFILE_HEADER_SIZE;
+
+/// Caps enforced while parsing untrusted or corrupted Tonie files to bound memory usage and parsing time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// Maximum number of Ogg pages parsed from a single input.
+    pub max_pages: usize,
+    /// Maximum size of a single input in bytes.
+    pub max_total_bytes: u64,
+    /// Maximum size of the protobuf header in bytes.
+    pub max_header_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_pages: DEFAULT_MAX_PAGES,
+            max_total_bytes: DEFAULT_MAX_TOTAL_BYTES,
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+        }
+    }
+}
+
+/// Errors raised when an input exceeds one of the configured [`Limits`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum LimitError {
+    TooManyPages(usize),
+    TooManyBytes(u64),
+    HeaderTooLarge(usize),
+}
+
+impl Display for LimitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            LimitError::TooManyPages(max) => {
+                write!(f, "Input exceeds the limit of {} Ogg pages.", max)
+            }
+            LimitError::TooManyBytes(max) => {
+                write!(f, "Input exceeds the size limit of {} bytes.", max)
+            }
+            LimitError::HeaderTooLarge(max) => {
+                write!(f, "Tonie header exceeds the size limit of {} bytes.", max)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LimitError {}
+
+impl Limits {
+    /// Checks the total size of an input in bytes.
+    pub fn check_total_bytes(&self, total_bytes: u64) -> Result<(), LimitError> {
+        if total_bytes > self.max_total_bytes {
+            return Err(LimitError::TooManyBytes(self.max_total_bytes));
+        }
+
+        return Ok(());
+    }
+
+    /// Checks the size of the protobuf header as announced by its length prefix.
+    pub fn check_header_size(&self, header_size: usize) -> Result<(), LimitError> {
+        if header_size > self.max_header_size {
+            return Err(LimitError::HeaderTooLarge(self.max_header_size));
+        }
+
+        return Ok(());
+    }
+
+    /// Checks the number of pages parsed so far.
+    pub fn check_pages(&self, page_count: usize) -> Result<(), LimitError> {
+        if page_count > self.max_pages {
+            return Err(LimitError::TooManyPages(self.max_pages));
+        }
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use audio2tonie::sd_card::TagUid;
+use audio2tonie::utils::format_duration;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use crate::check::check_tonie_file;
+use crate::i18n::{Language, Message};
+use crate::info::{get_audio_info, get_header_info};
+
+/// A Tonie file found in a library directory or on an SD card.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LibraryEntry {
+    /// The path relative to the listed directory.
+    pub path: PathBuf,
+    /// The file size in bytes.
+    pub size: u64,
+    /// The audio id of the header, `None` if the header cannot be read.
+    pub audio_id: Option<u32>,
+    /// The total duration in seconds, `None` if the header cannot be read.
+    pub duration: Option<f64>,
+    /// The number of chapters, `None` if the header cannot be read.
+    pub chapters: Option<usize>,
+    /// The problems found by the checks of `check`, empty for valid files.
+    pub errors: Vec<String>,
+}
+
+impl LibraryEntry {
+    pub fn is_valid(&self) -> bool {
+        return self.errors.is_empty();
+    }
+}
+
+/// Finds the Tonie files in a directory and its subdirectories, i.e. files with the extension `.taf` and files in
+/// the `CONTENT` layout of the SD card, e.g. `CONTENT/5634121E/500304E0`. Hidden files and directories are
+/// skipped. Returns the paths sorted by name.
+///
+/// # Arguments
+///
+/// * `directory` - The directory to search.
+pub fn find_tonie_files(directory: &Path) -> Result<Vec<PathBuf>> {
+    let mut tonie_files = vec![];
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                directories.push(path);
+            } else if is_tonie_file_path(&path) {
+                tonie_files.push(path);
+            }
+        }
+    }
+    tonie_files.sort();
+
+    return Ok(tonie_files);
+}
+
+/// Checks whether a path names a Tonie file, by its `.taf` extension or its location on the SD card.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+fn is_tonie_file_path(path: &Path) -> bool {
+    let is_taf = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("taf"));
+    return is_taf || TagUid::from_content_path(path).is_ok();
+}
+
+/// Reads the header, the durations and the validity of every Tonie file in a directory and its subdirectories.
+/// Files which cannot be read are listed as invalid instead of failing the listing.
+///
+/// # Arguments
+///
+/// * `directory` - The directory to list, e.g. the root of the SD card.
+/// * `limits` - Caps for the input size, header size and number of pages of every file.
+pub fn list_tonie_files(directory: &Path, limits: &Limits) -> Result<Vec<LibraryEntry>> {
+    return find_tonie_files(directory)?
+        .into_iter()
+        .map(|path| {
+            let mut entry = LibraryEntry {
+                path: path.strip_prefix(directory).unwrap_or(&path).to_path_buf(),
+                size: std::fs::metadata(&path)?.len(),
+                audio_id: None,
+                duration: None,
+                chapters: None,
+                errors: vec![],
+            };
+
+            let info = get_header_info(&path, limits).and_then(|header_info| {
+                let audio_info = get_audio_info(&path, &header_info, limits)?;
+                return Ok((header_info, audio_info));
+            });
+            match info {
+                Ok((header_info, audio_info)) => {
+                    entry.audio_id = Some(header_info.audio_id);
+                    entry.duration = Some(audio_info.total_duration());
+                    entry.chapters = Some(audio_info.chapter_durations.len());
+                }
+                Err(error) => {
+                    entry.errors.push(error.to_string());
+                    return Ok(entry);
+                }
+            }
+            match check_tonie_file(&path, limits) {
+                Ok(results) => entry
+                    .errors
+                    .extend(results.into_iter().filter_map(|result| result.error)),
+                Err(error) => entry.errors.push(error.to_string()),
+            }
+
+            return Ok(entry);
+        })
+        .collect();
+}
+
+/// Prints a table of the Tonie files in a directory with their audio id, duration, number of chapters, size and
+/// validity. The problems of invalid files are printed below the table.
+///
+/// # Arguments
+///
+/// * `directory` - The directory to list.
+/// * `limits` - Caps for the input size, header size and number of pages of every file.
+/// * `language` - The language of the table headers.
+/// * `json` - Print the files as JSON array instead of a table.
+pub fn print_list(directory: &Path, limits: &Limits, language: Language, json: bool) -> Result<()> {
+    let entries = list_tonie_files(directory, limits)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&list_json(&entries))?);
+        return Ok(());
+    }
+
+    let names = entries
+        .iter()
+        .map(|entry| entry.path.display().to_string())
+        .collect::<Vec<_>>();
+    let name_width = names
+        .iter()
+        .map(|name| name.chars().count())
+        .chain([language.translate(Message::Name).chars().count()])
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "{:<name_width$} {:>10} {:>9} {:>8} {:>9} {}",
+        language.translate(Message::Name),
+        language.translate(Message::AudioId),
+        language.translate(Message::Duration),
+        language.translate(Message::Chapters),
+        language.translate(Message::Size),
+        language.translate(Message::Valid)
+    );
+    for (entry, name) in entries.iter().zip(&names) {
+        println!(
+            "{:<name_width$} {:>10} {:>9} {:>8} {:>9} {}",
+            name,
+            entry.audio_id.map(|id| id.to_string()).unwrap_or_default(),
+            entry.duration.map(format_duration).unwrap_or_default(),
+            entry
+                .chapters
+                .map(|count| count.to_string())
+                .unwrap_or_default(),
+            format!("{:.1} MB", entry.size as f64 / 1_000_000.0),
+            language.translate(match entry.is_valid() {
+                true => Message::CheckPassed,
+                false => Message::CheckFailed,
+            })
+        );
+    }
+
+    let invalid_entries = entries.iter().filter(|entry| !entry.is_valid());
+    for (index, entry) in invalid_entries.enumerate() {
+        if index == 0 {
+            println!();
+        }
+        println!("{}: {}", entry.path.display(), entry.errors.join(", "));
+    }
+
+    return Ok(());
+}
+
+/// The Tonie files of a listing as JSON array.
+///
+/// # Arguments
+///
+/// * `entries` - The result of `list_tonie_files`.
+pub fn list_json(entries: &[LibraryEntry]) -> Value {
+    return entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "path": entry.path,
+                "size": entry.size,
+                "audio_id": entry.audio_id,
+                "duration": entry.duration,
+                "chapters": entry.chapters,
+                "valid": entry.is_valid(),
+                "errors": entry.errors,
+            })
+        })
+        .collect();
+}
@@ -0,0 +1,260 @@
+//! Loudness measurement following ITU-R BS.1770 / EBU R128 for 48 kHz interleaved 16 bit PCM.
+
+use std::f64::consts::PI;
+
+const SAMPLE_RATE: usize = 48000;
+// Gating blocks are 400ms long and overlap by 75%, i.e. a new block starts every 100ms
+const SUB_BLOCK_SIZE: usize = SAMPLE_RATE / 10;
+const SUB_BLOCKS_PER_BLOCK: usize = 4;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+// K-weighting pre-filter (high shelf) and RLB high pass coefficients for 48 kHz from BS.1770
+const SHELF_B: [f64; 3] = [1.53512485958697, -2.69169618940638, 1.19839281085285];
+const SHELF_A: [f64; 3] = [1.0, -1.69065929318241, 0.73248077421585];
+const HIGH_PASS_B: [f64; 3] = [1.0, -2.0, 1.0];
+const HIGH_PASS_A: [f64; 3] = [1.0, -1.99004745483398, 0.99007225036621];
+
+// True peak detection oversamples by a factor of 4 using a polyphase interpolation filter
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+#[derive(Clone, Debug, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    z1: f64,
+    z2: f64,
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Biquad {
+            b,
+            a,
+            ..Default::default()
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z1;
+        self.z1 = self.b[1] * x - self.a[1] * y + self.z2;
+        self.z2 = self.b[2] * x - self.a[2] * y;
+        return y;
+    }
+}
+
+/// Streaming loudness meter measuring the integrated loudness (LUFS) and true peak (dBTP) of interleaved PCM samples.
+#[derive(Clone, Debug)]
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<(Biquad, Biquad)>,
+    sub_block_energy: f64,
+    sub_block_samples: usize,
+    recent_sub_blocks: Vec<f64>,
+    block_powers: Vec<f64>,
+    interpolation_filter: Vec<f64>,
+    history: Vec<Vec<f64>>,
+    peak: f64,
+    sample_count: u64,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: usize) -> Self {
+        LoudnessMeter {
+            channels,
+            filters: (0..channels)
+                .map(|_| {
+                    (
+                        Biquad::new(SHELF_B, SHELF_A),
+                        Biquad::new(HIGH_PASS_B, HIGH_PASS_A),
+                    )
+                })
+                .collect(),
+            sub_block_energy: 0.0,
+            sub_block_samples: 0,
+            recent_sub_blocks: Vec::with_capacity(SUB_BLOCKS_PER_BLOCK),
+            block_powers: Vec::new(),
+            interpolation_filter: interpolation_filter(),
+            history: vec![vec![0.0; TAPS_PER_PHASE]; channels],
+            peak: 0.0,
+            sample_count: 0,
+        }
+    }
+
+    /// Adds interleaved 16 bit samples to the measurement.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Interleaved samples, following the order: left, right, left, right, ...
+    pub fn add_samples(&mut self, samples: &[i16]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                let x = *sample as f64 / 32768.0;
+
+                let (shelf, high_pass) = &mut self.filters[channel];
+                let weighted = high_pass.process(shelf.process(x));
+                self.sub_block_energy += weighted * weighted;
+
+                self.update_true_peak(channel, x);
+            }
+
+            self.sample_count += 1;
+            self.sub_block_samples += 1;
+            if self.sub_block_samples == SUB_BLOCK_SIZE {
+                self.finish_sub_block();
+            }
+        }
+    }
+
+    fn finish_sub_block(&mut self) {
+        if self.recent_sub_blocks.len() == SUB_BLOCKS_PER_BLOCK {
+            self.recent_sub_blocks.remove(0);
+        }
+        self.recent_sub_blocks.push(self.sub_block_energy);
+        self.sub_block_energy = 0.0;
+        self.sub_block_samples = 0;
+
+        if self.recent_sub_blocks.len() == SUB_BLOCKS_PER_BLOCK {
+            let block_energy: f64 = self.recent_sub_blocks.iter().sum();
+            self.block_powers
+                .push(block_energy / (SUB_BLOCK_SIZE * SUB_BLOCKS_PER_BLOCK) as f64);
+        }
+    }
+
+    fn update_true_peak(&mut self, channel: usize, x: f64) {
+        let history = &mut self.history[channel];
+        history.rotate_right(1);
+        history[0] = x;
+
+        for phase in 0..OVERSAMPLING {
+            let interpolated: f64 = history
+                .iter()
+                .enumerate()
+                .map(|(tap, sample)| sample * self.interpolation_filter[tap * OVERSAMPLING + phase])
+                .sum();
+            self.peak = self.peak.max(interpolated.abs());
+        }
+        self.peak = self.peak.max(x.abs());
+    }
+
+    /// The mean square powers of all complete 400ms gating blocks measured so far.
+    pub fn block_powers(&self) -> &[f64] {
+        return &self.block_powers;
+    }
+
+    /// The gated integrated loudness in LUFS. Returns negative infinity for silence or inputs shorter than one block.
+    pub fn integrated_loudness(&self) -> f64 {
+        return gated_loudness(&self.block_powers);
+    }
+
+    /// The true peak level in dBTP.
+    pub fn true_peak(&self) -> f64 {
+        return 20.0 * self.peak.log10();
+    }
+
+    /// The duration of the measured audio in seconds.
+    pub fn duration(&self) -> f64 {
+        return self.sample_count as f64 / SAMPLE_RATE as f64;
+    }
+}
+
+/// Computes the gated integrated loudness in LUFS from the mean square powers of 400ms blocks.
+///
+/// # Arguments
+///
+/// * `block_powers` - The block powers, possibly collected from several meters.
+pub fn gated_loudness(block_powers: &[f64]) -> f64 {
+    let above_absolute_gate = block_powers
+        .iter()
+        .copied()
+        .filter(|power| power_to_lufs(*power) > ABSOLUTE_GATE_LUFS)
+        .collect::<Vec<_>>();
+    if above_absolute_gate.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+
+    let relative_gate = power_to_lufs(mean(&above_absolute_gate)) + RELATIVE_GATE_LU;
+    let above_relative_gate = above_absolute_gate
+        .into_iter()
+        .filter(|power| power_to_lufs(*power) > relative_gate)
+        .collect::<Vec<_>>();
+    if above_relative_gate.is_empty() {
+        return f64::NEG_INFINITY;
+    }
+
+    return power_to_lufs(mean(&above_relative_gate));
+}
+
+/// Computes the gain in dB needed to bring audio to the target loudness. The gain is reduced
+/// if it would push the true peak above the given ceiling. Silence is left untouched.
+///
+/// # Arguments
+///
+/// * `loudness` - The measured integrated loudness in LUFS.
+/// * `true_peak` - The measured true peak in dBTP.
+/// * `target_loudness` - The target integrated loudness in LUFS.
+/// * `peak_ceiling` - The maximum true peak after applying the gain in dBTP.
+pub fn normalization_gain(
+    loudness: f64,
+    true_peak: f64,
+    target_loudness: f64,
+    peak_ceiling: f64,
+) -> f64 {
+    if !loudness.is_finite() {
+        return 0.0;
+    }
+
+    let gain = target_loudness - loudness;
+    if true_peak.is_finite() && true_peak + gain > peak_ceiling {
+        return peak_ceiling - true_peak;
+    }
+
+    return gain;
+}
+
+/// Applies a gain in dB to 16 bit samples in place, clamping to the valid sample range.
+///
+/// # Arguments
+///
+/// * `samples` - The samples to amplify or attenuate.
+/// * `gain` - The gain in dB.
+pub fn apply_gain(samples: &mut [i16], gain: f64) {
+    if gain == 0.0 {
+        return;
+    }
+
+    let factor = 10f64.powf(gain / 20.0);
+    for sample in samples.iter_mut() {
+        *sample = (*sample as f64 * factor)
+            .round()
+            .clamp(i16::MIN as f64, i16::MAX as f64) as i16;
+    }
+}
+
+fn power_to_lufs(power: f64) -> f64 {
+    return -0.691 + 10.0 * power.log10();
+}
+
+fn mean(values: &[f64]) -> f64 {
+    return values.iter().sum::<f64>() / values.len() as f64;
+}
+
+/// Hann windowed sinc low pass with a cutoff at the original Nyquist frequency, split into polyphase components.
+fn interpolation_filter() -> Vec<f64> {
+    let length = OVERSAMPLING * TAPS_PER_PHASE;
+    let center = (length - 1) as f64 / 2.0;
+
+    return (0..length)
+        .map(|n| {
+            let t = (n as f64 - center) / OVERSAMPLING as f64;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (PI * t).sin() / (PI * t)
+            };
+            let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / length as f64).cos();
+            sinc * window
+        })
+        .collect();
+}
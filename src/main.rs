@@ -1,30 +1,1234 @@
+#![allow(clippy::needless_return)]
+
+mod analyze;
+mod backup;
+mod catalog;
+mod check;
 mod cli;
-mod convert;
-mod extract;
-mod utils;
+mod i18n;
+mod identify;
+mod info;
+mod list;
+mod plan;
+mod restore;
+mod stats;
+mod summary;
+mod tui;
+mod upload;
 
 #[cfg(test)]
 mod tests;
 
-use crate::cli::{get_cli, CLICommands};
-use crate::convert::convert_to_tonie;
-use anyhow::Result;
-use extract::extract_tonie_to_opus;
+use crate::analyze::{print_page_analysis, print_page_inspection};
+use crate::backup::{backup_sd_card, print_backup};
+use crate::catalog::{build_catalog, write_catalog, CatalogFormat};
+use crate::check::print_check_report;
+use crate::cli::{
+    get_cli, split_arguments, split_convert_paths, CLICommands, HeaderCommands, TeddyCloudCommands,
+};
+use crate::restore::{print_restore, read_backup_manifest, restore_backup, RestoreStatus};
+use anyhow::{anyhow, Result};
+use audio2tonie::batch::{read_manifest, run_batch, BatchStatus};
+use audio2tonie::cancel::Cancellation;
+use audio2tonie::convert::{
+    album_output_path, bitrate_for_size, collect_input_files, convert_files_to_size,
+    convert_files_to_tonie, convert_files_to_writer, count_chapters, current_timestamp,
+    find_album_directories, inputs_modified_since, is_stdin_input, output_exists, part_output_path,
+    plan_files, preflight_check, read_input_list, resolve_output_path, split_conversion_plan,
+    ChapterOverflow, ConversionPlan, ConvertOptions, ExistingOutput, FailedTracks, Normalization,
+    SplitLimits, TrackOrder, OVERFLOW_SPLIT_SIZE,
+};
+use audio2tonie::download::{download_url_inputs, DownloadDir};
+use audio2tonie::encode::MIN_BITRATE;
+use audio2tonie::extract::{
+    extract_tonie_to_opus, extract_tonie_to_writer, verify_tonie_file, ExtractOptions,
+};
+use audio2tonie::header::{
+    apply_header_patch, header_json, read_tonie_header, retimestamp_tonie_file,
+};
+use audio2tonie::hooks::{
+    run_post_processors, ConversionMetadata, CoverArt, PostProcessor, ShellCommand, SidecarFile,
+    TeddyCloudCustomJson, TeddyCloudPlaylist, TeddyCloudUpload,
+};
+use audio2tonie::input::InputFile;
+use audio2tonie::play::{play_tonie_file, PlayOptions};
+use audio2tonie::podcast::{download_episodes, fetch_feed, select_episodes, EpisodeSelection};
+use audio2tonie::recode::{recode_tonie_file, RecodeOptions};
+use audio2tonie::sd_card::{TagUid, CONTENT_DIRECTORY};
+use audio2tonie::serve::{ConversionServer, ServeOptions};
+use audio2tonie::split::split_tonie_file;
+use audio2tonie::state::{last_modified, settings_hash, ConversionRecord, ConversionState};
+use audio2tonie::taf::MAX_CHAPTERS;
+use audio2tonie::teddycloud::TeddyCloudClient;
+use audio2tonie::throttle::set_process_priority;
+use audio2tonie::tonie_cloud::{
+    select_creative_tonie, TonieCloudClient, TONIE_CLOUD_API_URL, TONIE_CLOUD_AUTH_URL,
+};
+use audio2tonie::tonies::ToniesDatabase;
+use audio2tonie::utils::{format_duration, limit_path_length, sanitize_file_name};
+use audio2tonie::watch::{watch_directory, WatchOptions};
+use audio2tonie::{Audio2TonieError, Limits};
+use i18n::{Language, Message};
+use identify::{identify_tonie_files, print_identifications};
+use info::{get_audio_info, get_header_info, info_json, print_info};
+use list::print_list;
+use plan::{conversion_plan_json, print_conversion_plan};
+use serde_json::json;
+use stats::print_stats;
+use std::fs::File;
+use std::io::{BufReader, Cursor, IsTerminal, Write};
+use std::path::Path;
+use std::time::Duration;
+use summary::{batch_result_json, print_batch_result, print_batch_summary};
+use tui::run_tui;
+use upload::{
+    print_creative_tonies, print_library_files, print_server_tags, upload_to_teddycloud,
+    upload_to_tonie_cloud,
+};
+
+// The exit code of a process terminated by SIGINT
+const EXIT_CANCELLED: i32 = 130;
 
 fn main() -> Result<()> {
     let cli = get_cli();
 
+    if let Some(niceness) = cli.nice {
+        set_process_priority(niceness)?;
+    }
+    let language = cli.lang.unwrap_or_else(Language::from_env);
+    // The downloaded ffmpeg is found in the tool cache like an installed one
+    #[cfg(feature = "auto-ffmpeg")]
+    if cli.auto_ffmpeg {
+        audio2tonie::auto_ffmpeg::ensure_ffmpeg(|url| {
+            eprintln!("ffmpeg is not installed, downloading it from {}", url);
+        })?;
+    }
+
     match cli.command {
-        CLICommands::Extract { input, output } => {
-            return extract_tonie_to_opus(&input, output);
+        CLICommands::Extract {
+            input,
+            output,
+            transliterate,
+            format,
+            ffmpeg,
+            name_template,
+            no_verify,
+            merge_chapters,
+            chapters,
+            ignore_header,
+            limits,
+        } => {
+            // The chapters are numbered from 1 on the command line
+            let chapters = (!chapters.is_empty()).then(|| {
+                let mut chapters = chapters
+                    .into_iter()
+                    .flatten()
+                    .map(|chapter| chapter - 1)
+                    .collect::<Vec<_>>();
+                chapters.sort();
+                chapters.dedup();
+                chapters
+            });
+            let options = ExtractOptions {
+                limits: limits.into(),
+                transliterate,
+                io_throttle: cli.io_throttle,
+                format,
+                ffmpeg,
+                name_template,
+                verify: !no_verify,
+                merge_chapters,
+                chapters,
+                ignore_header,
+                chapter_titles: None,
+            };
+            if no_verify {
+                if let Err(error) = verify_tonie_file(&input, &options.limits) {
+                    eprintln!("Warning: {}", error);
+                }
+            }
+            if output.as_deref() == Some(Path::new("-")) {
+                if cli.json {
+                    return Err(anyhow!("--json cannot be combined with writing to stdout."));
+                }
+                let mut stdout = std::io::stdout().lock();
+                extract_tonie_to_writer(InputFile::open(&input)?, &mut stdout, &options)?;
+                stdout.flush()?;
+                return Ok(());
+            }
+            let file_paths = extract_tonie_to_opus(&input, output, &options)?;
+            if cli.json && ignore_header {
+                // Without a header the duration of the recovered audio is unknown
+                let report = json!({
+                    "input": input,
+                    "format": options.format.extension(),
+                    "files": file_paths.iter().map(|path| json!({ "path": path })).collect::<Vec<_>>(),
+                });
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else if cli.json {
+                let header_info = get_header_info(&input, &options.limits)?;
+                let audio_info = get_audio_info(&input, &header_info, &options.limits)?;
+                let files = match merge_chapters {
+                    true => file_paths
+                        .iter()
+                        .map(|path| json!({ "path": path, "duration": audio_info.total_duration() }))
+                        .collect::<Vec<_>>(),
+                    false => file_paths
+                        .iter()
+                        .zip(&audio_info.chapter_durations)
+                        .enumerate()
+                        .map(|(index, (path, duration))| {
+                            json!({ "chapter": index + 1, "path": path, "duration": duration })
+                        })
+                        .collect::<Vec<_>>(),
+                };
+                let report = json!({
+                    "input": input,
+                    "format": options.format.extension(),
+                    "files": files,
+                });
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+            return Ok(());
         }
         CLICommands::Convert {
+            inputs,
+            files_from,
+            output,
+            ffmpeg,
+            since,
+            normalize,
+            normalize_album,
+            target_loudness,
+            trim_silence,
+            fade_in,
+            fade_out,
+            speed,
+            post_commands,
+            teddycloud_url,
+            teddycloud_path,
+            sidecar,
+            teddycloud_json,
+            cover,
+            tap,
+            threads,
+            audio_id,
+            recursive,
+            rebuild,
+            max_duration,
+            max_size,
+            split_on_overflow,
+            preflight,
+            target_size,
+            on_too_many_chapters,
+            passthrough,
+            any_extension,
+            order,
+            bitrate,
+            opus_application,
+            cbr,
+            ffmpeg_args,
+            input_format,
+            downloader,
+            failure,
+            dry_run,
+            existing_output,
+            sd_root,
+            tag_uid,
+        } => {
+            let listed_inputs = match files_from {
+                Some(list) if list == Path::new("-") => {
+                    Some(read_input_list(std::io::stdin().lock())?)
+                }
+                Some(list) => Some(read_input_list(BufReader::new(File::open(list)?))?),
+                None => None,
+            };
+            let (inputs, output) = split_convert_paths(inputs, listed_inputs, output)
+                .map_err(|error| anyhow!(error))?;
+            let options = ConvertOptions {
+                ffmpeg,
+                io_throttle: cli.io_throttle,
+                show_progress: std::io::stderr().is_terminal(),
+                normalization: match (normalize, normalize_album) {
+                    (_, true) => Normalization::Album,
+                    (true, _) => Normalization::Track,
+                    _ => Normalization::None,
+                },
+                target_loudness,
+                threads: threads as usize,
+                audio_id,
+                trim_silence,
+                fade_in: Duration::from_millis(fade_in),
+                fade_out: Duration::from_millis(fade_out),
+                speed,
+                on_too_many_chapters,
+                existing_output: match (existing_output.into(), &since) {
+                    // Incremental runs are meant to update their previous output
+                    (ExistingOutput::Fail, Some(_)) => ExistingOutput::Overwrite,
+                    (existing_output, _) => existing_output,
+                },
+                passthrough,
+                any_extension,
+                track_order: order,
+                bitrate,
+                opus_application,
+                cbr,
+                ffmpeg_args: ffmpeg_args
+                    .iter()
+                    .map(|args| split_arguments(args))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|error| anyhow!(error))?
+                    .concat(),
+                input_format,
+                on_track_failure: (&failure).into(),
+                failed_tracks: FailedTracks::new(),
+                cancellation: Cancellation::new(),
+                ..ConvertOptions::default()
+            };
+            if !dry_run {
+                cancel_on_ctrl_c(&options.cancellation)?;
+            }
+
+            let split_output = on_too_many_chapters == ChapterOverflow::SplitOutput;
+            let split_max_size = match split_on_overflow {
+                true => Some(max_size.unwrap_or(u64::MAX).min(OVERFLOW_SPLIT_SIZE)),
+                false => max_size,
+            };
+            let split_limits = (max_duration.is_some() || split_max_size.is_some() || split_output)
+                .then_some(SplitLimits {
+                    max_duration,
+                    max_size: split_max_size,
+                    max_chapters: split_output.then_some(MAX_CHAPTERS),
+                });
+
+            let mut post_processors: Vec<Box<dyn PostProcessor>> = vec![];
+            if sidecar {
+                post_processors.push(Box::new(SidecarFile));
+            }
+            // The cover is written first, so tonies.custom.json references it
+            if let Some(source) = cover {
+                post_processors.push(Box::new(CoverArt::new(source, &options.ffmpeg)));
+            }
+            if tap {
+                post_processors.push(Box::new(TeddyCloudPlaylist::new(&options.ffmpeg)));
+            }
+            if let Some(url) = teddycloud_url {
+                post_processors.push(Box::new(TeddyCloudUpload::new(&url, &teddycloud_path)));
+            }
+            if let Some(path) = teddycloud_json {
+                post_processors.push(Box::new(TeddyCloudCustomJson::new(&path, &options.ffmpeg)));
+            }
+            for command in post_commands {
+                post_processors.push(Box::new(ShellCommand::new(&command)));
+            }
+
+            if inputs.iter().any(|input| is_stdin_input(input))
+                && (recursive
+                    || split_limits.is_some()
+                    || since.is_some()
+                    || dry_run
+                    || preflight
+                    || target_size.is_some()
+                    || options.normalization != Normalization::None)
+            {
+                return Err(anyhow!(
+                    "The audio from stdin can only be decoded once, so it cannot be split, normalized, planned or checked for modifications."
+                ));
+            }
+            // The downloads are removed when the conversions are done
+            let download_dir = DownloadDir::new(&cli.tmp_dir.unwrap_or_else(std::env::temp_dir));
+            let inputs =
+                download_url_inputs(inputs, downloader.as_deref(), &download_dir, |url| {
+                    eprintln!("Downloading {}", url);
+                })?;
+            if output == Path::new("-") {
+                if recursive
+                    || sd_root.is_some()
+                    || split_limits.is_some()
+                    || preflight
+                    || target_size.is_some()
+                    || since.is_some()
+                    || dry_run
+                    || cli.json
+                    || !post_processors.is_empty()
+                {
+                    return Err(anyhow!(
+                        "Writing to stdout only supports a single Tonie file, without splitting, size checks, incremental runs, dry runs, JSON reports or post-processing."
+                    ));
+                }
+                // The header is written last, so the Tonie file is kept in memory until it is complete
+                let input_files = collect_input_files(&inputs, &options)?;
+                let tonie_file =
+                    convert_files_to_writer(&input_files, Cursor::new(vec![]), &options)?;
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(tonie_file.get_ref())?;
+                stdout.flush()?;
+                return Ok(());
+            }
+
+            // Recursive runs record their conversions to skip the directories which did not change since
+            let mut state = (recursive && !dry_run).then(|| ConversionState::load(&output));
+            let settings = settings_hash(&options);
+            // The previous Tonie files of a changed directory are replaced
+            let overwrite_options = ConvertOptions {
+                existing_output: ExistingOutput::Overwrite,
+                ..options.clone()
+            };
+
+            let conversions = if recursive {
+                let [input] = inputs.as_slice() else {
+                    return Err(anyhow!("--recursive takes a single input directory."));
+                };
+                if !dry_run {
+                    std::fs::create_dir_all(&output)?;
+                }
+                find_album_directories(input, options.any_extension)?
+                    .into_iter()
+                    .map(|album| {
+                        let album_output = album_output_path(input, &album, &output);
+                        (vec![album], album_output)
+                    })
+                    .collect()
+            } else if let (Some(sd_root), Some(tag_uid)) = (sd_root, tag_uid) {
+                let content_path = tag_uid.content_path(&sd_root);
+                if !dry_run {
+                    if let Some(content_directory) = content_path.parent() {
+                        std::fs::create_dir_all(content_directory)?;
+                    }
+                }
+                vec![(inputs, content_path)]
+            } else {
+                vec![(inputs, output)]
+            };
+
+            let mut reports = vec![];
+            // The inputs that failed and why, for the summary at the end of the run
+            let mut failures = vec![];
+            let mut failed_conversions = 0;
+            'conversions: for (inputs, output) in conversions {
+                // Reports name a single input as a path and several inputs as a list
+                let input = match inputs.as_slice() {
+                    [input] => json!(input),
+                    inputs => json!(inputs),
+                };
+                let input_names = inputs
+                    .iter()
+                    .map(|input| input.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let output_path = resolve_output_path(&output);
+                // Splitting and the preflight check need the durations of the input files upfront
+                let mut plans =
+                    match dry_run || split_limits.is_some() || preflight || target_size.is_some() {
+                        true => {
+                            let plan =
+                                plan_files(collect_input_files(&inputs, &options)?, &options)?;
+                            match &split_limits {
+                                Some(split_limits) => split_conversion_plan(&plan, split_limits),
+                                None => vec![plan],
+                            }
+                        }
+                        false => vec![],
+                    };
+                let target_bitrate = match target_size {
+                    Some(target_size) => {
+                        let duration = plans.iter().map(ConversionPlan::duration).sum();
+                        let bitrate = bitrate_for_size(duration, target_size, options.bitrate)
+                            .ok_or_else(|| {
+                                anyhow!(
+                                    "{} of audio from {} do not fit into {:.1} MB, even at {} kbit/s.",
+                                    format_duration(duration),
+                                    input_names,
+                                    target_size as f64 / 1_000_000.0,
+                                    MIN_BITRATE
+                                )
+                            })?;
+                        for plan in &mut plans {
+                            plan.bitrate = bitrate;
+                        }
+                        if !cli.json && !dry_run {
+                            eprintln!(
+                                "{}: {} kbit/s",
+                                language.translate(Message::Bitrate),
+                                bitrate
+                            );
+                        }
+                        Some(bitrate)
+                    }
+                    None => None,
+                };
+                let part_outputs = match plans.len() {
+                    0 | 1 => vec![output_path.clone()],
+                    count => (1..=count)
+                        .map(|part| part_output_path(&output_path, part))
+                        .collect(),
+                };
+
+                if dry_run {
+                    for (plan, part_output) in plans.iter().zip(&part_outputs) {
+                        match cli.json {
+                            true => reports.push(conversion_plan_json(plan, part_output)),
+                            false => {
+                                print_conversion_plan(plan, part_output, language)?;
+                                println!();
+                            }
+                        }
+                    }
+                    continue;
+                }
+                if let Some(since) = &since {
+                    let mut modified = false;
+                    for input in &inputs {
+                        modified |=
+                            inputs_modified_since(input, &output, since, options.any_extension)?;
+                    }
+                    if !modified {
+                        match cli.json {
+                            true => reports.push(json!({
+                                "input": input,
+                                "output": output_path,
+                                "status": "skipped",
+                                "warnings": [language.translate(Message::InputsNotModified)],
+                            })),
+                            false => println!("{}", language.translate(Message::InputsNotModified)),
+                        }
+                        continue;
+                    }
+                }
+
+                let recorded = match (&state, inputs.as_slice()) {
+                    (Some(state), [input]) => {
+                        let modified = last_modified(input, &options)?;
+                        if !rebuild && state.is_up_to_date(input, modified, &settings) {
+                            match cli.json {
+                                true => reports.push(json!({
+                                    "input": input,
+                                    "output": output_path,
+                                    "status": "skipped",
+                                    "warnings": [language.translate(Message::InputsNotModified)],
+                                })),
+                                false => {
+                                    println!("{}", language.translate(Message::InputsNotModified))
+                                }
+                            }
+                            continue;
+                        }
+                        Some((modified, state.get(input).is_some()))
+                    }
+                    _ => None,
+                };
+                let options = match recorded {
+                    Some((_, true)) => &overwrite_options,
+                    _ => &options,
+                };
+                let target_options = target_bitrate.map(|bitrate| ConvertOptions {
+                    bitrate,
+                    ..options.clone()
+                });
+                let options = target_options.as_ref().unwrap_or(options);
+
+                // Fail before encoding rather than when the disk is full
+                if !plans.is_empty() {
+                    preflight_check(&plans, &part_outputs, max_size)?;
+                }
+                let parts = match plans.is_empty() {
+                    true => {
+                        collect_input_files(&inputs, options).map(|input_files| vec![input_files])
+                    }
+                    false => Ok(plans.into_iter().map(|plan| plan.input_files).collect()),
+                };
+                let parts = match parts {
+                    Ok(parts) => parts,
+                    Err(error) => {
+                        reports.push(json!({
+                            "input": input,
+                            "output": output_path,
+                            "status": "failed",
+                            "error": error.to_string(),
+                        }));
+                        failures.push((input_names, error.to_string()));
+                        failed_conversions += 1;
+                        if failure.fail_fast {
+                            break 'conversions;
+                        }
+                        continue;
+                    }
+                };
+
+                let part_count = parts.len();
+                let mut converted = vec![];
+                for (input_files, part_output) in parts.into_iter().zip(part_outputs) {
+                    if options.existing_output == ExistingOutput::Skip
+                        && output_exists(&part_output)
+                    {
+                        match cli.json {
+                            true => reports.push(json!({
+                                "input": input,
+                                "output": part_output,
+                                "status": "skipped",
+                                "warnings": [language.translate(Message::OutputExists)],
+                            })),
+                            false => println!("{}", language.translate(Message::OutputExists)),
+                        }
+                        continue;
+                    }
+                    // The size of the estimated bitrate is checked once the file is encoded
+                    let result = match target_size {
+                        Some(target_size) => {
+                            convert_files_to_size(&input_files, &part_output, target_size, options)
+                        }
+                        None => convert_files_to_tonie(&input_files, &part_output, options),
+                    };
+                    if let Err(error) = result {
+                        if options.cancellation.is_cancelled() {
+                            eprintln!("{}", language.translate(Message::Cancelled));
+                            std::process::exit(EXIT_CANCELLED);
+                        }
+                        if !cli.json {
+                            eprintln!("Failed to convert {}: {}", input_names, error);
+                        }
+                        reports.push(json!({
+                            "input": input,
+                            "output": part_output,
+                            "status": "failed",
+                            "error": error.to_string(),
+                        }));
+                        failures.push(match error {
+                            Audio2TonieError::TrackFailed { input_file, error } => {
+                                (input_file.display().to_string(), error.to_string())
+                            }
+                            error => (input_names.clone(), error.to_string()),
+                        });
+                        failed_conversions += 1;
+                        if failure.fail_fast {
+                            break 'conversions;
+                        }
+                        continue;
+                    }
+                    let failed_tracks = options.failed_tracks.take();
+                    failures.extend(failed_tracks.iter().map(|track| {
+                        let error = match track.truncated {
+                            true => {
+                                format!(
+                                    "{} ({})",
+                                    track.error,
+                                    language.translate(Message::Truncated)
+                                )
+                            }
+                            false => track.error.clone(),
+                        };
+                        (track.input_file.display().to_string(), error)
+                    }));
+
+                    let metadata = ConversionMetadata {
+                        output_path: part_output.clone(),
+                        chapters: count_chapters(&input_files, options)?,
+                        input_files,
+                    };
+                    run_post_processors(&post_processors, &metadata)?;
+                    if cli.json {
+                        reports.push(json!({
+                            "input": input,
+                            "output": part_output,
+                            "status": "converted",
+                            "input_files": metadata.input_files,
+                            "failed_tracks": failed_tracks.iter().map(|track| json!({
+                                "input_file": track.input_file,
+                                "error": track.error,
+                                "truncated": track.truncated,
+                            })).collect::<Vec<_>>(),
+                            "tonie": info_json(&part_output, &Limits::default())?,
+                        }));
+                    }
+                    converted.push(part_output);
+                }
+                // The state is saved after every directory, so an interrupted run keeps its progress
+                if let (Some(state), Some((modified, _)), [input]) =
+                    (state.as_mut(), recorded, inputs.as_slice())
+                {
+                    if converted.len() == part_count {
+                        let record = ConversionRecord {
+                            modified,
+                            settings: settings.clone(),
+                            outputs: converted,
+                        };
+                        state.record(input, record);
+                        state.save()?;
+                    }
+                }
+            }
+            if cli.json {
+                let report = json!({ "conversions": reports });
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_failures(&failures, language);
+            }
+            if failed_conversions > 0 {
+                return Err(anyhow!("{} conversions failed.", failed_conversions));
+            }
+            return Ok(());
+        }
+        CLICommands::Upload {
+            input,
+            url,
+            path,
+            token,
+            ca_cert,
+            ffmpeg,
+        } => {
+            let upload = TeddyCloudUpload::new(&url, &path)
+                .with_token(token)
+                .with_ca_cert(ca_cert);
+            let options = ConvertOptions {
+                ffmpeg,
+                io_throttle: cli.io_throttle,
+                show_progress: std::io::stderr().is_terminal(),
+                ..Default::default()
+            };
+            let temp_root = cli.tmp_dir.unwrap_or_else(std::env::temp_dir);
+            return upload_to_teddycloud(&input, &upload, &options, &temp_root);
+        }
+        CLICommands::UploadCloud {
+            inputs,
+            tonie,
+            list,
+            replace,
+            username,
+            password,
+            ffmpeg,
+        } => {
+            let client = TonieCloudClient::login(
+                TONIE_CLOUD_AUTH_URL,
+                TONIE_CLOUD_API_URL,
+                &username,
+                &password,
+            )?;
+            let tonies = client.creative_tonies()?;
+            if list {
+                return print_creative_tonies(&tonies, language, cli.json);
+            }
+
+            let tonie = select_creative_tonie(&tonies, tonie.as_deref())?;
+            let options = ConvertOptions {
+                ffmpeg,
+                show_progress: std::io::stderr().is_terminal(),
+                ..Default::default()
+            };
+            let temp_root = cli.tmp_dir.unwrap_or_else(std::env::temp_dir);
+            let updated =
+                upload_to_tonie_cloud(&inputs, &client, tonie, replace, &options, &temp_root)?;
+            return print_creative_tonies(&[updated], language, cli.json);
+        }
+        CLICommands::Podcast {
+            feed,
+            output,
+            latest,
+            episode,
+            list,
+            sidecar,
+            ffmpeg,
+            existing_output,
+        } => {
+            let feed = fetch_feed(&feed)?;
+            if list {
+                match cli.json {
+                    true => {
+                        let episodes = feed
+                            .episodes
+                            .iter()
+                            .map(|episode| {
+                                json!({
+                                    "title": episode.title,
+                                    "url": episode.url,
+                                    "published": episode.published,
+                                })
+                            })
+                            .collect::<Vec<_>>();
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&json!({
+                                "title": feed.title,
+                                "episodes": episodes,
+                            }))?
+                        );
+                    }
+                    false => {
+                        for (number, episode) in feed.episodes.iter().enumerate() {
+                            println!(
+                                "{:>4}  {:<31}  {}",
+                                number + 1,
+                                episode.published.as_deref().unwrap_or_default(),
+                                episode.title
+                            );
+                        }
+                    }
+                }
+                return Ok(());
+            }
+
+            let selection = match (latest, episode.is_empty()) {
+                (Some(count), _) => EpisodeSelection::Latest(count as usize),
+                (None, false) => EpisodeSelection::Matching(episode),
+                (None, true) => {
+                    return Err(anyhow!(
+                        "Select the episodes with --latest or --episode, or list them with --list."
+                    ));
+                }
+            };
+            let episodes = select_episodes(&feed, &selection)?;
+            let output = output.unwrap_or_else(|| {
+                limit_path_length(Path::new(&format!(
+                    "{}.taf",
+                    sanitize_file_name(&feed.title, false)
+                )))
+            });
+            let options = ConvertOptions {
+                ffmpeg,
+                io_throttle: cli.io_throttle,
+                show_progress: std::io::stderr().is_terminal(),
+                existing_output: existing_output.into(),
+                ..Default::default()
+            };
+            // Checked before the episodes are downloaded, which takes much longer than the conversion
+            if output_exists(&output) {
+                match options.existing_output {
+                    ExistingOutput::Fail => {
+                        return Err(
+                            Audio2TonieError::OutputExists(resolve_output_path(&output)).into()
+                        );
+                    }
+                    ExistingOutput::Skip => {
+                        println!("{}", language.translate(Message::OutputExists));
+                        return Ok(());
+                    }
+                    ExistingOutput::Overwrite => {}
+                }
+            }
+
+            let temp_root = cli.tmp_dir.unwrap_or_else(std::env::temp_dir);
+            let download_dir =
+                temp_root.join(format!("audio2tonie-podcast-{}", std::process::id()));
+            let result = download_episodes(&episodes, &download_dir, |episode| {
+                eprintln!("Downloading {}", episode.title);
+            })
+            .and_then(|episode_files| {
+                convert_files_to_tonie(&episode_files, &output, &options)?;
+                if sidecar {
+                    SidecarFile.process(&ConversionMetadata {
+                        output_path: resolve_output_path(&output),
+                        chapters: count_chapters(&episode_files, &options)?,
+                        input_files: episode_files,
+                    })?;
+                }
+                return Ok(());
+            });
+            std::fs::remove_dir_all(&download_dir).ok();
+
+            return result;
+        }
+        CLICommands::Batch {
+            manifest,
+            jobs,
+            sd_root,
+            bitrate,
+            ffmpeg,
+            failure,
+            existing_output,
+        } => {
+            let batch_jobs = read_manifest(&manifest, sd_root.as_deref())?;
+            let options = ConvertOptions {
+                ffmpeg,
+                io_throttle: cli.io_throttle,
+                // The progress bars of parallel jobs would overwrite each other
+                show_progress: jobs == 1 && !cli.json && std::io::stderr().is_terminal(),
+                existing_output: existing_output.into(),
+                bitrate,
+                on_track_failure: (&failure).into(),
+                cancellation: Cancellation::new(),
+                ..Default::default()
+            };
+            cancel_on_ctrl_c(&options.cancellation)?;
+
+            let results = run_batch(
+                &batch_jobs,
+                &options,
+                jobs as usize,
+                failure.fail_fast,
+                |job, result| {
+                    if !cli.json {
+                        print_batch_result(job, result, language);
+                    }
+                },
+            );
+            if options.cancellation.is_cancelled() {
+                eprintln!("{}", language.translate(Message::Cancelled));
+                std::process::exit(EXIT_CANCELLED);
+            }
+            let failed = results
+                .iter()
+                .filter(|result| matches!(result.status, BatchStatus::Failed(_)))
+                .count();
+            match cli.json {
+                true => {
+                    let reports = batch_jobs
+                        .iter()
+                        .zip(&results)
+                        .map(|(job, result)| batch_result_json(job, result))
+                        .collect::<Vec<_>>();
+                    let report = json!({ "conversions": reports });
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                }
+                false => print_batch_summary(&results, language),
+            }
+            if failed > 0 {
+                return Err(anyhow!(
+                    "{} of {} conversions failed.",
+                    failed,
+                    results.len()
+                ));
+            }
+            return Ok(());
+        }
+        CLICommands::Split {
+            input,
+            output,
+            transliterate,
+            name_template,
+            limits,
+        } => {
+            let options = ExtractOptions {
+                limits: limits.into(),
+                transliterate,
+                io_throttle: cli.io_throttle,
+                name_template,
+                ..ExtractOptions::default()
+            };
+            let output = match output {
+                Some(output) => output,
+                None => std::env::current_dir()?,
+            };
+            for chapter_file_path in split_tonie_file(&input, &output, &options)? {
+                println!("{}", chapter_file_path.display());
+            }
+            return Ok(());
+        }
+        CLICommands::Recode {
+            input,
+            output,
+            bitrate,
+            limits,
+        } => {
+            let options = RecodeOptions {
+                bitrate,
+                limits: limits.into(),
+                io_throttle: cli.io_throttle,
+            };
+            return recode_tonie_file(&input, &output, &options);
+        }
+        CLICommands::Watch {
             input,
             output,
             ffmpeg,
+            debounce,
+            any_extension,
+            existing_output,
+        } => {
+            let options = WatchOptions {
+                convert: ConvertOptions {
+                    ffmpeg,
+                    io_throttle: cli.io_throttle,
+                    existing_output: existing_output.into(),
+                    any_extension,
+                    ..Default::default()
+                },
+                debounce: Duration::from_secs(debounce),
+            };
+            return watch_directory(&input, &output, &options, |entry, result| match result {
+                Ok(output_file_path) => println!("{}", output_file_path.display()),
+                // A broken file must not stop the drop folder
+                Err(error) => eprintln!("Failed to convert {}: {}", entry.display(), error),
+            });
+        }
+        CLICommands::Tui {
+            directory,
+            output_dir,
+            ffmpeg,
+        } => {
+            let options = ConvertOptions {
+                ffmpeg,
+                io_throttle: cli.io_throttle,
+                track_order: TrackOrder::Tags,
+                ..Default::default()
+            };
+            return run_tui(&directory, &output_dir, &options);
+        }
+        CLICommands::Serve {
+            listen,
+            ffmpeg,
+            max_upload_size,
         } => {
-            let _file = convert_to_tonie(&input, &output, ffmpeg);
+            let temp_root = cli.tmp_dir.unwrap_or_else(std::env::temp_dir);
+            let options = ServeOptions {
+                convert: ConvertOptions {
+                    ffmpeg,
+                    io_throttle: cli.io_throttle,
+                    ..Default::default()
+                },
+                work_dir: temp_root.join(format!("audio2tonie-serve-{}", std::process::id())),
+                max_upload_size,
+            };
+            let server = ConversionServer::bind(&listen, &options)?;
+            if let Some(address) = server.local_addr() {
+                eprintln!("Listening on http://{}", address);
+            }
+            return server.run();
+        }
+        CLICommands::Uid { uid_or_path } => {
+            let tag_uid = match uid_or_path.contains(['/', '\\']) {
+                true => TagUid::from_content_path(Path::new(&uid_or_path))?,
+                false => TagUid::parse(&uid_or_path)?,
+            };
+            let content_path = Path::new(CONTENT_DIRECTORY)
+                .join(tag_uid.content_directory())
+                .join(tag_uid.content_file_name());
+            if cli.json {
+                let report = json!({
+                    "tag_uid": tag_uid.to_string(),
+                    "content_directory": tag_uid.content_directory(),
+                    "content_file_name": tag_uid.content_file_name(),
+                    "content_path": content_path,
+                });
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{:<16} {}", language.translate(Message::TagUid), tag_uid);
+                println!(
+                    "{:<16} {}",
+                    language.translate(Message::ContentPath),
+                    content_path.display()
+                );
+            }
             return Ok(());
         }
+        CLICommands::Check { input, limits } => {
+            return print_check_report(&input, &limits.into(), language, cli.json);
+        }
+        CLICommands::Analyze { input, limits } => {
+            return print_page_analysis(&input, &limits.into(), language, cli.json);
+        }
+        CLICommands::InspectPage {
+            input,
+            page,
+            limits,
+        } => {
+            return print_page_inspection(&input, page, &limits.into(), language, cli.json);
+        }
+        CLICommands::Play {
+            input,
+            chapter,
+            ffplay,
+            limits,
+        } => {
+            let options = PlayOptions {
+                ffplay,
+                chapter: chapter.map(|chapter| chapter as usize),
+                limits: limits.into(),
+            };
+            return play_tonie_file(&input, &options, |chapter| {
+                println!("{} {}", language.translate(Message::Chapter), chapter);
+            });
+        }
+        CLICommands::Info { input, limits } => {
+            return print_info(&input, &limits.into(), language, cli.json);
+        }
+        CLICommands::List { directory, limits } => {
+            return print_list(&directory, &limits.into(), language, cli.json);
+        }
+        CLICommands::Identify {
+            inputs,
+            tonies_json,
+            update,
+            limits,
+        } => {
+            let database = match tonies_json {
+                Some(source) => ToniesDatabase::load(&source)?,
+                None => ToniesDatabase::load_cached(update, |url| {
+                    eprintln!("Downloading tonies.json from {}", url);
+                })?,
+            };
+            let identifications = identify_tonie_files(&inputs, &database, &limits.into())?;
+            return print_identifications(&identifications, language, cli.json);
+        }
+        CLICommands::Backup {
+            sd_root,
+            output,
+            format,
+            transliterate,
+            tonies_json,
+            update,
+            ffmpeg,
+            limits,
+        } => {
+            let database = match tonies_json {
+                Some(source) => ToniesDatabase::load(&source)?,
+                None => ToniesDatabase::load_cached(update, |url| {
+                    eprintln!("Downloading tonies.json from {}", url);
+                })?,
+            };
+            let options = ExtractOptions {
+                limits: limits.into(),
+                transliterate,
+                io_throttle: cli.io_throttle,
+                format,
+                ffmpeg,
+                ..ExtractOptions::default()
+            };
+            let entries = backup_sd_card(&sd_root, &output, &database, &options)?;
+            print_backup(&entries, language, cli.json)?;
+            let failed = entries.iter().filter(|entry| entry.error.is_some()).count();
+            if failed > 0 {
+                return Err(anyhow!("{} Tonie files could not be backed up.", failed));
+            }
+            return Ok(());
+        }
+        CLICommands::Restore {
+            backup,
+            sd_root,
+            overwrite,
+            ffmpeg,
+        } => {
+            let options = ConvertOptions {
+                ffmpeg,
+                io_throttle: cli.io_throttle,
+                ..ConvertOptions::default()
+            };
+            let results = restore_backup(
+                read_backup_manifest(&backup)?,
+                &sd_root,
+                overwrite,
+                &options,
+            )?;
+            print_restore(&results, language, cli.json)?;
+            let failed = results
+                .iter()
+                .filter(|result| matches!(result.status, RestoreStatus::Failed(_)))
+                .count();
+            if failed > 0 {
+                return Err(anyhow!("{} Tonie files could not be restored.", failed));
+            }
+            return Ok(());
+        }
+        CLICommands::Catalog {
+            directory,
+            output,
+            format,
+            limits,
+        } => {
+            let entries = build_catalog(&directory, &limits.into())?;
+            let format = format.unwrap_or_else(|| CatalogFormat::from_path(&output));
+            if output == Path::new("-") {
+                return write_catalog(&entries, format, std::io::stdout().lock());
+            }
+            let catalog_file = File::create(&output)?;
+            return write_catalog(&entries, format, std::io::BufWriter::new(catalog_file));
+        }
+        CLICommands::Stats { input, limits } => {
+            return print_stats(&input, &limits.into(), language);
+        }
+        CLICommands::Retimestamp {
+            input,
+            timestamp,
+            limits,
+        } => {
+            let audio_id = timestamp.unwrap_or_else(current_timestamp);
+            let header = retimestamp_tonie_file(&input, audio_id, &limits.into())?;
+            println!(
+                "{:<16} {}",
+                language.translate(Message::AudioId),
+                header.audio_id
+            );
+            return Ok(());
+        }
+        CLICommands::TeddyCloud { command } => match command {
+            TeddyCloudCommands::List {
+                path,
+                tags,
+                overlay,
+                server,
+            } => {
+                let client = TeddyCloudClient::from(server);
+                if tags {
+                    let tags = client.list_tags(overlay.as_deref())?;
+                    return print_server_tags(&tags, language, cli.json);
+                }
+                let files = client.list_library(&path)?;
+                return print_library_files(&files, language, cli.json);
+            }
+            TeddyCloudCommands::Assign {
+                tag_uid,
+                library_path,
+                overlay,
+                server,
+            } => {
+                let client = TeddyCloudClient::from(server);
+                return client.assign(tag_uid, &library_path, overlay.as_deref());
+            }
+            TeddyCloudCommands::Delete {
+                library_path,
+                server,
+            } => {
+                return TeddyCloudClient::from(server).delete(&library_path);
+            }
+        },
+        CLICommands::Header { command } => match command {
+            HeaderCommands::Dump { input, limits } => {
+                let header = read_tonie_header(&input, &limits.into())?;
+                println!("{}", serde_json::to_string_pretty(&header_json(&header))?);
+                return Ok(());
+            }
+            HeaderCommands::Apply {
+                input,
+                patch,
+                limits,
+            } => {
+                let patch = serde_json::from_str(&std::fs::read_to_string(&patch)?)?;
+                let header = apply_header_patch(&input, &patch, &limits.into())?;
+                println!("{}", serde_json::to_string_pretty(&header_json(&header))?);
+                return Ok(());
+            }
+        },
     };
 }
+
+/// Prints the inputs that failed during a run and why, so failures are not lost between the progress output.
+///
+/// # Arguments
+///
+/// * `failures` - The names of the failed inputs and the reasons.
+/// * `language` - The language of the heading.
+fn print_failures(failures: &[(String, String)], language: Language) {
+    if failures.is_empty() {
+        return;
+    }
+    eprintln!();
+    eprintln!("{}:", language.translate(Message::FailedInputs));
+    for (input, error) in failures {
+        eprintln!("  {}: {}", input, error);
+    }
+}
+
+/// Cancels the conversion on Ctrl-C or SIGTERM, so ffmpeg is stopped and the partially written Tonie file is
+/// removed before exiting. Pressing Ctrl-C a second time exits immediately.
+///
+/// # Arguments
+///
+/// * `cancellation` - The cancellation of the conversion options.
+fn cancel_on_ctrl_c(cancellation: &Cancellation) -> Result<()> {
+    let cancellation = cancellation.clone();
+    ctrlc::set_handler(move || {
+        if cancellation.is_cancelled() {
+            std::process::exit(EXIT_CANCELLED);
+        }
+        cancellation.cancel();
+    })?;
+    return Ok(());
+}
@@ -0,0 +1,357 @@
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+use crate::limits::LimitError;
+
+pub const OGG_CAPTURE_PATTERN: &[u8; 4] = b"OggS";
+pub const OGG_PAGE_HEADER_SIZE: usize = 27;
+pub const OGG_MAX_SEGMENT_SIZE: usize = 255;
+
+pub const HEADER_TYPE_CONTINUED: u8 = 0x01;
+pub const HEADER_TYPE_BEGIN_OF_STREAM: u8 = 0x02;
+pub const HEADER_TYPE_END_OF_STREAM: u8 = 0x04;
+
+/// Errors raised while parsing Ogg pages.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OggPageError {
+    TruncatedHeader(usize),
+    MissingCapturePattern,
+    TruncatedSegmentTable,
+    TruncatedData,
+    LimitExceeded(LimitError),
+}
+
+impl Display for OggPageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            OggPageError::TruncatedHeader(size) => write!(
+                f,
+                "Expected at least {} bytes for an Ogg page header, got {}.",
+                OGG_PAGE_HEADER_SIZE, size
+            ),
+            OggPageError::MissingCapturePattern => {
+                write!(f, "Invalid Ogg page. Missing 'OggS' capture pattern.")
+            }
+            OggPageError::TruncatedSegmentTable => {
+                write!(f, "Ogg page segment table is truncated.")
+            }
+            OggPageError::TruncatedData => write!(f, "Ogg page data is truncated."),
+            OggPageError::LimitExceeded(error) => error.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OggPageError {}
+
+/// A single Ogg page consisting of the fixed size page header, the segment (lacing) table and the page payload.
+/// Pages are parsed from and serialized to plain byte slices so they work on in-memory buffers
+/// as well as on data read from files or network streams.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OggPage {
+    pub version: u8,
+    pub header_type: u8,
+    pub granule_position: u64,
+    pub serial_number: u32,
+    pub page_sequence_number: u32,
+    pub checksum: u32,
+    pub segment_table: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+impl OggPage {
+    /// Parses an Ogg page from the start of the buffer.
+    /// Returns the page and the number of bytes it occupies in the buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The bytes to parse. Must start with the `OggS` capture pattern.
+    pub fn parse(buffer: &[u8]) -> Result<(OggPage, usize), OggPageError> {
+        let segment_table_end = OggPage::header_size(buffer)?;
+        let data_size = data_size(&buffer[OGG_PAGE_HEADER_SIZE..segment_table_end]);
+        let page_end = segment_table_end + data_size;
+        if buffer.len() < page_end {
+            return Err(OggPageError::TruncatedData);
+        }
+
+        let page = OggPage::from_parts(
+            &buffer[..segment_table_end],
+            buffer[segment_table_end..page_end].to_vec(),
+        )?;
+        return Ok((page, page_end));
+    }
+
+    /// Validates the fixed size header and the segment table at the start of the buffer and returns their
+    /// combined size. The size of the page data is the sum of the segment table.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The bytes to parse. Must start with the `OggS` capture pattern.
+    pub fn header_size(buffer: &[u8]) -> Result<usize, OggPageError> {
+        if buffer.len() < OGG_PAGE_HEADER_SIZE {
+            return Err(OggPageError::TruncatedHeader(buffer.len()));
+        }
+        if &buffer[0..4] != OGG_CAPTURE_PATTERN {
+            return Err(OggPageError::MissingCapturePattern);
+        }
+
+        let segment_table_end = OGG_PAGE_HEADER_SIZE + buffer[26] as usize;
+        if buffer.len() < segment_table_end {
+            return Err(OggPageError::TruncatedSegmentTable);
+        }
+        return Ok(segment_table_end);
+    }
+
+    /// Builds a page from its header including the segment table and its data. The data is moved into the page,
+    /// so pages read from a stream are not copied again.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The fixed size header followed by the segment table.
+    /// * `data` - The page data, which must be as long as the segment table announces.
+    pub fn from_parts(header: &[u8], data: Vec<u8>) -> Result<OggPage, OggPageError> {
+        let segment_table_end = OggPage::header_size(header)?;
+        let segment_table = header[OGG_PAGE_HEADER_SIZE..segment_table_end].to_vec();
+        if data.len() != data_size(&segment_table) {
+            return Err(OggPageError::TruncatedData);
+        }
+
+        return Ok(OggPage {
+            version: header[4],
+            header_type: header[5],
+            granule_position: read_u64_le(&header[6..14]),
+            serial_number: read_u32_le(&header[14..18]),
+            page_sequence_number: read_u32_le(&header[18..22]),
+            checksum: read_u32_le(&header[22..26]),
+            segment_table,
+            data,
+        });
+    }
+
+    /// Serializes the page into its binary representation. The checksum is recomputed from the page content.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(self.size());
+        self.serialize_into(&mut buffer);
+        return buffer;
+    }
+
+    /// Appends the binary representation of the page to the buffer, so writers can reuse one buffer for all
+    /// pages. The checksum is recomputed from the page content.
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer` - The buffer to append the page to.
+    pub fn serialize_into(&self, buffer: &mut Vec<u8>) {
+        buffer.reserve(self.size());
+        buffer.extend_from_slice(&self.fixed_header(self.compute_checksum()));
+        buffer.extend_from_slice(&self.segment_table);
+        buffer.extend_from_slice(&self.data);
+    }
+
+    /// Computes the checksum of the page content without serializing the page.
+    pub fn compute_checksum(&self) -> u32 {
+        // The checksum is computed with the checksum field set to zero
+        let crc = crc32_update(0, &self.fixed_header(0));
+        let crc = crc32_update(crc, &self.segment_table);
+        return crc32_update(crc, &self.data);
+    }
+
+    fn fixed_header(&self, checksum: u32) -> [u8; OGG_PAGE_HEADER_SIZE] {
+        let mut header = [0u8; OGG_PAGE_HEADER_SIZE];
+        header[0..4].copy_from_slice(OGG_CAPTURE_PATTERN);
+        header[4] = self.version;
+        header[5] = self.header_type;
+        header[6..14].copy_from_slice(&self.granule_position.to_le_bytes());
+        header[14..18].copy_from_slice(&self.serial_number.to_le_bytes());
+        header[18..22].copy_from_slice(&self.page_sequence_number.to_le_bytes());
+        header[22..26].copy_from_slice(&checksum.to_le_bytes());
+        header[26] = self.segment_table.len() as u8;
+        return header;
+    }
+
+    /// The total size of the serialized page in bytes.
+    pub fn size(&self) -> usize {
+        return OGG_PAGE_HEADER_SIZE + self.segment_table.len() + self.data.len();
+    }
+
+    /// Checks whether the stored checksum matches the page content.
+    pub fn is_checksum_valid(&self) -> bool {
+        return self.compute_checksum() == self.checksum;
+    }
+
+    /// Whether the first packet of this page continues a packet from the previous page.
+    pub fn is_continued(&self) -> bool {
+        return self.header_type & HEADER_TYPE_CONTINUED != 0;
+    }
+
+    pub fn is_begin_of_stream(&self) -> bool {
+        return self.header_type & HEADER_TYPE_BEGIN_OF_STREAM != 0;
+    }
+
+    pub fn is_end_of_stream(&self) -> bool {
+        return self.header_type & HEADER_TYPE_END_OF_STREAM != 0;
+    }
+
+    /// Splits the page payload into packets according to the segment table.
+    /// A packet is terminated by a segment shorter than 255 bytes. The last packet
+    /// is incomplete if it continues on the next page.
+    pub fn packets(&self) -> Vec<&[u8]> {
+        let mut packets = Vec::new();
+        let mut packet_start = 0;
+        let mut packet_end = 0;
+
+        for segment_size in &self.segment_table {
+            packet_end += *segment_size as usize;
+            if (*segment_size as usize) < OGG_MAX_SEGMENT_SIZE {
+                packets.push(&self.data[packet_start..packet_end]);
+                packet_start = packet_end;
+            }
+        }
+        if packet_start < packet_end {
+            packets.push(&self.data[packet_start..packet_end]);
+        }
+
+        return packets;
+    }
+
+    /// Whether the last packet of this page is continued on the next page.
+    pub fn has_incomplete_packet(&self) -> bool {
+        return self
+            .segment_table
+            .last()
+            .is_some_and(|segment_size| *segment_size as usize == OGG_MAX_SEGMENT_SIZE);
+    }
+}
+
+/// Reassembles packets that span several Ogg pages, e.g. large packets of high bitrate VBR streams.
+/// Pages have to be pushed in stream order.
+#[derive(Clone, Debug, Default)]
+pub struct PacketAssembler {
+    partial_packet: Vec<u8>,
+}
+
+impl PacketAssembler {
+    pub fn new() -> Self {
+        PacketAssembler::default()
+    }
+
+    /// Adds the packets of the next page and returns all packets completed by it. Packets which lie completely
+    /// within the page are borrowed from it, only packets spanning several pages are copied.
+    /// The tail of a packet that continues on the next page is kept until that page is pushed.
+    ///
+    /// # Arguments
+    ///
+    /// * `page` - The next page of the stream.
+    pub fn push_page<'a>(&mut self, page: &'a OggPage) -> Vec<Cow<'a, [u8]>> {
+        let packets = page.packets();
+        let packet_count = packets.len();
+        let mut complete_packets = Vec::with_capacity(packet_count);
+
+        for (index, packet) in packets.into_iter().enumerate() {
+            let is_continuation = index == 0 && page.is_continued();
+            let is_continued = index == packet_count - 1 && page.has_incomplete_packet();
+            if !is_continuation {
+                // A partial packet without a continuation on this page is incomplete and dropped
+                self.partial_packet.clear();
+                if !is_continued {
+                    complete_packets.push(Cow::Borrowed(packet));
+                    continue;
+                }
+            }
+
+            self.partial_packet.extend_from_slice(packet);
+            if !is_continued {
+                complete_packets.push(Cow::Owned(core::mem::take(&mut self.partial_packet)));
+            }
+        }
+
+        return complete_packets;
+    }
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    return u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+}
+
+fn read_u64_le(bytes: &[u8]) -> u64 {
+    return u64::from_le_bytes([
+        bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+    ]);
+}
+
+pub(crate) fn data_size(segment_table: &[u8]) -> usize {
+    return segment_table.iter().map(|size| *size as usize).sum();
+}
+
+/// Computes the Ogg flavoured CRC32 checksum (polynomial 0x04C11DB7, no reflection, no final XOR).
+///
+/// # Arguments
+///
+/// * `data` - The bytes to compute the checksum for.
+pub fn crc32(data: &[u8]) -> u32 {
+    return crc32_update(0, data);
+}
+
+/// Continues a checksum computed with [`crc32`] with the following bytes, so the checksum of data spread over
+/// several buffers is computed without joining them.
+///
+/// # Arguments
+///
+/// * `crc` - The checksum of the preceding bytes, 0 for the first buffer.
+/// * `data` - The following bytes.
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    const CRC_TABLES: [[u32; 256]; 8] = crc32_tables();
+
+    // Slicing-by-8: eight bytes are folded into the checksum per step with one table lookup each
+    let mut chunks = data.chunks_exact(8);
+    let mut crc = crc;
+    for chunk in &mut chunks {
+        let high = crc ^ u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        crc = CRC_TABLES[7][(high >> 24) as usize]
+            ^ CRC_TABLES[6][((high >> 16) & 0xFF) as usize]
+            ^ CRC_TABLES[5][((high >> 8) & 0xFF) as usize]
+            ^ CRC_TABLES[4][(high & 0xFF) as usize]
+            ^ CRC_TABLES[3][chunk[4] as usize]
+            ^ CRC_TABLES[2][chunk[5] as usize]
+            ^ CRC_TABLES[1][chunk[6] as usize]
+            ^ CRC_TABLES[0][chunk[7] as usize];
+    }
+
+    return chunks.remainder().iter().fold(crc, |crc, byte| {
+        (crc << 8) ^ CRC_TABLES[0][(((crc >> 24) as u8) ^ byte) as usize]
+    });
+}
+
+// The first table is the bytewise table of the polynomial, table k advances a byte by k more bytes of zeros
+const fn crc32_tables() -> [[u32; 256]; 8] {
+    let mut tables = [[0u32; 256]; 8];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        tables[0][i] = crc;
+        i += 1;
+    }
+
+    let mut table = 1;
+    while table < 8 {
+        let mut i = 0;
+        while i < 256 {
+            let crc = tables[table - 1][i];
+            tables[table][i] = (crc << 8) ^ tables[0][(crc >> 24) as usize];
+            i += 1;
+        }
+        table += 1;
+    }
+
+    return tables;
+}
@@ -0,0 +1,195 @@
+//! Parsing of the TOC byte and the frame layout of Opus packets (RFC 6716, section 3), e.g. to check the coding
+//! mode of the packets in a Tonie file or to count their samples. Like the Ogg page parser it only depends on
+//! `core` and `alloc`.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+/// Errors raised while parsing Opus packets.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpusPacketError {
+    Empty,
+    MissingFrameCount,
+    NoFrames,
+    TruncatedPadding,
+    TruncatedFrameLength,
+    FramesExceedPacket,
+    UnequalFrames,
+}
+
+impl Display for OpusPacketError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            OpusPacketError::Empty => write!(f, "The packet is empty."),
+            OpusPacketError::MissingFrameCount => write!(f, "The frame count byte is missing."),
+            OpusPacketError::NoFrames => write!(f, "The packet contains no frames."),
+            OpusPacketError::TruncatedPadding => write!(f, "The padding length is truncated."),
+            OpusPacketError::TruncatedFrameLength => write!(f, "The frame length is truncated."),
+            OpusPacketError::FramesExceedPacket => {
+                write!(f, "The frames and padding exceed the packet.")
+            }
+            OpusPacketError::UnequalFrames => {
+                write!(f, "The frames of a constant size packet differ in size.")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for OpusPacketError {}
+
+/// The TOC byte and frame layout of an Opus packet (RFC 6716, section 3).
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpusPacket {
+    /// The configuration number, which selects the mode, bandwidth and frame duration.
+    pub config: u8,
+    pub stereo: bool,
+    /// The frame count code: 0 for one frame, 1 for two frames of equal size, 2 for two frames of different size
+    /// and 3 for an arbitrary number of frames.
+    pub code: u8,
+    /// Whether the frames of a code 3 packet have different sizes.
+    pub vbr: bool,
+    pub padding: usize,
+    /// The offset and size of every frame in the packet.
+    pub frames: Vec<(usize, usize)>,
+}
+
+impl OpusPacket {
+    /// Parses the TOC byte and the frame lengths of a complete Opus packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - The Opus packet.
+    pub fn parse(packet: &[u8]) -> Result<Self, OpusPacketError> {
+        let toc = *packet.first().ok_or(OpusPacketError::Empty)?;
+        let code = toc & 0x03;
+        let mut offset = 1;
+        let mut padding = 0;
+        let mut vbr = false;
+
+        let frame_sizes = match code {
+            0 => vec![packet.len() - offset],
+            1 => {
+                let data_size = packet.len() - offset;
+                if !data_size.is_multiple_of(2) {
+                    return Err(OpusPacketError::UnequalFrames);
+                }
+                vec![data_size / 2; 2]
+            }
+            2 => {
+                let first_size = read_frame_length(packet, &mut offset)?;
+                let second_size = (packet.len() - offset)
+                    .checked_sub(first_size)
+                    .ok_or(OpusPacketError::FramesExceedPacket)?;
+                vec![first_size, second_size]
+            }
+            _ => {
+                let frame_count_byte = *packet
+                    .get(offset)
+                    .ok_or(OpusPacketError::MissingFrameCount)?;
+                offset += 1;
+                vbr = frame_count_byte & 0x80 != 0;
+                let frame_count = (frame_count_byte & 0x3F) as usize;
+                if frame_count == 0 {
+                    return Err(OpusPacketError::NoFrames);
+                }
+                if frame_count_byte & 0x40 != 0 {
+                    loop {
+                        let length = *packet
+                            .get(offset)
+                            .ok_or(OpusPacketError::TruncatedPadding)?;
+                        offset += 1;
+                        // 255 adds 254 bytes of padding and continues with the next byte
+                        padding += if length == 255 { 254 } else { length as usize };
+                        if length < 255 {
+                            break;
+                        }
+                    }
+                }
+
+                let mut frame_sizes = vec![];
+                if vbr {
+                    for _ in 1..frame_count {
+                        frame_sizes.push(read_frame_length(packet, &mut offset)?);
+                    }
+                }
+                let data_size = (packet.len() - offset)
+                    .checked_sub(padding + frame_sizes.iter().sum::<usize>())
+                    .ok_or(OpusPacketError::FramesExceedPacket)?;
+                match vbr {
+                    true => frame_sizes.push(data_size),
+                    false if !data_size.is_multiple_of(frame_count) => {
+                        return Err(OpusPacketError::UnequalFrames)
+                    }
+                    false => frame_sizes = vec![data_size / frame_count; frame_count],
+                }
+                frame_sizes
+            }
+        };
+
+        let mut frames = vec![];
+        for size in frame_sizes {
+            frames.push((offset, size));
+            offset += size;
+        }
+        return Ok(OpusPacket {
+            config: toc >> 3,
+            stereo: toc & 0x04 != 0,
+            code,
+            vbr,
+            padding,
+            frames,
+        });
+    }
+
+    /// The coding mode of the configuration: SILK, Hybrid or CELT.
+    pub fn mode(&self) -> &'static str {
+        return match self.config {
+            0..=11 => "SILK",
+            12..=15 => "Hybrid",
+            _ => "CELT",
+        };
+    }
+
+    /// The audio bandwidth of the configuration, e.g. `FB` for fullband.
+    pub fn bandwidth(&self) -> &'static str {
+        return match self.config {
+            0..=3 | 16..=19 => "NB",
+            4..=7 => "MB",
+            8..=11 | 20..=23 => "WB",
+            12..=13 | 24..=27 => "SWB",
+            _ => "FB",
+        };
+    }
+
+    /// The duration of a single frame in microseconds.
+    pub fn frame_duration(&self) -> u32 {
+        return match self.config {
+            0..=11 => [10_000, 20_000, 40_000, 60_000][self.config as usize % 4],
+            12..=15 => [10_000, 20_000][self.config as usize % 2],
+            _ => [2_500, 5_000, 10_000, 20_000][self.config as usize % 4],
+        };
+    }
+
+    /// The duration of all frames in samples at 48 kHz, the unit of Ogg Opus granule positions.
+    pub fn samples(&self) -> u64 {
+        return self.frames.len() as u64 * self.frame_duration() as u64 * 48 / 1000;
+    }
+}
+
+// Reads a frame length of one or two bytes and advances the offset
+fn read_frame_length(packet: &[u8], offset: &mut usize) -> Result<usize, OpusPacketError> {
+    let first_byte = *packet
+        .get(*offset)
+        .ok_or(OpusPacketError::TruncatedFrameLength)? as usize;
+    *offset += 1;
+    if first_byte < 252 {
+        return Ok(first_byte);
+    }
+    let second_byte = *packet
+        .get(*offset)
+        .ok_or(OpusPacketError::TruncatedFrameLength)? as usize;
+    *offset += 1;
+    return Ok(first_byte + 4 * second_byte);
+}
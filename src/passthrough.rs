@@ -0,0 +1,162 @@
+//! Repackages Opus streams that the Toniebox can play as they are into a Tonie file without decoding and
+//! re-encoding them, which keeps the quality and is much faster than a conversion.
+
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::{BufReader, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::convert::{ConvertOptions, Normalization};
+use crate::encode::{fits_into_block, OpusApplication, TafEncoder, DEFAULT_BITRATE};
+use crate::error::Audio2TonieError;
+use crate::limits::Limits;
+use crate::ogg_page::PacketAssembler;
+use crate::taf::{OggPageReader, MAX_CHAPTERS};
+
+// TOC configurations from 16 on are CELT-only
+const CELT_ONLY_CONFIG: u8 = 16;
+const TOC_STEREO_FLAG: u8 = 0x04;
+
+/// Checks whether the input files can be passed through without re-encoding. Every file has to be a stereo Ogg
+/// Opus stream with CELT-only packets that fit into a 4kb block. Opus always codes at 48 kHz, so the input
+/// sample rate of the OpusHead is informational only. Options that change the audio, e.g. normalization or
+/// fades, encoder and ffmpeg options that differ from the defaults, e.g. the bitrate, and chapters from CUE
+/// sheets or chapter markers rule out the passthrough as well.
+///
+/// # Arguments
+///
+/// * `input_files` - The input audio files in the order of the chapters.
+/// * `options` - Options controlling the conversion.
+/// * `chapter_count` - The number of chapters of the conversion, which has to match the number of files.
+pub fn check_passthrough(
+    input_files: &[PathBuf],
+    options: &ConvertOptions,
+    chapter_count: usize,
+) -> Result<()> {
+    let changes_audio = options.normalization != Normalization::None
+        || options.trim_silence.is_some()
+        || !options.fade_in.is_zero()
+        || !options.fade_out.is_zero()
+        || options.speed != 1.0;
+    if changes_audio {
+        return Err(anyhow!(
+            "The audio cannot be passed through, because the options require re-encoding it."
+        ));
+    }
+    // The packets are copied as they are, so neither the encoder nor ffmpeg ever see the audio
+    let configures_encoding = options.bitrate != DEFAULT_BITRATE
+        || options.opus_application != OpusApplication::Audio
+        || options.cbr
+        || !options.ffmpeg_args.is_empty()
+        || options.input_format.is_some();
+    if configures_encoding {
+        return Err(anyhow!(
+            "The audio cannot be passed through, because the encoder or ffmpeg options require re-encoding it."
+        ));
+    }
+    if chapter_count != input_files.len() || chapter_count > MAX_CHAPTERS {
+        return Err(anyhow!(
+            "The audio cannot be passed through, because the chapters do not match the input files."
+        ));
+    }
+
+    for input_file in input_files {
+        read_opus_stream(input_file, |_| Ok(()))
+            .with_context(|| format!("{} cannot be passed through", input_file.display()))?;
+    }
+
+    return Ok(());
+}
+
+/// Writes the Opus packets of the input files into a Tonie file with one chapter per file. The files have to
+/// pass [`check_passthrough`]. The pre-skip of the first file is kept, the pre-skip of the following files is
+/// played.
+///
+/// # Arguments
+///
+/// * `input_files` - The input Opus files in the order of the chapters.
+/// * `writer` - The output, e.g. a file.
+/// * `audio_id` - The audio id of the Tonie file.
+/// * `comments` - User comments of the Opus header.
+/// * `max_audio_length` - The largest audio data length in bytes, see [`TafEncoder::set_max_audio_length`].
+pub fn pass_through_opus<W: Write + Seek>(
+    input_files: &[PathBuf],
+    writer: W,
+    audio_id: u32,
+    comments: &[String],
+    max_audio_length: u64,
+) -> Result<W> {
+    let first_file = input_files
+        .first()
+        .ok_or_else(|| anyhow!("There are no input files to pass through."))?;
+    let pre_skip = read_opus_stream(first_file, |_| Ok(()))?;
+
+    let mut encoder = TafEncoder::passthrough(writer, audio_id, pre_skip, comments)?;
+    encoder.set_max_audio_length(max_audio_length);
+    for (index, input_file) in input_files.iter().enumerate() {
+        if index > 0 {
+            encoder.new_chapter()?;
+        }
+        read_opus_stream(input_file, |packet| encoder.write_packet(packet))?;
+    }
+
+    return encoder.finalize();
+}
+
+// Reads an Ogg Opus stream page by page, validates its headers and audio packets and passes every audio packet
+// to the callback. Returns the pre-skip of the stream.
+fn read_opus_stream<F>(input_file: &Path, mut on_packet: F) -> Result<u16>
+where
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    let reader = BufReader::new(File::open(input_file)?);
+    let mut packet_assembler = PacketAssembler::new();
+    let mut serial_number = None;
+    let mut packet_count = 0;
+    let mut pre_skip = 0;
+
+    for page in OggPageReader::with_limits(reader, Limits::default()) {
+        let (_, page) = page?;
+        if *serial_number.get_or_insert(page.serial_number) != page.serial_number {
+            return Err(anyhow!("the file contains more than one Ogg stream"));
+        }
+
+        for packet in packet_assembler.push_page(&page) {
+            packet_count += 1;
+            match packet_count {
+                1 => pre_skip = parse_opus_head(input_file, &packet)?,
+                // OpusTags
+                2 => (),
+                _ => {
+                    let toc = *packet
+                        .first()
+                        .ok_or_else(|| anyhow!("the stream contains an empty packet"))?;
+                    if toc >> 3 < CELT_ONLY_CONFIG || toc & TOC_STEREO_FLAG == 0 {
+                        return Err(anyhow!("the stream is not encoded in stereo CELT mode"));
+                    }
+                    if !fits_into_block(packet.len()) {
+                        return Err(Audio2TonieError::PageTooLarge(packet.len()).into());
+                    }
+                    on_packet(&packet)?;
+                }
+            }
+        }
+    }
+    if packet_count < 2 {
+        return Err(Audio2TonieError::InvalidOpusHead(input_file.to_path_buf()).into());
+    }
+
+    return Ok(pre_skip);
+}
+
+// Returns the pre-skip of a stereo OpusHead with the channel mapping family 0
+fn parse_opus_head(input_file: &Path, packet: &[u8]) -> Result<u16> {
+    if packet.len() < 19 || !packet.starts_with(b"OpusHead") {
+        return Err(Audio2TonieError::InvalidOpusHead(input_file.to_path_buf()).into());
+    }
+    if packet[9] != 2 || packet[18] != 0 {
+        return Err(anyhow!("the stream is not stereo"));
+    }
+
+    return Ok(u16::from_le_bytes([packet[10], packet[11]]));
+}
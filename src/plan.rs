@@ -0,0 +1,92 @@
+use anyhow::Result;
+use audio2tonie::convert::ConversionPlan;
+use audio2tonie::utils::format_duration;
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::i18n::{Language, Message};
+
+/// The input files, chapters, duration and estimated size of a planned conversion as JSON object.
+///
+/// # Arguments
+///
+/// * `plan` - The planned conversion.
+/// * `output_file_path` - The path of the Tonie file.
+pub fn conversion_plan_json(plan: &ConversionPlan, output_file_path: &Path) -> Value {
+    let chapters = plan
+        .chapters
+        .iter()
+        .enumerate()
+        .map(|(index, chapter)| {
+            json!({
+                "number": index + 1,
+                "input_file": chapter.input_file,
+                "start": chapter.start,
+                "duration": chapter.duration,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    return json!({
+        "output": output_file_path,
+        "input_files": plan.input_files,
+        "chapters": chapters,
+        "duration": plan.duration(),
+        "bitrate": plan.bitrate,
+        "estimated_size": plan.estimated_size(),
+    });
+}
+
+/// Prints the output file, the estimated duration and size and the chapters of a planned conversion.
+///
+/// # Arguments
+///
+/// * `plan` - The planned conversion.
+/// * `output_file_path` - The path of the Tonie file.
+/// * `language` - The language of the labels.
+pub fn print_conversion_plan(
+    plan: &ConversionPlan,
+    output_file_path: &Path,
+    language: Language,
+) -> Result<()> {
+    let rows = [
+        (Message::OutputFile, output_file_path.display().to_string()),
+        (Message::TotalDuration, format_duration(plan.duration())),
+        (
+            Message::EstimatedSize,
+            format!(
+                "{:.1} MB ({} kbit/s)",
+                plan.estimated_size() as f64 / 1_000_000.0,
+                plan.bitrate
+            ),
+        ),
+    ];
+    for (label, value) in rows {
+        println!("{:<16} {}", language.translate(label), value);
+    }
+
+    println!();
+    println!(
+        "{:<8} {:>9}  {}",
+        language.translate(Message::Chapter),
+        language.translate(Message::Duration),
+        language.translate(Message::InputFile)
+    );
+    for (index, chapter) in plan.chapters.iter().enumerate() {
+        let duration = match chapter.duration {
+            Some(duration) => format_duration(duration),
+            None => String::from(language.translate(Message::UnknownDuration)),
+        };
+        let input_file = match chapter.start {
+            start if start > 0.0 => format!(
+                "{} @ {}",
+                chapter.input_file.display(),
+                format_duration(start)
+            ),
+            _ => chapter.input_file.display().to_string(),
+        };
+        println!("{:<8} {:>9}  {}", index + 1, duration, input_file);
+    }
+
+    return Ok(());
+}
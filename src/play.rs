@@ -0,0 +1,121 @@
+//! Plays a Tonie file for a local preview by piping the decoded audio into ffplay, which ships with ffmpeg.
+
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::Stdio;
+use toniefile::Toniefile;
+
+use crate::decode::decode_tonie_chapters;
+use crate::limits::Limits;
+use crate::utils::{check_input_limits, tool_command};
+
+/// Options controlling how a Tonie file is played.
+#[derive(Clone, Debug)]
+pub struct PlayOptions {
+    /// The path to the ffplay executable.
+    pub ffplay: String,
+    /// The chapter to start with, counting from 1. `None` starts with the first chapter.
+    pub chapter: Option<usize>,
+    /// Caps for the input size, header size and number of pages.
+    pub limits: Limits,
+}
+
+impl Default for PlayOptions {
+    fn default() -> Self {
+        PlayOptions {
+            ffplay: String::from("ffplay"),
+            chapter: None,
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// Decodes a Tonie file and plays it with ffplay until the end of the file or until ffplay is closed.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `options` - Options controlling the playback, e.g. the chapter to start with.
+/// * `on_chapter` - Called with the number of every chapter, counting from 1, when its playback starts.
+pub fn play_tonie_file<F>(
+    input_file_path: &Path,
+    options: &PlayOptions,
+    mut on_chapter: F,
+) -> Result<()>
+where
+    F: FnMut(usize),
+{
+    let mut tonie_file = File::open(input_file_path)?;
+    check_input_limits(&mut tonie_file, &options.limits)?;
+    let chapter_count = Toniefile::parse_header(&mut tonie_file)?
+        .track_page_nums
+        .len();
+    let first_chapter = options.chapter.unwrap_or(1);
+    if first_chapter == 0 || first_chapter > chapter_count {
+        return Err(anyhow!(
+            "Chapter {} does not exist, the Tonie file has {} chapters.",
+            first_chapter,
+            chapter_count
+        ));
+    }
+
+    let mut ffplay = tool_command(&options.ffplay)
+        .args(["-hide_banner", "-loglevel", "error", "-nodisp", "-autoexit"])
+        .args([
+            "-f",
+            "s16le",
+            "-ar",
+            "48000",
+            "-ch_layout",
+            "stereo",
+            "-i",
+            "-",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|error| anyhow!("Failed to start {}: {}", options.ffplay, error))?;
+    let mut ffplay_input = ffplay
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("Failed to open the input of ffplay."))?;
+
+    let mut current_chapter = None;
+    let result = decode_tonie_chapters(input_file_path, &options.limits, |chapter, samples| {
+        // Chapters are numbered from 1 like on the Toniebox
+        let chapter = chapter + 1;
+        if chapter < first_chapter {
+            return Ok(());
+        }
+        if current_chapter != Some(chapter) {
+            current_chapter = Some(chapter);
+            on_chapter(chapter);
+        }
+
+        let bytes = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect::<Vec<_>>();
+        ffplay_input.write_all(&bytes)?;
+        return Ok(());
+    });
+    drop(ffplay_input);
+
+    // Closing ffplay stops the playback early, which is not an error
+    let closed_early = result.as_ref().is_err_and(|error| {
+        error
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|error| error.kind() == ErrorKind::BrokenPipe)
+    });
+    let status = ffplay.wait()?;
+    if !closed_early {
+        result?;
+    }
+    if !status.success() && !closed_early {
+        return Err(anyhow!("ffplay failed with {}.", status));
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,228 @@
+//! Podcast feeds as input of a conversion. The audio enclosures of the selected episodes of an RSS feed are
+//! downloaded and converted into a Tonie file with one chapter per episode.
+
+use anyhow::{anyhow, Context, Result};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::convert::is_file_extension_supported;
+use crate::utils::{limit_path_length, sanitize_file_name};
+
+/// The file extension of downloaded episodes whose URL and type do not tell the format. ffmpeg detects the actual
+/// format from the content.
+const DEFAULT_EPISODE_EXTENSION: &str = "mp3";
+
+/// A podcast feed with the episodes that have an audio enclosure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Feed {
+    pub title: String,
+    /// The episodes in the order of the feed, i.e. usually the latest episode first.
+    pub episodes: Vec<Episode>,
+}
+
+/// An episode of a podcast feed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Episode {
+    pub title: String,
+    /// The URL of the audio enclosure.
+    pub url: String,
+    /// The MIME type of the enclosure, e.g. `audio/mpeg`.
+    pub mime_type: Option<String>,
+    /// The publication date as given in the feed, e.g. `Tue, 03 Sep 2024 06:00:00 +0200`.
+    pub published: Option<String>,
+}
+
+/// Which episodes of a feed are converted.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EpisodeSelection {
+    /// The given number of latest episodes.
+    Latest(usize),
+    /// The episodes whose titles contain any of the given texts, ignoring case.
+    Matching(Vec<String>),
+}
+
+/// Downloads and parses a podcast feed.
+///
+/// # Arguments
+///
+/// * `url` - The URL of the RSS feed.
+pub fn fetch_feed(url: &str) -> Result<Feed> {
+    let response = ureq::get(url)
+        .call()
+        .with_context(|| format!("Failed to download the feed {}", url))?;
+    // Feeds with a long history exceed the size limit of `into_string`
+    let mut xml = String::new();
+    response.into_reader().read_to_string(&mut xml)?;
+
+    return parse_feed(&xml).with_context(|| format!("Failed to parse the feed {}", url));
+}
+
+/// Parses an RSS feed. Items without an audio enclosure, e.g. announcements, are skipped.
+///
+/// # Arguments
+///
+/// * `xml` - The content of the RSS feed.
+pub fn parse_feed(xml: &str) -> Result<Feed> {
+    let options = roxmltree::ParsingOptions {
+        allow_dtd: true,
+        ..Default::default()
+    };
+    let document = roxmltree::Document::parse_with_options(xml, options)?;
+    let channel = document
+        .descendants()
+        .find(|node| node.has_tag_name("channel"))
+        .ok_or_else(|| anyhow!("The feed is not an RSS feed."))?;
+
+    let episodes = channel
+        .children()
+        .filter(|node| node.has_tag_name("item"))
+        .filter_map(|item| {
+            let enclosure = item
+                .children()
+                .find(|node| node.has_tag_name("enclosure"))?;
+            return Some(Episode {
+                title: child_text(item, "title").unwrap_or_default(),
+                url: enclosure.attribute("url")?.trim().to_string(),
+                mime_type: enclosure.attribute("type").map(str::to_string),
+                published: child_text(item, "pubDate"),
+            });
+        })
+        .filter(|episode| {
+            return !episode.url.is_empty()
+                && episode
+                    .mime_type
+                    .as_deref()
+                    .is_none_or(|mime_type| !mime_type.starts_with("video/"));
+        })
+        .collect();
+
+    return Ok(Feed {
+        title: child_text(channel, "title").unwrap_or_default(),
+        episodes,
+    });
+}
+
+/// The trimmed text of the first child element with the given name, without namespace, e.g. `title`.
+fn child_text(node: roxmltree::Node, name: &str) -> Option<String> {
+    return node
+        .children()
+        .find(|child| child.tag_name().name() == name && child.tag_name().namespace().is_none())
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string());
+}
+
+/// Selects episodes of a feed in the order of the chapters, i.e. the oldest episode first. Feeds list the latest
+/// episode first.
+///
+/// # Arguments
+///
+/// * `feed` - The feed to select from.
+/// * `selection` - Which episodes to select.
+pub fn select_episodes<'a>(
+    feed: &'a Feed,
+    selection: &EpisodeSelection,
+) -> Result<Vec<&'a Episode>> {
+    let mut episodes = match selection {
+        EpisodeSelection::Latest(count) => feed.episodes.iter().take(*count).collect::<Vec<_>>(),
+        EpisodeSelection::Matching(patterns) => {
+            let patterns = patterns
+                .iter()
+                .map(|pattern| pattern.to_lowercase())
+                .collect::<Vec<_>>();
+            if let Some(pattern) = patterns.iter().find(|pattern| {
+                !feed
+                    .episodes
+                    .iter()
+                    .any(|episode| episode.title.to_lowercase().contains(pattern.as_str()))
+            }) {
+                return Err(anyhow!("No episode of the feed matches '{}'.", pattern));
+            }
+            feed.episodes
+                .iter()
+                .filter(|episode| {
+                    let title = episode.title.to_lowercase();
+                    return patterns
+                        .iter()
+                        .any(|pattern| title.contains(pattern.as_str()));
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+    if episodes.is_empty() {
+        return Err(anyhow!("The feed does not contain any audio episodes."));
+    }
+    episodes.reverse();
+
+    return Ok(episodes);
+}
+
+/// Downloads the audio of the episodes into a directory. Every episode is stored in a numbered sub directory and
+/// named after its title, so the titles become the chapter names, e.g. in the sidecar file of the conversion.
+/// Returns the paths of the downloaded files in the order of the episodes.
+///
+/// # Arguments
+///
+/// * `episodes` - The episodes to download.
+/// * `download_dir` - The directory for the downloads, which is created if needed.
+/// * `on_download` - Called with the episode before it is downloaded, e.g. to show the progress.
+pub fn download_episodes<F>(
+    episodes: &[&Episode],
+    download_dir: &Path,
+    mut on_download: F,
+) -> Result<Vec<PathBuf>>
+where
+    F: FnMut(&Episode),
+{
+    return episodes
+        .iter()
+        .enumerate()
+        .map(|(index, episode)| {
+            on_download(episode);
+            let episode_dir = download_dir.join(format!("{:03}", index + 1));
+            std::fs::create_dir_all(&episode_dir)?;
+            let title = match episode.title.is_empty() {
+                true => format!("Episode {}", index + 1),
+                false => episode.title.clone(),
+            };
+            let file_name = format!("{}.{}", title, episode_extension(episode));
+            let episode_path =
+                limit_path_length(&episode_dir.join(sanitize_file_name(&file_name, false)));
+
+            let response = ureq::get(&episode.url)
+                .call()
+                .with_context(|| format!("Failed to download {}", episode.url))?;
+            std::io::copy(
+                &mut response.into_reader(),
+                &mut File::create(&episode_path)?,
+            )?;
+            return Ok(episode_path);
+        })
+        .collect();
+}
+
+/// The file extension of a downloaded episode, taken from the enclosure URL or its MIME type.
+///
+/// # Arguments
+///
+/// * `episode` - The episode.
+pub fn episode_extension(episode: &Episode) -> String {
+    // Enclosure URLs often carry tracking parameters, e.g. `episode.mp3?source=feed`
+    let url_path = episode.url.split(['?', '#']).next().unwrap_or_default();
+    let url_file_name = Path::new(url_path.rsplit('/').next().unwrap_or_default());
+    if is_file_extension_supported(url_file_name) {
+        let extension = url_file_name.extension().unwrap_or_default();
+        return extension.to_string_lossy().to_ascii_lowercase();
+    }
+
+    let extension = match episode.mime_type.as_deref() {
+        Some("audio/mp4" | "audio/x-m4a" | "audio/m4a") => "m4a",
+        Some("audio/aac") => "aac",
+        Some("audio/ogg" | "audio/vorbis") => "ogg",
+        Some("audio/opus") => "opus",
+        Some("audio/flac" | "audio/x-flac") => "flac",
+        Some("audio/wav" | "audio/x-wav") => "wav",
+        _ => DEFAULT_EPISODE_EXTENSION,
+    };
+    return extension.to_string();
+}
@@ -0,0 +1,69 @@
+use std::io::Write;
+
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// A single line progress bar on stderr, e.g. for the decoding progress of a track.
+pub struct ProgressBar {
+    label: String,
+    total: Option<f64>,
+    last_rendered: Option<String>,
+}
+
+impl ProgressBar {
+    /// # Arguments
+    ///
+    /// * `label` - Shown in front of the progress bar, e.g. the file name.
+    /// * `total` - The total amount of work, e.g. the duration in seconds. Shows only the current value if unknown.
+    pub fn new(label: &str, total: Option<f64>) -> Self {
+        ProgressBar {
+            label: label.to_string(),
+            total: total.filter(|total| *total > 0.0),
+            last_rendered: None,
+        }
+    }
+
+    /// Updates the progress bar to the current value. Only redraws when the rendered line changes.
+    pub fn update(&mut self, current: f64) {
+        let rendered = format_progress(&self.label, current, self.total);
+        if self.last_rendered.as_ref() != Some(&rendered) {
+            eprint!("\r{}", rendered);
+            std::io::stderr().flush().ok();
+            self.last_rendered = Some(rendered);
+        }
+    }
+
+    /// Completes the progress bar and moves to the next line.
+    pub fn finish(&mut self) {
+        if let Some(total) = self.total {
+            self.update(total);
+        }
+        eprintln!();
+    }
+}
+
+/// Renders a progress bar line like `story.mp3 [=======>      ]  42%`.
+///
+/// # Arguments
+///
+/// * `label` - Shown in front of the progress bar.
+/// * `current` - The current value, e.g. the decoded duration in seconds.
+/// * `total` - The total value. Shows only the current value if unknown.
+pub fn format_progress(label: &str, current: f64, total: Option<f64>) -> String {
+    let Some(total) = total else {
+        return format!("{} {:.0}s", label, current);
+    };
+
+    let fraction = (current / total).clamp(0.0, 1.0);
+    let filled = (fraction * PROGRESS_BAR_WIDTH as f64).round() as usize;
+    let bar = if filled == PROGRESS_BAR_WIDTH {
+        "=".repeat(PROGRESS_BAR_WIDTH)
+    } else {
+        format!(
+            "{}>{}",
+            "=".repeat(filled),
+            " ".repeat(PROGRESS_BAR_WIDTH - filled - 1)
+        )
+    };
+
+    return format!("{} [{}] {:>3.0}%", label, bar, fraction * 100.0);
+}
@@ -0,0 +1,116 @@
+//! Re-encodes the audio of a Tonie file with another Opus bitrate, e.g. to make an audiobook fit on the SD card.
+
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use toniefile::Toniefile;
+
+use crate::convert::{ExistingOutput, PartialOutput};
+use crate::decode::decode_tonie_chapters;
+use crate::encode::{TafEncoder, DEFAULT_BITRATE};
+use crate::limits::Limits;
+use crate::ogg_page::PacketAssembler;
+use crate::taf::{opus_comments, OggPageReader};
+use crate::throttle::ThrottledIo;
+use crate::utils::check_input_limits;
+
+/// Options controlling how a Tonie file is re-encoded.
+#[derive(Clone, Debug)]
+pub struct RecodeOptions {
+    /// The Opus bitrate of the new file in kbit/s.
+    pub bitrate: u32,
+    /// Caps for the input size, header size and number of pages.
+    pub limits: Limits,
+    /// Limits writing the new Tonie file to the given number of bytes per second.
+    pub io_throttle: Option<u64>,
+}
+
+impl Default for RecodeOptions {
+    fn default() -> Self {
+        RecodeOptions {
+            bitrate: DEFAULT_BITRATE,
+            limits: Limits::default(),
+            io_throttle: None,
+        }
+    }
+}
+
+/// Decodes a Tonie file and encodes the audio into a new Tonie file with the given bitrate. The chapters, the
+/// audio id and the Opus comments of the original file are kept. The new file is written next to the output file
+/// and only replaces it once it is complete.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `output_file_path` - The path of the new Tonie file.
+/// * `options` - Options controlling the encoding, e.g. the bitrate.
+pub fn recode_tonie_file(
+    input_file_path: &Path,
+    output_file_path: &Path,
+    options: &RecodeOptions,
+) -> Result<()> {
+    if output_file_path.exists()
+        && output_file_path.canonicalize()? == input_file_path.canonicalize()?
+    {
+        return Err(anyhow!(
+            "The output file must not be the input file, which is read while encoding."
+        ));
+    }
+
+    let mut tonie_file = File::open(input_file_path)?;
+    check_input_limits(&mut tonie_file, &options.limits)?;
+    let tonie_header = Toniefile::parse_header(&mut tonie_file)?;
+    let comments = read_opus_comments(&mut tonie_file, &options.limits)?;
+
+    let output = PartialOutput::create(output_file_path)?;
+    let output_file = ThrottledIo::new(output.file(), options.io_throttle);
+    let mut encoder = TafEncoder::new(
+        output_file,
+        tonie_header.audio_id,
+        options.bitrate,
+        &comments,
+    )?;
+
+    // Chapters without any audio are kept as well, so the chapter numbers stay the same
+    let mut current_chapter = 0;
+    decode_tonie_chapters(input_file_path, &options.limits, |chapter, samples| {
+        while current_chapter < chapter {
+            encoder.new_chapter()?;
+            current_chapter += 1;
+        }
+        return encoder.encode(samples);
+    })?;
+    while current_chapter + 1 < tonie_header.track_page_nums.len() {
+        encoder.new_chapter()?;
+        current_chapter += 1;
+    }
+    encoder.finalize()?;
+    output.complete(output_file_path, ExistingOutput::Overwrite)?;
+
+    return Ok(());
+}
+
+/// Reads the Opus comments of a Tonie file from the pages up to the comment header, so the audio is not read
+/// into memory.
+///
+/// # Arguments
+///
+/// * `tonie_file` - The Tonie file, positioned at the start of the audio data.
+/// * `limits` - Caps for the number of pages.
+fn read_opus_comments(tonie_file: &mut File, limits: &Limits) -> Result<Vec<String>> {
+    let mut header_pages = vec![];
+    let mut packet_count = 0;
+    let mut packet_assembler = PacketAssembler::new();
+    for page in OggPageReader::with_limits(BufReader::new(tonie_file), *limits) {
+        let (_, page) = page?;
+        packet_count += packet_assembler.push_page(&page).len();
+        page.serialize_into(&mut header_pages);
+        // The comment header is the second packet of the stream
+        if packet_count >= 2 {
+            break;
+        }
+    }
+
+    return Ok(opus_comments(&header_pages));
+}
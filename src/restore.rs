@@ -0,0 +1,285 @@
+use anyhow::{anyhow, Context, Result};
+use audio2tonie::convert::{
+    convert_files_to_tonie, estimate_tonie_size, probe_duration, ConvertOptions, ExistingOutput,
+    Passthrough,
+};
+use audio2tonie::sd_card::TagUid;
+use audio2tonie::utils::available_space;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use crate::backup::MANIFEST_FILE_NAME;
+use crate::i18n::{Language, Message};
+
+/// A Tonie file of a backup manifest, see `backup_sd_card`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestEntry {
+    pub tag_uid: TagUid,
+    pub title: String,
+    /// The audio id of the original Tonie file, which the restored file keeps.
+    pub audio_id: Option<u32>,
+    /// The audio files or the Tonie file of the backup, in chapter order.
+    pub files: Vec<PathBuf>,
+    /// Why the Tonie file could not be backed up.
+    pub error: Option<String>,
+}
+
+/// What happened to a Tonie file of the backup.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RestoreStatus {
+    Restored,
+    /// The SD card already has content for the tag.
+    Skipped,
+    Failed(String),
+}
+
+/// The outcome of restoring a Tonie file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestoreResult {
+    pub entry: ManifestEntry,
+    /// The path relative to the root of the SD card, e.g. `CONTENT/5634121E/500304E0`.
+    pub content_path: PathBuf,
+    pub status: RestoreStatus,
+}
+
+/// Reads the manifest of a backup. The paths of the files are resolved relative to the backup directory.
+///
+/// # Arguments
+///
+/// * `backup` - The backup directory or its `manifest.json`.
+pub fn read_backup_manifest(backup: &Path) -> Result<Vec<ManifestEntry>> {
+    let manifest_path = match backup.is_dir() {
+        true => backup.join(MANIFEST_FILE_NAME),
+        false => backup.to_path_buf(),
+    };
+    let backup_dir = manifest_path.parent().unwrap_or(Path::new(""));
+    let manifest = std::fs::read_to_string(&manifest_path)
+        .with_context(|| format!("Failed to read the manifest {}", manifest_path.display()))?;
+    let Value::Array(entries) = serde_json::from_str::<Value>(&manifest)? else {
+        return Err(anyhow!(
+            "The manifest {} is not a JSON array.",
+            manifest_path.display()
+        ));
+    };
+
+    return entries
+        .iter()
+        .map(|entry| {
+            let tag_uid = entry["tag_uid"]
+                .as_str()
+                .ok_or_else(|| anyhow!("An entry of the manifest has no tag UID."))?;
+            return Ok(ManifestEntry {
+                tag_uid: TagUid::parse(tag_uid)?,
+                title: entry["title"].as_str().unwrap_or_default().to_string(),
+                audio_id: entry["audio_id"]
+                    .as_u64()
+                    .and_then(|audio_id| u32::try_from(audio_id).ok()),
+                files: entry["files"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(|file| backup_dir.join(file))
+                    .collect(),
+                error: entry["error"].as_str().map(str::to_string),
+            });
+        })
+        .collect();
+}
+
+/// Writes the Tonie files of a backup into the `CONTENT` layout of an SD card, e.g. `CONTENT/5634121E/500304E0`
+/// for the tag `E0:04:03:50:1E:12:34:56`. Tonie files in the backup are copied, audio files are converted into a
+/// Tonie file with the original audio id. The Ogg files written by `backup` are passed through without
+/// re-encoding. Before anything is written, the free space of the SD card is checked against the estimated size
+/// of all files. Tags which already have content on the SD card are skipped unless `overwrite` is set.
+///
+/// # Arguments
+///
+/// * `entries` - The entries of the manifest.
+/// * `sd_root` - The mount point of the SD card.
+/// * `overwrite` - Replace the content of tags which already have content on the SD card.
+/// * `options` - Options controlling the conversion of audio files, e.g. the path to ffmpeg.
+pub fn restore_backup(
+    entries: Vec<ManifestEntry>,
+    sd_root: &Path,
+    overwrite: bool,
+    options: &ConvertOptions,
+) -> Result<Vec<RestoreResult>> {
+    let is_pending = |entry: &ManifestEntry| {
+        entry.error.is_none()
+            && !entry.files.is_empty()
+            && (overwrite || !entry.tag_uid.content_path(sd_root).exists())
+    };
+    let mut required = 0;
+    for entry in entries.iter().filter(|entry| is_pending(entry)) {
+        let replaced = std::fs::metadata(entry.tag_uid.content_path(sd_root))
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        required += estimate_restored_size(entry, options).saturating_sub(replaced);
+    }
+    if let Some(available) = available_space(sd_root) {
+        if available < required {
+            return Err(anyhow!(
+                "The SD card has {:.1} MB free, but the backup needs about {:.1} MB.",
+                available as f64 / 1_000_000.0,
+                required as f64 / 1_000_000.0
+            ));
+        }
+    }
+
+    let mut results = vec![];
+    for entry in entries {
+        let content_path = entry.tag_uid.content_path(sd_root);
+        let status = if let Some(error) = &entry.error {
+            RestoreStatus::Failed(format!("The backup failed: {}", error))
+        } else if entry.files.is_empty() {
+            RestoreStatus::Failed(String::from("The backup does not contain any files."))
+        } else if !is_pending(&entry) {
+            RestoreStatus::Skipped
+        } else {
+            match restore_entry(&entry, &content_path, options) {
+                Ok(()) => RestoreStatus::Restored,
+                Err(error) => RestoreStatus::Failed(format!("{:#}", error)),
+            }
+        };
+        results.push(RestoreResult {
+            content_path: content_path
+                .strip_prefix(sd_root)
+                .unwrap_or(&content_path)
+                .to_path_buf(),
+            entry,
+            status,
+        });
+    }
+
+    return Ok(results);
+}
+
+fn restore_entry(
+    entry: &ManifestEntry,
+    content_path: &Path,
+    options: &ConvertOptions,
+) -> Result<()> {
+    if let Some(parent) = content_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if let [tonie_file] = &entry.files[..] {
+        if is_taf_file(tonie_file) {
+            std::fs::copy(tonie_file, content_path)?;
+            return Ok(());
+        }
+    }
+
+    let options = ConvertOptions {
+        audio_id: entry.audio_id,
+        passthrough: Passthrough::Auto,
+        existing_output: ExistingOutput::Overwrite,
+        ..options.clone()
+    };
+    convert_files_to_tonie(&entry.files, content_path, &options)?;
+    return Ok(());
+}
+
+// Ogg files and Tonie files keep their size, other audio files are estimated by their duration
+fn estimate_restored_size(entry: &ManifestEntry, options: &ConvertOptions) -> u64 {
+    return entry
+        .files
+        .iter()
+        .map(|file| {
+            let size = std::fs::metadata(file)
+                .map(|metadata| metadata.len())
+                .unwrap_or_default();
+            let keeps_size = is_taf_file(file)
+                || file.extension().is_some_and(|extension| {
+                    extension.eq_ignore_ascii_case("ogg") || extension.eq_ignore_ascii_case("opus")
+                });
+            if keeps_size {
+                return size;
+            }
+            return match probe_duration(file, &options.ffmpeg) {
+                Ok(Some(duration)) => estimate_tonie_size(duration, options.bitrate),
+                _ => size,
+            };
+        })
+        .sum();
+}
+
+fn is_taf_file(path: &Path) -> bool {
+    return path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("taf"));
+}
+
+/// Prints the tag UID, the title and the outcome of every Tonie file of the backup, and the errors of the files
+/// which could not be restored below.
+///
+/// # Arguments
+///
+/// * `results` - The result of `restore_backup`.
+/// * `language` - The language of the table headers and outcomes.
+/// * `json` - Print the results as JSON array instead of a table.
+pub fn print_restore(results: &[RestoreResult], language: Language, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(&restore_json(results))?);
+        return Ok(());
+    }
+
+    let title_width = results
+        .iter()
+        .map(|result| result.entry.title.chars().count())
+        .chain([language.translate(Message::Title).chars().count()])
+        .max()
+        .unwrap_or_default();
+    println!(
+        "{:<23} {:<title_width$} {}",
+        language.translate(Message::TagUid),
+        language.translate(Message::Title),
+        language.translate(Message::ContentPath)
+    );
+    for result in results {
+        let status = match &result.status {
+            RestoreStatus::Restored => language.translate(Message::Converted),
+            RestoreStatus::Skipped => language.translate(Message::Skipped),
+            RestoreStatus::Failed(_) => language.translate(Message::Failed),
+        };
+        println!(
+            "{:<23} {:<title_width$} {} ({})",
+            result.entry.tag_uid.to_string(),
+            result.entry.title,
+            result.content_path.display(),
+            status
+        );
+    }
+    for result in results {
+        if let RestoreStatus::Failed(error) = &result.status {
+            eprintln!("{}: {}", result.entry.tag_uid, error);
+        }
+    }
+
+    return Ok(());
+}
+
+/// The outcomes of a restore as JSON array.
+///
+/// # Arguments
+///
+/// * `results` - The result of `restore_backup`.
+pub fn restore_json(results: &[RestoreResult]) -> Value {
+    return results
+        .iter()
+        .map(|result| {
+            let (status, error) = match &result.status {
+                RestoreStatus::Restored => ("restored", None),
+                RestoreStatus::Skipped => ("skipped", None),
+                RestoreStatus::Failed(error) => ("failed", Some(error)),
+            };
+            json!({
+                "tag_uid": result.entry.tag_uid.to_string(),
+                "title": result.entry.title,
+                "content_path": result.content_path,
+                "status": status,
+                "error": error,
+            })
+        })
+        .collect();
+}
@@ -0,0 +1,136 @@
+//! The layout of the SD card of a Toniebox. The audio of a tag with the UID `E0:04:03:50:1E:12:34:56` is
+//! stored in `CONTENT/5634121E/500304E0`, i.e. the hex digits of the reversed UID split into a directory and
+//! a file name.
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The directory of the Tonie files on the SD card.
+pub const CONTENT_DIRECTORY: &str = "CONTENT";
+
+/// The 8 byte UID of the NFC tag of a Tonie, e.g. `E0:04:03:50:1E:12:34:56`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TagUid([u8; 8]);
+
+impl TagUid {
+    /// Parses a tag UID from 16 hex digits. Colons, dashes and spaces between the bytes are ignored,
+    /// e.g. `E0:04:03:50:1E:12:34:56` or `e00403501e123456`.
+    ///
+    /// # Arguments
+    ///
+    /// * `uid` - The tag UID.
+    pub fn parse(uid: &str) -> Result<Self> {
+        let digits = uid
+            .chars()
+            .filter(|c| !matches!(c, ':' | '-' | ' '))
+            .collect::<String>();
+        if digits.len() != 16 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!(
+                "'{}' is not a valid tag UID. Expected 8 hex bytes, e.g. E0:04:03:50:1E:12:34:56.",
+                uid
+            ));
+        }
+
+        let mut bytes = [0u8; 8];
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16)?;
+        }
+        return Ok(TagUid(bytes));
+    }
+
+    /// Derives the tag UID from the path of a Tonie file on the SD card, e.g. `CONTENT/5634121E/500304E0`.
+    /// Only the directory and the file name are used, so absolute paths work as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the Tonie file.
+    pub fn from_content_path(path: &Path) -> Result<Self> {
+        let mut components = path
+            .components()
+            .rev()
+            .map(|component| component.as_os_str().to_string_lossy());
+        let (Some(file_name), Some(directory)) = (components.next(), components.next()) else {
+            return Err(anyhow!(
+                "'{}' is not a content path. Expected a directory and a file name, e.g. CONTENT/5634121E/500304E0.",
+                path.display()
+            ));
+        };
+        let is_hex = |name: &str| name.len() == 8 && name.chars().all(|c| c.is_ascii_hexdigit());
+        if !is_hex(&directory) || !is_hex(&file_name) {
+            return Err(anyhow!(
+                "'{}' is not a content path. The directory and the file name must have 8 hex digits each.",
+                path.display()
+            ));
+        }
+
+        let mut uid = TagUid::parse(&format!("{}{}", directory, file_name))?;
+        uid.0.reverse();
+        return Ok(uid);
+    }
+
+    /// Parses the reversed UID TeddyCloud identifies tags with, e.g. `5634121e500304e0` for
+    /// `E0:04:03:50:1E:12:34:56`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ruid` - The 16 hex digits of the reversed UID.
+    pub fn from_ruid(ruid: &str) -> Result<Self> {
+        let mut uid = TagUid::parse(ruid)?;
+        uid.0.reverse();
+        return Ok(uid);
+    }
+
+    /// The reversed UID TeddyCloud identifies tags with, e.g. `5634121e500304e0` for `E0:04:03:50:1E:12:34:56`.
+    pub fn ruid(&self) -> String {
+        return self.reversed_hex().to_ascii_lowercase();
+    }
+
+    /// The bytes of the UID in the order they are printed.
+    pub fn bytes(&self) -> [u8; 8] {
+        return self.0;
+    }
+
+    /// The hex digits of the reversed UID, e.g. `5634121E500304E0` for `E0:04:03:50:1E:12:34:56`.
+    fn reversed_hex(&self) -> String {
+        return self
+            .0
+            .iter()
+            .rev()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+    }
+
+    /// The directory below `CONTENT` holding the audio of the tag, e.g. `5634121E`.
+    pub fn content_directory(&self) -> String {
+        return self.reversed_hex()[..8].to_string();
+    }
+
+    /// The file name of the audio of the tag, e.g. `500304E0`.
+    pub fn content_file_name(&self) -> String {
+        return self.reversed_hex()[8..].to_string();
+    }
+
+    /// The path of the Tonie file of the tag on the SD card, e.g. `/media/SD/CONTENT/5634121E/500304E0`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sd_root` - The root directory of the SD card.
+    pub fn content_path(&self, sd_root: &Path) -> PathBuf {
+        return sd_root
+            .join(CONTENT_DIRECTORY)
+            .join(self.content_directory())
+            .join(self.content_file_name());
+    }
+}
+
+impl fmt::Display for TagUid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self
+            .0
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>();
+        return write!(f, "{}", bytes.join(":"));
+    }
+}
@@ -0,0 +1,590 @@
+//! A small HTTP server for remote conversions, e.g. on a NAS driven from a phone browser. Audio files are
+//! uploaded as a job, converted one job at a time and the Tonie file is downloaded when the job is done.
+//!
+//! * `GET /` - An HTML page with an upload form.
+//! * `POST /jobs` - Uploads the audio files of a `multipart/form-data` body as a new job. The files become the
+//!   chapters in the order they are uploaded, an optional `name` field names the Tonie file.
+//! * `GET /jobs` - Lists all jobs as JSON.
+//! * `GET /jobs/<id>` - The status of a job as JSON.
+//! * `GET /jobs/<id>/download` - Downloads the Tonie file of a finished job.
+
+use anyhow::{anyhow, Result};
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+use crate::convert::{convert_files_to_tonie, ConvertOptions};
+use crate::utils::sanitize_file_name;
+
+/// The default maximum size of an upload, 512 MiB. Uploads are streamed to disk, so the limit protects the
+/// disk of the server rather than its memory.
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 512 * 1024 * 1024;
+// The headers of a part and the text fields are kept in memory, so they are capped separately
+const MAX_PART_HEADER_SIZE: usize = 16 * 1024;
+const MAX_FIELD_SIZE: usize = 64 * 1024;
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+const UPLOAD_FORM: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1"><title>audio2tonie</title></head>
+<body>
+<h1>audio2tonie</h1>
+<form method="post" action="/jobs" enctype="multipart/form-data">
+<p><label>Name <input type="text" name="name" placeholder="500304E0"></label></p>
+<p><input type="file" name="files" multiple accept="audio/*"></p>
+<p><button type="submit">Convert</button></p>
+</form>
+<p><a href="/jobs">Jobs</a></p>
+</body>
+</html>
+"#;
+
+/// Options controlling the conversion server.
+#[derive(Clone, Debug)]
+pub struct ServeOptions {
+    /// Options controlling the conversion of every job.
+    pub convert: ConvertOptions,
+    /// The directory for the uploaded files and the Tonie files of the jobs.
+    pub work_dir: PathBuf,
+    /// The maximum size of an upload in bytes.
+    pub max_upload_size: u64,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            convert: ConvertOptions::default(),
+            work_dir: std::env::temp_dir()
+                .join(format!("audio2tonie-serve-{}", std::process::id())),
+            max_upload_size: DEFAULT_MAX_UPLOAD_SIZE,
+        }
+    }
+}
+
+/// The state of a conversion job.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JobStatus {
+    /// The job waits for the conversion of earlier jobs.
+    Queued,
+    /// The audio files are converted.
+    Converting,
+    /// The Tonie file can be downloaded.
+    Done,
+    /// The conversion failed with the given error.
+    Failed(String),
+}
+
+/// A conversion job of uploaded audio files.
+#[derive(Clone, Debug)]
+pub struct Job {
+    /// The number of the job, counting from 1.
+    pub id: usize,
+    /// The name of the Tonie file, used for the download.
+    pub name: String,
+    /// The uploaded audio files in chapter order.
+    pub input_files: Vec<PathBuf>,
+    /// The path of the Tonie file.
+    pub output_path: PathBuf,
+    /// The state of the conversion.
+    pub status: JobStatus,
+}
+
+impl Job {
+    /// The job as JSON object.
+    pub fn to_json(&self) -> Value {
+        let (status, error) = match &self.status {
+            JobStatus::Queued => ("queued", None),
+            JobStatus::Converting => ("converting", None),
+            JobStatus::Done => ("done", None),
+            JobStatus::Failed(error) => ("failed", Some(error)),
+        };
+        let files = self
+            .input_files
+            .iter()
+            .filter_map(|input_file| input_file.file_name())
+            .map(|file_name| file_name.to_string_lossy())
+            .collect::<Vec<_>>();
+
+        return json!({
+            "id": self.id,
+            "name": self.name,
+            "status": status,
+            "error": error,
+            "files": files,
+            "download": format!("/jobs/{}/download", self.id),
+        });
+    }
+}
+
+/// An HTTP server converting uploaded audio files into Tonie files.
+pub struct ConversionServer {
+    server: Server,
+    options: ServeOptions,
+    jobs: Arc<Mutex<Vec<Job>>>,
+    // Numbers the directories of uploads in progress, which become the job directories once complete
+    uploads: AtomicUsize,
+}
+
+impl ConversionServer {
+    /// Listens on the given address, e.g. `0.0.0.0:8080`. Port 0 picks a free port.
+    ///
+    /// # Arguments
+    ///
+    /// * `listen` - The address to listen on.
+    /// * `options` - Options controlling the uploads and the conversions.
+    pub fn bind(listen: &str, options: &ServeOptions) -> Result<Self> {
+        let server = Server::http(listen)
+            .map_err(|error| anyhow!("Failed to listen on {}: {}", listen, error))?;
+        std::fs::create_dir_all(&options.work_dir)?;
+
+        return Ok(ConversionServer {
+            server,
+            options: options.clone(),
+            jobs: Arc::new(Mutex::new(vec![])),
+            uploads: AtomicUsize::new(0),
+        });
+    }
+
+    /// The address the server listens on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        return self.server.server_addr().to_ip();
+    }
+
+    /// Answers requests until the server fails. The jobs are converted one after another on a separate thread.
+    pub fn run(self) -> Result<()> {
+        let (sender, receiver) = channel::<usize>();
+        let jobs = self.jobs.clone();
+        let convert_options = self.options.convert.clone();
+        std::thread::spawn(move || {
+            for id in receiver {
+                let Some(job) = update_job(&jobs, id, JobStatus::Converting) else {
+                    continue;
+                };
+                let status = match convert_files_to_tonie(
+                    &job.input_files,
+                    &job.output_path,
+                    &convert_options,
+                ) {
+                    Ok(_) => JobStatus::Done,
+                    Err(error) => JobStatus::Failed(error.to_string()),
+                };
+                update_job(&jobs, id, status);
+            }
+        });
+
+        for request in self.server.incoming_requests() {
+            // A failed response only affects the client which sent the request
+            let _ = self.handle_request(request, &sender);
+        }
+        return Ok(());
+    }
+
+    fn handle_request(&self, mut request: Request, sender: &Sender<usize>) -> Result<()> {
+        let url = request.url().to_string();
+        let segments = url
+            .split('?')
+            .next()
+            .unwrap_or_default()
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+
+        let response = match (request.method(), segments.as_slice()) {
+            (Method::Get, []) => html_response(UPLOAD_FORM)?,
+            (Method::Get, ["jobs"]) => {
+                let jobs = self
+                    .jobs
+                    .lock()
+                    .map_err(|_| anyhow!("The job list is poisoned."))?;
+                json_response(
+                    200,
+                    json!(jobs.iter().map(Job::to_json).collect::<Vec<_>>()),
+                )?
+            }
+            (Method::Post, ["jobs"]) => match self.create_job(&mut request) {
+                Ok(job) => {
+                    sender.send(job.id)?;
+                    json_response(201, job.to_json())?
+                }
+                Err((status, error)) => json_response(status, json!({ "error": error }))?,
+            },
+            (Method::Get, ["jobs", id]) => match self.find_job(id) {
+                Some(job) => json_response(200, job.to_json())?,
+                None => json_response(404, json!({ "error": "The job does not exist." }))?,
+            },
+            (Method::Get, ["jobs", id, "download"]) => match self.find_job(id) {
+                Some(job) if job.status == JobStatus::Done => {
+                    let disposition = content_disposition(&format!("{}.taf", job.name));
+                    match File::open(&job.output_path) {
+                        Ok(file) => {
+                            let response = Response::from_file(file)
+                                .with_header(header("Content-Type", "application/octet-stream")?)
+                                .with_header(header("Content-Disposition", &disposition)?);
+                            return Ok(request.respond(response)?);
+                        }
+                        Err(error) => json_response(500, json!({ "error": error.to_string() }))?,
+                    }
+                }
+                Some(_) => json_response(409, json!({ "error": "The job is not done yet." }))?,
+                None => json_response(404, json!({ "error": "The job does not exist." }))?,
+            },
+            _ => json_response(404, json!({ "error": "Not found." }))?,
+        };
+
+        return Ok(request.respond(response)?);
+    }
+
+    // Stores the uploaded files of a new job, the errors come with the HTTP status code. The body is streamed
+    // into an upload directory, which is renamed to the job directory once the upload is complete.
+    fn create_job(&self, request: &mut Request) -> Result<Job, (u16, String)> {
+        let content_type = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Content-Type"))
+            .map(|header| header.value.to_string())
+            .unwrap_or_default();
+        let upload_dir = self.options.work_dir.join(format!(
+            "upload-{}",
+            self.uploads.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut body = request.as_reader().take(self.options.max_upload_size + 1);
+        let upload = store_multipart(&content_type, &mut body, &upload_dir);
+        let upload = match (upload, body.limit()) {
+            (_, 0) => Err((413, "The upload is too large.".to_string())),
+            (Ok(upload), _) if upload.files.is_empty() => {
+                Err((400, "The upload does not contain any files.".to_string()))
+            }
+            (Ok(upload), _) => Ok(upload),
+            (Err(error), _) => Err((400, error.to_string())),
+        };
+        let upload = match upload {
+            Ok(upload) => upload,
+            Err(error) => {
+                std::fs::remove_dir_all(&upload_dir).ok();
+                return Err(error);
+            }
+        };
+
+        let mut jobs = self
+            .jobs
+            .lock()
+            .map_err(|_| (500, "The job list is poisoned.".to_string()))?;
+        let id = jobs.len() + 1;
+        let job_dir = self.options.work_dir.join(id.to_string());
+        std::fs::rename(&upload_dir, &job_dir).map_err(|error| {
+            std::fs::remove_dir_all(&upload_dir).ok();
+            (500, error.to_string())
+        })?;
+
+        let job = Job {
+            id,
+            name: upload.name.unwrap_or_else(|| {
+                Path::new(&upload.files[0])
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().to_string())
+                    .unwrap_or_else(|| id.to_string())
+            }),
+            input_files: upload.files.iter().map(|file| job_dir.join(file)).collect(),
+            output_path: job_dir.join("output.taf"),
+            status: JobStatus::Queued,
+        };
+        jobs.push(job.clone());
+        return Ok(job);
+    }
+
+    fn find_job(&self, id: &str) -> Option<Job> {
+        let id = id.parse::<usize>().ok()?;
+        let jobs = self.jobs.lock().ok()?;
+        return jobs.iter().find(|job| job.id == id).cloned();
+    }
+}
+
+fn update_job(jobs: &Mutex<Vec<Job>>, id: usize, status: JobStatus) -> Option<Job> {
+    let mut jobs = jobs.lock().ok()?;
+    let job = jobs.iter_mut().find(|job| job.id == id)?;
+    job.status = status;
+    return Some(job.clone());
+}
+
+// Browsers may send the full path of a file, only its sanitized name is kept
+fn upload_file_name(file_name: &str) -> String {
+    let file_name = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    return sanitize_file_name(file_name, false);
+}
+
+/// The files and the name of an upload, see [`store_multipart`].
+struct Upload {
+    /// The value of the `name` field, sanitized for a file name.
+    name: Option<String>,
+    /// The stored files relative to the upload directory in upload order, e.g. `001/story.mp3`.
+    files: Vec<PathBuf>,
+}
+
+// Streams a `multipart/form-data` body into the upload directory. Every file gets its own directory, so files
+// with the same name do not overwrite each other. Only the part headers and the text fields are kept in memory.
+fn store_multipart<R: Read>(content_type: &str, reader: R, upload_dir: &Path) -> Result<Upload> {
+    let boundary = multipart_boundary(content_type)?;
+    let mut reader = MultipartReader {
+        reader,
+        // The delimiters are preceded by a line break, except for the first one at the start of the body
+        buffer: b"\r\n".to_vec(),
+        delimiter: format!("\r\n--{}", boundary).into_bytes(),
+    };
+    // The preamble before the first delimiter is ignored
+    reader.copy_part(&mut std::io::sink(), usize::MAX)?;
+
+    let mut upload = Upload {
+        name: None,
+        files: vec![],
+    };
+    // The last delimiter is followed by "--"
+    while !reader.next_starts_with(b"--")? {
+        let headers = reader.read_part_headers()?;
+        let disposition = headers
+            .lines()
+            .find(|line| {
+                line.to_ascii_lowercase()
+                    .starts_with("content-disposition:")
+            })
+            .unwrap_or_default();
+        let name = disposition_parameter(disposition, "name").unwrap_or_default();
+        match disposition_parameter(disposition, "filename") {
+            // Browsers send an empty file field without a file name
+            Some(file_name) if file_name.is_empty() => {
+                reader.copy_part(&mut std::io::sink(), usize::MAX)?;
+            }
+            Some(file_name) => {
+                let file_dir = PathBuf::from(format!("{:03}", upload.files.len() + 1));
+                let file = file_dir.join(upload_file_name(&file_name));
+                std::fs::create_dir_all(upload_dir.join(&file_dir))?;
+                let mut writer = std::io::BufWriter::new(File::create(upload_dir.join(&file))?);
+                reader.copy_part(&mut writer, usize::MAX)?;
+                writer.flush()?;
+                upload.files.push(file);
+            }
+            None => {
+                let mut data = vec![];
+                reader.copy_part(&mut data, MAX_FIELD_SIZE)?;
+                if name == "name" {
+                    upload.name = Some(sanitize_file_name(
+                        String::from_utf8_lossy(&data).trim(),
+                        false,
+                    ))
+                    .filter(|name| !name.is_empty());
+                }
+            }
+        }
+    }
+
+    return Ok(upload);
+}
+
+// Reads a multipart body in chunks. The buffer starts right after the last delimiter that was read.
+struct MultipartReader<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    delimiter: Vec<u8>,
+}
+
+impl<R: Read> MultipartReader<R> {
+    // Reads the next chunk into the buffer, fails at the end of the body
+    fn fill(&mut self) -> Result<()> {
+        let mut chunk = vec![0u8; UPLOAD_CHUNK_SIZE];
+        let read = self.reader.read(&mut chunk)?;
+        if read == 0 {
+            return Err(anyhow!("The multipart body is truncated."));
+        }
+        self.buffer.extend_from_slice(&chunk[..read]);
+        return Ok(());
+    }
+
+    fn next_starts_with(&mut self, prefix: &[u8]) -> Result<bool> {
+        while self.buffer.len() < prefix.len() {
+            self.fill()?;
+        }
+        return Ok(self.buffer.starts_with(prefix));
+    }
+
+    fn read_part_headers(&mut self) -> Result<String> {
+        if !self.next_starts_with(b"\r\n")? {
+            return Err(anyhow!("The multipart body is malformed."));
+        }
+        loop {
+            if let Some(headers_end) = find(&self.buffer, b"\r\n\r\n") {
+                let headers = String::from_utf8_lossy(&self.buffer[2..headers_end]).to_string();
+                self.buffer.drain(..headers_end + 4);
+                return Ok(headers);
+            }
+            if self.buffer.len() > MAX_PART_HEADER_SIZE {
+                return Err(anyhow!("The headers of a multipart part are too large."));
+            }
+            self.fill()?;
+        }
+    }
+
+    // Copies the data up to the next delimiter and skips the delimiter
+    fn copy_part<W: Write>(&mut self, writer: &mut W, max_size: usize) -> Result<()> {
+        let mut size = 0;
+        loop {
+            let (data_end, consumed) = match find(&self.buffer, &self.delimiter) {
+                Some(data_end) => (data_end, data_end + self.delimiter.len()),
+                // The end of the buffer may be the start of the delimiter
+                None => {
+                    let data_end = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+                    (data_end, data_end)
+                }
+            };
+            size += data_end;
+            if size > max_size {
+                return Err(anyhow!("A field of the multipart body is too large."));
+            }
+            writer.write_all(&self.buffer[..data_end])?;
+            let found = consumed > data_end;
+            self.buffer.drain(..consumed);
+            if found {
+                return Ok(());
+            }
+            self.fill()?;
+        }
+    }
+}
+
+/// A part of a `multipart/form-data` body.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormPart {
+    /// The name of the form field.
+    pub name: String,
+    /// The file name of an uploaded file, `None` for other fields.
+    pub file_name: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Splits a `multipart/form-data` body into its parts.
+///
+/// # Arguments
+///
+/// * `content_type` - The content type of the request with the boundary, e.g.
+///   `multipart/form-data; boundary=----abc`.
+/// * `body` - The request body.
+pub fn parse_multipart(content_type: &str, body: &[u8]) -> Result<Vec<FormPart>> {
+    let boundary = multipart_boundary(content_type)?;
+    let delimiter = format!("--{}", boundary).into_bytes();
+
+    let mut parts = vec![];
+    let mut remaining = body;
+    let start = find(remaining, &delimiter).ok_or_else(|| anyhow!("The body has no parts."))?;
+    remaining = &remaining[start + delimiter.len()..];
+    // The last delimiter is followed by "--"
+    while !remaining.starts_with(b"--") {
+        let remaining_part = remaining
+            .strip_prefix(b"\r\n")
+            .ok_or_else(|| anyhow!("The multipart body is malformed."))?;
+        let headers_end = find(remaining_part, b"\r\n\r\n")
+            .ok_or_else(|| anyhow!("The multipart body is malformed."))?;
+        let headers = String::from_utf8_lossy(&remaining_part[..headers_end]);
+        let data_start = headers_end + 4;
+        let mut next_delimiter = b"\r\n".to_vec();
+        next_delimiter.extend_from_slice(&delimiter);
+        let data_end = find(&remaining_part[data_start..], &next_delimiter)
+            .ok_or_else(|| anyhow!("The multipart body is truncated."))?
+            + data_start;
+
+        let disposition = headers
+            .lines()
+            .find(|line| {
+                line.to_ascii_lowercase()
+                    .starts_with("content-disposition:")
+            })
+            .unwrap_or_default();
+        parts.push(FormPart {
+            name: disposition_parameter(disposition, "name").unwrap_or_default(),
+            file_name: disposition_parameter(disposition, "filename"),
+            data: remaining_part[data_start..data_end].to_vec(),
+        });
+        remaining = &remaining_part[data_end + next_delimiter.len()..];
+    }
+
+    return Ok(parts);
+}
+
+fn multipart_boundary(content_type: &str) -> Result<&str> {
+    return Ok(content_type
+        .split(';')
+        .map(str::trim)
+        .find_map(|parameter| parameter.strip_prefix("boundary="))
+        .filter(|_| content_type.trim_start().starts_with("multipart/form-data"))
+        .ok_or_else(|| anyhow!("Expected a multipart/form-data body."))?
+        .trim_matches('"'));
+}
+
+// Reads a quoted parameter of a Content-Disposition header, e.g. `filename="story.mp3"`
+fn disposition_parameter(disposition: &str, name: &str) -> Option<String> {
+    return disposition.split(';').map(str::trim).find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case(name) {
+            return None;
+        }
+        return Some(value.trim().trim_matches('"').to_string());
+    });
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    return haystack
+        .windows(needle.len())
+        .position(|window| window == needle);
+}
+
+/// The `Content-Disposition` header value of a download. Header values are ASCII, so the file name is sent as
+/// ASCII fallback with the other characters replaced and percent-encoded as UTF-8 according to RFC 5987, e.g.
+/// `attachment; filename="M_rchen.taf"; filename*=UTF-8''M%C3%A4rchen.taf`.
+///
+/// # Arguments
+///
+/// * `file_name` - The file name of the download.
+pub fn content_disposition(file_name: &str) -> String {
+    let fallback = file_name
+        .chars()
+        .map(|character| match character {
+            '"' | '\\' => '_',
+            character if character.is_ascii() && !character.is_ascii_control() => character,
+            _ => '_',
+        })
+        .collect::<String>();
+    let mut encoded = String::with_capacity(file_name.len());
+    for byte in file_name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => encoded.push(byte as char),
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    return format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    );
+}
+
+fn header(field: &str, value: &str) -> Result<Header> {
+    return Header::from_bytes(field.as_bytes(), value.as_bytes())
+        .map_err(|_| anyhow!("The value of the header {} is not valid ASCII.", field));
+}
+
+fn html_response(html: &str) -> Result<Response<std::io::Cursor<Vec<u8>>>> {
+    return Ok(Response::from_string(html)
+        .with_header(header("Content-Type", "text/html; charset=utf-8")?));
+}
+
+fn json_response(status: u16, value: Value) -> Result<Response<std::io::Cursor<Vec<u8>>>> {
+    return Ok(Response::from_string(value.to_string())
+        .with_status_code(StatusCode(status))
+        .with_header(header("Content-Type", "application/json")?));
+}
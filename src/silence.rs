@@ -0,0 +1,123 @@
+//! Removes silence at the start and the end of a track from interleaved 16 bit PCM while it is streamed.
+
+/// The level in dBFS below which audio counts as silence.
+pub const DEFAULT_SILENCE_THRESHOLD: f64 = -50.0;
+/// The minimum length of silence in milliseconds that is removed.
+pub const DEFAULT_SILENCE_MIN_DURATION: u64 = 500;
+
+const SAMPLE_RATE: u64 = 48000;
+
+/// Parameters of the silence detection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SilenceTrim {
+    /// The level in dBFS below which audio counts as silence.
+    pub threshold: f64,
+    /// The minimum length of silence in milliseconds that is removed. Shorter pauses are kept.
+    pub min_duration: u64,
+}
+
+impl Default for SilenceTrim {
+    fn default() -> Self {
+        SilenceTrim {
+            threshold: DEFAULT_SILENCE_THRESHOLD,
+            min_duration: DEFAULT_SILENCE_MIN_DURATION,
+        }
+    }
+}
+
+/// Streaming trimmer for the leading and trailing silence of a track. Silence within the track is kept, so
+/// silent samples are held back until it is known whether more audio follows.
+#[derive(Clone, Debug)]
+pub struct SilenceTrimmer {
+    channels: usize,
+    threshold: i32,
+    min_frames: usize,
+    at_start: bool,
+    // Silent samples that are either dropped or passed on once the silence ends
+    pending: Vec<i16>,
+    // The silence at the start is long enough to be removed, so further silent samples are dropped right away
+    dropping: bool,
+}
+
+impl SilenceTrimmer {
+    /// Creates a trimmer for a new track.
+    ///
+    /// # Arguments
+    ///
+    /// * `channels` - The number of interleaved channels.
+    /// * `trim` - The threshold and minimum length of the removed silence.
+    pub fn new(channels: usize, trim: &SilenceTrim) -> Self {
+        SilenceTrimmer {
+            channels,
+            threshold: (32768.0 * 10f64.powf(trim.threshold / 20.0)).round() as i32,
+            min_frames: (trim.min_duration * SAMPLE_RATE / 1000) as usize,
+            at_start: true,
+            pending: vec![],
+            dropping: false,
+        }
+    }
+
+    /// Passes the samples without the leading silence to the callback and holds back trailing silent samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Interleaved samples, following the order: left, right, left, right, ...
+    /// * `on_samples` - Called with the samples to keep.
+    pub fn process<F>(&mut self, samples: &[i16], mut on_samples: F)
+    where
+        F: FnMut(&[i16]),
+    {
+        // The start of the samples that are not passed on or held back yet
+        let mut start = 0;
+        for (index, frame) in samples.chunks_exact(self.channels).enumerate() {
+            let offset = index * self.channels;
+            let is_silent = frame
+                .iter()
+                .all(|sample| (*sample as i32).abs() < self.threshold);
+
+            if is_silent {
+                if start < offset {
+                    on_samples(&samples[start..offset]);
+                }
+                start = offset + self.channels;
+                if !self.dropping {
+                    self.pending.extend_from_slice(frame);
+                }
+                if self.at_start && self.pending.len() >= self.min_frames * self.channels {
+                    self.pending.clear();
+                    self.dropping = true;
+                }
+            } else {
+                self.at_start = false;
+                self.dropping = false;
+                if !self.pending.is_empty() {
+                    on_samples(&self.pending);
+                    self.pending.clear();
+                }
+            }
+        }
+
+        if start < samples.len() {
+            on_samples(&samples[start..]);
+        }
+    }
+
+    /// Ends the track. The held back silence is dropped if it is long enough, otherwise it is passed on.
+    /// The trimmer is reset for the next track.
+    ///
+    /// # Arguments
+    ///
+    /// * `on_samples` - Called with the held back samples to keep.
+    pub fn finish<F>(&mut self, mut on_samples: F)
+    where
+        F: FnMut(&[i16]),
+    {
+        if !self.pending.is_empty() && self.pending.len() < self.min_frames * self.channels {
+            on_samples(&self.pending);
+        }
+
+        self.pending.clear();
+        self.at_start = true;
+        self.dropping = false;
+    }
+}
@@ -0,0 +1,150 @@
+//! Splits a Tonie file with several chapters into standalone Tonie files, one per chapter.
+//! The Opus audio is repackaged without re-encoding.
+
+use anyhow::{anyhow, Result};
+use sha1::{Digest, Sha1};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use toniefile::Toniefile;
+
+use crate::encode::opus_header_pages;
+use crate::extract::{chapter_file_name, read_chapter_titles, ExtractOptions};
+use crate::ogg_page::{HEADER_TYPE_BEGIN_OF_STREAM, HEADER_TYPE_END_OF_STREAM};
+use crate::taf::{encode_header, opus_comments, OggPageIterator, TONIEFILE_FRAME_SIZE};
+use crate::throttle::ThrottledIo;
+use crate::utils::check_input_limits;
+
+// The OpusHead and OpusTags packets occupy a page each
+const HEADER_PAGE_COUNT: usize = 2;
+
+/// Splits a Tonie file into one Tonie file per chapter. Every file gets its own header with a single chapter and
+/// an Ogg stream starting with the Opus headers of the original file, followed by the unchanged audio pages of the
+/// chapter with renumbered pages and granule positions.
+///
+/// The files are named like the files of extracted chapters. The audio id of the original file is increased by
+/// the chapter index, so the first file keeps the original audio id and all files stay distinguishable.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `output_directory` - The directory to write the Tonie files to.
+/// * `options` - Options controlling the file names and resource limits.
+pub fn split_tonie_file(
+    input_file_path: &Path,
+    output_directory: &Path,
+    options: &ExtractOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut tonie_file = ThrottledIo::new(File::open(input_file_path)?, options.io_throttle);
+    check_input_limits(&mut tonie_file, &options.limits)?;
+
+    let tonie_header = Toniefile::parse_header(&mut tonie_file)?;
+    let audio_data = Toniefile::extract_audio(&mut tonie_file)?;
+    options
+        .limits
+        .check_pages(audio_data.len() / TONIEFILE_FRAME_SIZE)?;
+
+    let mut pages = vec![];
+    for page in OggPageIterator::with_limits(&audio_data, options.limits) {
+        let (page_offset, page) = page?;
+        if page.is_continued() {
+            return Err(anyhow!(
+                "Page {} continues a packet of the previous page, which is not supported.",
+                page.page_sequence_number
+            ));
+        }
+        pages.push(((page_offset / TONIEFILE_FRAME_SIZE) as u32, page));
+    }
+    if pages.len() <= HEADER_PAGE_COUNT {
+        return Err(anyhow!("The Tonie file does not contain an Opus stream."));
+    }
+
+    let opus_head = pages[0].1.packets().first().map(|packet| packet.to_vec());
+    let opus_head = opus_head
+        .filter(|packet| packet.len() >= 12 && packet.starts_with(b"OpusHead"))
+        .ok_or_else(|| anyhow!("The Tonie file does not contain an Opus stream."))?;
+    let pre_skip = u16::from_le_bytes([opus_head[10], opus_head[11]]) as u64;
+    let serial_number = pages[0].1.serial_number;
+    // The first block holds the Opus headers and the start of the first chapter. Every other chapter starts at a
+    // block boundary, so its headers have to fill a block on their own.
+    let first_header_pages = pages[..HEADER_PAGE_COUNT]
+        .iter()
+        .flat_map(|(_, page)| page.serialize())
+        .collect::<Vec<_>>();
+    let header_pages = opus_header_pages(&opus_head, &opus_comments(&audio_data), serial_number)?;
+
+    let chapter_count = tonie_header.track_page_nums.len();
+    let titles = read_chapter_titles(Some(input_file_path), &audio_data, chapter_count);
+    let output_file_path = output_directory.join(
+        input_file_path
+            .file_name()
+            .ok_or_else(|| anyhow!("The input path has no file name."))?,
+    );
+
+    let mut chapter_file_paths = vec![];
+    for (chapter, first_block) in tonie_header.track_page_nums.iter().enumerate() {
+        let end_block = tonie_header
+            .track_page_nums
+            .get(chapter + 1)
+            .copied()
+            .unwrap_or(u32::MAX);
+        let chapter_pages = pages
+            .iter()
+            .skip(HEADER_PAGE_COUNT)
+            .filter(|(block, _)| block >= first_block && *block < end_block)
+            .map(|(_, page)| page)
+            .collect::<Vec<_>>();
+        if chapter_pages.is_empty() {
+            return Err(anyhow!(
+                "Chapter {} points outside of the audio data. The Tonie header is corrupt.",
+                chapter
+            ));
+        }
+
+        // The granule positions of the new stream count from the start of the chapter
+        let chapter_start = pages
+            .iter()
+            .skip(HEADER_PAGE_COUNT)
+            .take_while(|(block, _)| block < first_block)
+            .last()
+            .map(|(_, page)| page.granule_position.saturating_sub(pre_skip))
+            .unwrap_or_default();
+
+        let mut chapter_data = match chapter {
+            0 => first_header_pages.clone(),
+            _ => header_pages.clone(),
+        };
+        for (index, page) in chapter_pages.iter().enumerate() {
+            let mut page = (*page).clone();
+            page.page_sequence_number = (HEADER_PAGE_COUNT + index) as u32;
+            page.granule_position = page.granule_position.saturating_sub(chapter_start);
+            page.header_type &= !(HEADER_TYPE_BEGIN_OF_STREAM | HEADER_TYPE_END_OF_STREAM);
+            if index == chapter_pages.len() - 1 {
+                page.header_type |= HEADER_TYPE_END_OF_STREAM;
+            }
+            chapter_data.extend_from_slice(&page.serialize());
+        }
+
+        let header = encode_header(
+            &Sha1::digest(&chapter_data),
+            chapter_data.len() as u64,
+            tonie_header.audio_id.wrapping_add(chapter as u32),
+            &[0],
+        )
+        .ok_or_else(|| anyhow!("Failed to encode the Tonie header."))?;
+
+        let chapter_file_path = output_directory.join(chapter_file_name(
+            &output_file_path,
+            chapter,
+            titles.get(chapter).map(String::as_str),
+            options,
+        ));
+        let mut chapter_file =
+            ThrottledIo::new(File::create(&chapter_file_path)?, options.io_throttle);
+        chapter_file.write_all(&header)?;
+        chapter_file.write_all(&chapter_data)?;
+        chapter_file_paths.push(chapter_file_path);
+    }
+
+    return Ok(chapter_file_paths);
+}
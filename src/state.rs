@@ -0,0 +1,206 @@
+//! The state of incremental batch conversions. Every converted input is recorded with the modification time of its
+//! files and the conversion settings, so a later run over the same library only converts new or changed inputs.
+
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use sha1::{Digest, Sha1};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::convert::{filter_input_files, ConvertOptions};
+
+/// The name of the state file in the output directory of a batch conversion.
+pub const STATE_FILE_NAME: &str = ".audio2tonie-state.json";
+
+/// A converted input of a batch conversion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConversionRecord {
+    /// The latest modification time of the input in milliseconds since the Unix epoch, see [`last_modified`].
+    pub modified: u64,
+    /// The hash of the conversion settings, see [`settings_hash`].
+    pub settings: String,
+    /// The Tonie files written for the input, several if it was split into parts.
+    pub outputs: Vec<PathBuf>,
+}
+
+/// The converted inputs of a batch conversion, stored as JSON in the output directory.
+#[derive(Clone, Debug, Default)]
+pub struct ConversionState {
+    path: PathBuf,
+    records: BTreeMap<PathBuf, ConversionRecord>,
+}
+
+impl ConversionState {
+    /// Loads the state of the batch conversions into the given output directory. A missing or unreadable state
+    /// file results in an empty state, so everything is converted again.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_dir` - The output directory of the batch conversion.
+    pub fn load(output_dir: &Path) -> Self {
+        let path = output_dir.join(STATE_FILE_NAME);
+        let records = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|state| serde_json::from_str::<Value>(&state).ok())
+            .and_then(|state| parse_records(&state))
+            .unwrap_or_default();
+        return ConversionState { path, records };
+    }
+
+    /// Writes the state file. It is replaced atomically, so an interrupted run keeps the previous state.
+    pub fn save(&self) -> Result<()> {
+        let records = self
+            .records
+            .iter()
+            .map(|(input, record)| {
+                let record = json!({
+                    "modified": record.modified,
+                    "settings": record.settings,
+                    "outputs": record.outputs,
+                });
+                return (input.to_string_lossy().to_string(), record);
+            })
+            .collect::<Map<_, _>>();
+        let state = json!({ "version": 1, "inputs": records });
+
+        let mut temp_file_name = self.path.file_name().unwrap_or_default().to_os_string();
+        temp_file_name.push(".part");
+        let temp_path = self.path.with_file_name(temp_file_name);
+        std::fs::write(&temp_path, serde_json::to_string_pretty(&state)?)?;
+        std::fs::rename(&temp_path, &self.path)?;
+        return Ok(());
+    }
+
+    /// The record of a converted input.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input file or directory.
+    pub fn get(&self, input: &Path) -> Option<&ConversionRecord> {
+        return self.records.get(&state_key(input));
+    }
+
+    /// Whether an input was converted with the same settings after its last modification and all of its Tonie
+    /// files still exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input file or directory.
+    /// * `modified` - The latest modification time of the input, see [`last_modified`].
+    /// * `settings` - The hash of the current conversion settings, see [`settings_hash`].
+    pub fn is_up_to_date(&self, input: &Path, modified: u64, settings: &str) -> bool {
+        return self.get(input).is_some_and(|record| {
+            record.modified == modified
+                && record.settings == settings
+                && !record.outputs.is_empty()
+                && record.outputs.iter().all(|output| output.is_file())
+        });
+    }
+
+    /// Records a converted input, replacing its previous record. Like the inputs, the outputs are recorded with
+    /// their absolute path.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The input file or directory.
+    /// * `record` - The conversion of the input.
+    pub fn record(&mut self, input: &Path, mut record: ConversionRecord) {
+        record.outputs = record
+            .outputs
+            .iter()
+            .map(|output| state_key(output))
+            .collect();
+        self.records.insert(state_key(input), record);
+    }
+}
+
+// Inputs are recorded with their absolute path, so the state does not depend on the working directory
+fn state_key(input: &Path) -> PathBuf {
+    return std::fs::canonicalize(input).unwrap_or_else(|_| input.to_path_buf());
+}
+
+fn parse_records(state: &Value) -> Option<BTreeMap<PathBuf, ConversionRecord>> {
+    if state.get("version")?.as_u64()? != 1 {
+        return None;
+    }
+    let records = state
+        .get("inputs")?
+        .as_object()?
+        .iter()
+        .filter_map(|(input, record)| {
+            let record = ConversionRecord {
+                modified: record.get("modified")?.as_u64()?,
+                settings: record.get("settings")?.as_str()?.to_string(),
+                outputs: record
+                    .get("outputs")?
+                    .as_array()?
+                    .iter()
+                    .filter_map(|output| output.as_str().map(PathBuf::from))
+                    .collect(),
+            };
+            return Some((PathBuf::from(input), record));
+        })
+        .collect();
+    return Some(records);
+}
+
+/// The latest modification time of an input in milliseconds since the Unix epoch. Directories count as modified
+/// when one of their audio files changes or when files are added, removed or renamed.
+///
+/// # Arguments
+///
+/// * `input` - The input file or directory.
+/// * `options` - Options of the conversion, which decide which files count as input files.
+pub fn last_modified(input: &PathBuf, options: &ConvertOptions) -> Result<u64> {
+    let mut paths = filter_input_files(input, options.any_extension)?;
+    paths.push(input.clone());
+
+    let mut last_modified = 0;
+    for path in paths {
+        let modified = std::fs::metadata(path)?.modified()?;
+        let modified = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        last_modified = last_modified.max(modified.as_millis() as u64);
+    }
+    return Ok(last_modified);
+}
+
+/// A hash of the conversion settings which change the audio of a Tonie file, so inputs are converted again
+/// when the settings of a batch conversion change. Paths, throttling and the number of threads are left out.
+///
+/// # Arguments
+///
+/// * `options` - Options of the conversion.
+pub fn settings_hash(options: &ConvertOptions) -> String {
+    // Tuples implement Debug for up to 12 elements
+    let settings = format!(
+        "{:?} {:?}",
+        (
+            options.normalization,
+            options.target_loudness,
+            options.audio_id,
+            options.trim_silence,
+            options.fade_in,
+            options.fade_out,
+            options.speed,
+        ),
+        (
+            options.on_too_many_chapters,
+            options.passthrough,
+            options.any_extension,
+            options.track_order,
+            options.bitrate,
+            options.opus_application,
+            options.cbr,
+            options.tonie_header,
+            options.max_audio_length,
+            &options.comments,
+            &options.ffmpeg_args,
+            &options.input_format,
+        )
+    );
+    return Sha1::digest(settings.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+}
@@ -0,0 +1,58 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use audio2tonie::loudness::LoudnessMeter;
+use std::path::Path;
+
+use crate::i18n::{Language, Message};
+use audio2tonie::decode::decode_tonie_chapters;
+use audio2tonie::utils::format_duration;
+
+/// Measures duration, integrated loudness and true peak of every chapter of a Tonie file.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+pub fn get_chapter_stats(input_file_path: &Path, limits: &Limits) -> Result<Vec<LoudnessMeter>> {
+    let mut meters: Vec<LoudnessMeter> = vec![];
+
+    decode_tonie_chapters(input_file_path, limits, |chapter, samples| {
+        while meters.len() <= chapter {
+            meters.push(LoudnessMeter::new(2));
+        }
+        meters[chapter].add_samples(samples);
+        return Ok(());
+    })?;
+
+    return Ok(meters);
+}
+
+/// Prints a table with duration, loudness and true peak per chapter.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to the Tonie file.
+/// * `limits` - Caps for the input size, header size and number of pages.
+/// * `language` - The language of the table headers.
+pub fn print_stats(input_file_path: &Path, limits: &Limits, language: Language) -> Result<()> {
+    let meters = get_chapter_stats(input_file_path, limits)?;
+
+    println!(
+        "{:<8} {:>9} {:>14} {:>12}",
+        language.translate(Message::Chapter),
+        language.translate(Message::Duration),
+        language.translate(Message::Loudness),
+        language.translate(Message::TruePeak)
+    );
+    for (index, meter) in meters.iter().enumerate() {
+        println!(
+            "{:<8} {:>9} {:>9.1} LUFS {:>7.1} dBTP",
+            index + 1,
+            format_duration(meter.duration()),
+            meter.integrated_loudness(),
+            meter.true_peak()
+        );
+    }
+
+    return Ok(());
+}
@@ -0,0 +1,131 @@
+use audio2tonie::batch::{BatchJob, BatchResult, BatchStatus};
+use audio2tonie::utils::format_duration;
+use serde_json::{json, Value};
+
+use crate::i18n::{Language, Message};
+
+/// A job of a batch run and its result as JSON object, like the reports of `convert`.
+///
+/// # Arguments
+///
+/// * `job` - The job of the manifest.
+/// * `result` - The result of the job.
+pub fn batch_result_json(job: &BatchJob, result: &BatchResult) -> Value {
+    let (status, error) = match &result.status {
+        BatchStatus::Converted => ("converted", None),
+        BatchStatus::Skipped => ("skipped", None),
+        BatchStatus::Failed(error) => ("failed", Some(error)),
+        BatchStatus::NotStarted => ("not_started", None),
+    };
+    let failed_tracks = result
+        .failed_tracks
+        .iter()
+        .map(|track| {
+            json!({
+                "input_file": track.input_file,
+                "error": track.error,
+                "truncated": track.truncated,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    return json!({
+        "input": job.inputs,
+        "output": job.output,
+        "title": job.title,
+        "tag_uid": job.tag_uid.map(|tag_uid| tag_uid.to_string()),
+        "status": status,
+        "error": error,
+        "failed_tracks": failed_tracks,
+        "duration": result.duration.as_secs_f64(),
+    });
+}
+
+/// Prints the status, the output file and the duration of a job of a batch run once it is done, followed by the
+/// input files left out of or truncated in its Tonie file.
+///
+/// # Arguments
+///
+/// * `job` - The job of the manifest.
+/// * `result` - The result of the job.
+/// * `language` - The language of the status.
+pub fn print_batch_result(job: &BatchJob, result: &BatchResult, language: Language) {
+    let name = match &job.title {
+        Some(title) => format!("{} ({})", title, job.output.display()),
+        None => job.output.display().to_string(),
+    };
+    let duration = format_duration(result.duration.as_secs_f64());
+    let status = match &result.status {
+        BatchStatus::Converted => {
+            format!(
+                "{:<16} {} [{}]",
+                language.translate(Message::Converted),
+                name,
+                duration
+            )
+        }
+        BatchStatus::Skipped => format!("{:<16} {}", language.translate(Message::Skipped), name),
+        BatchStatus::Failed(error) => {
+            format!(
+                "{:<16} {}: {}",
+                language.translate(Message::Failed),
+                name,
+                error
+            )
+        }
+        BatchStatus::NotStarted => {
+            format!("{:<16} {}", language.translate(Message::NotStarted), name)
+        }
+    };
+    println!("{}", status);
+    for track in &result.failed_tracks {
+        match track.truncated {
+            true => println!(
+                "{:<16} {}: {} ({})",
+                "",
+                track.input_file.display(),
+                track.error,
+                language.translate(Message::Truncated)
+            ),
+            false => println!("{:<16} {}: {}", "", track.input_file.display(), track.error),
+        }
+    }
+}
+
+/// Prints the number of converted, skipped, failed and not started jobs of a batch run.
+///
+/// # Arguments
+///
+/// * `results` - The results of all jobs.
+/// * `language` - The language of the labels.
+pub fn print_batch_summary(results: &[BatchResult], language: Language) {
+    let count = |status: fn(&BatchStatus) -> bool| {
+        return results
+            .iter()
+            .filter(|result| status(&result.status))
+            .count();
+    };
+    let counts = [
+        (
+            Message::Converted,
+            count(|status| *status == BatchStatus::Converted),
+        ),
+        (
+            Message::Skipped,
+            count(|status| *status == BatchStatus::Skipped),
+        ),
+        (
+            Message::Failed,
+            count(|status| matches!(status, BatchStatus::Failed(_))),
+        ),
+        (
+            Message::NotStarted,
+            count(|status| *status == BatchStatus::NotStarted),
+        ),
+    ];
+
+    println!();
+    for (message, count) in counts {
+        println!("{:<16} {}", language.translate(message), count);
+    }
+}
@@ -0,0 +1,416 @@
+//! Layout of Tonie audio files (TAF): a length prefixed protobuf header padded to the first 4kb block,
+//! followed by an Ogg Opus stream whose pages are aligned to 4kb blocks.
+
+use crate::limits::Limits;
+use crate::ogg_page::{OggPage, OggPageError, PacketAssembler, OGG_CAPTURE_PATTERN};
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub const TONIEFILE_FRAME_SIZE: usize = 4096;
+pub const TONIEFILE_HEADER_SIZE: usize = 4096;
+/// The number of chapters the Toniebox can navigate.
+pub const MAX_CHAPTERS: usize = 99;
+/// The largest audio data length of a Tonie file. The Toniebox stores the data length of the header as a signed
+/// 32 bit integer, so the audio data is limited to 2 GiB. The default input limits are derived from it, see
+/// [`crate::limits::DEFAULT_MAX_TOTAL_BYTES`].
+pub const MAX_AUDIO_LENGTH: u64 = i32::MAX as u64;
+
+const HEADER_LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Reads the length of the protobuf header from the big endian length prefix at the start of a Tonie file.
+/// Returns `None` if the buffer is too short.
+///
+/// # Arguments
+///
+/// * `buffer` - The first bytes of a Tonie file.
+pub fn header_length(buffer: &[u8]) -> Option<usize> {
+    return buffer
+        .get(..HEADER_LENGTH_PREFIX_SIZE)
+        .map(|prefix| u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize);
+}
+
+/// Returns the offset of the Ogg audio stream, i.e. the end of the header block.
+///
+/// # Arguments
+///
+/// * `buffer` - The first bytes of a Tonie file.
+pub fn audio_offset(buffer: &[u8]) -> Option<usize> {
+    return header_length(buffer).map(|length| length + HEADER_LENGTH_PREFIX_SIZE);
+}
+
+/// Encodes the protobuf header of a Tonie file including the length prefix. The header is padded with its
+/// fill field so it occupies exactly the first 4kb block, like the headers written by the toniefile encoder.
+/// Returns `None` if the fields do not fit into the block.
+///
+/// # Arguments
+///
+/// * `sha1_hash` - The SHA1 hash of the audio data.
+/// * `num_bytes` - The length of the audio data.
+/// * `audio_id` - The audio id, usually the Unix timestamp of the conversion.
+/// * `track_page_nums` - The index of the first 4kb block of every chapter.
+pub fn encode_header(
+    sha1_hash: &[u8],
+    num_bytes: u64,
+    audio_id: u32,
+    track_page_nums: &[u32],
+) -> Option<Vec<u8>> {
+    let mut fields = Vec::new();
+    // Field 1: sha1_hash (length delimited)
+    fields.push(0x0a);
+    write_varint(&mut fields, sha1_hash.len() as u64);
+    fields.extend_from_slice(sha1_hash);
+    // Field 2: num_bytes (varint)
+    fields.push(0x10);
+    write_varint(&mut fields, num_bytes);
+    // Field 3: audio_id (varint)
+    fields.push(0x18);
+    write_varint(&mut fields, audio_id as u64);
+    // Field 4: track_page_nums (packed varints)
+    let mut page_nums = Vec::new();
+    for page_num in track_page_nums {
+        write_varint(&mut page_nums, *page_num as u64);
+    }
+    fields.push(0x22);
+    write_varint(&mut fields, page_nums.len() as u64);
+    fields.extend_from_slice(&page_nums);
+
+    // Field 5: fill (length delimited), sized so the header fills the block
+    let header_size = TONIEFILE_HEADER_SIZE - HEADER_LENGTH_PREFIX_SIZE;
+    let available = header_size.checked_sub(fields.len() + 1)?;
+    let fill_length = (0..=available)
+        .rev()
+        .find(|fill_length| fill_length + varint_size(*fill_length as u64) == available)?;
+    fields.push(0x2a);
+    write_varint(&mut fields, fill_length as u64);
+    fields.resize(header_size, 0);
+
+    let mut header = Vec::with_capacity(TONIEFILE_HEADER_SIZE);
+    header.extend_from_slice(&(header_size as u32).to_be_bytes());
+    header.extend_from_slice(&fields);
+    return Some(header);
+}
+
+/// The fields of the protobuf header of a Tonie file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TonieHeader {
+    /// The SHA1 hash of the audio data.
+    pub sha1_hash: Vec<u8>,
+    /// The length of the audio data in bytes.
+    pub num_bytes: u64,
+    /// The audio id, usually the Unix timestamp of the conversion.
+    pub audio_id: u32,
+    /// The index of the first 4kb block of every chapter.
+    pub track_page_nums: Vec<u32>,
+    /// The size of the fill field padding the header to the first 4kb block.
+    pub fill_size: usize,
+}
+
+/// Decodes the protobuf header of a Tonie file including the length prefix, the counterpart of [`encode_header`].
+/// Returns `None` if the header is truncated or malformed.
+///
+/// # Arguments
+///
+/// * `buffer` - The first bytes of a Tonie file, at least the complete header.
+pub fn decode_header(buffer: &[u8]) -> Option<TonieHeader> {
+    let header_size = header_length(buffer)?;
+    let mut remaining =
+        buffer.get(HEADER_LENGTH_PREFIX_SIZE..HEADER_LENGTH_PREFIX_SIZE + header_size)?;
+
+    let mut header = TonieHeader::default();
+    while !remaining.is_empty() {
+        let key = read_varint(&mut remaining)?;
+        match (key >> 3, key & 0x07) {
+            (1, 2) => header.sha1_hash = read_length_delimited(&mut remaining)?.to_vec(),
+            (2, 0) => header.num_bytes = read_varint(&mut remaining)?,
+            (3, 0) => header.audio_id = read_varint(&mut remaining)? as u32,
+            // Chapter pages are usually packed, but may also be encoded one by one
+            (4, 2) => {
+                let mut page_nums = read_length_delimited(&mut remaining)?;
+                while !page_nums.is_empty() {
+                    header
+                        .track_page_nums
+                        .push(read_varint(&mut page_nums)? as u32);
+                }
+            }
+            (4, 0) => header
+                .track_page_nums
+                .push(read_varint(&mut remaining)? as u32),
+            (5, 2) => header.fill_size = read_length_delimited(&mut remaining)?.len(),
+            (_, 0) => {
+                read_varint(&mut remaining)?;
+            }
+            (_, 2) => {
+                read_length_delimited(&mut remaining)?;
+            }
+            _ => return None,
+        }
+    }
+
+    return Some(header);
+}
+
+fn read_varint(remaining: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (index, byte) in remaining.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * index);
+        if byte & 0x80 == 0 {
+            *remaining = &remaining[index + 1..];
+            return Some(value);
+        }
+    }
+
+    return None;
+}
+
+fn read_length_delimited<'a>(remaining: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let length = read_varint(remaining)? as usize;
+    let value = remaining.get(..length)?;
+    *remaining = &remaining[length..];
+    return Some(value);
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn varint_size(value: u64) -> usize {
+    let mut buffer = Vec::new();
+    write_varint(&mut buffer, value);
+    return buffer.len();
+}
+
+/// Iterates over consecutive Ogg pages in a buffer, e.g. the audio stream of a Tonie file.
+/// Yields the byte offset of each page within the buffer together with the parsed page.
+pub struct OggPageIterator<'a> {
+    buffer: &'a [u8],
+    offset: usize,
+    page_count: usize,
+    limits: Limits,
+}
+
+impl<'a> OggPageIterator<'a> {
+    pub fn new(buffer: &'a [u8]) -> Self {
+        OggPageIterator::with_limits(buffer, Limits::default())
+    }
+
+    /// Creates an iterator that stops with an error once the page limit is exceeded.
+    pub fn with_limits(buffer: &'a [u8], limits: Limits) -> Self {
+        OggPageIterator {
+            buffer,
+            offset: 0,
+            page_count: 0,
+            limits,
+        }
+    }
+}
+
+impl Iterator for OggPageIterator<'_> {
+    type Item = Result<(usize, OggPage), OggPageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.buffer.len() {
+            return None;
+        }
+
+        self.page_count += 1;
+        if let Err(error) = self.limits.check_pages(self.page_count) {
+            self.offset = self.buffer.len();
+            return Some(Err(OggPageError::LimitExceeded(error)));
+        }
+
+        return match OggPage::parse(&self.buffer[self.offset..]) {
+            Ok((page, page_size)) => {
+                let page_offset = self.offset;
+                self.offset += page_size;
+                Some(Ok((page_offset, page)))
+            }
+            Err(error) => {
+                // Stop iterating after the first error
+                self.offset = self.buffer.len();
+                Some(Err(error))
+            }
+        };
+    }
+}
+
+/// Reads consecutive Ogg pages from a reader one at a time, so long streams are never held in memory as a
+/// whole. Yields the byte offset of each page relative to the start of the reader together with the parsed page.
+#[cfg(feature = "std")]
+pub struct OggPageReader<R: std::io::Read> {
+    reader: R,
+    offset: usize,
+    page_count: usize,
+    limits: Limits,
+    done: bool,
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> OggPageReader<R> {
+    /// Creates a reader that stops with an error once the page limit is exceeded.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - The Ogg stream, positioned at the start of a page.
+    /// * `limits` - Caps for the number of pages.
+    pub fn with_limits(reader: R, limits: Limits) -> Self {
+        OggPageReader {
+            reader,
+            offset: 0,
+            page_count: 0,
+            limits,
+            done: false,
+        }
+    }
+
+    fn read_page(&mut self) -> std::io::Result<Option<OggPage>> {
+        let mut buffer = vec![0u8; crate::ogg_page::OGG_PAGE_HEADER_SIZE];
+        let header_size = read_up_to(&mut self.reader, &mut buffer)?;
+        if header_size == 0 {
+            return Ok(None);
+        }
+        buffer.truncate(header_size);
+
+        // The header and the data are read separately, so the data is moved into the page without a copy.
+        // Truncated pages fail to parse.
+        let invalid_data = |error| std::io::Error::new(std::io::ErrorKind::InvalidData, error);
+        let mut data = vec![];
+        if let Some(&segment_count) = buffer.get(26) {
+            let mut segment_table = vec![0u8; segment_count as usize];
+            let segment_table_size = read_up_to(&mut self.reader, &mut segment_table)?;
+            buffer.extend_from_slice(&segment_table[..segment_table_size]);
+            OggPage::header_size(&buffer).map_err(invalid_data)?;
+
+            data.resize(crate::ogg_page::data_size(&segment_table), 0);
+            let read = read_up_to(&mut self.reader, &mut data)?;
+            data.truncate(read);
+        }
+
+        let page = OggPage::from_parts(&buffer, data).map_err(invalid_data)?;
+        self.offset += page.size();
+        return Ok(Some(page));
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> Iterator for OggPageReader<R> {
+    type Item = std::io::Result<(usize, OggPage)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        self.page_count += 1;
+        if let Err(error) = self.limits.check_pages(self.page_count) {
+            self.done = true;
+            let error = OggPageError::LimitExceeded(error);
+            return Some(Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                error,
+            )));
+        }
+
+        let page_offset = self.offset;
+        return match self.read_page() {
+            Ok(Some(page)) => Some(Ok((page_offset, page))),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(error) => {
+                // Stop reading after the first error
+                self.done = true;
+                Some(Err(error))
+            }
+        };
+    }
+}
+
+// Fills the buffer until the end of the reader and returns the number of bytes read
+#[cfg(feature = "std")]
+fn read_up_to<R: std::io::Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(error) if error.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(error),
+        }
+    }
+    return Ok(filled);
+}
+
+/// Searches the buffer for the `OggS` capture pattern, which starts every Ogg page, and returns its offset.
+/// The pattern can also occur in the payload of a page, so a match has to be parsed to be sure.
+///
+/// # Arguments
+///
+/// * `buffer` - The bytes to search.
+pub fn find_capture_pattern(buffer: &[u8]) -> Option<usize> {
+    return memchr::memmem::find(buffer, OGG_CAPTURE_PATTERN);
+}
+
+/// Reads the user comments of the OpusTags header, the second packet of an Ogg Opus stream, e.g. `TITLE=...`.
+/// Returns an empty list if the stream has no valid comment header.
+///
+/// # Arguments
+///
+/// * `audio_data` - The Ogg Opus stream of a Tonie file.
+pub fn opus_comments(audio_data: &[u8]) -> Vec<String> {
+    let mut packet_assembler = PacketAssembler::new();
+    let packet = OggPageIterator::new(audio_data)
+        .map_while(|page| page.ok())
+        .flat_map(|(_, page)| {
+            let packets = packet_assembler.push_page(&page);
+            packets.into_iter().map(Cow::into_owned).collect::<Vec<_>>()
+        })
+        .nth(1);
+
+    return packet
+        .and_then(|packet| parse_opus_tags(&packet))
+        .unwrap_or_default();
+}
+
+fn parse_opus_tags(packet: &[u8]) -> Option<Vec<String>> {
+    let mut remaining = packet.strip_prefix(b"OpusTags")?;
+
+    let vendor_length = read_u32(&mut remaining)?;
+    remaining = remaining.get(vendor_length..)?;
+
+    return parse_comments(remaining, true).or_else(|| parse_comments(remaining, false));
+}
+
+/// Parses the length prefixed comments following the vendor string. The toniefile encoder omits the
+/// comment count, so without it the comments are read until the end of the packet.
+fn parse_comments(mut remaining: &[u8], with_count: bool) -> Option<Vec<String>> {
+    let comment_count = match with_count {
+        true => read_u32(&mut remaining)?,
+        false => usize::MAX,
+    };
+
+    let mut comments = Vec::new();
+    while comments.len() < comment_count && !remaining.is_empty() {
+        let comment_length = read_u32(&mut remaining)?;
+        let comment = remaining.get(..comment_length)?;
+        comments.push(String::from_utf8_lossy(comment).into_owned());
+        remaining = &remaining[comment_length..];
+    }
+    if with_count && comments.len() < comment_count {
+        return None;
+    }
+
+    return Some(comments);
+}
+
+/// Reads a little endian 32 bit number, which all lengths and counts of the OpusTags header are.
+fn read_u32(remaining: &mut &[u8]) -> Option<usize> {
+    let value = u32::from_le_bytes(remaining.get(..4)?.try_into().ok()?);
+    *remaining = &remaining[4..];
+    return Some(value as usize);
+}
@@ -0,0 +1,177 @@
+//! Tonie audio playlists (`.tap`) of TeddyCloud. A playlist is a JSON file listing audio files, which TeddyCloud
+//! encodes into one Tonie file with a chapter per file:
+//!
+//! ```json
+//! {
+//!   "type": "tap",
+//!   "audio_id": 1700000000,
+//!   "filepath": "lib://gruffalo.taf",
+//!   "name": "Gruffalo",
+//!   "files": [{ "filepath": "lib://gruffalo/01.mp3", "name": "Der Grüffelo" }]
+//! }
+//! ```
+//!
+//! Paths starting with `lib://` are relative to the library of TeddyCloud. Locally they are resolved relative to
+//! the directory of the playlist, so a playlist in the root of a copy of the library works unchanged.
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+/// The prefix of paths in the library of TeddyCloud.
+pub const LIBRARY_PREFIX: &str = "lib://";
+
+/// An entry of a playlist.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TapFile {
+    /// The path of the audio file, usually starting with [`LIBRARY_PREFIX`].
+    pub filepath: String,
+    /// The title of the chapter.
+    pub name: String,
+}
+
+/// A Tonie audio playlist.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TapPlaylist {
+    /// The name of the playlist.
+    pub name: String,
+    /// The audio id of the Tonie file encoded from the playlist, `None` to let TeddyCloud choose one.
+    pub audio_id: Option<u32>,
+    /// The path of the Tonie file encoded from the playlist.
+    pub filepath: String,
+    pub files: Vec<TapFile>,
+}
+
+impl TapPlaylist {
+    /// Parses the content of a `.tap` file.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The JSON object of the playlist.
+    pub fn parse(json: &str) -> Result<Self> {
+        let playlist = serde_json::from_str::<Value>(json)?;
+        if playlist["type"].as_str() != Some("tap") {
+            return Err(anyhow!("The file is not a Tonie audio playlist."));
+        }
+        let text = |value: &Value, key: &str| value[key].as_str().unwrap_or_default().to_string();
+        let files = playlist["files"]
+            .as_array()
+            .ok_or_else(|| anyhow!("The playlist does not contain a file list."))?
+            .iter()
+            .map(|file| TapFile {
+                filepath: text(file, "filepath"),
+                name: text(file, "name"),
+            })
+            .collect::<Vec<_>>();
+        if let Some(file) = files.iter().find(|file| file.filepath.is_empty()) {
+            return Err(anyhow!(
+                "The entry '{}' of the playlist has no file path.",
+                file.name
+            ));
+        }
+
+        return Ok(TapPlaylist {
+            name: text(&playlist, "name"),
+            // TeddyCloud writes the audio id as number or as string
+            audio_id: match &playlist["audio_id"] {
+                Value::Number(audio_id) => audio_id.as_u64(),
+                Value::String(audio_id) => audio_id.trim().parse().ok(),
+                _ => None,
+            }
+            .and_then(|audio_id| u32::try_from(audio_id).ok())
+            .filter(|audio_id| *audio_id != 0),
+            filepath: text(&playlist, "filepath"),
+            files,
+        });
+    }
+
+    /// Reads a `.tap` file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the playlist.
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the playlist {}", path.display()))?;
+        return TapPlaylist::parse(&json)
+            .with_context(|| format!("Failed to parse the playlist {}", path.display()));
+    }
+
+    /// The local paths of the audio files in playlist order, see [`resolve_path`].
+    ///
+    /// # Arguments
+    ///
+    /// * `base_dir` - The directory of the playlist.
+    pub fn resolve_files(&self, base_dir: &Path) -> Vec<PathBuf> {
+        return self
+            .files
+            .iter()
+            .map(|file| resolve_path(&file.filepath, base_dir))
+            .collect();
+    }
+
+    /// The playlist as JSON object in the format of TeddyCloud.
+    pub fn to_json(&self) -> Value {
+        let files = self
+            .files
+            .iter()
+            .map(|file| json!({ "filepath": file.filepath, "name": file.name }))
+            .collect::<Vec<_>>();
+        return json!({
+            "type": "tap",
+            "audio_id": self.audio_id.unwrap_or_default(),
+            "filepath": self.filepath,
+            "name": self.name,
+            "files": files,
+        });
+    }
+}
+
+/// Checks whether a path is a Tonie audio playlist by its extension.
+///
+/// # Arguments
+///
+/// * `path` - The path of the file.
+pub fn is_tap_file(path: &Path) -> bool {
+    return path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("tap"));
+}
+
+/// Resolves a path of a playlist to a local path. Library paths and relative paths are relative to the directory
+/// of the playlist, absolute paths are kept.
+///
+/// # Arguments
+///
+/// * `filepath` - The path in the playlist, e.g. `lib://gruffalo/01.mp3`.
+/// * `base_dir` - The directory of the playlist.
+pub fn resolve_path(filepath: &str, base_dir: &Path) -> PathBuf {
+    return match filepath.strip_prefix(LIBRARY_PREFIX) {
+        Some(library_path) => base_dir.join(library_path.trim_start_matches('/')),
+        None => base_dir.join(filepath),
+    };
+}
+
+/// The path of a local file as written into a playlist: a library path relative to the directory of the playlist
+/// if the file is inside it, else the absolute path.
+///
+/// # Arguments
+///
+/// * `path` - The local path of the file.
+/// * `base_dir` - The directory of the playlist.
+pub fn playlist_path(path: &Path, base_dir: &Path) -> String {
+    let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let path = absolute(path);
+    return match path.strip_prefix(absolute(base_dir)) {
+        Ok(relative_path) => format!(
+            "{}{}",
+            LIBRARY_PREFIX,
+            relative_path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        ),
+        Err(_) => path.to_string_lossy().to_string(),
+    };
+}
@@ -0,0 +1,232 @@
+//! A client for the HTTP API of a TeddyCloud server: the files of its library, the tags it knows and the content
+//! assigned to them.
+
+use anyhow::{anyhow, Context, Result};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::CertificateDer;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::sd_card::TagUid;
+
+/// A file or directory in the library of a TeddyCloud server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LibraryFile {
+    pub name: String,
+    pub is_dir: bool,
+    /// The size in bytes.
+    pub size: u64,
+    /// The audio id of Tonie files.
+    pub audio_id: Option<u32>,
+    /// The series and episode TeddyCloud knows for Tonie files, e.g. from its `tonies.json`.
+    pub title: Option<String>,
+}
+
+/// A tag known to a TeddyCloud server, i.e. a tag that was placed on one of its Tonieboxes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerTag {
+    /// The reversed UID as used by TeddyCloud, e.g. `5634121e500304e0` for `E0:04:03:50:1E:12:34:56`.
+    pub ruid: String,
+    /// The content assigned to the tag, e.g. `lib://audiobooks/gruffalo.taf`. Empty for the content of the tag.
+    pub source: String,
+    /// The series and episode of the content, if TeddyCloud knows them.
+    pub title: Option<String>,
+}
+
+impl ServerTag {
+    /// The UID of the tag, `None` if the server reported an unexpected identifier.
+    pub fn uid(&self) -> Option<TagUid> {
+        return TagUid::from_ruid(&self.ruid).ok();
+    }
+}
+
+/// The connection to a TeddyCloud server.
+#[derive(Clone, Debug)]
+pub struct TeddyCloudClient {
+    url: String,
+    token: Option<String>,
+    ca_cert: Option<PathBuf>,
+}
+
+impl TeddyCloudClient {
+    /// # Arguments
+    ///
+    /// * `url` - The base URL of the TeddyCloud server, e.g. `http://teddycloud.local`.
+    pub fn new(url: &str) -> Self {
+        TeddyCloudClient {
+            url: url.trim_end_matches('/').to_string(),
+            token: None,
+            ca_cert: None,
+        }
+    }
+
+    /// Authenticates with the given token, sent as `Authorization: Bearer <token>` header.
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        return self;
+    }
+
+    /// Trusts the certificates of the given PEM file in addition to the default root certificates,
+    /// e.g. for a TeddyCloud server with a self-signed certificate.
+    pub fn with_ca_cert(mut self, ca_cert: Option<PathBuf>) -> Self {
+        self.ca_cert = ca_cert;
+        return self;
+    }
+
+    fn agent(&self) -> Result<ureq::Agent> {
+        let mut agent = ureq::AgentBuilder::new();
+
+        if let Some(ca_cert) = &self.ca_cert {
+            let mut root_store = rustls::RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            };
+            for certificate in CertificateDer::pem_file_iter(ca_cert)
+                .with_context(|| format!("Failed to read {}", ca_cert.display()))?
+            {
+                root_store.add(certificate?)?;
+            }
+            let tls_config = rustls::ClientConfig::builder()
+                .with_root_certificates(root_store)
+                .with_no_client_auth();
+            agent = agent.tls_config(Arc::new(tls_config));
+        }
+
+        return Ok(agent.build());
+    }
+
+    /// Creates an authenticated request to an endpoint of the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The HTTP method, e.g. `POST`.
+    /// * `endpoint` - The path of the endpoint, e.g. `/api/fileUpload`.
+    pub fn request(&self, method: &str, endpoint: &str) -> Result<ureq::Request> {
+        let mut request = self
+            .agent()?
+            .request(method, &format!("{}{}", self.url, endpoint));
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        return Ok(request);
+    }
+
+    /// Lists a directory of the library.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory in the library, empty for the root.
+    pub fn list_library(&self, path: &str) -> Result<Vec<LibraryFile>> {
+        let index = self
+            .request("GET", "/api/fileIndexV2")?
+            .query("path", path)
+            .query("special", "library")
+            .call()
+            .with_context(|| format!("Failed to list the library directory '{}'", path))?
+            .into_string()?;
+        let index = serde_json::from_str::<Value>(&index)?;
+        let files = index["files"]
+            .as_array()
+            .ok_or_else(|| anyhow!("The server did not return a file list."))?;
+
+        return Ok(files
+            .iter()
+            .filter_map(|file| {
+                let name = file["name"].as_str()?;
+                // The listing contains the parent directory
+                if name == ".." {
+                    return None;
+                }
+                let audio_id = file["tafHeader"]["audioId"]
+                    .as_u64()
+                    .and_then(|audio_id| u32::try_from(audio_id).ok());
+                return Some(LibraryFile {
+                    name: name.to_string(),
+                    is_dir: file["isDir"].as_bool().unwrap_or_default(),
+                    size: file["size"].as_u64().unwrap_or_default(),
+                    audio_id,
+                    title: tonie_title(&file["tonieInfo"]),
+                });
+            })
+            .collect());
+    }
+
+    /// Lists the tags the server knows.
+    ///
+    /// # Arguments
+    ///
+    /// * `overlay` - The ID of the Toniebox whose settings overlay is used, `None` for the default settings.
+    pub fn list_tags(&self, overlay: Option<&str>) -> Result<Vec<ServerTag>> {
+        let mut request = self.request("GET", "/api/getTagIndex")?;
+        if let Some(overlay) = overlay {
+            request = request.query("overlay", overlay);
+        }
+        let index = request
+            .call()
+            .context("Failed to list the tags")?
+            .into_string()?;
+        let index = serde_json::from_str::<Value>(&index)?;
+        let tags = index["tags"]
+            .as_array()
+            .ok_or_else(|| anyhow!("The server did not return a tag list."))?;
+
+        return Ok(tags
+            .iter()
+            .filter_map(|tag| {
+                let ruid = tag["ruid"].as_str()?;
+                return Some(ServerTag {
+                    ruid: ruid.to_ascii_lowercase(),
+                    source: tag["source"].as_str().unwrap_or_default().to_string(),
+                    title: tonie_title(&tag["tonieInfo"]),
+                });
+            })
+            .collect());
+    }
+
+    /// Assigns a Tonie file of the library to a tag, so the Toniebox plays it instead of the content of the tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `tag_uid` - The UID of the tag.
+    /// * `library_path` - The path of the Tonie file in the library, e.g. `audiobooks/gruffalo.taf`.
+    /// * `overlay` - The ID of the Toniebox whose settings overlay is changed, `None` for the default settings.
+    pub fn assign(&self, tag_uid: TagUid, library_path: &str, overlay: Option<&str>) -> Result<()> {
+        let source = format!("lib://{}", library_path.trim_start_matches('/'));
+        let mut request = self.request("POST", &format!("/content/json/set/{}", tag_uid.ruid()))?;
+        if let Some(overlay) = overlay {
+            request = request.query("overlay", overlay);
+        }
+        request
+            .send_form(&[("source", &source)])
+            .with_context(|| format!("Failed to assign {} to the tag {}", source, tag_uid))?;
+
+        return Ok(());
+    }
+
+    /// Deletes a file from the library.
+    ///
+    /// # Arguments
+    ///
+    /// * `library_path` - The path of the file in the library, e.g. `audiobooks/gruffalo.taf`.
+    pub fn delete(&self, library_path: &str) -> Result<()> {
+        let library_path = format!("/{}", library_path.trim_start_matches('/'));
+        self.request("POST", "/api/fileDelete")?
+            .query("special", "library")
+            .set("Content-Type", "text/plain")
+            .send_string(&library_path)
+            .with_context(|| format!("Failed to delete {} from the library", library_path))?;
+
+        return Ok(());
+    }
+}
+
+/// The series and episode of the `tonieInfo` of a file or tag, e.g. `Gruffalo – Der Grüffelo`.
+fn tonie_title(tonie_info: &Value) -> Option<String> {
+    let field = |key: &str| tonie_info[key].as_str().unwrap_or_default().trim();
+    return match (field("series"), field("episode")) {
+        ("", "") => None,
+        (series, "") => Some(series.to_string()),
+        ("", episode) => Some(episode.to_string()),
+        (series, episode) => Some(format!("{} – {}", series, episode)),
+    };
+}
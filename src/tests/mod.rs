@@ -1,2 +1,72 @@
+mod test_analyze;
+#[cfg(feature = "auto-ffmpeg")]
+mod test_auto_ffmpeg;
+mod test_backup;
+mod test_batch;
+mod test_catalog;
+mod test_check;
 mod test_convert;
+mod test_cue;
+mod test_download;
 mod test_extract;
+mod test_fade;
+mod test_hashing;
+mod test_header;
+mod test_hooks;
+mod test_i18n;
+mod test_info;
+mod test_input;
+mod test_list;
+mod test_loudness;
+mod test_ogg_page;
+mod test_passthrough;
+mod test_play;
+mod test_podcast;
+mod test_progress;
+mod test_recode;
+mod test_restore;
+mod test_sd_card;
+mod test_serve;
+mod test_silence;
+mod test_split;
+mod test_state;
+mod test_stats;
+mod test_tap;
+mod test_teddycloud;
+mod test_throttle;
+mod test_tonie_cloud;
+mod test_tonies;
+mod test_tui;
+mod test_utils;
+mod test_watch;
+
+use std::{f64::consts::PI, fs::File, path::Path};
+use toniefile::Toniefile;
+
+/// Generates interleaved stereo samples of a sine wave at 48 kHz.
+pub fn sine_samples(frequency: f64, amplitude_dbfs: f64, seconds: f64) -> Vec<i16> {
+    let amplitude = 32767.0 * 10f64.powf(amplitude_dbfs / 20.0);
+    let sample_count = (48000.0 * seconds) as usize;
+
+    return (0..sample_count)
+        .flat_map(|n| {
+            let sample = (amplitude * (2.0 * PI * frequency * n as f64 / 48000.0).sin()) as i16;
+            [sample, sample]
+        })
+        .collect();
+}
+
+/// Encodes every buffer of samples as a separate chapter into a new Tonie file.
+pub fn create_test_tonie_file(path: &Path, chapters: &[Vec<i16>]) -> anyhow::Result<()> {
+    let mut toniefile = Toniefile::new(File::create(path)?, 0x12345678, None)?;
+
+    for (index, samples) in chapters.iter().enumerate() {
+        if index > 0 {
+            toniefile.new_chapter()?;
+        }
+        toniefile.encode(samples)?;
+    }
+    toniefile.finalize()?;
+
+    return Ok(());
+}
@@ -0,0 +1,141 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use audio2tonie::opus_packet::OpusPacket;
+use tempfile::tempdir;
+
+use crate::analyze::{analyze_pages, inspect_page, opus_padding};
+use crate::tests::{create_test_tonie_file, sine_samples};
+
+#[test]
+fn test_analyze_pages_of_tonie_file() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    create_test_tonie_file(&tonie_path, &[sine_samples(440.0, -20.0, 3.0)])?;
+
+    let analysis = analyze_pages(&tonie_path, &Limits::default())?;
+
+    assert!(analysis.error.is_none());
+    assert!(analysis.pages.len() > 2);
+    assert_eq!(analysis.pages[0].offset, 0x1000);
+    assert_eq!(analysis.pages[0].page_sequence_number, 0);
+    assert!(analysis.pages.iter().all(|page| page.checksum_valid));
+    assert!(analysis
+        .pages
+        .iter()
+        .all(|page| page.within_block == Some(true)));
+    assert!(analysis.pages.windows(2).all(|pages| {
+        pages[0].offset + pages[0].size == pages[1].offset
+            && pages[0].granule_position <= pages[1].granule_position
+    }));
+    Ok(())
+}
+
+#[test]
+fn test_analyze_pages_of_ogg_file() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    create_test_tonie_file(&tonie_path, &[sine_samples(440.0, -20.0, 3.0)])?;
+
+    // Strip the header block and corrupt the second audio page
+    let mut ogg_data = std::fs::read(&tonie_path)?[0x1000..].to_vec();
+    let ogg_path = temp_dir.path().join("test.ogg");
+    ogg_data[0x1100] ^= 0xFF;
+    std::fs::write(&ogg_path, &ogg_data)?;
+
+    let analysis = analyze_pages(&ogg_path, &Limits::default())?;
+
+    assert!(analysis.error.is_none());
+    assert_eq!(analysis.pages[0].offset, 0);
+    assert!(analysis
+        .pages
+        .iter()
+        .all(|page| page.within_block.is_none()));
+    let invalid_pages = analysis
+        .pages
+        .iter()
+        .filter(|page| !page.checksum_valid)
+        .map(|page| page.offset)
+        .collect::<Vec<_>>();
+    assert_eq!(invalid_pages, vec![0x1000]);
+    Ok(())
+}
+
+#[test]
+fn test_opus_padding() {
+    // Code 0 packets have no padding
+    assert_eq!(opus_padding(&[0xFC, 0x01, 0x02]), 0);
+    // Code 3 packets without the padding flag
+    assert_eq!(opus_padding(&[0xFF, 0x01, 0x02]), 0);
+    assert_eq!(opus_padding(&[0xFF, 0x41, 0x07]), 7);
+    assert_eq!(opus_padding(&[0xFF, 0x41, 0xFF, 0xFF, 0x02]), 510);
+    assert_eq!(opus_padding(b"OpusHead"), 0);
+}
+
+#[test]
+fn test_parse_opus_packet() -> Result<()> {
+    // CELT fullband 20 ms, stereo, one frame
+    let packet = OpusPacket::parse(&[0xFC, 0x01, 0x02, 0x03])?;
+    assert_eq!(packet.config, 31);
+    assert!(packet.stereo);
+    assert_eq!(
+        (packet.mode(), packet.bandwidth(), packet.frame_duration()),
+        ("CELT", "FB", 20_000)
+    );
+    assert_eq!(packet.frames, vec![(1, 3)]);
+
+    // SILK narrowband 60 ms, mono, two frames of different size
+    let packet = OpusPacket::parse(&[0x1A, 0x02, 0xAA, 0xBB, 0xCC])?;
+    assert_eq!(
+        (packet.mode(), packet.bandwidth(), packet.frame_duration()),
+        ("SILK", "NB", 60_000)
+    );
+    assert!(!packet.stereo);
+    assert_eq!(packet.frames, vec![(2, 2), (4, 1)]);
+
+    // Code 3 with three CBR frames of 2 bytes and 2 bytes of padding
+    let packet = OpusPacket::parse(&[0xFF, 0x43, 0x02, 1, 1, 2, 2, 3, 3, 0, 0])?;
+    assert!(!packet.vbr);
+    assert_eq!(packet.padding, 2);
+    assert_eq!(packet.frames, vec![(3, 2), (5, 2), (7, 2)]);
+
+    // Code 3 with two VBR frames
+    let packet = OpusPacket::parse(&[0xFF, 0x82, 0x01, 1, 2, 2])?;
+    assert!(packet.vbr);
+    assert_eq!(packet.frames, vec![(3, 1), (4, 2)]);
+
+    assert!(OpusPacket::parse(&[]).is_err());
+    assert!(OpusPacket::parse(&[0xFD, 0x01, 0x02, 0x03]).is_err());
+    assert!(OpusPacket::parse(&[0xFF, 0x03, 1, 1]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_inspect_page() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    create_test_tonie_file(&tonie_path, &[sine_samples(440.0, -20.0, 3.0)])?;
+
+    let inspection = inspect_page(&tonie_path, 2, &Limits::default())?;
+
+    assert_eq!(inspection.page.page_sequence_number, 2);
+    assert_eq!(
+        inspection.header().len(),
+        27 + inspection.page.segment_table.len()
+    );
+    assert!(inspection.header().starts_with(b"OggS"));
+    assert_eq!(
+        inspection.segment_packets.len(),
+        inspection.page.segment_table.len()
+    );
+    assert_eq!(inspection.packets.len(), inspection.page.packets().len());
+    let packet = inspection.packets[0].clone().unwrap().unwrap();
+    assert_eq!(packet.mode(), "CELT");
+    assert!(!packet.frames.is_empty());
+
+    // The OpusHead packet has no TOC byte
+    let inspection = inspect_page(&tonie_path, 0, &Limits::default())?;
+    assert!(inspection.packets[0].clone().unwrap().is_err());
+
+    assert!(inspect_page(&tonie_path, 100_000, &Limits::default()).is_err());
+    Ok(())
+}
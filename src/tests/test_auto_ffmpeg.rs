@@ -0,0 +1,117 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::tempdir;
+
+use audio2tonie::auto_ffmpeg::{install_ffmpeg, ArchiveFormat};
+
+const FFMPEG_CONTENT: &[u8] = b"#!/bin/sh\necho ffmpeg\n";
+
+fn sha256(path: &Path) -> Result<String> {
+    return Ok(Sha256::digest(std::fs::read(path)?)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect());
+}
+
+#[test]
+fn test_install_ffmpeg_from_zip() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let archive_path = temp_dir.path().join("ffmpeg.zip");
+    let mut archive = zip::ZipWriter::new(File::create(&archive_path)?);
+    let options = zip::write::SimpleFileOptions::default();
+    archive.start_file("ffmpeg-7.1-essentials_build/README.txt", options)?;
+    archive.write_all(b"readme")?;
+    archive.start_file("ffmpeg-7.1-essentials_build/bin/ffmpeg", options)?;
+    archive.write_all(FFMPEG_CONTENT)?;
+    archive.finish()?;
+
+    let cache_dir = temp_dir.path().join("bin");
+    let ffmpeg_path = install_ffmpeg(
+        &archive_path,
+        ArchiveFormat::Zip,
+        &sha256(&archive_path)?,
+        &cache_dir,
+    )?;
+
+    assert_eq!(ffmpeg_path, cache_dir.join("ffmpeg"));
+    assert_eq!(std::fs::read(&ffmpeg_path)?, FFMPEG_CONTENT);
+    assert_eq!(
+        std::fs::metadata(&ffmpeg_path)?.permissions().mode() & 0o111,
+        0o111
+    );
+    // Only ffmpeg is left in the cache
+    assert_eq!(std::fs::read_dir(&cache_dir)?.count(), 1);
+    return Ok(());
+}
+
+#[test]
+fn test_install_ffmpeg_from_tar_xz() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let mut tar = tar::Builder::new(vec![]);
+    for (path, content) in [
+        ("ffmpeg-7.0.2-amd64-static/ffprobe", b"ffprobe".as_slice()),
+        ("ffmpeg-7.0.2-amd64-static/ffmpeg", FFMPEG_CONTENT),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        tar.append_data(&mut header, path, content)?;
+    }
+    let tar = tar.into_inner()?;
+    let archive_path = temp_dir.path().join("ffmpeg.tar.xz");
+    lzma_rs::xz_compress(&mut tar.as_slice(), &mut File::create(&archive_path)?)?;
+
+    let cache_dir = temp_dir.path().join("bin");
+    let ffmpeg_path = install_ffmpeg(
+        &archive_path,
+        ArchiveFormat::TarXz,
+        &sha256(&archive_path)?,
+        &cache_dir,
+    )?;
+    assert_eq!(std::fs::read(&ffmpeg_path)?, FFMPEG_CONTENT);
+    assert_eq!(std::fs::read_dir(&cache_dir)?.count(), 1);
+
+    // Archives without ffmpeg are rejected and leave nothing behind
+    let archive_path = temp_dir.path().join("empty.zip");
+    zip::ZipWriter::new(File::create(&archive_path)?).finish()?;
+    let empty_cache_dir = temp_dir.path().join("empty");
+    assert!(install_ffmpeg(
+        &archive_path,
+        ArchiveFormat::Zip,
+        &sha256(&archive_path)?,
+        &empty_cache_dir
+    )
+    .is_err());
+    assert_eq!(std::fs::read_dir(&empty_cache_dir)?.count(), 0);
+    return Ok(());
+}
+
+#[test]
+fn test_install_ffmpeg_with_checksum_mismatch() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let archive_path = temp_dir.path().join("ffmpeg.zip");
+    let mut archive = zip::ZipWriter::new(File::create(&archive_path)?);
+    archive.start_file("bin/ffmpeg", zip::write::SimpleFileOptions::default())?;
+    archive.write_all(FFMPEG_CONTENT)?;
+    archive.finish()?;
+    let cache_dir = temp_dir.path().join("bin");
+    std::fs::create_dir_all(&cache_dir)?;
+    std::fs::write(cache_dir.join("ffmpeg"), b"stale")?;
+
+    // A tampered archive is neither extracted nor kept, and the cached ffmpeg is removed as well
+    let result = install_ffmpeg(
+        &archive_path,
+        ArchiveFormat::Zip,
+        &"0".repeat(64),
+        &cache_dir,
+    );
+    assert!(result.is_err());
+    assert!(!archive_path.exists());
+    assert_eq!(std::fs::read_dir(&cache_dir)?.count(), 0);
+    return Ok(());
+}
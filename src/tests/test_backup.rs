@@ -0,0 +1,111 @@
+use anyhow::Result;
+use audio2tonie::extract::ExtractOptions;
+use audio2tonie::tonies::ToniesDatabase;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+use crate::backup::{backup_sd_card, MANIFEST_FILE_NAME};
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_TONIE_FILE: &str = "resources/test/test_1.taf";
+const TEST_CHAPTERS_TONIE_FILE: &str = "resources/test/multiple_chapters.taf";
+
+const TONIES_JSON: &str = r#"[{
+    "model": "10000123",
+    "audio_id": [],
+    "hash": ["5A2D0EF510B6798CE0AE5DCD001E800993DDF24F"],
+    "series": "Benjamin Blümchen",
+    "episodes": "Folge 12",
+    "tracks": ["Das Zoofest"]
+}]"#;
+
+fn copy_to_sd_card(sd_root: &Path, tonie_file: &str, content_path: &str) -> Result<()> {
+    let content_path = sd_root.join(content_path);
+    std::fs::create_dir_all(content_path.parent().unwrap())?;
+    std::fs::copy(Path::new(TEST_FILES_DIR).join(tonie_file), content_path)?;
+    return Ok(());
+}
+
+#[test]
+fn test_backup_sd_card() -> Result<()> {
+    let sd_root = tempdir()?;
+    let backup_dir = tempdir()?;
+    copy_to_sd_card(sd_root.path(), TEST_TONIE_FILE, "CONTENT/5634121E/500304E0")?;
+    copy_to_sd_card(
+        sd_root.path(),
+        TEST_CHAPTERS_TONIE_FILE,
+        "CONTENT/0000000A/0000000B",
+    )?;
+    std::fs::create_dir_all(sd_root.path().join("CONTENT/11111111"))?;
+    std::fs::write(sd_root.path().join("CONTENT/11111111/22222222"), "broken")?;
+    // Files outside the content layout are no Tonies
+    std::fs::write(sd_root.path().join("CONTENT/notes.taf"), "not on a tag")?;
+
+    let entries = backup_sd_card(
+        sd_root.path(),
+        backup_dir.path(),
+        &ToniesDatabase::parse(TONIES_JSON)?,
+        &ExtractOptions::default(),
+    )?;
+
+    assert_eq!(entries.len(), 3);
+    let known = &entries[2];
+    assert_eq!(
+        known.content_path,
+        PathBuf::from("CONTENT/5634121E/500304E0")
+    );
+    assert_eq!(known.title(), "Benjamin Blümchen – Folge 12");
+    assert_eq!(
+        known.files,
+        vec![PathBuf::from(
+            "Benjamin Blümchen – Folge 12 (E00403501E123456)/Benjamin Blümchen – Folge 12.ogg"
+        )]
+    );
+    assert!(backup_dir.path().join(&known.files[0]).is_file());
+
+    let unknown = &entries[0];
+    assert_eq!(
+        unknown.directory,
+        PathBuf::from("Unknown (0B0000000A000000)")
+    );
+    assert_eq!(unknown.files.len(), 3);
+    assert!(entries[1].error.is_some());
+
+    let manifest = std::fs::read_to_string(backup_dir.path().join(MANIFEST_FILE_NAME))?;
+    let manifest = serde_json::from_str::<serde_json::Value>(&manifest)?;
+    assert_eq!(manifest[2]["tag_uid"], "E0:04:03:50:1E:12:34:56");
+    assert_eq!(manifest[2]["tonie"]["match"], "hash");
+    assert_eq!(manifest[2]["tonie"]["tracks"][0], "Das Zoofest");
+    assert!(manifest[1]["error"].is_string());
+    assert!(manifest[0]["tonie"].is_null());
+    return Ok(());
+}
+
+#[test]
+fn test_backup_requires_content_directory() -> Result<()> {
+    let sd_root = tempdir()?;
+    let backup_dir = tempdir()?;
+
+    let error = backup_sd_card(
+        sd_root.path(),
+        backup_dir.path(),
+        &ToniesDatabase::default(),
+        &ExtractOptions::default(),
+    )
+    .unwrap_err();
+    assert!(error.to_string().contains("CONTENT"));
+
+    // The CONTENT directory itself works as well
+    copy_to_sd_card(sd_root.path(), TEST_TONIE_FILE, "CONTENT/5634121E/500304E0")?;
+    let entries = backup_sd_card(
+        &sd_root.path().join("CONTENT"),
+        backup_dir.path(),
+        &ToniesDatabase::default(),
+        &ExtractOptions::default(),
+    )?;
+    assert_eq!(
+        entries[0].content_path,
+        PathBuf::from("CONTENT/5634121E/500304E0")
+    );
+    return Ok(());
+}
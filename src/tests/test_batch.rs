@@ -0,0 +1,113 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+use audio2tonie::batch::{parse_manifest, run_batch, BatchStatus};
+use audio2tonie::convert::{ConvertOptions, ExistingOutput};
+use audio2tonie::sd_card::TagUid;
+
+const TEST_MANIFEST: &str = "
+sd_root: /media/toniebox
+jobs:
+  - inputs: [intro.mp3, stories/]
+    output: stories.taf
+    bitrate: 64
+  - input: songs/
+    title: 'Kids Songs: Best Of'
+  - input: /music/lullabies
+    tag_uid: E0:04:03:50:1E:12:34:56
+";
+
+#[test]
+fn test_parse_manifest() -> Result<()> {
+    let base_dir = Path::new("/library");
+    let jobs = parse_manifest(TEST_MANIFEST, base_dir, None)?;
+
+    assert_eq!(jobs.len(), 3);
+    assert_eq!(
+        jobs[0].inputs,
+        [base_dir.join("intro.mp3"), base_dir.join("stories/")]
+    );
+    assert_eq!(jobs[0].output, base_dir.join("stories.taf"));
+    assert_eq!(jobs[0].bitrate, Some(64));
+    // The title names the output file
+    assert_eq!(jobs[1].output, base_dir.join("Kids Songs_ Best Of.taf"));
+    assert_eq!(jobs[1].bitrate, None);
+    assert_eq!(jobs[2].inputs, [PathBuf::from("/music/lullabies")]);
+    assert_eq!(
+        jobs[2].tag_uid,
+        Some(TagUid::parse("E0:04:03:50:1E:12:34:56")?)
+    );
+    assert_eq!(
+        jobs[2].output,
+        Path::new("/media/toniebox/CONTENT/5634121E/500304E0")
+    );
+
+    // The SD card of the command line replaces the one of the manifest
+    let jobs = parse_manifest(TEST_MANIFEST, base_dir, Some(Path::new("/mnt/sd")))?;
+    assert_eq!(
+        jobs[2].output,
+        Path::new("/mnt/sd/CONTENT/5634121E/500304E0")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_json_manifest() -> Result<()> {
+    let manifest = r#"[{"inputs": ["a.mp3", "b.mp3"], "output": "out.taf"}]"#;
+    let jobs = parse_manifest(manifest, Path::new(""), None)?;
+
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(
+        jobs[0].inputs,
+        [PathBuf::from("a.mp3"), PathBuf::from("b.mp3")]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_parse_invalid_manifest() {
+    let base_dir = Path::new("");
+    for manifest in [
+        "jobs: 5",
+        "- inputs: [a.mp3]",
+        "- inputs: []\n  output: out.taf",
+        "- input: a.mp3\n  output: out.taf\n  bitrate: 1000",
+        "- input: a.mp3\n  tag_uid: E0:04:03:50:1E:12:34:56",
+        "- input: a.mp3\n  output: out.taf\n  tag_uid: E0:04:03:50:1E:12:34:56",
+    ] {
+        assert!(
+            parse_manifest(manifest, base_dir, None).is_err(),
+            "{}",
+            manifest
+        );
+    }
+}
+
+#[test]
+fn test_run_batch() -> Result<()> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join("existing.taf"), b"")?;
+    let manifest = "
+- input: missing.mp3
+  output: missing.taf
+- input: missing.mp3
+  output: existing.taf
+";
+    let jobs = parse_manifest(manifest, temp_dir.path(), None)?;
+    let options = ConvertOptions {
+        existing_output: ExistingOutput::Skip,
+        ..Default::default()
+    };
+
+    // A failed job does not stop the others, and the results keep the order of the jobs
+    let results = run_batch(&jobs, &options, 2, false, |_, _| {});
+    assert!(matches!(results[0].status, BatchStatus::Failed(_)));
+    assert_eq!(results[1].status, BatchStatus::Skipped);
+
+    // The jobs after the first failure are not started
+    let results = run_batch(&jobs, &options, 1, true, |_, _| {});
+    assert!(matches!(results[0].status, BatchStatus::Failed(_)));
+    assert_eq!(results[1].status, BatchStatus::NotStarted);
+    Ok(())
+}
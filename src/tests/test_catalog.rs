@@ -0,0 +1,70 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+use crate::catalog::{build_catalog, write_catalog, CatalogFormat};
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_TONIE_FILE: &str = "resources/test/test_1.taf";
+
+#[test]
+fn test_catalog_format_from_path() {
+    assert_eq!(
+        CatalogFormat::from_path(Path::new("library.CSV")),
+        CatalogFormat::Csv
+    );
+    assert_eq!(
+        CatalogFormat::from_path(Path::new("library.json")),
+        CatalogFormat::Json
+    );
+    assert_eq!(
+        CatalogFormat::from_path(Path::new("-")),
+        CatalogFormat::Json
+    );
+}
+
+#[test]
+fn test_build_catalog() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let content_dir = temp_dir.path().join("CONTENT").join("5634121E");
+    std::fs::create_dir_all(&content_dir)?;
+    std::fs::copy(
+        Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE),
+        content_dir.join("500304E0"),
+    )?;
+    std::fs::write(temp_dir.path().join("broken.taf"), b"not a Tonie file")?;
+
+    let entries = build_catalog(temp_dir.path(), &Limits::default())?;
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        entries[0].path,
+        Path::new("CONTENT").join("5634121E").join("500304E0")
+    );
+    assert_eq!(
+        entries[0].tag_uid.map(|uid| uid.to_string()),
+        Some(String::from("E0:04:03:50:1E:12:34:56"))
+    );
+    assert!(entries[0].info.is_ok());
+    assert_eq!(entries[1].path, PathBuf::from("broken.taf"));
+    assert!(entries[1].info.is_err());
+
+    let mut json_catalog = vec![];
+    write_catalog(&entries, CatalogFormat::Json, &mut json_catalog)?;
+    let json_catalog = serde_json::from_slice::<serde_json::Value>(&json_catalog)?;
+    assert_eq!(json_catalog[0]["tag_uid"], "E0:04:03:50:1E:12:34:56");
+    assert_eq!(json_catalog[0]["chapters"][0]["number"], 1);
+    assert!(json_catalog[0]["sha1_hash"].is_string());
+    assert!(json_catalog[0]["comments"].is_array());
+    assert!(json_catalog[1]["error"].is_string());
+
+    let mut csv_catalog = vec![];
+    write_catalog(&entries, CatalogFormat::Csv, &mut csv_catalog)?;
+    let csv_catalog = String::from_utf8(csv_catalog)?;
+    let rows = csv_catalog.lines().collect::<Vec<_>>();
+    assert_eq!(rows.len(), 3);
+    assert!(rows[0].starts_with("path,tag_uid,size,audio_id"));
+    assert!(rows[2].starts_with("broken.taf,,16,,"));
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use audio2tonie::ogg_page::OggPage;
+use tempfile::tempdir;
+
+use crate::check::{check_report_json, check_tonie_file, Check};
+use crate::tests::{create_test_tonie_file, sine_samples};
+
+#[test]
+fn test_check_valid_tonie_file() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    create_test_tonie_file(&tonie_path, &[sine_samples(440.0, -20.0, 3.0)])?;
+
+    let results = check_tonie_file(&tonie_path, &Limits::default())?;
+
+    assert_eq!(results.len(), 6);
+    assert!(results.iter().all(|result| result.is_ok()));
+    Ok(())
+}
+
+#[test]
+fn test_check_corrupted_tonie_file() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    create_test_tonie_file(&tonie_path, &[sine_samples(440.0, -20.0, 3.0)])?;
+
+    // Flip a byte in the audio data of the second block
+    let mut tonie_data = std::fs::read(&tonie_path)?;
+    tonie_data[0x2100] ^= 0xFF;
+    std::fs::write(&tonie_path, &tonie_data)?;
+
+    let results = check_tonie_file(&tonie_path, &Limits::default())?;
+    let failed = results
+        .iter()
+        .filter(|result| !result.is_ok())
+        .map(|result| result.check)
+        .collect::<Vec<_>>();
+
+    assert_eq!(failed, vec![Check::Sha1Hash, Check::Checksums]);
+
+    let report = check_report_json(&tonie_path, &results);
+    assert_eq!(report["valid"], false);
+    assert_eq!(report["checks"][0]["check"], "sha1_hash");
+    assert_eq!(report["checks"][0]["ok"], false);
+    assert!(report["checks"][0]["error"].is_string());
+    assert_eq!(report["checks"][1]["ok"], true);
+    assert!(report["checks"][1]["error"].is_null());
+    Ok(())
+}
+
+#[test]
+fn test_check_granule_position_drift() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    create_test_tonie_file(&tonie_path, &[sine_samples(440.0, -20.0, 3.0)])?;
+
+    // Move the granule position of the page in the third block, as if audio was skipped before it
+    let mut tonie_data = std::fs::read(&tonie_path)?;
+    let (mut page, page_size) = OggPage::parse(&tonie_data[0x3000..])?;
+    page.granule_position += 960;
+    tonie_data[0x3000..0x3000 + page_size].copy_from_slice(&page.serialize());
+    std::fs::write(&tonie_path, &tonie_data)?;
+
+    let results = check_tonie_file(&tonie_path, &Limits::default())?;
+    let granule_result = results
+        .iter()
+        .find(|result| result.check == Check::GranulePositions)
+        .unwrap();
+
+    let error = granule_result.error.clone().unwrap_or_default();
+    assert!(error.starts_with("page 4 at offset 0x3000 in chapter 1"));
+    assert!(error.contains("drift of 960 samples"));
+    // The drift is reported where it begins and where it ends on the next page
+    assert_eq!(error.matches("drift of").count(), 2);
+    Ok(())
+}
@@ -1,15 +1,30 @@
 use anyhow::Result;
 use rand::rng;
 use rand::seq::SliceRandom;
+use rand::Rng;
 use std::{
     fs::File,
-    os::unix::fs::MetadataExt,
+    io::Cursor,
+    os::unix::fs::{MetadataExt, PermissionsExt},
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 use tempfile::{tempdir, NamedTempFile};
 use toniefile::Toniefile;
 
-use crate::convert::{audiofile_to_wav, convert_to_tonie, filter_input_files};
+use crate::cli::{parse_audio_id, parse_duration_limit, split_arguments, split_convert_paths};
+
+use audio2tonie::convert::{
+    album_output_path, atempo_filter, audiofile_to_wav, bitrate_for_size, collect_input_files,
+    convert_files_to_tonie, convert_to_tonie, convert_to_writer, estimate_tonie_size,
+    ffmpeg_output_args, filter_input_files, find_album_directories, inputs_modified_since,
+    merge_shortest_chapters, output_exists, parse_ffmpeg_chapters, parse_ffmpeg_track_number,
+    part_output_path, partial_file_path, preflight_check, read_input_list, split_conversion_plan,
+    stream_pcm, ConversionPlan, ConvertOptions, ExistingOutput, PlannedChapter, Since, SplitLimits,
+    TrackFailure, TrackOrder, OVERFLOW_SPLIT_SIZE, STDIN_INPUT,
+};
+use audio2tonie::taf::MAX_AUDIO_LENGTH;
+use audio2tonie::Audio2TonieError;
 
 const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
 const TEST_TONIE_FILE: &str = "resources/test/test_1.taf";
@@ -34,11 +49,8 @@ fn test_convert_to_tonie_from_single_file() -> anyhow::Result<()> {
 
     let temp_file = NamedTempFile::new()?;
 
-    let converted_file = convert_to_tonie(
-        &test_mp3_path,
-        &temp_file.path().to_path_buf(),
-        String::from("ffmpeg"),
-    )?;
+    let converted_file =
+        convert_to_tonie(&test_mp3_path, temp_file.path(), &ConvertOptions::default())?;
 
     // Check that the converted file exists and has content
     assert!(converted_file.metadata()?.size() > 0);
@@ -66,8 +78,11 @@ fn test_convert_to_tonie_from_directory() -> anyhow::Result<()> {
     let test_input_path = Path::new(TEST_FILES_DIR).join("resources").join("test");
     let temp_output_path = temp_dir.join("test_tonie.taf");
 
-    let converted_file =
-        convert_to_tonie(&test_input_path, &temp_output_path, String::from("ffmpeg"))?;
+    let converted_file = convert_to_tonie(
+        &test_input_path,
+        &temp_output_path,
+        &ConvertOptions::default(),
+    )?;
 
     assert!(converted_file.metadata()?.size() > 0);
 
@@ -79,13 +94,128 @@ fn test_convert_to_tonie_from_directory() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_convert_to_tonie_with_bitrate() -> Result<()> {
+    let temp_dir = tempdir()?;
+    // Stands in for ffmpeg and decodes every file into five seconds of noise, which needs the full bitrate
+    let noise_path = temp_dir.path().join("noise.pcm");
+    let mut noise = vec![0u8; 48000 * 4 * 5];
+    rng().fill(noise.as_mut_slice());
+    std::fs::write(&noise_path, noise)?;
+    let ffmpeg_path = temp_dir.path().join("ffmpeg");
+    std::fs::write(
+        &ffmpeg_path,
+        format!("#!/bin/sh\ncat '{}'\n", noise_path.display()),
+    )?;
+    std::fs::set_permissions(&ffmpeg_path, std::fs::Permissions::from_mode(0o755))?;
+    let input_path = temp_dir.path().join("story.mp3");
+    std::fs::write(&input_path, b"")?;
+
+    let mut sizes = vec![];
+    for bitrate in [32, 96] {
+        let output_path = temp_dir.path().join(format!("{}.taf", bitrate));
+        let options = ConvertOptions {
+            ffmpeg: ffmpeg_path.to_string_lossy().to_string(),
+            bitrate,
+            ..ConvertOptions::default()
+        };
+        convert_to_tonie(&input_path, &output_path, &options)?;
+
+        let header = Toniefile::parse_header(&mut File::open(&output_path)?)?;
+        assert_eq!(header.track_page_nums.len(), 1);
+        sizes.push(std::fs::metadata(&output_path)?.len());
+    }
+    // The audio data scales with the bitrate, the first two blocks hold the headers
+    assert!(sizes[0] - 8192 < (sizes[1] - 8192) / 2, "{:?}", sizes);
+
+    return Ok(());
+}
+
+#[test]
+fn test_convert_to_tonie_cancelled() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let ffmpeg_path = temp_dir.path().join("ffmpeg");
+    std::fs::write(&ffmpeg_path, "#!/bin/sh\nhead -c 1920000 /dev/zero\n")?;
+    std::fs::set_permissions(&ffmpeg_path, std::fs::Permissions::from_mode(0o755))?;
+    let input_path = temp_dir.path().join("story.mp3");
+    std::fs::write(&input_path, b"")?;
+    let output_path = temp_dir.path().join("500304E0");
+    let options = ConvertOptions {
+        ffmpeg: ffmpeg_path.to_string_lossy().to_string(),
+        ..ConvertOptions::default()
+    };
+
+    // Cancelling while decoding stops ffmpeg after the current chunk
+    let mut chunks = 0;
+    let result = stream_pcm(&input_path, &options, |_| {
+        chunks += 1;
+        options.cancellation.cancel();
+        return Ok(());
+    });
+    assert!(matches!(result, Err(Audio2TonieError::Cancelled)));
+    assert_eq!(chunks, 1);
+
+    // The partially written Tonie file is removed
+    let result = convert_to_tonie(&input_path, &output_path, &options);
+    assert!(matches!(result, Err(Audio2TonieError::Cancelled)));
+    assert!(!output_path.exists());
+
+    return Ok(());
+}
+
+#[test]
+fn test_convert_to_tonie_writes_partial_file() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let output_dir = temp_dir.path().join("output");
+    std::fs::create_dir(&output_dir)?;
+    // Lists the output directory while the conversion is running
+    let listing_path = temp_dir.path().join("listing.txt");
+    let ffmpeg_path = temp_dir.path().join("ffmpeg");
+    std::fs::write(
+        &ffmpeg_path,
+        format!(
+            "#!/bin/sh\nls '{}' > '{}'\nhead -c 192000 /dev/zero\n",
+            output_dir.display(),
+            listing_path.display()
+        ),
+    )?;
+    std::fs::set_permissions(&ffmpeg_path, std::fs::Permissions::from_mode(0o755))?;
+    let input_path = temp_dir.path().join("story.mp3");
+    std::fs::write(&input_path, b"")?;
+    let output_path = output_dir.join("500304E0");
+
+    for existing_output in [ExistingOutput::Fail, ExistingOutput::Overwrite] {
+        let options = ConvertOptions {
+            ffmpeg: ffmpeg_path.to_string_lossy().to_string(),
+            existing_output,
+            ..ConvertOptions::default()
+        };
+        convert_to_tonie(&input_path, &output_path, &options)?;
+
+        let listing = std::fs::read_to_string(&listing_path)?;
+        assert!(listing.contains("500304E0.part"), "{}", listing);
+        assert!(!partial_file_path(&output_path).exists());
+        let header = Toniefile::parse_header(&mut File::open(&output_path)?)?;
+        assert_eq!(header.track_page_nums.len(), 1);
+    }
+    assert_eq!(
+        partial_file_path(&output_path),
+        output_dir.join("500304E0.part")
+    );
+
+    return Ok(());
+}
+
 #[test]
 fn test_convert_to_tonie_with_default_output() -> anyhow::Result<()> {
     let test_input_path = PathBuf::from(TEST_FILES_DIR);
     let temp_output_path = tempdir()?.into_path();
 
-    let converted_file =
-        convert_to_tonie(&test_input_path, &temp_output_path, String::from("ffmpeg"))?;
+    let converted_file = convert_to_tonie(
+        &test_input_path,
+        &temp_output_path,
+        &ConvertOptions::default(),
+    )?;
 
     assert!(converted_file.metadata()?.size() > 0);
 
@@ -98,8 +228,11 @@ fn test_convert_to_tonie_with_two_directories() -> anyhow::Result<()> {
     let temp_output_path = tempdir()?.into_path();
     let expected_output_path = temp_output_path.join("500304E0");
 
-    let converted_file =
-        convert_to_tonie(&test_mp3_path, &temp_output_path, String::from("ffmpeg"))?;
+    let converted_file = convert_to_tonie(
+        &test_mp3_path,
+        &temp_output_path,
+        &ConvertOptions::default(),
+    )?;
 
     assert!(converted_file.metadata()?.size() > 0);
     assert!(expected_output_path.exists());
@@ -110,13 +243,31 @@ fn test_convert_to_tonie_with_two_directories() -> anyhow::Result<()> {
 #[test]
 fn test_audiofile_to_wav() -> Result<()> {
     let test_mp3_path = Path::new(TEST_FILES_DIR).join(TEST_MP3_FILE);
-    let temp_wav_buffer = audiofile_to_wav(&test_mp3_path, "ffmpeg")?;
+    let temp_wav_buffer = audiofile_to_wav(&test_mp3_path, &ConvertOptions::default())?;
 
     assert_eq!(temp_wav_buffer.len() / (2 * 2 * 48000), 208); // Stereo = 2 channel á 48000Hz; 2 bytes per second
 
     Ok(())
 }
 
+#[test]
+fn test_stream_pcm() -> Result<()> {
+    let test_mp3_path = Path::new(TEST_FILES_DIR).join(TEST_MP3_FILE);
+    let mut sample_count = 0;
+    let mut largest_chunk = 0;
+    stream_pcm(&test_mp3_path, &ConvertOptions::default(), |samples| {
+        sample_count += samples.len();
+        largest_chunk = largest_chunk.max(samples.len());
+        return Ok(());
+    })?;
+
+    assert_eq!(sample_count / (2 * 48000), 208);
+    // The decoded audio is never buffered as a whole
+    assert!(largest_chunk <= 64 * 1024);
+
+    Ok(())
+}
+
 #[test]
 fn test_filter_input_files() -> Result<()> {
     let temp_dir = tempdir()?;
@@ -138,7 +289,7 @@ fn test_filter_input_files() -> Result<()> {
         File::create(file_name)?;
     }
 
-    let validated_paths = filter_input_files(&temp_path.to_path_buf())?;
+    let validated_paths = filter_input_files(&temp_path.to_path_buf(), false)?;
     assert_eq!(temp_input_files, validated_paths);
 
     // Shuffle file name order. This should conflict with the sorted and validated input files
@@ -147,3 +298,765 @@ fn test_filter_input_files() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_filter_input_files_with_any_extension() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let temp_path = temp_dir.path();
+    for file_name in [
+        "1. Intro.AIFF",
+        "2. Story.wma",
+        "3. Outro.dsf",
+        "album.cue",
+        "cover.jpg",
+        ".DS_Store",
+    ] {
+        File::create(temp_path.join(file_name))?;
+    }
+
+    let input_files = filter_input_files(&temp_path.to_path_buf(), false)?;
+    assert_eq!(
+        input_files,
+        vec![
+            temp_path.join("1. Intro.AIFF"),
+            temp_path.join("2. Story.wma")
+        ]
+    );
+
+    let input_files = filter_input_files(&temp_path.to_path_buf(), true)?;
+    assert_eq!(
+        input_files,
+        vec![
+            temp_path.join("1. Intro.AIFF"),
+            temp_path.join("2. Story.wma"),
+            temp_path.join("3. Outro.dsf"),
+        ]
+    );
+
+    let unknown_file = temp_path.join("3. Outro.dsf");
+    assert!(filter_input_files(&unknown_file, false).is_err());
+    assert_eq!(filter_input_files(&unknown_file, true)?, vec![unknown_file]);
+
+    Ok(())
+}
+
+#[test]
+fn test_filter_input_files_with_glob_pattern() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let temp_path = temp_dir.path();
+    for disc in ["Disc 1", "Disc 2", "Disc 10", "Bonus"] {
+        std::fs::create_dir(temp_path.join(disc))?;
+        for file_name in ["01 Intro.mp3", "02 Story.mp3", "notes.mp3", "cover.jpg"] {
+            File::create(temp_path.join(disc).join(file_name))?;
+        }
+    }
+
+    let pattern = temp_path.join("Disc*").join("[0-9]*.mp3");
+    let input_files = filter_input_files(&pattern, false)?;
+    let expected = ["Disc 1", "Disc 2", "Disc 10"]
+        .iter()
+        .flat_map(|disc| {
+            [
+                temp_path.join(disc).join("01 Intro.mp3"),
+                temp_path.join(disc).join("02 Story.mp3"),
+            ]
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(input_files, expected);
+
+    assert!(filter_input_files(&temp_path.join("Disc*").join("*.flac"), false).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_collect_input_files_keeps_the_given_order() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let temp_path = temp_dir.path();
+    std::fs::create_dir(temp_path.join("Stories"))?;
+    for file_name in [
+        "Stories/2 Second.mp3",
+        "Stories/1 First.mp3",
+        "Outro.mp3",
+        "Intro.mp3",
+    ] {
+        File::create(temp_path.join(file_name))?;
+    }
+
+    let inputs = [
+        temp_path.join("Intro.mp3"),
+        temp_path.join("Stories"),
+        temp_path.join("Outro.mp3"),
+    ];
+    let input_files = collect_input_files(&inputs, &ConvertOptions::default())?;
+    let expected = [
+        "Intro.mp3",
+        "Stories/1 First.mp3",
+        "Stories/2 Second.mp3",
+        "Outro.mp3",
+    ]
+    .iter()
+    .map(|file_name| temp_path.join(file_name))
+    .collect::<Vec<_>>();
+    assert_eq!(input_files, expected);
+
+    // Files without track number tags keep the order of their names
+    let options = ConvertOptions {
+        ffmpeg: String::from("/nonexistent/ffmpeg"),
+        track_order: TrackOrder::Tags,
+        ..Default::default()
+    };
+    assert_eq!(collect_input_files(&inputs, &options)?, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_ffmpeg_track_number() {
+    let id3_output = "Input #0, mp3, from 'story.mp3':
+  Metadata:
+    title           : Chapter: The End
+    track           : 3/12
+    disc            : 2/2
+  Duration: 00:03:28.03, start: 0.025057, bitrate: 128 kb/s";
+    assert_eq!(parse_ffmpeg_track_number(id3_output), Some((2, 3)));
+
+    let vorbis_output = "Input #0, flac, from 'story.flac':
+  Metadata:
+    TRACKNUMBER     : 7
+  Duration: 00:03:28.03, start: 0.000000, bitrate: 900 kb/s";
+    assert_eq!(parse_ffmpeg_track_number(vorbis_output), Some((1, 7)));
+
+    let untagged_output = "Input #0, wav, from 'story.wav':
+  Duration: 00:03:28.03, bitrate: 1411 kb/s";
+    assert_eq!(parse_ffmpeg_track_number(untagged_output), None);
+}
+
+#[test]
+fn test_read_input_list() -> Result<()> {
+    let list = "# Picked by hand\r\nStories/2 Second.mp3\r\n\nIntro.mp3\n";
+    assert_eq!(
+        read_input_list(list.as_bytes())?,
+        vec![
+            PathBuf::from("Stories/2 Second.mp3"),
+            PathBuf::from("Intro.mp3")
+        ]
+    );
+    assert!(read_input_list("#EXTM3U\n\n".as_bytes()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_split_convert_paths() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let intro = temp_dir.path().join("intro.mp3");
+    let story = temp_dir.path().join("story.mp3");
+    File::create(&intro)?;
+    File::create(&story)?;
+    let output = temp_dir.path().join("out.taf");
+
+    // Without --output the last of several paths is the output
+    assert_eq!(
+        split_convert_paths(vec![intro.clone(), output.clone()], None, None),
+        Ok((vec![intro.clone()], output.clone()))
+    );
+    assert_eq!(
+        split_convert_paths(vec![intro.clone()], None, None),
+        Ok((vec![intro.clone()], PathBuf::from("500304E0")))
+    );
+    assert_eq!(
+        split_convert_paths(
+            vec![story.clone(), intro.clone()],
+            None,
+            Some(output.clone())
+        ),
+        Ok((vec![story.clone(), intro.clone()], output.clone()))
+    );
+    assert!(split_convert_paths(
+        vec![intro.clone(), output.clone()],
+        None,
+        Some(output.clone())
+    )
+    .is_err());
+
+    // Listed inputs replace the positional inputs, a single path is the output
+    let listed = Some(vec![story.clone(), intro.clone()]);
+    assert_eq!(
+        split_convert_paths(vec![output.clone()], listed.clone(), None),
+        Ok((vec![story.clone(), intro.clone()], output.clone()))
+    );
+    assert!(split_convert_paths(vec![intro.clone(), output.clone()], listed, None).is_err());
+
+    // Stdin is a single input of its own
+    let stdin = PathBuf::from(STDIN_INPUT);
+    assert_eq!(
+        split_convert_paths(vec![stdin.clone(), output.clone()], None, None),
+        Ok((vec![stdin.clone()], output.clone()))
+    );
+    assert!(split_convert_paths(
+        vec![stdin.clone(), intro.clone(), output.clone()],
+        None,
+        None
+    )
+    .is_err());
+    assert_eq!(filter_input_files(&stdin, false)?, vec![stdin.clone()]);
+
+    Ok(())
+}
+
+#[test]
+fn test_inputs_modified_since_timestamp() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let temp_path = temp_dir.path().to_path_buf();
+    File::create(temp_path.join("1. MyFile.mp3"))?;
+
+    let output_path = temp_path.join("500304E0");
+    let past = Since::Timestamp(SystemTime::now() - Duration::from_secs(3600));
+    let future = Since::Timestamp(SystemTime::now() + Duration::from_secs(3600));
+
+    assert!(inputs_modified_since(
+        &temp_path,
+        &output_path,
+        &past,
+        false
+    )?);
+    assert!(!inputs_modified_since(
+        &temp_path,
+        &output_path,
+        &future,
+        false
+    )?);
+
+    Ok(())
+}
+
+#[test]
+fn test_inputs_modified_since_last_run() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let input_path = temp_dir.path().join("1. MyFile.mp3");
+    let output_path = temp_dir.path().join("output.taf");
+
+    File::create(&input_path)?;
+
+    // Without a previous output file every input counts as modified
+    assert!(inputs_modified_since(
+        &input_path,
+        &output_path,
+        &Since::LastRun,
+        false
+    )?);
+
+    let output_file = File::create(&output_path)?;
+    output_file.set_modified(SystemTime::now() + Duration::from_secs(3600))?;
+    assert!(!inputs_modified_since(
+        &input_path,
+        &output_path,
+        &Since::LastRun,
+        false
+    )?);
+
+    Ok(())
+}
+
+#[test]
+fn test_convert_to_tonie_with_threads() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let test_input_path = Path::new(TEST_FILES_DIR).join("resources").join("test");
+    let serial_output_path = temp_dir.path().join("serial.taf");
+    let parallel_output_path = temp_dir.path().join("parallel.taf");
+
+    convert_to_tonie(
+        &test_input_path,
+        &serial_output_path,
+        &ConvertOptions {
+            audio_id: Some(0x12345678),
+            ..Default::default()
+        },
+    )?;
+    convert_to_tonie(
+        &test_input_path,
+        &parallel_output_path,
+        &ConvertOptions {
+            threads: 2,
+            audio_id: Some(0x12345678),
+            ..Default::default()
+        },
+    )?;
+
+    // The chapters are encoded in the original order regardless of the decoding order
+    assert_eq!(
+        std::fs::read(serial_output_path)?,
+        std::fs::read(parallel_output_path)?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_convert_to_writer() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let test_mp3_path = Path::new(TEST_FILES_DIR).join(TEST_MP3_FILE);
+    let output_path = temp_dir.path().join("500304E0");
+    let options = ConvertOptions {
+        audio_id: Some(0x12345678),
+        ..Default::default()
+    };
+
+    convert_to_tonie(&test_mp3_path, &output_path, &options)?;
+    let buffer = convert_to_writer(&test_mp3_path, Cursor::new(vec![]), &options)?;
+
+    // The Tonie file is the same whether it is written to a file or into memory
+    assert_eq!(buffer.into_inner(), std::fs::read(output_path)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_error_sources() {
+    use std::error::Error;
+
+    let io_error = Audio2TonieError::Io(std::io::Error::other("disk full"));
+    assert_eq!(io_error.source().unwrap().to_string(), "disk full");
+    let track_failed = Audio2TonieError::TrackFailed {
+        input_file: PathBuf::from("story.mp3"),
+        error: Box::new(io_error),
+    };
+    let inner = track_failed.source().unwrap();
+    assert!(matches!(
+        inner.downcast_ref::<Audio2TonieError>(),
+        Some(Audio2TonieError::Io(_))
+    ));
+    assert_eq!(inner.source().unwrap().to_string(), "disk full");
+    let other = Audio2TonieError::Other(anyhow::anyhow!("unexpected"));
+    assert_eq!(other.source().unwrap().to_string(), "unexpected");
+    assert!(Audio2TonieError::NoAudio.source().is_none());
+}
+
+#[test]
+fn test_convert_with_failed_track() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let corrupt_path = temp_dir.path().join("corrupt.mp3");
+    std::fs::write(&corrupt_path, b"not an mp3 file")?;
+    let output_path = temp_dir.path().join("500304E0");
+
+    // A track that fails to decode fails the conversion by default instead of leaving a chapter out
+    let error = convert_to_tonie(&corrupt_path, &output_path, &ConvertOptions::default())
+        .expect_err("The corrupt track fails the conversion.");
+    assert!(
+        matches!(&error, Audio2TonieError::TrackFailed { input_file, .. } if *input_file == corrupt_path)
+    );
+    assert!(!output_path.exists());
+
+    let test_mp3_path = Path::new(TEST_FILES_DIR).join(TEST_MP3_FILE);
+    let input_files = [corrupt_path.clone(), test_mp3_path];
+    for threads in [1, 2] {
+        let options = ConvertOptions {
+            on_track_failure: TrackFailure::Skip,
+            threads,
+            ..Default::default()
+        };
+        convert_files_to_tonie(&input_files, &output_path, &options)?;
+        let failed_tracks = options.failed_tracks.take();
+        assert_eq!(failed_tracks.len(), 1);
+        assert_eq!(failed_tracks[0].input_file, corrupt_path);
+        assert!(!failed_tracks[0].truncated);
+        assert!(options.failed_tracks.take().is_empty());
+        std::fs::remove_file(&output_path)?;
+
+        // Without any decodable track there is no Tonie file to write
+        let error = convert_to_tonie(&corrupt_path, &output_path, &options)
+            .expect_err("A conversion without audio fails.");
+        assert!(matches!(error, Audio2TonieError::NoAudio));
+        assert!(!output_path.exists());
+        assert_eq!(options.failed_tracks.take().len(), 1);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_convert_to_tonie_with_audio_id() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let test_mp3_path = Path::new(TEST_FILES_DIR).join(TEST_MP3_FILE);
+    let output_path = temp_dir.path().join("500304E0");
+
+    convert_to_tonie(
+        &test_mp3_path,
+        &output_path,
+        &ConvertOptions {
+            audio_id: Some(0xCAFE),
+            ..Default::default()
+        },
+    )?;
+
+    let header = Toniefile::parse_header(&mut File::open(output_path)?)?;
+    assert_eq!(header.audio_id, 0xCAFE);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_audio_id() {
+    assert_eq!(parse_audio_id("1700000000"), Ok(1700000000));
+    assert_eq!(parse_audio_id("0x12345678"), Ok(0x12345678));
+    assert_eq!(parse_audio_id("0XcafE"), Ok(0xCAFE));
+    assert!(parse_audio_id("0x123456789").is_err());
+    assert!(parse_audio_id("now").is_err());
+}
+
+#[test]
+fn test_find_album_directories() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let library = temp_dir.path();
+    for album in [
+        "Artist/Album 2",
+        "Artist/Album 10",
+        "Compilation/CD1",
+        "Empty",
+    ] {
+        std::fs::create_dir_all(library.join(album))?;
+    }
+    File::create(library.join("Artist/Album 2/01.mp3"))?;
+    File::create(library.join("Artist/Album 10/01.mp3"))?;
+    File::create(library.join("Compilation/CD1/01.ogg"))?;
+    File::create(library.join("Compilation/cover.jpg"))?;
+
+    let albums = find_album_directories(library, false)?;
+
+    assert_eq!(
+        albums,
+        vec![
+            library.join("Artist/Album 2"),
+            library.join("Artist/Album 10"),
+            library.join("Compilation/CD1"),
+        ]
+    );
+    assert_eq!(
+        album_output_path(library, &albums[0], Path::new("out")),
+        Path::new("out").join("Artist - Album 2.taf")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_ffmpeg_chapters() {
+    let ffmpeg_output = "Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'book.m4b':
+  Duration: 00:25:00.00, start: 0.000000, bitrate: 64 kb/s
+  Chapters:
+    Chapter #0:0: start 0.000000, end 600.000000
+      Metadata:
+        title           : Chapter 1
+    Chapter #0:1: start 600.000000, end 1230.500000
+      Metadata:
+        title           : Chapter 2
+    Chapter #0:2: start 1230.500000, end 1500.000000
+  Stream #0:0(und): Audio: aac (LC) (mp4a / 0x6134706D), 44100 Hz, stereo, fltp, 62 kb/s (default)
+";
+
+    assert_eq!(
+        parse_ffmpeg_chapters(ffmpeg_output),
+        vec![0.0, 600.0, 1230.5]
+    );
+    assert!(parse_ffmpeg_chapters("  Duration: 00:03:28.03, start: 0.0").is_empty());
+}
+
+#[test]
+fn test_estimate_tonie_size() {
+    assert_eq!(estimate_tonie_size(0.0, 96), 2 * 4096);
+    // The multiple chapters test file has 8:33 of audio and 5.8 MB
+    let estimate = estimate_tonie_size(513.0, 96);
+    assert_eq!(estimate % 4096, 0);
+    assert!(estimate > 5_500_000 && estimate < 6_500_000);
+    assert!(estimate_tonie_size(513.0, 64) < estimate * 3 / 4);
+}
+
+#[test]
+fn test_atempo_filter() {
+    assert_eq!(atempo_filter(1.15), "atempo=1.15");
+    assert_eq!(atempo_filter(0.5), "atempo=0.5");
+    assert_eq!(atempo_filter(3.0), "atempo=2,atempo=1.5");
+    assert_eq!(atempo_filter(0.25), "atempo=0.5,atempo=0.5");
+}
+
+#[test]
+fn test_ffmpeg_output_args() {
+    let args = |ffmpeg_args: &[&str], speed: f64| {
+        let options = ConvertOptions {
+            ffmpeg_args: ffmpeg_args.iter().map(|arg| arg.to_string()).collect(),
+            speed,
+            ..ConvertOptions::default()
+        };
+        return ffmpeg_output_args(&options);
+    };
+
+    assert!(args(&[], 1.0).is_empty());
+    assert_eq!(args(&["-ss", "10"], 1.0), ["-ss", "10"]);
+    assert_eq!(
+        args(&["-ss", "10"], 1.15),
+        ["-ss", "10", "-af", "atempo=1.15"]
+    );
+    // The speed is applied after the audio filter of the additional arguments
+    assert_eq!(
+        args(&["-af", "loudnorm", "-t", "60"], 1.15),
+        ["-af", "loudnorm,atempo=1.15", "-t", "60"]
+    );
+}
+
+#[test]
+fn test_split_arguments() {
+    assert_eq!(
+        split_arguments("  -af   loudnorm "),
+        Ok(vec!["-af".to_string(), "loudnorm".to_string()])
+    );
+    assert_eq!(
+        split_arguments("-metadata \"title=Der Räuber\" -ss '' 10"),
+        Ok(vec![
+            "-metadata".to_string(),
+            "title=Der Räuber".to_string(),
+            "-ss".to_string(),
+            String::new(),
+            "10".to_string(),
+        ])
+    );
+    assert!(split_arguments("-af \"loudnorm").is_err());
+}
+
+fn planned_files(durations: &[f64]) -> ConversionPlan {
+    let input_files = (1..=durations.len())
+        .map(|index| PathBuf::from(format!("{}.mp3", index)))
+        .collect::<Vec<_>>();
+    let chapters = input_files
+        .iter()
+        .zip(durations)
+        .map(|(input_file, duration)| PlannedChapter {
+            input_file: input_file.clone(),
+            start: 0.0,
+            duration: Some(*duration),
+        })
+        .collect();
+
+    return ConversionPlan {
+        input_files,
+        chapters,
+        bitrate: 96,
+    };
+}
+
+#[test]
+fn test_split_conversion_plan() {
+    let plan = planned_files(&[1800.0, 2400.0, 600.0, 7200.0, 60.0]);
+
+    let parts = split_conversion_plan(
+        &plan,
+        &SplitLimits {
+            max_duration: Some(5400.0),
+            ..Default::default()
+        },
+    );
+
+    // Files are never cut, so the file longer than the limit gets a part on its own
+    let part_files = parts
+        .iter()
+        .map(|part| part.input_files.len())
+        .collect::<Vec<_>>();
+    assert_eq!(part_files, vec![3, 1, 1]);
+    assert_eq!(parts[0].duration(), 4800.0);
+    assert_eq!(parts[1].chapters[0].input_file, PathBuf::from("4.mp3"));
+    assert_eq!(parts[2].chapters[0].input_file, PathBuf::from("5.mp3"));
+
+    let parts = split_conversion_plan(
+        &plan,
+        &SplitLimits {
+            max_size: Some(estimate_tonie_size(3000.0, 96)),
+            ..Default::default()
+        },
+    );
+    assert!(parts
+        .iter()
+        .filter(|part| part.input_files.len() > 1)
+        .all(|part| part.estimated_size() <= estimate_tonie_size(3000.0, 96)));
+    assert_eq!(parts.len(), 4);
+
+    assert_eq!(
+        split_conversion_plan(&plan, &SplitLimits::default()).len(),
+        1
+    );
+
+    // About 28 hours at 96 kbit/s each, which exceed the data length of a single Tonie file together
+    let long_plan = planned_files(&[100_000.0, 100_000.0]);
+    let parts = split_conversion_plan(
+        &long_plan,
+        &SplitLimits {
+            max_size: Some(OVERFLOW_SPLIT_SIZE),
+            ..Default::default()
+        },
+    );
+    assert_eq!(parts.len(), 2);
+    assert!(parts
+        .iter()
+        .all(|part| part.estimated_size() <= MAX_AUDIO_LENGTH));
+
+    let parts = split_conversion_plan(
+        &plan,
+        &SplitLimits {
+            max_chapters: Some(2),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        parts
+            .iter()
+            .map(|part| part.chapters.len())
+            .collect::<Vec<_>>(),
+        vec![2, 2, 1]
+    );
+}
+
+#[test]
+fn test_bitrate_for_size() {
+    // Ten hours fit into 200 MB at about 40 kbit/s
+    let bitrate = bitrate_for_size(36_000.0, 200_000_000, 96).unwrap();
+    assert!(bitrate < 96);
+    assert!(estimate_tonie_size(36_000.0, bitrate) <= 200_000_000 / 20 * 19);
+    assert!(estimate_tonie_size(36_000.0, bitrate + 1) > 200_000_000 / 20 * 19);
+
+    // The bitrate is never raised above the maximum
+    assert_eq!(bitrate_for_size(600.0, 200_000_000, 96), Some(96));
+    assert_eq!(bitrate_for_size(360_000.0, 10_000_000, 96), None);
+}
+
+#[test]
+fn test_preflight_check() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let output = temp_dir.path().join("missing").join("output.taf");
+    let plans = vec![planned_files(&[1800.0, 600.0]), planned_files(&[3600.0])];
+    let outputs = vec![output.clone(), part_output_path(&output, 2)];
+
+    preflight_check(&plans, &outputs, None)?;
+    preflight_check(&plans, &outputs, Some(estimate_tonie_size(3600.0, 96)))?;
+    match preflight_check(&plans, &outputs, Some(estimate_tonie_size(3000.0, 96))) {
+        Err(Audio2TonieError::SizeLimitExceeded { output, max, .. }) => {
+            assert_eq!(output, part_output_path(&outputs[0], 2));
+            assert_eq!(max, estimate_tonie_size(3000.0, 96));
+        }
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    // About 111 hours at 96 kbit/s exceed the data length of a single Tonie file
+    assert!(matches!(
+        preflight_check(&[planned_files(&[400_000.0])], &outputs, None),
+        Err(Audio2TonieError::DataLengthOverflow)
+    ));
+    // The header block does not count towards the data length
+    let longest_duration = 524_286.0 * 4053.0 / 12_000.0 - 0.01;
+    assert!(estimate_tonie_size(longest_duration, 96) > MAX_AUDIO_LENGTH);
+    assert!(!matches!(
+        preflight_check(&[planned_files(&[longest_duration])], &outputs, None),
+        Err(Audio2TonieError::DataLengthOverflow)
+    ));
+    // Thousands of Tonie files with 28 hours each take several terabytes
+    let huge_plans = vec![planned_files(&[100_000.0]); 4000];
+    let huge_outputs = (1..=huge_plans.len())
+        .map(|part| part_output_path(&output, part))
+        .collect::<Vec<_>>();
+    assert!(matches!(
+        preflight_check(&huge_plans, &huge_outputs, None),
+        Err(Audio2TonieError::InsufficientSpace { .. })
+    ));
+    return Ok(());
+}
+#[test]
+fn test_merge_shortest_chapters() {
+    let durations = [600.0, 30.0, 40.0, 900.0, 20.0, 10.0];
+
+    // The shortest neighbours are merged first, so the long chapters stay separate
+    assert_eq!(
+        merge_shortest_chapters(&durations, 4),
+        vec![false, false, true, false, false, true]
+    );
+    assert_eq!(
+        merge_shortest_chapters(&durations, 2),
+        vec![false, true, true, false, true, true]
+    );
+    assert_eq!(merge_shortest_chapters(&durations, 99), vec![false; 6]);
+}
+
+#[test]
+fn test_convert_to_tonie_with_too_many_chapters() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir(&input_dir)?;
+    for track in 1..=100 {
+        File::create(input_dir.join(format!("{}.mp3", track)))?;
+    }
+    let output_path = temp_dir.path().join("500304E0");
+
+    let result = convert_to_tonie(&input_dir, &output_path, &ConvertOptions::default());
+
+    assert!(matches!(
+        result,
+        Err(Audio2TonieError::TooManyChapters {
+            chapters: 100,
+            max: 99
+        })
+    ));
+    assert!(!output_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_part_output_path() {
+    assert_eq!(
+        part_output_path(Path::new("/tonies/output.taf"), 2),
+        PathBuf::from("/tonies/output_part2.taf")
+    );
+    assert_eq!(
+        part_output_path(Path::new("500304E0"), 1),
+        PathBuf::from("500304E0_part1")
+    );
+}
+
+#[test]
+fn test_parse_duration_limit() {
+    assert_eq!(parse_duration_limit("90m"), Ok(5400.0));
+    assert_eq!(parse_duration_limit("1h30m"), Ok(5400.0));
+    assert_eq!(parse_duration_limit("5400"), Ok(5400.0));
+    assert_eq!(parse_duration_limit("1m30s"), Ok(90.0));
+    assert!(parse_duration_limit("90x").is_err());
+    assert!(parse_duration_limit("1h30").is_err());
+    assert!(parse_duration_limit("0").is_err());
+}
+
+#[test]
+fn test_convert_to_tonie_keeps_existing_output() -> anyhow::Result<()> {
+    let temp_dir = tempdir()?;
+    let input_path = temp_dir.path().join("story.mp3");
+    File::create(&input_path)?;
+    let output_path = temp_dir.path().join("500304E0");
+    assert!(!output_exists(&output_path));
+    std::fs::write(&output_path, b"previous conversion")?;
+    assert!(output_exists(&output_path));
+    // Directories resolve to the default file name inside of them
+    assert!(output_exists(temp_dir.path()));
+
+    for existing_output in [ExistingOutput::Fail, ExistingOutput::Skip] {
+        let result = convert_to_tonie(
+            &input_path,
+            &output_path,
+            &ConvertOptions {
+                existing_output,
+                ..Default::default()
+            },
+        );
+        assert!(
+            matches!(&result, Err(Audio2TonieError::OutputExists(path)) if *path == output_path)
+        );
+        assert!(result.is_err_and(|error| error.to_string().contains("already exists")));
+        assert_eq!(std::fs::read(&output_path)?, b"previous conversion");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use anyhow::Result;
+use std::fs::File;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::tempdir;
+use toniefile::Toniefile;
+
+use audio2tonie::convert::{
+    convert_to_tonie, count_chapters, estimate_tonie_size, plan_conversion, ConvertOptions,
+};
+use audio2tonie::cue::{find_cue_sheet, parse_cue_sheet, CueTrack};
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_MP3_FILE: &str = "resources/test/test_1.mp3";
+
+const TEST_CUE_SHEET: &str = r#"REM GENRE Audiobook
+PERFORMER "Some Narrator"
+TITLE "Some Story"
+FILE "Some Story.flac" WAVE
+  TRACK 01 AUDIO
+    TITLE "Chapter 1"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE "Chapter 2"
+    PERFORMER "Another Narrator"
+    INDEX 00 01:09:70
+    INDEX 01 01:10:15
+  TRACK 03 AUDIO
+    TITLE "Chapter 3"
+    INDEX 01 02:30:00
+"#;
+
+#[test]
+fn test_parse_cue_sheet() -> Result<()> {
+    let cue_sheet = parse_cue_sheet(TEST_CUE_SHEET)?;
+
+    assert_eq!(cue_sheet.file.as_deref(), Some("Some Story.flac"));
+    assert_eq!(cue_sheet.title.as_deref(), Some("Some Story"));
+    assert_eq!(cue_sheet.performer.as_deref(), Some("Some Narrator"));
+    assert_eq!(cue_sheet.tracks.len(), 3);
+    assert_eq!(
+        cue_sheet.tracks[1],
+        CueTrack {
+            number: 2,
+            title: Some(String::from("Chapter 2")),
+            performer: Some(String::from("Another Narrator")),
+            start: 70 * 75 + 15,
+        }
+    );
+    // 70.2 seconds at 48 kHz
+    assert_eq!(cue_sheet.tracks[1].start_sample(48000), 3369600);
+    assert_eq!(cue_sheet.tracks[2].start_sample(48000), 150 * 48000);
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_invalid_cue_sheet() {
+    assert!(parse_cue_sheet("FILE \"a.flac\" WAVE\n").is_err());
+    assert!(parse_cue_sheet("TRACK 01 AUDIO\nINDEX 01 00:61:00\n").is_err());
+    assert!(parse_cue_sheet("TRACK 01 AUDIO\nINDEX 01 00:00:75\n").is_err());
+    assert!(parse_cue_sheet(
+        "FILE \"a.flac\" WAVE\nTRACK 01 AUDIO\nFILE \"b.flac\" WAVE\nTRACK 02 AUDIO\n"
+    )
+    .is_err());
+}
+
+#[test]
+fn test_find_cue_sheet() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let audio_file = temp_dir.path().join("Some Story.flac");
+    File::create(&audio_file)?;
+
+    assert_eq!(find_cue_sheet(&audio_file), None);
+
+    File::create(temp_dir.path().join("Some Story.flac.cue"))?;
+    assert_eq!(
+        find_cue_sheet(&audio_file),
+        Some(temp_dir.path().join("Some Story.flac.cue"))
+    );
+
+    File::create(temp_dir.path().join("Some Story.cue"))?;
+    assert_eq!(
+        find_cue_sheet(&audio_file),
+        Some(temp_dir.path().join("Some Story.cue"))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_convert_to_tonie_with_cue_sheet() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let audio_file = temp_dir.path().join("test_1.mp3");
+    std::fs::copy(Path::new(TEST_FILES_DIR).join(TEST_MP3_FILE), &audio_file)?;
+    std::fs::write(
+        temp_dir.path().join("test_1.cue"),
+        "FILE \"test_1.mp3\" MP3\nTRACK 01 AUDIO\nINDEX 01 00:00:00\nTRACK 02 AUDIO\nINDEX 01 01:00:00\nTRACK 03 AUDIO\nINDEX 01 02:00:00\n",
+    )?;
+    let output_path = temp_dir.path().join("500304E0");
+
+    convert_to_tonie(&audio_file, &output_path, &ConvertOptions::default())?;
+
+    let header = Toniefile::parse_header(&mut File::open(output_path)?)?;
+    assert_eq!(header.track_page_nums.len(), 3);
+    assert_eq!(
+        count_chapters(&[audio_file], &ConvertOptions::default())?,
+        3
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_plan_conversion_with_cue_sheet() -> Result<()> {
+    // Stand-in for ffmpeg that only prints the input information
+    let temp_dir = tempdir()?;
+    let fake_ffmpeg = temp_dir.path().join("ffmpeg");
+    std::fs::write(
+        &fake_ffmpeg,
+        "#!/bin/sh\necho '  Duration: 00:03:00.50, start: 0.000000, bitrate: 128 kb/s' >&2\nexit 1\n",
+    )?;
+    std::fs::set_permissions(&fake_ffmpeg, std::fs::Permissions::from_mode(0o755))?;
+
+    let audio_file = temp_dir.path().join("Some Story.flac");
+    File::create(&audio_file)?;
+    std::fs::write(temp_dir.path().join("Some Story.cue"), TEST_CUE_SHEET)?;
+
+    let plan = plan_conversion(
+        &audio_file,
+        &ConvertOptions {
+            ffmpeg: fake_ffmpeg.to_string_lossy().to_string(),
+            ..ConvertOptions::default()
+        },
+    )?;
+
+    assert_eq!(plan.input_files, vec![audio_file.clone()]);
+    assert_eq!(plan.chapters.len(), 3);
+    assert!(plan
+        .chapters
+        .iter()
+        .all(|chapter| chapter.input_file == audio_file));
+    assert_eq!(plan.chapters[1].start, 70.2);
+    assert_eq!(plan.chapters[2].start, 150.0);
+    assert_eq!(plan.chapters[2].duration, Some(30.5));
+    assert_eq!(plan.duration(), 180.5);
+    assert_eq!(plan.estimated_size(), estimate_tonie_size(180.5, 96));
+
+    // Playing twice as fast halves the positions and durations of the chapters
+    let fast_plan = plan_conversion(
+        &audio_file,
+        &ConvertOptions {
+            ffmpeg: fake_ffmpeg.to_string_lossy().to_string(),
+            speed: 2.0,
+            ..ConvertOptions::default()
+        },
+    )?;
+    assert_eq!(fast_plan.chapters[2].start, 75.0);
+    assert_eq!(fast_plan.chapters[2].duration, Some(15.25));
+    assert_eq!(fast_plan.duration(), 90.25);
+
+    Ok(())
+}
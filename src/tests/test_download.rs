@@ -0,0 +1,72 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+use audio2tonie::download::{download_url_inputs, is_url_input, DownloadDir};
+
+#[test]
+fn test_is_url_input() {
+    assert!(is_url_input(Path::new(
+        "https://www.youtube.com/watch?v=abc"
+    )));
+    assert!(is_url_input(Path::new("HTTP://example.com/song")));
+    assert!(!is_url_input(Path::new("songs/https.mp3")));
+    assert!(!is_url_input(Path::new("-")));
+}
+
+#[test]
+fn test_download_url_inputs_without_downloader() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let download_dir = DownloadDir::new(temp_dir.path());
+
+    // Local inputs are kept as they are
+    let inputs = vec![PathBuf::from("intro.mp3")];
+    assert_eq!(
+        download_url_inputs(inputs.clone(), None, &download_dir, |_| {})?,
+        inputs
+    );
+    let inputs = vec![PathBuf::from("https://example.com/song")];
+    assert!(download_url_inputs(inputs, None, &download_dir, |_| {}).is_err());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_download_url_inputs() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempdir()?;
+    // Prints the output template of yt-dlp, which is the third to last argument, as downloaded file
+    let downloader = temp_dir.path().join("yt-dlp");
+    std::fs::write(
+        &downloader,
+        "#!/bin/sh\nwhile [ $# -gt 3 ]; do shift; done\necho \"$1\"\n",
+    )?;
+    std::fs::set_permissions(&downloader, std::fs::Permissions::from_mode(0o755))?;
+
+    let download_dir = DownloadDir::new(temp_dir.path());
+    let mut downloaded_urls = vec![];
+    let inputs = vec![
+        PathBuf::from("intro.mp3"),
+        PathBuf::from("https://example.com/song"),
+    ];
+    let inputs = download_url_inputs(inputs, downloader.to_str(), &download_dir, |url| {
+        downloaded_urls.push(url.to_string())
+    })?;
+
+    assert_eq!(downloaded_urls, ["https://example.com/song"]);
+    assert_eq!(
+        inputs,
+        [
+            PathBuf::from("intro.mp3"),
+            download_dir
+                .path()
+                .join("002/%(autonumber)03d/%(title)s.%(ext)s")
+        ]
+    );
+
+    let path = download_dir.path().to_path_buf();
+    drop(download_dir);
+    assert!(!path.exists());
+    Ok(())
+}
@@ -1,8 +1,8 @@
 use std::{
     ffi::OsStr,
     fs::File,
-    io::Read,
-    os::unix::fs::MetadataExt,
+    io::{Cursor, Read},
+    os::unix::fs::{MetadataExt, PermissionsExt},
     path::{Path, PathBuf},
 };
 
@@ -10,7 +10,16 @@ use anyhow::{Context, Ok, Result};
 use glob::glob;
 use tempfile::Builder;
 
-use crate::extract::extract_tonie_to_opus;
+use crate::cli::parse_chapter_range;
+
+use audio2tonie::limits::Limits;
+use audio2tonie::Audio2TonieError;
+
+use audio2tonie::extract::{
+    chapter_file_name, extract_tonie_from_reader, extract_tonie_to_opus, extract_tonie_to_writer,
+    ExtractOptions, OutputFormat,
+};
+use audio2tonie::taf::{audio_offset, opus_comments, OggPageIterator};
 
 const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
 const TEST_TONIE_FILE: &str = "resources/test/test_1.taf";
@@ -30,7 +39,7 @@ fn test_extract_tonie_to_opus_without_output_path() -> Result<()> {
     let expected_output_path =
         PathBuf::from(".").join(test_tonie_path.with_extension("ogg").file_name().unwrap());
 
-    extract_tonie_to_opus(&test_tonie_path, None)?;
+    extract_tonie_to_opus(&test_tonie_path, None, &ExtractOptions::default())?;
 
     let mut expected_output_file = File::open(&expected_output_path).with_context(|| {
         format!(
@@ -68,7 +77,11 @@ fn test_extract_tonie_to_opus_with_output_path() -> Result<()> {
         std::fs::remove_file(&expected_output_path)?;
     }
 
-    extract_tonie_to_opus(&test_tonie_path, Some(output_path.clone()))?;
+    extract_tonie_to_opus(
+        &test_tonie_path,
+        Some(output_path.clone()),
+        &ExtractOptions::default(),
+    )?;
 
     let expected_output_file = File::open(&expected_output_path).with_context(|| {
         format!(
@@ -97,6 +110,7 @@ fn test_extract_tonie_to_opus_with_output_file_name() -> Result<()> {
     extract_tonie_to_opus(
         &test_tonie_path,
         Some(expected_output_file.path().to_path_buf()),
+        &ExtractOptions::default(),
     )?;
 
     assert!(expected_output_file.as_file().metadata()?.size() > 0);
@@ -114,6 +128,7 @@ fn test_extract_tonie_to_opus_with_multiple_chapters() -> Result<()> {
     extract_tonie_to_opus(
         &test_tonie_path,
         Some(expected_output_dir.path().to_path_buf()),
+        &ExtractOptions::default(),
     )?;
 
     let glob_path = expected_output_dir.path().join("*.ogg");
@@ -134,3 +149,439 @@ fn test_extract_tonie_to_opus_with_multiple_chapters() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_extract_tonie_to_opus_with_chapter_selection() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS);
+    let all_dir = Builder::new().prefix("tonie_test_dir").tempdir()?;
+    let all_chapters = extract_tonie_to_opus(
+        &test_tonie_path,
+        Some(all_dir.path().to_path_buf()),
+        &ExtractOptions::default(),
+    )?;
+    let selected_dir = Builder::new().prefix("tonie_test_dir").tempdir()?;
+    let options = ExtractOptions {
+        chapters: Some(vec![0, 2]),
+        ..ExtractOptions::default()
+    };
+
+    let selected_chapters = extract_tonie_to_opus(
+        &test_tonie_path,
+        Some(selected_dir.path().to_path_buf()),
+        &options,
+    )?;
+    assert_eq!(selected_chapters.len(), 2);
+    assert_eq!(std::fs::read_dir(selected_dir.path())?.count(), 2);
+    for (selected, chapter) in selected_chapters.iter().zip([0, 2]) {
+        assert_eq!(
+            std::fs::read(selected)?,
+            std::fs::read(&all_chapters[chapter])?
+        );
+    }
+
+    let options = ExtractOptions {
+        chapters: Some(vec![3]),
+        ..ExtractOptions::default()
+    };
+    let error = extract_tonie_to_opus(
+        &test_tonie_path,
+        Some(selected_dir.path().to_path_buf()),
+        &options,
+    )
+    .unwrap_err();
+    assert!(
+        error.to_string().contains("Chapter 4 does not exist"),
+        "{}",
+        error
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_tonie_to_opus_with_chapter_titles() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS);
+    let output_dir = Builder::new().prefix("tonie_test_dir").tempdir()?;
+    let options = ExtractOptions {
+        chapter_titles: Some(vec![
+            String::from("Intro"),
+            String::from("Story"),
+            String::from("Song"),
+        ]),
+        ..ExtractOptions::default()
+    };
+
+    let chapters = extract_tonie_to_opus(
+        &test_tonie_path,
+        Some(output_dir.path().to_path_buf()),
+        &options,
+    )?;
+    assert_eq!(chapters[1].file_name(), Some(OsStr::new("02 - Story.ogg")));
+
+    // Titles are only used if every chapter has one
+    let options = ExtractOptions {
+        chapter_titles: Some(vec![String::from("Intro")]),
+        ..ExtractOptions::default()
+    };
+    let chapters = extract_tonie_to_opus(
+        &test_tonie_path,
+        Some(output_dir.path().to_path_buf()),
+        &options,
+    )?;
+    assert!(!chapters[1].to_string_lossy().contains("Intro"));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_chapter_range() {
+    assert_eq!(parse_chapter_range("2").unwrap(), 2..=2);
+    assert_eq!(parse_chapter_range("5-7").unwrap(), 5..=7);
+    assert_eq!(parse_chapter_range(" 5 - 7 ").unwrap(), 5..=7);
+    assert!(parse_chapter_range("0").is_err());
+    assert!(parse_chapter_range("7-5").is_err());
+    assert!(parse_chapter_range("a-b").is_err());
+}
+
+#[test]
+fn test_extract_tonie_to_opus_with_merged_chapters() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS);
+    let output_dir = Builder::new().prefix("tonie_test_dir").tempdir()?;
+    let options = ExtractOptions {
+        merge_chapters: true,
+        ..ExtractOptions::default()
+    };
+
+    let file_paths = extract_tonie_to_opus(
+        &test_tonie_path,
+        Some(output_dir.path().to_path_buf()),
+        &options,
+    )?;
+    assert_eq!(
+        file_paths,
+        vec![output_dir.path().join("multiple_chapters.ogg")]
+    );
+
+    let ogg_data = std::fs::read(&file_paths[0])?;
+    let comments = opus_comments(&ogg_data);
+    let chapter_marks = comments
+        .iter()
+        .filter(|comment| comment.starts_with("CHAPTER") && !comment.contains("NAME="))
+        .collect::<Vec<_>>();
+    assert_eq!(chapter_marks.len(), 3);
+    assert_eq!(chapter_marks[0], "CHAPTER001=00:00:00.000");
+    assert!(chapter_marks[1].starts_with("CHAPTER002=00:03:28."));
+    assert!(chapter_marks[2].starts_with("CHAPTER003=00:06:1"));
+
+    // Only the OpusTags page is replaced, the audio pages are copied as they are
+    let tonie_data = std::fs::read(&test_tonie_path)?;
+    let audio_start = audio_offset(&tonie_data).unwrap() + 4096;
+    assert!(ogg_data.ends_with(&tonie_data[audio_start..]));
+    assert!(OggPageIterator::new(&ogg_data)
+        .all(|page| page.is_ok_and(|(_, page)| page.is_checksum_valid())));
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_tonie_to_opus_with_exceeded_limits() -> Result<()> {
+    // Test the "extract" command with resource limits that are smaller than the input file.
+    // Expect to fail before extracting anything.
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE);
+    let output_dir = Builder::new().prefix("tonie_test_dir").tempdir()?;
+
+    let size_limits = Limits {
+        max_total_bytes: 1024,
+        ..Limits::default()
+    };
+    let header_limits = Limits {
+        max_header_size: 16,
+        ..Limits::default()
+    };
+    let page_limits = Limits {
+        max_pages: 1,
+        ..Limits::default()
+    };
+
+    for limits in [size_limits, header_limits, page_limits] {
+        let options = ExtractOptions {
+            limits,
+            ..ExtractOptions::default()
+        };
+        let result = extract_tonie_to_opus(
+            &test_tonie_path,
+            Some(output_dir.path().to_path_buf()),
+            &options,
+        );
+        assert!(result.is_err());
+    }
+
+    assert_eq!(std::fs::read_dir(output_dir.path())?.count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_tonie_to_wav_with_multiple_chapters() -> Result<()> {
+    // Stand-in for ffmpeg that writes the raw PCM from stdin to the output file given as last argument
+    let temp_dir = Builder::new().prefix("tonie_test_dir").tempdir()?;
+    let fake_ffmpeg = temp_dir.path().join("ffmpeg");
+    std::fs::write(
+        &fake_ffmpeg,
+        "#!/bin/sh\nfor last; do :; done\ncat > \"$last\"\n",
+    )?;
+    std::fs::set_permissions(&fake_ffmpeg, std::fs::Permissions::from_mode(0o755))?;
+
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS);
+    let output_dir = temp_dir.path().join("output");
+    std::fs::create_dir(&output_dir)?;
+
+    extract_tonie_to_opus(
+        &test_tonie_path,
+        Some(output_dir.clone()),
+        &ExtractOptions {
+            format: OutputFormat::Wav,
+            ffmpeg: fake_ffmpeg.to_string_lossy().to_string(),
+            ..ExtractOptions::default()
+        },
+    )?;
+
+    for chapter in 0..3 {
+        let chapter_path = output_dir.join(format!("{}_multiple_chapters.wav", chapter));
+        let pcm_size = chapter_path.metadata()?.size();
+        // Stereo 16 bit samples
+        assert!(pcm_size > 0);
+        assert_eq!(pcm_size % 4, 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_chapter_file_name() {
+    let output_path = Path::new("out").join("story.ogg");
+    let options = ExtractOptions::default();
+
+    assert_eq!(
+        chapter_file_name(&output_path, 1, None, &options),
+        "1_story.ogg"
+    );
+    assert_eq!(
+        chapter_file_name(&output_path, 1, Some("Part 1/2"), &options),
+        "02 - Part 1_2.ogg"
+    );
+
+    let options = ExtractOptions {
+        name_template: Some(String::from("{name} {index} {title}")),
+        ..ExtractOptions::default()
+    };
+    assert_eq!(
+        chapter_file_name(&output_path, 0, None, &options),
+        "story 0 story.ogg"
+    );
+}
+
+#[test]
+fn test_opus_comments() -> Result<()> {
+    let tonie_data = std::fs::read(Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS))?;
+    let audio_data = &tonie_data[audio_offset(&tonie_data).unwrap()..];
+
+    let comments = opus_comments(audio_data);
+
+    assert!(comments.contains(&String::from("test_1.mp3")));
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_tonie_to_opus_with_sidecar_titles() -> Result<()> {
+    let temp_dir = Builder::new().prefix("tonie_test_dir").tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    std::fs::copy(
+        Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS),
+        &tonie_path,
+    )?;
+    std::fs::write(
+        temp_dir.path().join("500304E0.json"),
+        r#"{"output": "500304E0", "inputs": ["in/Intro.mp3", "in/The Story.mp3", "in/Outro.mp3"], "chapters": 3}"#,
+    )?;
+    let output_dir = temp_dir.path().join("output");
+    std::fs::create_dir(&output_dir)?;
+
+    extract_tonie_to_opus(
+        &tonie_path,
+        Some(output_dir.clone()),
+        &ExtractOptions::default(),
+    )?;
+
+    for file_name in ["01 - Intro.ogg", "02 - The Story.ogg", "03 - Outro.ogg"] {
+        assert!(
+            output_dir.join(file_name).exists(),
+            "{} is missing",
+            file_name
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_tonie_to_opus_verifies_sha1_hash() -> Result<()> {
+    let temp_dir = Builder::new().prefix("tonie_test_dir").tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    let mut tonie_data =
+        std::fs::read(Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS))?;
+    // Flip a byte in the first page of the second audio block
+    tonie_data[0x2100] ^= 0xFF;
+    std::fs::write(&tonie_path, &tonie_data)?;
+    let output_dir = temp_dir.path().join("output");
+    std::fs::create_dir(&output_dir)?;
+
+    let error = extract_tonie_to_opus(
+        &tonie_path,
+        Some(output_dir.clone()),
+        &ExtractOptions::default(),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        error,
+        Audio2TonieError::HashMismatch(Some(0x2000))
+    ));
+    assert!(error.to_string().contains("offset 0x2000"), "{}", error);
+    assert_eq!(std::fs::read_dir(&output_dir)?.count(), 0);
+
+    let file_paths = extract_tonie_to_opus(
+        &tonie_path,
+        Some(output_dir.clone()),
+        &ExtractOptions {
+            verify: false,
+            ..ExtractOptions::default()
+        },
+    )?;
+    assert_eq!(file_paths.len(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_tonie_to_opus_with_truncated_header() -> Result<()> {
+    let temp_dir = Builder::new().prefix("tonie_test_dir").tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    let tonie_data = std::fs::read(Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS))?;
+    std::fs::write(&tonie_path, &tonie_data[..1024])?;
+
+    let error = extract_tonie_to_opus(
+        &tonie_path,
+        Some(temp_dir.path().to_path_buf()),
+        &ExtractOptions::default(),
+    )
+    .unwrap_err();
+    assert!(
+        matches!(error, Audio2TonieError::HeaderCorrupt(_)),
+        "{:?}",
+        error
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_tonie_to_opus_ignoring_destroyed_header() -> Result<()> {
+    let temp_dir = Builder::new().prefix("tonie_test_dir").tempdir()?;
+    let tonie_data = std::fs::read(Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE))?;
+    let audio_data = tonie_data[audio_offset(&tonie_data).unwrap()..].to_vec();
+
+    // Overwrite the header with garbage and corrupt a page in the middle of the audio data
+    let mut broken_data = vec![0xA5u8; 1000];
+    broken_data.extend_from_slice(&audio_data);
+    broken_data[1000 + 0x3100] ^= 0xFF;
+    let tonie_path = temp_dir.path().join("500304E0");
+    std::fs::write(&tonie_path, &broken_data)?;
+
+    let options = ExtractOptions::default();
+    assert!(matches!(
+        extract_tonie_to_opus(&tonie_path, Some(temp_dir.path().to_path_buf()), &options),
+        Err(Audio2TonieError::HeaderCorrupt(_)) | Err(Audio2TonieError::LimitExceeded(_))
+    ));
+
+    let options = ExtractOptions {
+        ignore_header: true,
+        ..ExtractOptions::default()
+    };
+    let output_path = temp_dir.path().join("500304E0.ogg");
+    let file_paths =
+        extract_tonie_to_opus(&tonie_path, Some(temp_dir.path().to_path_buf()), &options)?;
+
+    assert_eq!(file_paths, vec![output_path.clone()]);
+    // Only the corrupt page in the third block is missing
+    let recovered_data = std::fs::read(&output_path)?;
+    let mut expected_data = audio_data[..0x3000].to_vec();
+    expected_data.extend_from_slice(&audio_data[0x4000..]);
+    assert_eq!(recovered_data, expected_data);
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_tonie_from_reader() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS);
+    let tonie_data = std::fs::read(&test_tonie_path)?;
+    let file_dir = Builder::new().tempdir()?;
+    let reader_dir = Builder::new().tempdir()?;
+
+    let file_paths = extract_tonie_to_opus(
+        &test_tonie_path,
+        Some(file_dir.path().to_path_buf()),
+        &ExtractOptions::default(),
+    )?;
+    let reader_paths = extract_tonie_from_reader(
+        Cursor::new(tonie_data),
+        &reader_dir.path().join("multiple_chapters.ogg"),
+        &ExtractOptions::default(),
+    )?;
+
+    // Without a sidecar file the chapters are named the same way
+    assert!(reader_paths.len() > 1);
+    assert_eq!(reader_paths.len(), file_paths.len());
+    for (file_path, reader_path) in file_paths.iter().zip(&reader_paths) {
+        assert_eq!(file_path.file_name(), reader_path.file_name());
+        assert_eq!(std::fs::read(file_path)?, std::fs::read(reader_path)?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_tonie_to_writer() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS);
+    let output_dir = Builder::new().tempdir()?;
+    let merged_options = ExtractOptions {
+        merge_chapters: true,
+        ..Default::default()
+    };
+    let merged_paths = extract_tonie_to_opus(
+        &test_tonie_path,
+        Some(output_dir.path().to_path_buf()),
+        &merged_options,
+    )?;
+
+    // The stream contains all chapters with chapter marks like a merged extraction
+    let mut stream = vec![];
+    extract_tonie_to_writer(
+        File::open(&test_tonie_path)?,
+        &mut stream,
+        &ExtractOptions::default(),
+    )?;
+    assert_eq!(stream, std::fs::read(&merged_paths[0])?);
+
+    let chapter_options = ExtractOptions {
+        chapters: Some(vec![0]),
+        ..Default::default()
+    };
+    assert!(
+        extract_tonie_to_writer(File::open(&test_tonie_path)?, &mut vec![], &chapter_options)
+            .is_err()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use audio2tonie::fade::Fader;
+use std::time::Duration;
+
+fn fade(track: &[i16], chunk_size: usize, fade_in: u64, fade_out: u64) -> Vec<i16> {
+    let mut fader = Fader::new(
+        2,
+        Duration::from_millis(fade_in),
+        Duration::from_millis(fade_out),
+    );
+    let mut output = vec![];
+    for chunk in track.chunks(chunk_size) {
+        fader.process(chunk, |samples| output.extend_from_slice(samples));
+    }
+    fader.finish(|samples| output.extend_from_slice(samples));
+    return output;
+}
+
+#[test]
+fn test_fade_in_and_out() {
+    // One second of a constant signal, passed in chunks that do not align with the fades
+    let track = vec![10000i16; 48000 * 2];
+    let faded = fade(&track, 1000, 100, 200);
+
+    assert_eq!(faded.len(), track.len());
+    // The fade-in rises from silence over 4800 frames
+    assert_eq!(faded[0], 0);
+    assert_eq!(faded[2400 * 2], 5000);
+    assert_eq!(faded[2400 * 2 + 1], 5000);
+    assert_eq!(faded[4800 * 2], 10000);
+    // The middle is untouched
+    assert!(faded[4800 * 2..(48000 - 9600) * 2]
+        .iter()
+        .all(|sample| *sample == 10000));
+    // The fade-out falls to silence over the last 9600 frames
+    assert!((faded[(48000 - 4800) * 2] - 5000).abs() <= 1);
+    assert_eq!(faded[faded.len() - 1], 0);
+}
+
+#[test]
+fn test_fade_without_fades() {
+    let track = vec![1234i16; 4800 * 2];
+    assert_eq!(fade(&track, 4096, 0, 0), track);
+}
+
+#[test]
+fn test_fade_out_longer_than_track() {
+    let track = vec![10000i16; 480 * 2];
+
+    let faded = fade(&track, 4096, 0, 1000);
+
+    // The whole track fades out over its remaining length without jumping
+    assert_eq!(faded.len(), track.len());
+    assert!(faded[0] <= 10000 && faded[0] > 0);
+    assert_eq!(faded[faded.len() - 1], 0);
+    assert!(faded.windows(2).all(|pair| pair[0] >= pair[1]));
+}
@@ -0,0 +1,34 @@
+use anyhow::Result;
+use sha1::{Digest, Sha1};
+use std::io::Write;
+
+use audio2tonie::hashing::HashingWriter;
+
+// Accepts at most three bytes per write, like a writer interrupted by a signal
+struct ShortWriter(Vec<u8>);
+
+impl Write for ShortWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let bytes = buf.len().min(3);
+        self.0.extend_from_slice(&buf[..bytes]);
+        return Ok(bytes);
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return Ok(());
+    }
+}
+
+#[test]
+fn test_hashing_writer() -> Result<()> {
+    let mut writer = HashingWriter::new(ShortWriter(vec![]));
+    writer.write_all(b"OggS page")?;
+    writer.write_all(b" and more")?;
+    assert_eq!(writer.bytes_written(), 18);
+
+    let (inner, sha1_hash, bytes_written) = writer.finish();
+    assert_eq!(inner.0, b"OggS page and more");
+    assert_eq!(sha1_hash, Sha1::digest(b"OggS page and more").to_vec());
+    assert_eq!(bytes_written, 18);
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use std::path::Path;
+
+use audio2tonie::convert::partial_file_path;
+use audio2tonie::header::{
+    apply_header_patch, header_json, read_tonie_header, retimestamp_tonie_file,
+};
+use audio2tonie::taf::{decode_header, encode_header, OggPageIterator, TONIEFILE_HEADER_SIZE};
+use audio2tonie::Limits;
+use serde_json::json;
+use tempfile::tempdir;
+
+use crate::check::check_tonie_file;
+
+const TEST_TONIE_FILE_WITH_CHAPTERS: &str = "resources/test/multiple_chapters.taf";
+
+#[test]
+fn test_decode_header() {
+    let sha1_hash = [0xABu8; 20];
+    let encoded_header = encode_header(&sha1_hash, 12345, 0x12345678, &[0, 3, 7]).unwrap();
+    assert_eq!(encoded_header.len(), TONIEFILE_HEADER_SIZE);
+
+    let header = decode_header(&encoded_header).unwrap();
+    assert_eq!(header.sha1_hash, sha1_hash);
+    assert_eq!(header.num_bytes, 12345);
+    assert_eq!(header.audio_id, 0x12345678);
+    assert_eq!(header.track_page_nums, vec![0, 3, 7]);
+    assert!(header.fill_size > 0);
+
+    assert!(decode_header(&encoded_header[..20]).is_none());
+}
+
+#[test]
+fn test_header_dump() -> anyhow::Result<()> {
+    let header = read_tonie_header(Path::new(TEST_TONIE_FILE_WITH_CHAPTERS), &Limits::default())?;
+    let dump = header_json(&header);
+
+    assert_eq!(dump["sha1_hash"].as_str().unwrap().len(), 40);
+    assert_eq!(dump["track_page_nums"].as_array().unwrap().len(), 3);
+    assert_eq!(dump["track_page_nums"][0], 0);
+    assert!(dump["num_bytes"].as_u64().unwrap() > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_header_apply() -> anyhow::Result<()> {
+    let output_dir = tempdir()?;
+    let tonie_file_path = output_dir.path().join("test.taf");
+    std::fs::copy(TEST_TONIE_FILE_WITH_CHAPTERS, &tonie_file_path)?;
+    let original = std::fs::read(&tonie_file_path)?;
+    let original_header = read_tonie_header(&tonie_file_path, &Limits::default())?;
+
+    let header = apply_header_patch(
+        &tonie_file_path,
+        &json!({ "audio_id": 42, "track_page_nums": [0, 1] }),
+        &Limits::default(),
+    )?;
+    assert_eq!(header.audio_id, 42);
+    assert_eq!(header.track_page_nums, vec![0, 1]);
+    assert_eq!(header.sha1_hash, original_header.sha1_hash);
+    assert_eq!(header.num_bytes, original_header.num_bytes);
+
+    let patched = std::fs::read(&tonie_file_path)?;
+    assert_eq!(patched.len(), original.len());
+    assert_eq!(
+        patched[TONIEFILE_HEADER_SIZE..],
+        original[TONIEFILE_HEADER_SIZE..]
+    );
+    assert_eq!(
+        read_tonie_header(&tonie_file_path, &Limits::default())?,
+        header
+    );
+
+    let limits = Limits::default();
+    assert!(apply_header_patch(&tonie_file_path, &json!({ "title": "x" }), &limits).is_err());
+    assert!(apply_header_patch(&tonie_file_path, &json!({ "sha1_hash": "abc" }), &limits).is_err());
+    assert!(apply_header_patch(&tonie_file_path, &json!({ "audio_id": -1 }), &limits).is_err());
+    assert!(apply_header_patch(&tonie_file_path, &json!([1, 2]), &limits).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_retimestamp_tonie_file() -> anyhow::Result<()> {
+    let output_dir = tempdir()?;
+    let tonie_file_path = output_dir.path().join("test.taf");
+    std::fs::copy(TEST_TONIE_FILE_WITH_CHAPTERS, &tonie_file_path)?;
+    let original_size = std::fs::metadata(&tonie_file_path)?.len();
+    let original_header = read_tonie_header(&tonie_file_path, &Limits::default())?;
+
+    let header = retimestamp_tonie_file(&tonie_file_path, 0x1000, &Limits::default())?;
+    assert_eq!(header.audio_id, 0x1000);
+    assert_eq!(header.num_bytes, original_header.num_bytes);
+    assert_eq!(header.track_page_nums, original_header.track_page_nums);
+    assert_ne!(header.sha1_hash, original_header.sha1_hash);
+    assert_eq!(std::fs::metadata(&tonie_file_path)?.len(), original_size);
+
+    let tonie_data = std::fs::read(&tonie_file_path)?;
+    for page in OggPageIterator::new(&tonie_data[TONIEFILE_HEADER_SIZE..]) {
+        let (_, page) = page?;
+        assert_eq!(page.serial_number, 0x1000);
+    }
+    let results = check_tonie_file(&tonie_file_path, &Limits::default())?;
+    assert!(results.iter().all(|result| result.is_ok()));
+    assert!(!partial_file_path(&tonie_file_path).exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_retimestamp_truncated_tonie_file() -> anyhow::Result<()> {
+    let output_dir = tempdir()?;
+    let tonie_file_path = output_dir.path().join("test.taf");
+    let mut tonie_data = std::fs::read(TEST_TONIE_FILE_WITH_CHAPTERS)?;
+    tonie_data.truncate(tonie_data.len() - 100);
+    std::fs::write(&tonie_file_path, &tonie_data)?;
+
+    // The last page is incomplete, so the file is left as it was
+    assert!(retimestamp_tonie_file(&tonie_file_path, 0x1000, &Limits::default()).is_err());
+    assert_eq!(std::fs::read(&tonie_file_path)?, tonie_data);
+    assert!(!partial_file_path(&tonie_file_path).exists());
+
+    Ok(())
+}
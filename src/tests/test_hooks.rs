@@ -0,0 +1,227 @@
+use anyhow::{anyhow, Result};
+use audio2tonie::hooks::{
+    run_post_processors, ConversionMetadata, CoverArt, CoverSource, PostProcessor, ShellCommand,
+    SidecarFile, TeddyCloudCustomJson, TeddyCloudUpload,
+};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::rc::Rc;
+use tempfile::TempDir;
+
+use crate::tests::{create_test_tonie_file, sine_samples};
+
+struct RecordingHook {
+    calls: Rc<RefCell<Vec<PathBuf>>>,
+}
+
+impl PostProcessor for RecordingHook {
+    fn name(&self) -> &str {
+        return "recording";
+    }
+
+    fn process(&self, metadata: &ConversionMetadata) -> Result<()> {
+        self.calls.borrow_mut().push(metadata.output_path.clone());
+        return Ok(());
+    }
+}
+
+struct FailingHook;
+
+impl PostProcessor for FailingHook {
+    fn name(&self) -> &str {
+        return "failing";
+    }
+
+    fn process(&self, _metadata: &ConversionMetadata) -> Result<()> {
+        return Err(anyhow!("Something went wrong"));
+    }
+}
+
+fn metadata(output_path: PathBuf) -> ConversionMetadata {
+    return ConversionMetadata {
+        output_path,
+        input_files: vec![PathBuf::from("01 \"Intro\".mp3"), PathBuf::from("02.mp3")],
+        chapters: 2,
+    };
+}
+
+#[test]
+fn test_run_post_processors_in_order() -> Result<()> {
+    let calls = Rc::new(RefCell::new(vec![]));
+    let post_processors: Vec<Box<dyn PostProcessor>> = vec![
+        Box::new(RecordingHook {
+            calls: calls.clone(),
+        }),
+        Box::new(FailingHook),
+        Box::new(RecordingHook {
+            calls: calls.clone(),
+        }),
+    ];
+
+    let error =
+        run_post_processors(&post_processors, &metadata(PathBuf::from("500304E0"))).unwrap_err();
+
+    assert!(error.to_string().contains("'failing'"));
+    assert_eq!(*calls.borrow(), vec![PathBuf::from("500304E0")]);
+    return Ok(());
+}
+
+#[test]
+fn test_sidecar_file() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let output_path = temp_dir.path().join("500304E0");
+
+    SidecarFile.process(&metadata(output_path.clone()))?;
+
+    let json = std::fs::read_to_string(temp_dir.path().join("500304E0.json"))?;
+    let sidecar = serde_json::from_str::<Value>(&json)?;
+    assert_eq!(sidecar["output"], output_path.display().to_string());
+    assert_eq!(sidecar["inputs"], json!(["01 \"Intro\".mp3", "02.mp3"]));
+    assert_eq!(sidecar["chapters"], 2);
+    return Ok(());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_teddycloud_custom_json() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let album_path = temp_dir.path().join("Gruffalo");
+    std::fs::create_dir(&album_path)?;
+    std::fs::write(album_path.join("cover.jpg"), b"")?;
+    let output_path = temp_dir.path().join("500304E0");
+    create_test_tonie_file(&output_path, &[sine_samples(440.0, -20.0, 1.0)])?;
+    let metadata = ConversionMetadata {
+        output_path,
+        input_files: vec![
+            album_path.join("01 Intro.mp3"),
+            album_path.join("02 Forest.mp3"),
+        ],
+        chapters: 2,
+    };
+
+    // Without tags the names of the input directory and files are used
+    let json_path = temp_dir.path().join("tonies.custom.json");
+    let custom_json = TeddyCloudCustomJson::new(&json_path, "/nonexistent/ffmpeg");
+    custom_json.process(&metadata)?;
+    custom_json.process(&metadata)?;
+
+    let entries: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json_path)?)?;
+    assert_eq!(entries.as_array().map(Vec::len), Some(2));
+    let entry = &entries[1];
+    assert_eq!(entry["no"], "1");
+    assert_eq!(entry["audio_id"][0], 0x12345678.to_string());
+    assert_eq!(entry["hash"][0].as_str().map(str::len), Some(40));
+    assert_eq!(entry["title"], "Gruffalo");
+    assert_eq!(entry["episodes"], "Gruffalo");
+    assert_eq!(
+        entry["tracks"],
+        serde_json::json!(["01 Intro", "02 Forest"])
+    );
+    assert_eq!(
+        entry["pic"],
+        album_path.join("cover.jpg").to_string_lossy().as_ref()
+    );
+    assert_eq!(entry["category"], "custom");
+
+    // The cover written next to the Tonie file takes precedence
+    let cover_path = CoverArt::path(&metadata.output_path);
+    std::fs::write(&cover_path, b"")?;
+    custom_json.process(&metadata)?;
+    let entries: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json_path)?)?;
+    assert_eq!(entries[2]["pic"], cover_path.to_string_lossy().as_ref());
+    return Ok(());
+}
+
+#[test]
+fn test_cover_art() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let album_path = temp_dir.path().join("Gruffalo");
+    std::fs::create_dir(&album_path)?;
+    std::fs::write(album_path.join("folder.jpg"), b"")?;
+    let output_path = temp_dir.path().join("500304E0");
+    let metadata = ConversionMetadata {
+        output_path: output_path.clone(),
+        input_files: vec![album_path.join("01 Intro.mp3")],
+        chapters: 1,
+    };
+    assert_eq!(
+        CoverArt::path(&output_path),
+        temp_dir.path().join("500304E0.jpg")
+    );
+
+    // Detected covers are optional, a given cover has to be written
+    CoverArt::new(CoverSource::Auto, "/nonexistent/ffmpeg").process(&metadata)?;
+    assert!(!CoverArt::path(&output_path).exists());
+    let cover = CoverSource::File(album_path.join("folder.jpg"));
+    assert!(CoverArt::new(cover, "/nonexistent/ffmpeg")
+        .process(&metadata)
+        .is_err());
+    return Ok(());
+}
+
+#[test]
+fn test_shell_command() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let log_path = temp_dir.path().join("hook.log");
+    let command = format!(
+        "echo \"$AUDIO2TONIE_OUTPUT $AUDIO2TONIE_CHAPTERS\" > '{}'",
+        log_path.display()
+    );
+
+    ShellCommand::new(&command).process(&metadata(PathBuf::from("500304E0")))?;
+    assert_eq!(std::fs::read_to_string(log_path)?, "500304E0 2\n");
+
+    assert!(ShellCommand::new("exit 3")
+        .process(&metadata(PathBuf::from("500304E0")))
+        .is_err());
+    return Ok(());
+}
+
+#[test]
+fn test_teddycloud_upload() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let output_path = temp_dir.path().join("500304E0");
+    std::fs::write(&output_path, b"tonie")?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    let server = std::thread::spawn(move || -> Result<(Vec<String>, String)> {
+        let (stream, _) = listener.accept()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut headers = vec![];
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            if line.trim().is_empty() {
+                break;
+            }
+            headers.push(line.trim().to_string());
+        }
+        let content_length = headers
+            .iter()
+            .find_map(|header| header.strip_prefix("Content-Length: "))
+            .unwrap_or("0")
+            .parse::<usize>()?;
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        (&stream).write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")?;
+        return Ok((headers, String::from_utf8_lossy(&body).to_string()));
+    });
+
+    TeddyCloudUpload::new(&url, "audiobooks")
+        .with_token(Some(String::from("secret")))
+        .process(&metadata(output_path))?;
+
+    let (headers, body) = server.join().unwrap()?;
+    assert_eq!(
+        headers[0],
+        "POST /api/fileUpload?path=audiobooks&special=library HTTP/1.1"
+    );
+    assert!(headers.contains(&String::from("Authorization: Bearer secret")));
+    assert!(body.contains("filename=\"500304E0\""));
+    assert!(body.contains("tonie"));
+    return Ok(());
+}
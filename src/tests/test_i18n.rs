@@ -0,0 +1,17 @@
+use crate::i18n::{Language, Message};
+
+#[test]
+fn test_language_from_locale() {
+    assert_eq!(Language::from_locale("de_DE.UTF-8"), Language::De);
+    assert_eq!(Language::from_locale("fr_CH"), Language::Fr);
+    assert_eq!(Language::from_locale("en_US.UTF-8"), Language::En);
+    assert_eq!(Language::from_locale("C"), Language::En);
+    assert_eq!(Language::from_locale(""), Language::En);
+}
+
+#[test]
+fn test_translate() {
+    assert_eq!(Language::En.translate(Message::Chapter), "Chapter");
+    assert_eq!(Language::De.translate(Message::Chapter), "Kapitel");
+    assert_eq!(Language::Fr.translate(Message::Chapter), "Chapitre");
+}
@@ -0,0 +1,85 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use tempfile::tempdir;
+
+use crate::info::{get_audio_info, get_header_info, info_json};
+use crate::tests::{create_test_tonie_file, sine_samples};
+
+#[test]
+fn test_get_header_and_audio_info() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    create_test_tonie_file(
+        &tonie_path,
+        &[
+            sine_samples(440.0, -20.0, 6.0),
+            sine_samples(440.0, -20.0, 3.0),
+        ],
+    )?;
+
+    let header_info = get_header_info(&tonie_path, &Limits::default())?;
+    assert_eq!(header_info.audio_id, 0x12345678);
+    assert_eq!(header_info.sha1_hash.len(), 20);
+    assert_eq!(header_info.track_page_nums.len(), 2);
+    assert_eq!(header_info.header_size + 4, 4096);
+    assert_eq!(
+        header_info.data_length,
+        std::fs::metadata(&tonie_path)?.len() - 4096
+    );
+
+    let audio_info = get_audio_info(&tonie_path, &header_info, &Limits::default())?;
+    assert_eq!(audio_info.chapter_durations.len(), 2);
+    // Chapters start at 4kb block boundaries, so a few packets may move to the next chapter
+    assert!((audio_info.chapter_durations[0] - 6.0).abs() < 0.5);
+    assert!((audio_info.chapter_durations[1] - 3.0).abs() < 0.5);
+    assert!((audio_info.total_duration() - 9.0).abs() < 0.2);
+
+    let chapter_starts = audio_info.chapter_starts();
+    assert_eq!(audio_info.chapter_start_granules.len(), 2);
+    assert_eq!(chapter_starts[0], 0.0);
+    assert!((chapter_starts[1] - audio_info.chapter_durations[0]).abs() < 1e-9);
+
+    // Every page of the audio data belongs to a chapter
+    assert_eq!(
+        audio_info.chapter_sizes.iter().sum::<u64>(),
+        header_info.data_length
+    );
+    assert!(audio_info
+        .chapter_page_counts
+        .iter()
+        .all(|count| *count > 0));
+    assert!(audio_info
+        .chapter_bitrates()
+        .iter()
+        .all(|bitrate| *bitrate > 0.0));
+
+    Ok(())
+}
+
+#[test]
+fn test_info_json() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    create_test_tonie_file(
+        &tonie_path,
+        &[
+            sine_samples(440.0, -20.0, 6.0),
+            sine_samples(440.0, -20.0, 3.0),
+        ],
+    )?;
+
+    let info = info_json(&tonie_path, &Limits::default())?;
+    let header_info = get_header_info(&tonie_path, &Limits::default())?;
+    assert_eq!(info["path"], tonie_path.to_string_lossy().as_ref());
+    assert_eq!(info["audio_id"], 0x12345678);
+    assert_eq!(info["sha1_hash"], header_info.sha1_hex());
+    assert_eq!(info["chapters"].as_array().map(Vec::len), Some(2));
+    assert_eq!(info["chapters"][1]["number"], 2);
+    assert_eq!(
+        info["chapters"][1]["start"],
+        info["chapters"][0]["duration"]
+    );
+    assert!((info["duration"].as_f64().unwrap_or_default() - 9.0).abs() < 0.2);
+
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use anyhow::Result;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use audio2tonie::input::InputFile;
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_TONIE_FILE: &str = "resources/test/test_1.taf";
+
+#[test]
+fn test_input_file() -> Result<()> {
+    let tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE);
+    let tonie_data = std::fs::read(&tonie_path)?;
+
+    let mut input_file = InputFile::open(&tonie_path)?;
+    assert_eq!(input_file.len()?, tonie_data.len() as u64);
+
+    // Reading and seeking behave like a file, with or without the mmap feature
+    let mut block = vec![0u8; 4096];
+    input_file.seek(SeekFrom::Start(0x1000))?;
+    input_file.read_exact(&mut block)?;
+    assert_eq!(block, &tonie_data[0x1000..0x2000]);
+    assert_eq!(input_file.stream_position()?, 0x2000);
+
+    assert_eq!(input_file.read_all()?.as_ref(), tonie_data.as_slice());
+    Ok(())
+}
+
+#[test]
+fn test_input_file_empty() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let empty_path = temp_dir.path().join("empty.taf");
+    std::fs::write(&empty_path, [])?;
+
+    let mut input_file = InputFile::open(&empty_path)?;
+    assert!(input_file.is_empty()?);
+    assert!(input_file.read_all()?.is_empty());
+    assert!(InputFile::open(temp_dir.path().join("missing.taf")).is_err());
+    Ok(())
+}
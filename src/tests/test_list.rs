@@ -0,0 +1,67 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+use crate::list::{find_tonie_files, list_json, list_tonie_files};
+use crate::tests::{create_test_tonie_file, sine_samples};
+
+#[test]
+fn test_find_tonie_files() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let content_dir = temp_dir.path().join("CONTENT").join("5634121E");
+    std::fs::create_dir_all(&content_dir)?;
+    std::fs::create_dir_all(temp_dir.path().join("library").join(".trash"))?;
+    for path in [
+        "CONTENT/5634121E/500304E0",
+        "CONTENT/5634121E/notes.txt",
+        "library/story.TAF",
+        "library/cover.jpg",
+        "library/.trash/old.taf",
+    ] {
+        std::fs::write(temp_dir.path().join(path), b"")?;
+    }
+
+    let tonie_files = find_tonie_files(temp_dir.path())?;
+
+    assert_eq!(
+        tonie_files,
+        vec![
+            content_dir.join("500304E0"),
+            temp_dir.path().join("library").join("story.TAF"),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_list_tonie_files() -> Result<()> {
+    let temp_dir = tempdir()?;
+    create_test_tonie_file(
+        &temp_dir.path().join("story.taf"),
+        &[
+            sine_samples(440.0, -20.0, 2.0),
+            sine_samples(440.0, -20.0, 3.0),
+        ],
+    )?;
+    std::fs::write(temp_dir.path().join("broken.taf"), b"not a Tonie file")?;
+
+    let entries = list_tonie_files(temp_dir.path(), &Limits::default())?;
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].path, PathBuf::from("broken.taf"));
+    assert!(!entries[0].is_valid());
+    assert_eq!(entries[0].audio_id, None);
+    assert_eq!(entries[1].path, PathBuf::from("story.taf"));
+    assert!(entries[1].is_valid());
+    assert_eq!(entries[1].audio_id, Some(0x12345678));
+    assert_eq!(entries[1].chapters, Some(2));
+    assert!((entries[1].duration.unwrap_or_default() - 5.0).abs() < 0.1);
+
+    let listing = list_json(&entries);
+    assert_eq!(listing[0]["valid"], false);
+    assert!(listing[0]["chapters"].is_null());
+    assert_eq!(listing[1]["chapters"], 2);
+    assert_eq!(listing[1]["errors"].as_array().map(Vec::len), Some(0));
+    Ok(())
+}
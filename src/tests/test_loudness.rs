@@ -0,0 +1,63 @@
+use audio2tonie::loudness::{apply_gain, gated_loudness, normalization_gain, LoudnessMeter};
+
+use crate::tests::sine_samples;
+
+#[test]
+fn test_loudness_of_sine() {
+    // A 1 kHz sine at -20 dBFS on both channels measures -20 LUFS
+    let mut meter = LoudnessMeter::new(2);
+    meter.add_samples(&sine_samples(1000.0, -20.0, 5.0));
+
+    assert!((meter.integrated_loudness() + 20.0).abs() < 0.2);
+    assert!((meter.true_peak() + 20.0).abs() < 0.2);
+    assert!((meter.duration() - 5.0).abs() < 0.001);
+}
+
+#[test]
+fn test_loudness_of_silence() {
+    let mut meter = LoudnessMeter::new(2);
+    meter.add_samples(&vec![0; 48000 * 2 * 2]);
+
+    assert_eq!(meter.integrated_loudness(), f64::NEG_INFINITY);
+    assert_eq!(meter.true_peak(), f64::NEG_INFINITY);
+}
+
+#[test]
+fn test_loudness_relative_gate() {
+    // Quiet passages more than 10 LU below the overall level are ignored
+    let mut meter = LoudnessMeter::new(2);
+    meter.add_samples(&sine_samples(1000.0, -20.0, 5.0));
+    meter.add_samples(&sine_samples(1000.0, -50.0, 20.0));
+
+    assert!((meter.integrated_loudness() + 20.0).abs() < 0.5);
+    assert_eq!(
+        gated_loudness(meter.block_powers()),
+        meter.integrated_loudness()
+    );
+}
+
+#[test]
+fn test_normalization_gain() {
+    assert_eq!(normalization_gain(-20.0, -10.0, -16.0, -1.0), 4.0);
+    // The gain is limited by the true peak ceiling
+    assert_eq!(normalization_gain(-20.0, -3.0, -16.0, -1.0), 2.0);
+    // Silence is not amplified
+    assert_eq!(
+        normalization_gain(f64::NEG_INFINITY, f64::NEG_INFINITY, -16.0, -1.0),
+        0.0
+    );
+}
+
+#[test]
+fn test_apply_gain() {
+    let mut samples = sine_samples(1000.0, -20.0, 5.0);
+    apply_gain(&mut samples, 6.0);
+
+    let mut meter = LoudnessMeter::new(2);
+    meter.add_samples(&samples);
+    assert!((meter.integrated_loudness() + 14.0).abs() < 0.2);
+
+    let mut clipped = vec![i16::MAX, i16::MIN, 0];
+    apply_gain(&mut clipped, 6.0);
+    assert_eq!(clipped, vec![i16::MAX, i16::MIN, 0]);
+}
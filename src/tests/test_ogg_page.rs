@@ -0,0 +1,245 @@
+use anyhow::Result;
+use std::{borrow::Cow, fs::File, path::Path};
+use toniefile::Toniefile;
+
+use audio2tonie::limits::Limits;
+use audio2tonie::ogg_page::{crc32, OggPage, OggPageError, PacketAssembler};
+use audio2tonie::taf::{
+    audio_offset, find_capture_pattern, OggPageIterator, OggPageReader, TONIEFILE_FRAME_SIZE,
+};
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_TONIE_FILE: &str = "resources/test/test_1.taf";
+
+#[test]
+fn test_ogg_page_parse_and_serialize_roundtrip() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE);
+    let audio_data = Toniefile::extract_audio(&mut File::open(test_tonie_path)?)?;
+
+    let mut offset = 0;
+    let mut page_count = 0;
+    while offset < audio_data.len() {
+        let (page, page_size) = OggPage::parse(&audio_data[offset..])?;
+
+        assert_eq!(page.size(), page_size);
+        assert!(page.is_checksum_valid());
+        assert_eq!(page.serialize(), audio_data[offset..offset + page_size]);
+
+        offset += page_size;
+        page_count += 1;
+    }
+
+    assert_eq!(offset, audio_data.len());
+    assert!(page_count > 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_ogg_page_parse_header_pages() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE);
+    let audio_data = Toniefile::extract_audio(&mut File::open(test_tonie_path)?)?;
+
+    let (opus_head, opus_head_size) = OggPage::parse(&audio_data)?;
+    assert!(opus_head.is_begin_of_stream());
+    assert_eq!(opus_head.page_sequence_number, 0);
+    assert!(opus_head.data.starts_with(b"OpusHead"));
+
+    let (opus_tags, _) = OggPage::parse(&audio_data[opus_head_size..])?;
+    assert!(!opus_tags.is_begin_of_stream());
+    assert_eq!(opus_tags.page_sequence_number, 1);
+    assert!(opus_tags.data.starts_with(b"OpusTags"));
+
+    Ok(())
+}
+
+#[test]
+fn test_ogg_page_parse_invalid_data() {
+    assert!(OggPage::parse(b"OggS").is_err());
+    assert!(OggPage::parse(&[0; 64]).is_err());
+}
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b""), 0);
+    // Reference value of the CRC-32/MPEG-2 variant without initial value and final XOR
+    assert_eq!(crc32(b"123456789"), 0x89A1_897F);
+
+    // The checksum is computed eight bytes at a time, every length of the remainder has to match bit by bit
+    let data = (0..100u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+        .collect::<Vec<_>>();
+    for length in 0..data.len() {
+        let expected = data[..length].iter().fold(0u32, |mut crc, byte| {
+            crc ^= (*byte as u32) << 24;
+            for _ in 0..8 {
+                crc = match crc & 0x8000_0000 {
+                    0 => crc << 1,
+                    _ => (crc << 1) ^ 0x04C1_1DB7,
+                };
+            }
+            crc
+        });
+        assert_eq!(crc32(&data[..length]), expected);
+    }
+}
+
+#[test]
+fn test_ogg_page_iterator_over_tonie_file() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE);
+    let tonie_data = std::fs::read(test_tonie_path)?;
+    let audio_offset = audio_offset(&tonie_data).expect("Expected a Tonie header");
+
+    assert_eq!(audio_offset, TONIEFILE_FRAME_SIZE);
+
+    let pages = OggPageIterator::new(&tonie_data[audio_offset..]).collect::<Result<Vec<_>, _>>()?;
+
+    // The first audio page fills up the block after the Opus header pages, all following pages are aligned to 4kb blocks
+    for (page_offset, page) in pages.iter().skip(3) {
+        assert_eq!((audio_offset + page_offset) % TONIEFILE_FRAME_SIZE, 0);
+        assert!(!page.packets().is_empty());
+    }
+
+    let (_, opus_head) = &pages[0];
+    assert_eq!(opus_head.packets(), vec![&opus_head.data[..]]);
+
+    Ok(())
+}
+
+#[test]
+fn test_ogg_page_iterator_with_page_limit() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE);
+    let audio_data = Toniefile::extract_audio(&mut File::open(test_tonie_path)?)?;
+    let limits = Limits {
+        max_pages: 3,
+        ..Limits::default()
+    };
+
+    let pages = OggPageIterator::with_limits(&audio_data, limits).collect::<Vec<_>>();
+
+    assert_eq!(pages.len(), 4);
+    assert!(pages[..3].iter().all(Result::is_ok));
+    assert!(matches!(pages[3], Err(OggPageError::LimitExceeded(_))));
+
+    Ok(())
+}
+
+#[test]
+fn test_ogg_page_reader_matches_iterator() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE);
+    let audio_data = Toniefile::extract_audio(&mut File::open(test_tonie_path)?)?;
+
+    let expected = OggPageIterator::new(&audio_data).collect::<Result<Vec<_>, _>>()?;
+    let pages = OggPageReader::with_limits(&audio_data[..], Limits::default())
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(pages, expected);
+
+    // A truncated last page is an error instead of the end of the stream
+    let truncated = &audio_data[..audio_data.len() - 10];
+    let pages = OggPageReader::with_limits(truncated, Limits::default()).collect::<Vec<_>>();
+    assert_eq!(pages.len(), expected.len());
+    assert!(pages.last().unwrap().is_err());
+
+    let limits = Limits {
+        max_pages: 3,
+        ..Limits::default()
+    };
+    let pages = OggPageReader::with_limits(&audio_data[..], limits).collect::<Vec<_>>();
+    assert_eq!(pages.len(), 4);
+    assert!(pages[3].is_err());
+
+    Ok(())
+}
+
+fn page_with_segments(header_type: u8, segment_table: Vec<u8>, fill: u8) -> OggPage {
+    let data_size = segment_table.iter().map(|size| *size as usize).sum();
+    return OggPage {
+        version: 0,
+        header_type,
+        granule_position: 0,
+        serial_number: 1,
+        page_sequence_number: 0,
+        checksum: 0,
+        segment_table,
+        data: vec![fill; data_size],
+    };
+}
+
+#[test]
+fn test_packet_assembler_spanning_packets() {
+    let mut packet_assembler = PacketAssembler::new();
+
+    // A complete 100 byte packet followed by the start of a packet that continues on the next pages
+    let first_page = page_with_segments(0x00, vec![100, 255, 255], 1);
+    let packets = packet_assembler.push_page(&first_page);
+    assert_eq!(packets, vec![vec![1; 100]]);
+
+    // A page that only contains the middle of the packet
+    let second_page = page_with_segments(0x01, vec![255], 2);
+    assert!(packet_assembler.push_page(&second_page).is_empty());
+
+    let third_page = page_with_segments(0x01, vec![90, 10], 3);
+    let packets = packet_assembler.push_page(&third_page);
+    assert_eq!(packets.len(), 2);
+    assert_eq!(packets[0].len(), 255 * 3 + 90);
+    assert_eq!(&packets[0][..510], &[1; 510][..]);
+    assert_eq!(&packets[0][510..765], &[2; 255][..]);
+    assert_eq!(&packets[0][765..], &[3; 90][..]);
+    assert_eq!(packets[1], vec![3; 10]);
+    // Only the packet spanning several pages is copied
+    assert!(matches!(packets[0], Cow::Owned(_)));
+    assert!(matches!(packets[1], Cow::Borrowed(_)));
+}
+
+#[test]
+fn test_find_capture_pattern() -> Result<()> {
+    let tonie_data = std::fs::read(Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE))?;
+
+    // The protobuf header does not contain the pattern, so the first match is the first page
+    assert_eq!(find_capture_pattern(&tonie_data), audio_offset(&tonie_data));
+    assert_eq!(
+        find_capture_pattern(&tonie_data[0x1001..0x2003]),
+        Some(0x2f - 1)
+    );
+    assert_eq!(find_capture_pattern(b"OggOggS"), Some(3));
+    assert_eq!(find_capture_pattern(b"OggXOgg"), None);
+    Ok(())
+}
+
+#[test]
+fn test_ogg_page_from_parts() -> Result<()> {
+    let tonie_data = std::fs::read(Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE))?;
+    let page_data = &tonie_data[0x2000..0x3000];
+
+    let header_size = OggPage::header_size(page_data)?;
+    let page = OggPage::from_parts(&page_data[..header_size], page_data[header_size..].to_vec())?;
+
+    assert_eq!(page, OggPage::parse(page_data)?.0);
+    assert_eq!(page.compute_checksum(), page.checksum);
+    assert_eq!(page.serialize(), page_data);
+    assert_eq!(
+        OggPage::from_parts(&page_data[..header_size], vec![0; 10]),
+        Err(OggPageError::TruncatedData)
+    );
+    assert_eq!(
+        OggPage::header_size(&page_data[..30]),
+        Err(OggPageError::TruncatedSegmentTable)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_ogg_page_serialize_into() -> Result<()> {
+    let tonie_data = std::fs::read(Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE))?;
+    let (first_page, _) = OggPage::parse(&tonie_data[0x1000..])?;
+    let (second_page, _) = OggPage::parse(&tonie_data[0x2000..])?;
+
+    // Pages are appended, so one buffer can hold several pages
+    let mut buffer = vec![];
+    first_page.serialize_into(&mut buffer);
+    second_page.serialize_into(&mut buffer);
+    assert_eq!(buffer.len(), first_page.size() + second_page.size());
+    assert_eq!(&buffer[..first_page.size()], first_page.serialize());
+    assert_eq!(&buffer[first_page.size()..], &tonie_data[0x2000..0x3000]);
+    Ok(())
+}
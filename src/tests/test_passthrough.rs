@@ -0,0 +1,219 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use audio2tonie::convert::{
+    convert_files_to_size, convert_files_to_tonie, ConvertOptions, Normalization, Passthrough,
+};
+use audio2tonie::encode::OpusApplication;
+use audio2tonie::extract::{extract_tonie_to_opus, ExtractOptions};
+use audio2tonie::passthrough::check_passthrough;
+use audio2tonie::taf::TONIEFILE_HEADER_SIZE;
+use audio2tonie::{Audio2TonieError, Limits};
+
+use crate::check::check_tonie_file;
+use crate::info::get_header_info;
+
+const TEST_TONIE_FILE: &str = "resources/test/test_1.taf";
+
+fn extract_opus_file(output_dir: &Path) -> Result<PathBuf> {
+    let file_paths = extract_tonie_to_opus(
+        &PathBuf::from(TEST_TONIE_FILE),
+        Some(output_dir.to_path_buf()),
+        &ExtractOptions::default(),
+    )?;
+    return Ok(file_paths[0].clone());
+}
+
+#[test]
+fn test_convert_to_tonie_with_passthrough() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let opus_file = extract_opus_file(temp_dir.path())?;
+    let input_files = vec![opus_file.clone(), opus_file];
+    let output_file = temp_dir.path().join("passthrough.taf");
+
+    convert_files_to_tonie(
+        &input_files,
+        &output_file,
+        &ConvertOptions {
+            passthrough: Passthrough::Always,
+            ..ConvertOptions::default()
+        },
+    )?;
+
+    let results = check_tonie_file(&output_file, &Limits::default())?;
+    assert!(results.iter().all(|result| result.is_ok()));
+    let header_info = get_header_info(&output_file, &Limits::default())?;
+    assert_eq!(header_info.track_page_nums.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_convert_with_options_builder() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let input_files = vec![extract_opus_file(temp_dir.path())?];
+    let builder = ConvertOptions::builder()
+        .passthrough(Passthrough::Always)
+        .audio_id(0x12345678)
+        .comments(vec![String::from("TITLE=Builder")]);
+
+    let tonie_file = temp_dir.path().join("with_header.taf");
+    convert_files_to_tonie(&input_files, &tonie_file, &builder.clone().build())?;
+    let header_info = get_header_info(&tonie_file, &Limits::default())?;
+    assert_eq!(header_info.audio_id, 0x12345678);
+
+    // Without the header, the output is the plain Ogg stream of the Tonie file
+    let ogg_file = temp_dir.path().join("without_header.ogg");
+    convert_files_to_tonie(
+        &input_files,
+        &ogg_file,
+        &builder.clone().tonie_header(false).build(),
+    )?;
+    let tonie_data = std::fs::read(&tonie_file)?;
+    let ogg_data = std::fs::read(&ogg_file)?;
+    assert!(ogg_data.starts_with(b"OggS"));
+    assert_eq!(&tonie_data[TONIEFILE_HEADER_SIZE..], &ogg_data[..]);
+    assert!(ogg_data
+        .windows(b"TITLE=Builder".len())
+        .any(|window| window == b"TITLE=Builder"));
+
+    // The audio data may not exceed the given length
+    let result = convert_files_to_tonie(
+        &input_files,
+        &temp_dir.path().join("too_long.taf"),
+        &builder.max_audio_length(4096).build(),
+    );
+    assert!(matches!(result, Err(Audio2TonieError::DataLengthOverflow)));
+
+    Ok(())
+}
+
+#[test]
+fn test_check_passthrough() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let opus_file = extract_opus_file(temp_dir.path())?;
+    let input_files = vec![opus_file];
+
+    assert!(check_passthrough(&input_files, &ConvertOptions::default(), 1).is_ok());
+    // Chapters from CUE sheets or chapter markers cannot be passed through
+    assert!(check_passthrough(&input_files, &ConvertOptions::default(), 2).is_err());
+    // Options changing the audio require re-encoding
+    let options = ConvertOptions {
+        normalization: Normalization::Track,
+        ..ConvertOptions::default()
+    };
+    assert!(check_passthrough(&input_files, &options, 1).is_err());
+
+    let mp3_files = vec![PathBuf::from("resources/test/test_1.mp3")];
+    assert!(check_passthrough(&mp3_files, &ConvertOptions::default(), 1).is_err());
+    let output_file = temp_dir.path().join("mp3.taf");
+    let result = convert_files_to_tonie(
+        &mp3_files,
+        &output_file,
+        &ConvertOptions {
+            passthrough: Passthrough::Always,
+            ..ConvertOptions::default()
+        },
+    );
+    assert!(result.is_err());
+    assert!(!output_file.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_check_passthrough_with_bitrate() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let input_files = vec![extract_opus_file(temp_dir.path())?];
+    let options = ConvertOptions {
+        bitrate: 32,
+        ..ConvertOptions::default()
+    };
+    assert!(check_passthrough(&input_files, &options, 1).is_err());
+
+    // Passing the input through anyway would ignore the bitrate
+    let output_file = temp_dir.path().join("32kbits.taf");
+    let result = convert_files_to_tonie(
+        &input_files,
+        &output_file,
+        &ConvertOptions {
+            passthrough: Passthrough::Always,
+            ..options
+        },
+    );
+    assert!(result.is_err());
+    assert!(!output_file.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_check_passthrough_with_opus_application() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let input_files = vec![extract_opus_file(temp_dir.path())?];
+    let options = ConvertOptions {
+        opus_application: OpusApplication::Voip,
+        ..ConvertOptions::default()
+    };
+    assert!(check_passthrough(&input_files, &options, 1).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_check_passthrough_with_cbr() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let input_files = vec![extract_opus_file(temp_dir.path())?];
+    let options = ConvertOptions {
+        cbr: true,
+        ..ConvertOptions::default()
+    };
+    assert!(check_passthrough(&input_files, &options, 1).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_check_passthrough_with_ffmpeg_args() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let input_files = vec![extract_opus_file(temp_dir.path())?];
+    let options = ConvertOptions {
+        ffmpeg_args: vec![String::from("-ss"), String::from("100")],
+        ..ConvertOptions::default()
+    };
+    assert!(check_passthrough(&input_files, &options, 1).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_check_passthrough_with_input_format() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let input_files = vec![extract_opus_file(temp_dir.path())?];
+    let options = ConvertOptions {
+        input_format: Some(String::from("ogg")),
+        ..ConvertOptions::default()
+    };
+    assert!(check_passthrough(&input_files, &options, 1).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_convert_to_size_without_passthrough() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let input_files = vec![extract_opus_file(temp_dir.path())?];
+    let output_file = temp_dir.path().join("target.taf");
+
+    // The bitrate selected for the target size would be ignored
+    let options = ConvertOptions {
+        passthrough: Passthrough::Always,
+        ..ConvertOptions::default()
+    };
+    assert!(convert_files_to_size(&input_files, &output_file, 1_000_000, &options).is_err());
+    assert!(!output_file.exists());
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use anyhow::Result;
+use std::io::Cursor;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::tempdir;
+
+use audio2tonie::decode::{decode_tonie_chapters, decode_tonie_reader};
+use audio2tonie::limits::Limits;
+use audio2tonie::ogg_page::OggPage;
+use audio2tonie::play::{play_tonie_file, PlayOptions};
+use audio2tonie::taf::audio_offset;
+use audio2tonie::Audio2TonieError;
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_TONIE_FILE_WITH_CHAPTERS: &str = "resources/test/multiple_chapters.taf";
+
+#[test]
+fn test_play_tonie_file_from_chapter() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS);
+    let temp_dir = tempdir()?;
+    let played_path = temp_dir.path().join("played.pcm");
+    // Stands in for ffplay and records the piped audio
+    let ffplay_path = temp_dir.path().join("ffplay");
+    std::fs::write(
+        &ffplay_path,
+        format!("#!/bin/sh\ncat > '{}'\n", played_path.display()),
+    )?;
+    std::fs::set_permissions(&ffplay_path, std::fs::Permissions::from_mode(0o755))?;
+
+    let mut expected_samples = 0;
+    let mut chapter_count = 0;
+    decode_tonie_chapters(&test_tonie_path, &Limits::default(), |chapter, samples| {
+        if chapter >= 1 {
+            expected_samples += samples.len();
+        }
+        chapter_count = chapter + 1;
+        return Ok(());
+    })?;
+
+    let options = PlayOptions {
+        ffplay: ffplay_path.to_string_lossy().to_string(),
+        chapter: Some(2),
+        ..PlayOptions::default()
+    };
+    let mut played_chapters = vec![];
+    play_tonie_file(&test_tonie_path, &options, |chapter| {
+        played_chapters.push(chapter)
+    })?;
+
+    assert_eq!(played_chapters, (2..=chapter_count).collect::<Vec<_>>());
+    assert_eq!(
+        std::fs::metadata(&played_path)?.len() as usize,
+        expected_samples * 2
+    );
+
+    let options = PlayOptions {
+        chapter: Some(chapter_count + 1),
+        ..options
+    };
+    let error = play_tonie_file(&test_tonie_path, &options, |_| ()).unwrap_err();
+    assert!(error.to_string().contains("does not exist"));
+
+    Ok(())
+}
+
+#[test]
+fn test_decode_tonie_file_with_unsupported_sample_rate() -> Result<()> {
+    let mut tonie_file =
+        std::fs::read(Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS))?;
+    // Rewrite the input sample rate of the OpusHead with a valid page checksum
+    let offset = audio_offset(&tonie_file).unwrap();
+    let (mut page, size) = OggPage::parse(&tonie_file[offset..])?;
+    page.data[12..16].copy_from_slice(&44100u32.to_le_bytes());
+    page.checksum = page.compute_checksum();
+    tonie_file[offset..offset + size].copy_from_slice(&page.serialize());
+
+    let error = decode_tonie_reader(Cursor::new(tonie_file), &Limits::default(), |_, _| Ok(()))
+        .unwrap_err();
+    assert!(matches!(
+        Audio2TonieError::from(error),
+        Audio2TonieError::UnsupportedSampleRate(44100)
+    ));
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+
+use audio2tonie::podcast::{episode_extension, parse_feed, select_episodes, EpisodeSelection};
+
+const TEST_FEED: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Bedtime Stories</title>
+    <itunes:title>Ignored</itunes:title>
+    <item>
+      <title>Episode 3: The Dragon</title>
+      <pubDate>Tue, 17 Sep 2024 06:00:00 +0200</pubDate>
+      <enclosure url="https://example.com/ep3.mp3?source=rss" type="audio/mpeg" length="1"/>
+    </item>
+    <item>
+      <title>Announcement</title>
+    </item>
+    <item>
+      <title>Episode 2: The Castle</title>
+      <enclosure url="https://example.com/media/2" type="audio/x-m4a" length="1"/>
+    </item>
+    <item>
+      <title><![CDATA[Episode 1: The Forest & the Fox]]></title>
+      <enclosure url="https://example.com/ep1" length="1"/>
+    </item>
+  </channel>
+</rss>"#;
+
+#[test]
+fn test_parse_feed() -> Result<()> {
+    let feed = parse_feed(TEST_FEED)?;
+
+    assert_eq!(feed.title, "Bedtime Stories");
+    // The announcement has no audio enclosure
+    let titles = feed
+        .episodes
+        .iter()
+        .map(|episode| episode.title.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        titles,
+        [
+            "Episode 3: The Dragon",
+            "Episode 2: The Castle",
+            "Episode 1: The Forest & the Fox"
+        ]
+    );
+    assert_eq!(
+        feed.episodes[0].url,
+        "https://example.com/ep3.mp3?source=rss"
+    );
+    assert_eq!(
+        feed.episodes[0].published.as_deref(),
+        Some("Tue, 17 Sep 2024 06:00:00 +0200")
+    );
+    assert_eq!(feed.episodes[1].published, None);
+
+    assert!(parse_feed("<html><body/></html>").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_select_episodes() -> Result<()> {
+    let feed = parse_feed(TEST_FEED)?;
+    let titles = |selection| -> Result<Vec<String>> {
+        return Ok(select_episodes(&feed, &selection)?
+            .iter()
+            .map(|episode| episode.title.clone())
+            .collect());
+    };
+
+    // The oldest selected episode becomes the first chapter
+    assert_eq!(
+        titles(EpisodeSelection::Latest(2))?,
+        ["Episode 2: The Castle", "Episode 3: The Dragon"]
+    );
+    assert_eq!(titles(EpisodeSelection::Latest(10))?.len(), 3);
+    assert_eq!(
+        titles(EpisodeSelection::Matching(vec![
+            String::from("dragon"),
+            String::from("FOREST")
+        ]))?,
+        ["Episode 1: The Forest & the Fox", "Episode 3: The Dragon"]
+    );
+    assert!(titles(EpisodeSelection::Matching(vec![String::from("unicorn")])).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_episode_extension() -> Result<()> {
+    let feed = parse_feed(TEST_FEED)?;
+
+    assert_eq!(episode_extension(&feed.episodes[0]), "mp3");
+    assert_eq!(episode_extension(&feed.episodes[1]), "m4a");
+    // Neither the URL nor the type tell the format, ffmpeg detects it
+    assert_eq!(episode_extension(&feed.episodes[2]), "mp3");
+    Ok(())
+}
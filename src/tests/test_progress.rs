@@ -0,0 +1,32 @@
+use audio2tonie::convert::parse_ffmpeg_duration;
+use audio2tonie::progress::format_progress;
+
+#[test]
+fn test_parse_ffmpeg_duration() {
+    let ffmpeg_output = "Input #0, mp3, from 'test_1.mp3':\n  Duration: 00:03:28.03, start: 0.025057, bitrate: 256 kb/s\n  Stream #0:0: Audio: mp3";
+
+    assert_eq!(parse_ffmpeg_duration(ffmpeg_output), Some(208.03));
+    assert_eq!(
+        parse_ffmpeg_duration("  Duration: 01:00:00.50, start: 0.0"),
+        Some(3600.5)
+    );
+    assert_eq!(parse_ffmpeg_duration("  Duration: N/A, bitrate: N/A"), None);
+    assert_eq!(parse_ffmpeg_duration("No such file or directory"), None);
+}
+
+#[test]
+fn test_format_progress() {
+    assert_eq!(
+        format_progress("story.mp3", 0.0, Some(10.0)),
+        format!("story.mp3 [>{}]   0%", " ".repeat(29))
+    );
+    assert_eq!(
+        format_progress("story.mp3", 5.0, Some(10.0)),
+        format!("story.mp3 [{}>{}]  50%", "=".repeat(15), " ".repeat(14))
+    );
+    assert_eq!(
+        format_progress("story.mp3", 12.0, Some(10.0)),
+        format!("story.mp3 [{}] 100%", "=".repeat(30))
+    );
+    assert_eq!(format_progress("story.mp3", 42.4, None), "story.mp3 42s");
+}
@@ -0,0 +1,216 @@
+use anyhow::Result;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+use tempfile::tempdir;
+use toniefile::Toniefile;
+
+use audio2tonie::encode::{OpusApplication, TafEncoder, DEFAULT_BITRATE};
+use audio2tonie::limits::Limits;
+use audio2tonie::recode::{recode_tonie_file, RecodeOptions};
+use audio2tonie::taf::{encode_header, MAX_CHAPTERS, TONIEFILE_FRAME_SIZE};
+use audio2tonie::Audio2TonieError;
+
+use crate::analyze::inspect_page;
+use crate::check::check_tonie_file;
+use crate::info::{get_audio_info, get_header_info};
+use crate::tests::sine_samples;
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_TONIE_FILE_WITH_CHAPTERS: &str = "resources/test/multiple_chapters.taf";
+
+#[test]
+fn test_recode_tonie_file() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS);
+    let output_dir = tempdir()?;
+    let output_path = output_dir.path().join("500304E0");
+    let limits = Limits::default();
+
+    let options = RecodeOptions {
+        bitrate: 32,
+        ..RecodeOptions::default()
+    };
+    recode_tonie_file(&test_tonie_path, &output_path, &options)?;
+
+    assert!(check_tonie_file(&output_path, &limits)?
+        .iter()
+        .all(|result| result.is_ok()));
+    assert!(output_path.metadata()?.len() < test_tonie_path.metadata()?.len() / 2);
+
+    let original_header = get_header_info(&test_tonie_path, &limits)?;
+    let original_audio = get_audio_info(&test_tonie_path, &original_header, &limits)?;
+    let header = get_header_info(&output_path, &limits)?;
+    let audio = get_audio_info(&output_path, &header, &limits)?;
+    assert_eq!(header.audio_id, original_header.audio_id);
+    assert_eq!(audio.chapter_durations.len(), 3);
+    for (duration, original_duration) in audio
+        .chapter_durations
+        .iter()
+        .zip(&original_audio.chapter_durations)
+    {
+        // Chapters start at the block after the last page of the previous chapter
+        assert!((duration - original_duration).abs() < 0.5);
+    }
+    assert!((audio.total_duration() - original_audio.total_duration()).abs() < 0.01);
+
+    Ok(())
+}
+
+#[test]
+fn test_recode_keeps_trailing_empty_chapters() -> Result<()> {
+    // A last chapter that starts after the audio has no pages, so the decoder never reaches it
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS);
+    let tonie_data = std::fs::read(&test_tonie_path)?;
+    let original_header = Toniefile::parse_header(&mut File::open(&test_tonie_path)?)?;
+    let mut track_page_nums = original_header.track_page_nums.clone();
+    track_page_nums.push((tonie_data.len() / TONIEFILE_FRAME_SIZE) as u32);
+    let header = encode_header(
+        &original_header.sha1_hash,
+        original_header.num_bytes,
+        original_header.audio_id,
+        &track_page_nums,
+    )
+    .unwrap();
+    let output_dir = tempdir()?;
+    let tonie_path = output_dir.path().join("input.taf");
+    std::fs::write(
+        &tonie_path,
+        [&header[..], &tonie_data[TONIEFILE_FRAME_SIZE..]].concat(),
+    )?;
+    let output_path = output_dir.path().join("500304E0");
+
+    recode_tonie_file(&tonie_path, &output_path, &RecodeOptions::default())?;
+
+    let header = Toniefile::parse_header(&mut File::open(&output_path)?)?;
+    assert_eq!(header.track_page_nums.len(), track_page_nums.len());
+    // The new file is only written next to the output while encoding
+    assert_eq!(std::fs::read_dir(output_dir.path())?.count(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_recode_into_input_file() -> Result<()> {
+    let output_dir = tempdir()?;
+    let tonie_path = output_dir.path().join("500304E0");
+    std::fs::copy(
+        Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS),
+        &tonie_path,
+    )?;
+
+    assert!(recode_tonie_file(&tonie_path, &tonie_path, &RecodeOptions::default()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_taf_encoder_silence() -> Result<()> {
+    // Silence is encoded into tiny packets, so the pages are limited by the size of their segment tables
+    let mut encoder = TafEncoder::new(Cursor::new(vec![]), 0x12345678, 64, &[])?;
+    encoder.encode(&vec![0; 48000 * 2 * 30])?;
+    encoder.new_chapter()?;
+    encoder.encode(&sine_samples(440.0, -6.0, 10.5))?;
+    let tonie_data = encoder.finalize()?.into_inner();
+
+    let output_dir = tempdir()?;
+    let output_path = output_dir.path().join("500304E0");
+    std::fs::write(&output_path, &tonie_data)?;
+    let limits = Limits::default();
+    assert!(check_tonie_file(&output_path, &limits)?
+        .iter()
+        .all(|result| result.is_ok()));
+
+    let header = Toniefile::parse_header(&mut File::open(&output_path)?)?;
+    assert_eq!(header.track_page_nums.len(), 2);
+    let header_info = get_header_info(&output_path, &limits)?;
+    let audio = get_audio_info(&output_path, &header_info, &limits)?;
+    assert!((audio.total_duration() - 40.5).abs() < 0.01);
+
+    Ok(())
+}
+
+#[test]
+fn test_taf_encoder_cbr() -> Result<()> {
+    // Silence takes hardly any space with a variable bitrate, but the full bitrate with a constant one
+    let encoded_size = |cbr: bool| -> Result<usize> {
+        let mut encoder = TafEncoder::with_settings(
+            Cursor::new(vec![]),
+            0x12345678,
+            64,
+            OpusApplication::Audio,
+            cbr,
+            &[],
+        )?;
+        assert_eq!(encoder.cbr(), cbr);
+        encoder.encode(&vec![0i16; 48000 * 2 * 10])?;
+        return Ok(encoder.finalize()?.into_inner().len());
+    };
+    let vbr_size = encoded_size(false)?;
+    let cbr_size = encoded_size(true)?;
+    // 10 seconds with 64 kbit/s are 80 kB
+    assert!(cbr_size >= 80_000, "{}", cbr_size);
+    assert!(vbr_size * 2 < cbr_size, "{} {}", vbr_size, cbr_size);
+
+    Ok(())
+}
+
+#[test]
+fn test_taf_encoder_application() -> Result<()> {
+    let output_dir = tempdir()?;
+    let limits = Limits::default();
+    let coding_mode = |application: OpusApplication| -> Result<String> {
+        let mut encoder =
+            TafEncoder::with_application(Cursor::new(vec![]), 0x12345678, 24, application, &[])?;
+        encoder.encode(&sine_samples(220.0, -12.0, 5.0))?;
+        let output_path = output_dir.path().join("500304E0");
+        std::fs::write(&output_path, encoder.finalize()?.into_inner())?;
+        assert!(check_tonie_file(&output_path, &limits)?
+            .iter()
+            .all(|result| result.is_ok()));
+
+        let inspection = inspect_page(&output_path, 2, &limits)?;
+        let packet = inspection.packets[1].clone().unwrap().unwrap();
+        return Ok(packet.mode().to_string());
+    };
+
+    // Speech is coded with SILK, the low delay profile only uses CELT
+    assert_ne!(coding_mode(OpusApplication::Voip)?, "CELT");
+    assert_eq!(coding_mode(OpusApplication::LowDelay)?, "CELT");
+    return Ok(());
+}
+
+#[test]
+fn test_taf_encoder_chapter_limit() -> Result<()> {
+    let mut encoder = TafEncoder::new(Cursor::new(vec![]), 0x12345678, 96, &[])?;
+    for _ in 1..MAX_CHAPTERS {
+        encoder.encode(&[0; 2880 * 2])?;
+        encoder.new_chapter()?;
+    }
+
+    let error = Audio2TonieError::from(encoder.new_chapter().unwrap_err());
+    assert!(matches!(
+        error,
+        Audio2TonieError::TooManyChapters {
+            chapters: 100,
+            max: MAX_CHAPTERS
+        }
+    ));
+    return Ok(());
+}
+
+#[test]
+fn test_taf_encoder_data_length_overflow() -> Result<()> {
+    // The default bitrate of every conversion, with room for the Opus headers and about a second of audio
+    let mut encoder = TafEncoder::new(Cursor::new(vec![]), 0x12345678, DEFAULT_BITRATE, &[])?;
+    encoder.set_max_audio_length(8 * 4096);
+
+    let error = encoder
+        .encode(&sine_samples(440.0, -6.0, 10.0))
+        .and_then(|()| encoder.finalize().map(|_| ()))
+        .unwrap_err();
+    assert!(matches!(
+        Audio2TonieError::from(error),
+        Audio2TonieError::DataLengthOverflow
+    ));
+    return Ok(());
+}
@@ -0,0 +1,111 @@
+use anyhow::Result;
+use audio2tonie::convert::ConvertOptions;
+use audio2tonie::extract::ExtractOptions;
+use audio2tonie::limits::Limits;
+use audio2tonie::tonies::ToniesDatabase;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+use crate::backup::{backup_sd_card, MANIFEST_FILE_NAME};
+use crate::info::get_header_info;
+use crate::restore::{read_backup_manifest, restore_backup, RestoreStatus};
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_TONIE_FILE: &str = "resources/test/test_1.taf";
+
+const MANIFEST: &str = r#"[
+    {"tag_uid": "E0:04:03:50:1E:12:34:56", "audio_id": 1700000000, "title": "Stories", "files": ["stories.taf"], "error": null},
+    {"tag_uid": "E0:04:03:50:0A:00:00:00", "audio_id": null, "title": "Unknown", "files": [], "error": "Invalid header"}
+]"#;
+
+#[test]
+fn test_restore_tonie_files() -> Result<()> {
+    let backup_dir = tempdir()?;
+    let sd_root = tempdir()?;
+    std::fs::write(backup_dir.path().join(MANIFEST_FILE_NAME), MANIFEST)?;
+    std::fs::copy(
+        Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE),
+        backup_dir.path().join("stories.taf"),
+    )?;
+
+    let entries = read_backup_manifest(backup_dir.path())?;
+    assert_eq!(
+        entries[0].files,
+        vec![backup_dir.path().join("stories.taf")]
+    );
+    assert_eq!(entries[0].audio_id, Some(1700000000));
+    assert_eq!(
+        read_backup_manifest(&backup_dir.path().join(MANIFEST_FILE_NAME))?,
+        entries
+    );
+
+    let results = restore_backup(
+        entries.clone(),
+        sd_root.path(),
+        false,
+        &ConvertOptions::default(),
+    )?;
+    assert_eq!(
+        results[0].content_path,
+        PathBuf::from("CONTENT/5634121E/500304E0")
+    );
+    assert_eq!(results[0].status, RestoreStatus::Restored);
+    assert!(matches!(results[1].status, RestoreStatus::Failed(_)));
+    assert_eq!(
+        std::fs::read(sd_root.path().join("CONTENT/5634121E/500304E0"))?,
+        std::fs::read(Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE))?
+    );
+
+    // Existing content is only replaced on request
+    std::fs::write(sd_root.path().join("CONTENT/5634121E/500304E0"), "other")?;
+    let results = restore_backup(
+        entries.clone(),
+        sd_root.path(),
+        false,
+        &ConvertOptions::default(),
+    )?;
+    assert_eq!(results[0].status, RestoreStatus::Skipped);
+    let results = restore_backup(entries, sd_root.path(), true, &ConvertOptions::default())?;
+    assert_eq!(results[0].status, RestoreStatus::Restored);
+    return Ok(());
+}
+
+#[test]
+fn test_restore_extracted_backup() -> Result<()> {
+    let sd_root = tempdir()?;
+    let backup_dir = tempdir()?;
+    let restored_root = tempdir()?;
+    let content_path = "CONTENT/5634121E/500304E0";
+    std::fs::create_dir_all(sd_root.path().join("CONTENT/5634121E"))?;
+    std::fs::copy(
+        Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE),
+        sd_root.path().join(content_path),
+    )?;
+    backup_sd_card(
+        sd_root.path(),
+        backup_dir.path(),
+        &ToniesDatabase::default(),
+        &ExtractOptions::default(),
+    )?;
+
+    let results = restore_backup(
+        read_backup_manifest(backup_dir.path())?,
+        restored_root.path(),
+        false,
+        &ConvertOptions::default(),
+    )?;
+
+    assert_eq!(results[0].status, RestoreStatus::Restored);
+    let limits = Limits::default();
+    let original = get_header_info(&sd_root.path().join(content_path), &limits)?;
+    let restored = get_header_info(&restored_root.path().join(content_path), &limits)?;
+    assert_eq!(restored.audio_id, original.audio_id);
+    return Ok(());
+}
+
+#[test]
+fn test_restore_requires_manifest() {
+    let backup_dir = tempdir().unwrap();
+    let error = read_backup_manifest(backup_dir.path()).unwrap_err();
+    assert!(error.to_string().contains(MANIFEST_FILE_NAME));
+}
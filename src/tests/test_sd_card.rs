@@ -0,0 +1,61 @@
+use std::path::Path;
+
+use audio2tonie::sd_card::TagUid;
+
+#[test]
+fn test_parse_tag_uid() -> anyhow::Result<()> {
+    let uid = TagUid::parse("E0:04:03:50:1E:12:34:56")?;
+    assert_eq!(
+        uid.bytes(),
+        [0xE0, 0x04, 0x03, 0x50, 0x1E, 0x12, 0x34, 0x56]
+    );
+    assert_eq!(uid.to_string(), "E0:04:03:50:1E:12:34:56");
+
+    assert_eq!(TagUid::parse("e00403501e123456")?, uid);
+    assert_eq!(TagUid::parse("E0-04-03-50-1E-12-34-56")?, uid);
+    assert_eq!(TagUid::parse("E0 04 03 50 1E 12 34 56")?, uid);
+
+    assert!(TagUid::parse("E0:04:03:50").is_err());
+    assert!(TagUid::parse("E0:04:03:50:1E:12:34:56:78").is_err());
+    assert!(TagUid::parse("E0:04:03:50:1E:12:34:XY").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_content_path() -> anyhow::Result<()> {
+    let uid = TagUid::parse("E0:04:03:50:1E:12:34:56")?;
+
+    assert_eq!(uid.content_directory(), "5634121E");
+    assert_eq!(uid.content_file_name(), "500304E0");
+    assert_eq!(
+        uid.content_path(Path::new("/media/SD")),
+        Path::new("/media/SD/CONTENT/5634121E/500304E0")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_uid_from_content_path() -> anyhow::Result<()> {
+    let uid = TagUid::parse("E0:04:03:50:1E:12:34:56")?;
+
+    assert_eq!(
+        TagUid::from_content_path(Path::new("CONTENT/5634121E/500304E0"))?,
+        uid
+    );
+    assert_eq!(
+        TagUid::from_content_path(&uid.content_path(Path::new("/media/SD")))?,
+        uid
+    );
+    assert_eq!(
+        TagUid::from_content_path(Path::new("5634121e/500304e0"))?,
+        uid
+    );
+
+    assert!(TagUid::from_content_path(Path::new("500304E0")).is_err());
+    assert!(TagUid::from_content_path(Path::new("CONTENT/500304E0")).is_err());
+    assert!(TagUid::from_content_path(Path::new("CONTENT/5634121E/500304E0.taf")).is_err());
+
+    Ok(())
+}
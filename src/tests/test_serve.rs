@@ -0,0 +1,231 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+use audio2tonie::convert::ConvertOptions;
+use audio2tonie::serve::{
+    content_disposition, parse_multipart, ConversionServer, FormPart, ServeOptions,
+};
+
+const BOUNDARY: &str = "----audio2tonie";
+
+fn multipart_body(parts: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+    let mut body = vec![];
+    for (name, file_name, data) in parts {
+        body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+        let disposition = match file_name {
+            Some(file_name) => format!(
+                "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: audio/mpeg\r\n\r\n",
+                name, file_name
+            ),
+            None => format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name),
+        };
+        body.extend_from_slice(disposition.as_bytes());
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+    return body;
+}
+
+#[test]
+fn test_parse_multipart() -> Result<()> {
+    let body = multipart_body(&[
+        ("name", None, b"Bedtime"),
+        ("files", Some("01 Intro.mp3"), b"first\r\n--not a boundary"),
+        ("files", Some("C:\\Music\\02.mp3"), b""),
+    ]);
+    let content_type = format!("multipart/form-data; boundary={}", BOUNDARY);
+    let parts = parse_multipart(&content_type, &body)?;
+
+    assert_eq!(
+        parts,
+        vec![
+            FormPart {
+                name: "name".to_string(),
+                file_name: None,
+                data: b"Bedtime".to_vec(),
+            },
+            FormPart {
+                name: "files".to_string(),
+                file_name: Some("01 Intro.mp3".to_string()),
+                data: b"first\r\n--not a boundary".to_vec(),
+            },
+            FormPart {
+                name: "files".to_string(),
+                file_name: Some("C:\\Music\\02.mp3".to_string()),
+                data: vec![],
+            },
+        ]
+    );
+    assert!(parse_multipart("application/json", &body).is_err());
+    assert!(parse_multipart(&content_type, &body[..body.len() / 2]).is_err());
+    return Ok(());
+}
+
+#[test]
+fn test_serve_converts_uploaded_files() -> Result<()> {
+    let temp_dir = tempdir()?;
+    // Stands in for ffmpeg and decodes every file into one second of silence
+    let ffmpeg_path = temp_dir.path().join("ffmpeg");
+    std::fs::write(&ffmpeg_path, "#!/bin/sh\nhead -c 192000 /dev/zero\n")?;
+    std::fs::set_permissions(&ffmpeg_path, std::fs::Permissions::from_mode(0o755))?;
+
+    let options = ServeOptions {
+        convert: ConvertOptions {
+            ffmpeg: ffmpeg_path.to_string_lossy().to_string(),
+            ..ConvertOptions::default()
+        },
+        work_dir: temp_dir.path().join("work"),
+        max_upload_size: 1024,
+    };
+    let server = ConversionServer::bind("127.0.0.1:0", &options)?;
+    let base_url = format!("http://{}", server.local_addr().unwrap());
+    std::thread::spawn(move || server.run());
+
+    let content_type = format!("multipart/form-data; boundary={}", BOUNDARY);
+    let body = multipart_body(&[
+        ("name", None, "Märchen".as_bytes()),
+        ("files", Some("01.mp3"), b"first"),
+        ("files", Some("../02.mp3"), b"second"),
+    ]);
+    let job: Value = serde_json::from_str(
+        &ureq::post(&format!("{}/jobs", base_url))
+            .set("Content-Type", &content_type)
+            .send_bytes(&body)?
+            .into_string()?,
+    )?;
+    assert_eq!(job["id"], 1);
+    assert_eq!(job["name"], "Märchen");
+    assert_eq!(job["files"], serde_json::json!(["01.mp3", "02.mp3"]));
+
+    let started = Instant::now();
+    let job = loop {
+        let job: Value = serde_json::from_str(
+            &ureq::get(&format!("{}/jobs/1", base_url))
+                .call()?
+                .into_string()?,
+        )?;
+        if job["status"] != "queued" && job["status"] != "converting" {
+            break job;
+        }
+        assert!(started.elapsed() < Duration::from_secs(30));
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(job["status"], "done", "{}", job);
+
+    let response = ureq::get(&format!("{}/jobs/1/download", base_url)).call()?;
+    assert_eq!(
+        response.header("Content-Disposition"),
+        Some("attachment; filename=\"M_rchen.taf\"; filename*=UTF-8''M%C3%A4rchen.taf")
+    );
+    let mut tonie_file = vec![];
+    response.into_reader().read_to_end(&mut tonie_file)?;
+    assert!(tonie_file.len() > 4096);
+    // The uploaded files were streamed into the job directory
+    let job_dir = temp_dir.path().join("work").join("1");
+    assert_eq!(
+        std::fs::read(job_dir.join("002").join("02.mp3"))?,
+        b"second"
+    );
+
+    let jobs: Value = serde_json::from_str(
+        &ureq::get(&format!("{}/jobs", base_url))
+            .call()?
+            .into_string()?,
+    )?;
+    assert_eq!(jobs.as_array().map(Vec::len), Some(1));
+
+    // Unknown jobs, uploads without files and too large uploads are rejected
+    let status = |result: Result<ureq::Response, ureq::Error>| match result {
+        Ok(response) => response.status(),
+        Err(ureq::Error::Status(status, _)) => status,
+        Err(error) => panic!("{}", error),
+    };
+    assert_eq!(
+        status(ureq::get(&format!("{}/jobs/2/download", base_url)).call()),
+        404
+    );
+    // A download whose output is gone gets an error response
+    std::fs::remove_file(job_dir.join("output.taf"))?;
+    assert_eq!(
+        status(ureq::get(&format!("{}/jobs/1/download", base_url)).call()),
+        500
+    );
+    assert_eq!(
+        status(
+            ureq::post(&format!("{}/jobs", base_url))
+                .set("Content-Type", &content_type)
+                .send_bytes(&multipart_body(&[("name", None, b"Empty")]))
+        ),
+        400
+    );
+    assert_eq!(
+        status(
+            ureq::post(&format!("{}/jobs", base_url))
+                .set("Content-Type", &content_type)
+                .send_bytes(&multipart_body(&[("files", Some("big.mp3"), &[0u8; 2048])]))
+        ),
+        413
+    );
+
+    return Ok(());
+}
+
+#[test]
+fn test_serve_streams_large_uploads() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let options = ServeOptions {
+        convert: ConvertOptions {
+            ffmpeg: temp_dir.path().join("ffmpeg").to_string_lossy().to_string(),
+            ..ConvertOptions::default()
+        },
+        work_dir: temp_dir.path().join("work"),
+        max_upload_size: 1024 * 1024,
+    };
+    let server = ConversionServer::bind("127.0.0.1:0", &options)?;
+    let base_url = format!("http://{}", server.local_addr().unwrap());
+    std::thread::spawn(move || server.run());
+
+    // Spans several read chunks and contains almost-delimiters at every offset, the bytes stay below the
+    // last character of the boundary
+    let data = (0..300_000u32)
+        .flat_map(|index| match index % 7 {
+            0 => b"\r\n--".to_vec(),
+            1 => BOUNDARY.as_bytes()[..(index as usize % BOUNDARY.len())].to_vec(),
+            _ => vec![(index % 100) as u8],
+        })
+        .collect::<Vec<_>>();
+    let body = multipart_body(&[("files", Some("long.mp3"), &data), ("name", None, b"Long")]);
+    let job: Value = serde_json::from_str(
+        &ureq::post(&format!("{}/jobs", base_url))
+            .set(
+                "Content-Type",
+                &format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .send_bytes(&body)?
+            .into_string()?,
+    )?;
+    assert_eq!(job["name"], "Long");
+    let stored = std::fs::read(
+        temp_dir
+            .path()
+            .join("work")
+            .join("1")
+            .join("001")
+            .join("long.mp3"),
+    )?;
+    assert!(stored == data);
+    return Ok(());
+}
+
+#[test]
+fn test_content_disposition() {
+    assert_eq!(
+        content_disposition("Märchen \"1\".taf"),
+        "attachment; filename=\"M_rchen _1_.taf\"; filename*=UTF-8''M%C3%A4rchen%20%221%22.taf"
+    );
+}
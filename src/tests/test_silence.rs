@@ -0,0 +1,98 @@
+use audio2tonie::silence::{SilenceTrim, SilenceTrimmer};
+
+use crate::cli::parse_trim_silence;
+use crate::tests::sine_samples;
+
+fn trim(chunks: &[Vec<i16>], trim: &SilenceTrim) -> Vec<i16> {
+    let mut trimmer = SilenceTrimmer::new(2, trim);
+    let mut output = vec![];
+    for chunk in chunks {
+        trimmer.process(chunk, |samples| output.extend_from_slice(samples));
+    }
+    trimmer.finish(|samples| output.extend_from_slice(samples));
+    return output;
+}
+
+#[test]
+fn test_trim_leading_and_trailing_silence() {
+    let tone = sine_samples(440.0, -20.0, 1.0);
+    let silence = vec![0i16; 48000 * 2];
+    let short_pause = vec![0i16; 4800 * 2];
+
+    let track = [
+        silence.clone(),
+        tone.clone(),
+        short_pause.clone(),
+        tone.clone(),
+        silence.clone(),
+    ];
+    let trimmed = trim(&track, &SilenceTrim::default());
+
+    // The zero crossings of the sine are silent as well, so only the first and last sample pair may be missing
+    let expected = [tone.clone(), short_pause, tone.clone()].concat();
+    assert!(trimmed.len() <= expected.len() && trimmed.len() + 4 >= expected.len());
+    assert_eq!(trimmed[..], expected[2..trimmed.len() + 2]);
+}
+
+#[test]
+fn test_trim_keeps_short_silence() {
+    let tone = sine_samples(440.0, -20.0, 0.5);
+    let short_silence = vec![0i16; 4800 * 2];
+    let track = [short_silence.clone(), tone.clone(), short_silence.clone()].concat();
+
+    // Chunks of odd sizes must not change the result
+    let chunks = track
+        .chunks(1234)
+        .map(|chunk| chunk.to_vec())
+        .collect::<Vec<_>>();
+    assert_eq!(trim(&chunks, &SilenceTrim::default()), track);
+}
+
+#[test]
+fn test_trim_silent_track() {
+    let silence = vec![0i16; 48000 * 2];
+    assert!(trim(&[silence], &SilenceTrim::default()).is_empty());
+}
+
+#[test]
+fn test_trim_threshold() {
+    let quiet_tone = sine_samples(440.0, -60.0, 1.0);
+    let tone = sine_samples(440.0, -20.0, 1.0);
+    let track = [quiet_tone.clone(), tone.clone()];
+
+    assert!(trim(&track, &SilenceTrim::default()).len() < tone.len() + 4);
+    let lower_threshold = SilenceTrim {
+        threshold: -70.0,
+        ..Default::default()
+    };
+    assert!(trim(&track, &lower_threshold).len() > quiet_tone.len());
+}
+
+#[test]
+fn test_parse_trim_silence() {
+    assert_eq!(parse_trim_silence(""), Ok(SilenceTrim::default()));
+    assert_eq!(
+        parse_trim_silence("-40,300"),
+        Ok(SilenceTrim {
+            threshold: -40.0,
+            min_duration: 300,
+        })
+    );
+    assert_eq!(
+        parse_trim_silence("-60dB"),
+        Ok(SilenceTrim {
+            threshold: -60.0,
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        parse_trim_silence(",1000ms"),
+        Ok(SilenceTrim {
+            min_duration: 1000,
+            ..Default::default()
+        })
+    );
+    assert!(parse_trim_silence("loud").is_err());
+    assert!(parse_trim_silence("10").is_err());
+    assert!(parse_trim_silence("-40,300,1").is_err());
+}
@@ -0,0 +1,66 @@
+use anyhow::Result;
+use std::fs::File;
+use std::path::Path;
+use tempfile::tempdir;
+use toniefile::Toniefile;
+
+use audio2tonie::extract::ExtractOptions;
+use audio2tonie::limits::Limits;
+use audio2tonie::split::split_tonie_file;
+use audio2tonie::taf::encode_header;
+
+use crate::check::check_tonie_file;
+use crate::info::{get_audio_info, get_header_info};
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_TONIE_FILE_WITH_CHAPTERS: &str = "resources/test/multiple_chapters.taf";
+
+#[test]
+fn test_encode_header() -> Result<()> {
+    let header = encode_header(&[0xAB; 20], 1234567, 0x12345678, &[0, 14, 300])
+        .expect("The header fits into the first block");
+
+    assert_eq!(header.len(), 4096);
+    let parsed_header = Toniefile::parse_header(&mut std::io::Cursor::new(header))?;
+    assert_eq!(parsed_header.sha1_hash, vec![0xAB; 20]);
+    assert_eq!(parsed_header.num_bytes, 1234567);
+    assert_eq!(parsed_header.audio_id, 0x12345678);
+    assert_eq!(parsed_header.track_page_nums, vec![0, 14, 300]);
+
+    Ok(())
+}
+
+#[test]
+fn test_split_tonie_file() -> Result<()> {
+    let test_tonie_path = Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE_WITH_CHAPTERS);
+    let output_dir = tempdir()?;
+    let limits = Limits::default();
+
+    let chapter_files = split_tonie_file(
+        &test_tonie_path,
+        output_dir.path(),
+        &ExtractOptions::default(),
+    )?;
+
+    assert_eq!(chapter_files.len(), 3);
+    let original_header = get_header_info(&test_tonie_path, &limits)?;
+    let original_audio = get_audio_info(&test_tonie_path, &original_header, &limits)?;
+    for (chapter, chapter_file) in chapter_files.iter().enumerate() {
+        assert!(check_tonie_file(chapter_file, &limits)?
+            .iter()
+            .all(|result| result.is_ok()));
+
+        let header = Toniefile::parse_header(&mut File::open(chapter_file)?)?;
+        assert_eq!(header.track_page_nums, vec![0]);
+        assert_eq!(header.audio_id, original_header.audio_id + chapter as u32);
+
+        // The audio is repackaged as is, so every file is exactly as long as its chapter
+        let header_info = get_header_info(chapter_file, &limits)?;
+        let audio_info = get_audio_info(chapter_file, &header_info, &limits)?;
+        assert!(
+            (audio_info.total_duration() - original_audio.chapter_durations[chapter]).abs() < 0.01
+        );
+    }
+
+    Ok(())
+}
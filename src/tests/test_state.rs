@@ -0,0 +1,87 @@
+use anyhow::Result;
+use std::fs::File;
+use std::time::{Duration, SystemTime};
+use tempfile::tempdir;
+
+use audio2tonie::convert::ConvertOptions;
+use audio2tonie::state::{
+    last_modified, settings_hash, ConversionRecord, ConversionState, STATE_FILE_NAME,
+};
+
+#[test]
+fn test_conversion_state() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let album = temp_dir.path().join("album");
+    std::fs::create_dir(&album)?;
+    File::create(album.join("01.mp3"))?;
+    let output = temp_dir.path().join("album.taf");
+    std::fs::write(&output, b"tonie")?;
+    let options = ConvertOptions::default();
+    let settings = settings_hash(&options);
+    let modified = last_modified(&album, &options)?;
+
+    let mut state = ConversionState::load(temp_dir.path());
+    assert!(!state.is_up_to_date(&album, modified, &settings));
+    state.record(
+        &album,
+        ConversionRecord {
+            modified,
+            settings: settings.clone(),
+            outputs: vec![output.clone()],
+        },
+    );
+    state.save()?;
+    assert!(temp_dir.path().join(STATE_FILE_NAME).is_file());
+
+    let state = ConversionState::load(temp_dir.path());
+    assert!(state.is_up_to_date(&album, modified, &settings));
+    // Changed inputs, changed settings and missing outputs are converted again
+    assert!(!state.is_up_to_date(&album, modified + 1, &settings));
+    let other_settings = settings_hash(&ConvertOptions {
+        bitrate: 64,
+        ..ConvertOptions::default()
+    });
+    assert_ne!(other_settings, settings);
+    assert!(!state.is_up_to_date(&album, modified, &other_settings));
+    std::fs::remove_file(&output)?;
+    assert!(!state.is_up_to_date(&album, modified, &settings));
+
+    // Settings which do not change the audio keep the inputs up to date
+    let threaded_settings = settings_hash(&ConvertOptions {
+        threads: 4,
+        ffmpeg: String::from("/opt/ffmpeg/bin/ffmpeg"),
+        ..ConvertOptions::default()
+    });
+    assert_eq!(threaded_settings, settings);
+
+    return Ok(());
+}
+
+#[test]
+fn test_last_modified() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let album = temp_dir.path().join("album");
+    std::fs::create_dir(&album)?;
+    let track = File::create(album.join("01.mp3"))?;
+    let past = SystemTime::now() - Duration::from_secs(3600);
+    track.set_modified(past)?;
+    let options = ConvertOptions::default();
+
+    let modified = last_modified(&album, &options)?;
+    // A modified track counts as a modification of the directory
+    track.set_modified(SystemTime::now() + Duration::from_secs(60))?;
+    assert!(last_modified(&album, &options)? > modified);
+
+    return Ok(());
+}
+
+#[test]
+fn test_conversion_state_ignores_malformed_file() -> Result<()> {
+    let temp_dir = tempdir()?;
+    std::fs::write(temp_dir.path().join(STATE_FILE_NAME), b"{ not json")?;
+
+    let state = ConversionState::load(temp_dir.path());
+    assert!(state.get(temp_dir.path()).is_none());
+
+    return Ok(());
+}
@@ -0,0 +1,29 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use tempfile::tempdir;
+
+use crate::stats::get_chapter_stats;
+use crate::tests::{create_test_tonie_file, sine_samples};
+
+#[test]
+fn test_get_chapter_stats() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let tonie_path = temp_dir.path().join("500304E0");
+    create_test_tonie_file(
+        &tonie_path,
+        &[
+            sine_samples(440.0, -20.0, 6.0),
+            sine_samples(440.0, -30.0, 6.0),
+        ],
+    )?;
+
+    let stats = get_chapter_stats(&tonie_path, &Limits::default())?;
+
+    assert_eq!(stats.len(), 2);
+    assert!((stats[0].integrated_loudness() + 20.0).abs() < 1.0);
+    assert!((stats[1].integrated_loudness() + 30.0).abs() < 1.0);
+    assert!(stats[0].true_peak() > stats[1].true_peak());
+    assert!((stats[0].duration() + stats[1].duration() - 12.0).abs() < 0.5);
+
+    Ok(())
+}
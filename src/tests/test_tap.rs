@@ -0,0 +1,124 @@
+use anyhow::Result;
+use audio2tonie::convert::filter_input_files;
+use audio2tonie::hooks::{ConversionMetadata, PostProcessor, TeddyCloudPlaylist};
+use audio2tonie::tap::{playlist_path, resolve_path, TapPlaylist};
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_TONIE_FILE: &str = "resources/test/test_1.taf";
+const TEST_MP3_FILES: [&str; 2] = ["resources/test/test_1.mp3", "resources/test/test_2.mp3"];
+
+const PLAYLIST: &str = r#"{
+    "type": "tap",
+    "audio_id": "1700000000",
+    "filepath": "lib://stories.taf",
+    "name": "Stories",
+    "files": [
+        {"filepath": "lib://stories/test_2.mp3", "name": "Second"},
+        {"filepath": "stories/test_1.mp3", "name": "First"}
+    ]
+}"#;
+
+#[test]
+fn test_parse_tap_playlist() -> Result<()> {
+    let playlist = TapPlaylist::parse(PLAYLIST)?;
+
+    assert_eq!(playlist.name, "Stories");
+    assert_eq!(playlist.audio_id, Some(1700000000));
+    assert_eq!(playlist.files[0].name, "Second");
+    assert_eq!(
+        playlist.resolve_files(Path::new("library")),
+        vec![
+            PathBuf::from("library/stories/test_2.mp3"),
+            PathBuf::from("library/stories/test_1.mp3")
+        ]
+    );
+    assert_eq!(
+        TapPlaylist::parse(&playlist.to_json().to_string())?,
+        playlist
+    );
+
+    assert!(TapPlaylist::parse(r#"{"type": "playlist", "files": []}"#).is_err());
+    assert!(TapPlaylist::parse(r#"{"type": "tap", "files": [{"name": "No path"}]}"#).is_err());
+    return Ok(());
+}
+
+#[test]
+fn test_playlist_paths() {
+    assert_eq!(
+        playlist_path(Path::new("library/stories/01.mp3"), Path::new("library")),
+        "lib://stories/01.mp3"
+    );
+    let outside = playlist_path(Path::new("music/01.mp3"), Path::new("library"));
+    assert!(Path::new(&outside).is_absolute());
+    assert_eq!(
+        resolve_path(&outside, Path::new("library")),
+        PathBuf::from(&outside)
+    );
+}
+
+#[test]
+fn test_tap_playlist_as_input() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let stories_dir = temp_dir.path().join("stories");
+    std::fs::create_dir(&stories_dir)?;
+    for mp3_file in TEST_MP3_FILES {
+        let mp3_file = Path::new(TEST_FILES_DIR).join(mp3_file);
+        std::fs::copy(&mp3_file, stories_dir.join(mp3_file.file_name().unwrap()))?;
+    }
+    let tap_path = temp_dir.path().join("stories.tap");
+    std::fs::write(&tap_path, PLAYLIST)?;
+
+    // The files keep the order of the playlist
+    assert_eq!(
+        filter_input_files(&tap_path, false)?,
+        vec![
+            stories_dir.join("test_2.mp3"),
+            stories_dir.join("test_1.mp3")
+        ]
+    );
+    // Playlists are not taken for audio files of a directory
+    assert_eq!(
+        filter_input_files(&temp_dir.path().to_path_buf(), true)?.len(),
+        0
+    );
+
+    std::fs::remove_file(stories_dir.join("test_1.mp3"))?;
+    assert!(filter_input_files(&tap_path, false).is_err());
+    return Ok(());
+}
+
+#[test]
+fn test_teddycloud_playlist() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let output_path = temp_dir.path().join("stories.taf");
+    std::fs::copy(
+        Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE),
+        &output_path,
+    )?;
+    let input_file = temp_dir.path().join("stories").join("01 Intro.mp3");
+
+    assert_eq!(
+        TeddyCloudPlaylist::path(&output_path),
+        temp_dir.path().join("stories.tap")
+    );
+    assert_eq!(
+        TeddyCloudPlaylist::path(Path::new("CONTENT/5634121E/500304E0")),
+        PathBuf::from("CONTENT/5634121E/500304E0.tap")
+    );
+
+    TeddyCloudPlaylist::new("ffmpeg").process(&ConversionMetadata {
+        output_path: output_path.clone(),
+        input_files: vec![input_file],
+        chapters: 1,
+    })?;
+
+    let playlist = TapPlaylist::read(&temp_dir.path().join("stories.tap"))?;
+    assert_eq!(playlist.name, "stories");
+    assert_eq!(playlist.filepath, "lib://stories.taf");
+    assert!(playlist.audio_id.is_some());
+    assert_eq!(playlist.files[0].filepath, "lib://stories/01 Intro.mp3");
+    assert_eq!(playlist.files[0].name, "01 Intro");
+    return Ok(());
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use audio2tonie::sd_card::TagUid;
+use audio2tonie::teddycloud::TeddyCloudClient;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread::JoinHandle;
+
+use crate::upload::{library_files_json, server_tags_json};
+
+/// The request line and headers, and the body of a request.
+type Request = (Vec<String>, String);
+
+/// Answers a single request with the given JSON body. Returns the URL of the server and a handle to the request.
+fn serve_once(response: &'static str) -> Result<(String, JoinHandle<Result<Request>>)> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    let server = std::thread::spawn(move || -> Result<Request> {
+        let (stream, _) = listener.accept()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut headers = vec![];
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+            if line.trim().is_empty() {
+                break;
+            }
+            headers.push(line.trim().to_string());
+        }
+        let content_length = headers
+            .iter()
+            .find_map(|header| header.strip_prefix("Content-Length: "))
+            .unwrap_or("0")
+            .parse::<usize>()?;
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+        write!(
+            &stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            response.len(),
+            response
+        )?;
+        return Ok((headers, String::from_utf8_lossy(&body).to_string()));
+    });
+    return Ok((url, server));
+}
+
+#[test]
+fn test_list_library() -> Result<()> {
+    let (url, server) = serve_once(
+        r#"{"files": [
+            {"name": "..", "isDir": true, "size": 0},
+            {"name": "audiobooks", "isDir": true, "size": 0},
+            {"name": "gruffalo.taf", "isDir": false, "size": 2371584,
+             "tafHeader": {"audioId": 305419896, "sha1Hash": "5a2d0ef5"},
+             "tonieInfo": {"series": "Gruffalo", "episode": "Der Grüffelo"}}
+        ]}"#,
+    )?;
+
+    let files = TeddyCloudClient::new(&url)
+        .with_token(Some(String::from("secret")))
+        .list_library("stories")?;
+
+    let (headers, _) = server.join().unwrap()?;
+    assert_eq!(
+        headers[0],
+        "GET /api/fileIndexV2?path=stories&special=library HTTP/1.1"
+    );
+    assert!(headers.contains(&String::from("Authorization: Bearer secret")));
+    assert_eq!(files.len(), 2);
+    assert!(files[0].is_dir);
+    assert_eq!(files[1].audio_id, Some(305419896));
+    assert_eq!(files[1].title.as_deref(), Some("Gruffalo – Der Grüffelo"));
+
+    let listing = library_files_json(&files);
+    assert_eq!(listing[0]["audio_id"], serde_json::Value::Null);
+    assert_eq!(listing[1]["size"], 2371584);
+    return Ok(());
+}
+
+#[test]
+fn test_list_tags() -> Result<()> {
+    let (url, server) = serve_once(
+        r#"{"tags": [
+            {"ruid": "5634121E500304E0", "source": "lib://gruffalo.taf", "tonieInfo": {"series": "Gruffalo"}},
+            {"ruid": "invalid", "source": ""}
+        ]}"#,
+    )?;
+
+    let tags = TeddyCloudClient::new(&url).list_tags(Some("box1"))?;
+
+    let (headers, _) = server.join().unwrap()?;
+    assert_eq!(headers[0], "GET /api/getTagIndex?overlay=box1 HTTP/1.1");
+    assert_eq!(tags.len(), 2);
+    assert_eq!(tags[0].ruid, "5634121e500304e0");
+    assert_eq!(tags[0].title.as_deref(), Some("Gruffalo"));
+    assert!(tags[1].uid().is_none());
+
+    let listing = server_tags_json(&tags);
+    assert_eq!(listing[0]["uid"], "E0:04:03:50:1E:12:34:56");
+    assert_eq!(listing[0]["source"], "lib://gruffalo.taf");
+    assert!(listing[1]["uid"].is_null());
+    return Ok(());
+}
+
+#[test]
+fn test_assign_and_delete() -> Result<()> {
+    let tag_uid = TagUid::parse("E0:04:03:50:1E:12:34:56")?;
+    let (url, server) = serve_once("{}")?;
+    TeddyCloudClient::new(&url).assign(tag_uid, "/audiobooks/gruffalo.taf", None)?;
+    let (headers, body) = server.join().unwrap()?;
+    assert_eq!(
+        headers[0],
+        "POST /content/json/set/5634121e500304e0 HTTP/1.1"
+    );
+    assert_eq!(body, "source=lib%3A%2F%2Faudiobooks%2Fgruffalo.taf");
+
+    let (url, server) = serve_once("{}")?;
+    TeddyCloudClient::new(&url).delete("audiobooks/gruffalo.taf")?;
+    let (headers, body) = server.join().unwrap()?;
+    assert_eq!(headers[0], "POST /api/fileDelete?special=library HTTP/1.1");
+    assert_eq!(body, "/audiobooks/gruffalo.taf");
+    return Ok(());
+}
@@ -0,0 +1,42 @@
+use std::io::{Cursor, Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::cli::parse_size;
+use audio2tonie::throttle::ThrottledIo;
+
+#[test]
+fn test_throttled_writer() -> anyhow::Result<()> {
+    let mut writer = ThrottledIo::new(Cursor::new(vec![]), Some(100 * 1024));
+
+    let started = Instant::now();
+    writer.write_all(&[0; 50 * 1024])?;
+
+    assert!(started.elapsed() >= Duration::from_millis(450));
+
+    Ok(())
+}
+
+#[test]
+fn test_unthrottled_reader() -> anyhow::Result<()> {
+    let mut reader = ThrottledIo::new(Cursor::new(vec![1; 1024 * 1024]), None);
+    let mut buffer = vec![];
+
+    let started = Instant::now();
+    reader.read_to_end(&mut buffer)?;
+
+    assert_eq!(buffer.len(), 1024 * 1024);
+    assert!(started.elapsed() < Duration::from_millis(450));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("512"), Ok(512));
+    assert_eq!(parse_size("10K"), Ok(10 * 1024));
+    assert_eq!(parse_size("500M"), Ok(500 * 1024 * 1024));
+    assert_eq!(parse_size("2g"), Ok(2 * 1024 * 1024 * 1024));
+    assert!(parse_size("fast").is_err());
+    assert!(parse_size("18446744073709551615G").is_err());
+    assert_eq!(parse_size("18446744073709551615"), Ok(u64::MAX));
+}
@@ -0,0 +1,209 @@
+use anyhow::Result;
+use audio2tonie::convert::ConvertOptions;
+use audio2tonie::tonie_cloud::{
+    select_creative_tonie, CloudChapter, CreativeTonie, TonieCloudClient,
+};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use tempfile::tempdir;
+
+use crate::upload::{cloud_chapter_files, creative_tonies_json};
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_MP3_FILE: &str = "resources/test/test_1.mp3";
+const TEST_CHAPTERS_TONIE_FILE: &str = "resources/test/multiple_chapters.taf";
+
+/// The request line and the body of a request.
+type Request = (String, String);
+
+/// Answers one request per response in the given order and returns the requests.
+fn serve(listener: TcpListener, responses: Vec<String>) -> JoinHandle<Result<Vec<Request>>> {
+    return std::thread::spawn(move || -> Result<Vec<Request>> {
+        let mut requests = vec![];
+        for response in responses {
+            let (stream, _) = listener.accept()?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line)?;
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some(length) = line.trim().strip_prefix("Content-Length: ") {
+                    content_length = length.parse()?;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            write!(
+                &stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            )?;
+            requests.push((
+                request_line.trim().to_string(),
+                String::from_utf8_lossy(&body).to_string(),
+            ));
+        }
+        return Ok(requests);
+    });
+}
+
+fn creative_tonie(id: &str, name: &str) -> CreativeTonie {
+    return CreativeTonie {
+        id: id.to_string(),
+        household_id: String::from("household"),
+        name: name.to_string(),
+        chapters: vec![],
+        seconds_remaining: 5400.0,
+    };
+}
+
+#[test]
+fn test_login_and_list_creative_tonies() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let server = serve(
+        listener,
+        vec![
+            String::from(r#"{"access_token": "token", "token_type": "bearer"}"#),
+            String::from(r#"[{"id": "household", "name": "Home"}]"#),
+            String::from(
+                r#"[{"id": "tonie", "name": "Lotte", "secondsRemaining": 5280.5,
+                     "chapters": [{"id": "c1", "title": "Intro", "file": "f1", "seconds": 119.5, "transcoding": false}]}]"#,
+            ),
+        ],
+    );
+
+    let client = TonieCloudClient::login(
+        &format!("{}/token", url),
+        &url,
+        "user@example.com",
+        "secret",
+    )?;
+    let tonies = client.creative_tonies()?;
+
+    let requests = server.join().unwrap()?;
+    assert_eq!(requests[0].0, "POST /token HTTP/1.1");
+    assert!(requests[0].1.contains("grant_type=password"));
+    assert!(requests[0].1.contains("username=user%40example.com"));
+    assert_eq!(requests[1].0, "GET /households HTTP/1.1");
+    assert_eq!(
+        requests[2].0,
+        "GET /households/household/creativetonies HTTP/1.1"
+    );
+    assert_eq!(tonies.len(), 1);
+    assert_eq!(tonies[0].household_id, "household");
+    assert_eq!(tonies[0].chapters[0].title, "Intro");
+
+    let listing = creative_tonies_json(&tonies);
+    assert_eq!(listing[0]["name"], "Lotte");
+    assert_eq!(listing[0]["seconds_remaining"], 5280.5);
+    assert_eq!(listing[0]["chapters"][0]["seconds"], 119.5);
+    return Ok(());
+}
+
+#[test]
+fn test_upload_file_and_add_chapters() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let server = serve(
+        listener,
+        vec![
+            format!(
+                r#"{{"fileId": "file1", "request": {{"url": "{}/storage", "fields": {{"key": "file1"}}}}}}"#,
+                url
+            ),
+            String::new(),
+            String::from(
+                r#"{"id": "tonie", "name": "Lotte", "secondsRemaining": 5400,
+                    "chapters": [{"id": "file1", "title": "test_1", "file": "file1", "seconds": 0, "transcoding": true}]}"#,
+            ),
+        ],
+    );
+
+    let client = TonieCloudClient::new(&url, "token");
+    let file_id = client.upload_file(&Path::new(TEST_FILES_DIR).join(TEST_MP3_FILE))?;
+    let mut tonie = creative_tonie("tonie", "Lotte");
+    tonie.chapters = vec![CloudChapter {
+        id: String::from("c1"),
+        title: String::from("Intro"),
+        file: String::from("f1"),
+        seconds: 119.5,
+        transcoding: false,
+    }];
+    let updated = client.add_chapters(&tonie, &[(String::from("test_1"), file_id)], false)?;
+
+    let requests = server.join().unwrap()?;
+    assert_eq!(requests[0].0, "POST /file HTTP/1.1");
+    assert_eq!(requests[1].0, "POST /storage HTTP/1.1");
+    // The signed fields precede the file
+    let key = requests[1]
+        .1
+        .find("name=\"key\"")
+        .expect("The key is sent.");
+    let file = requests[1]
+        .1
+        .find("name=\"file\"")
+        .expect("The file is sent.");
+    assert!(key < file);
+    assert!(requests[1].1.contains("filename=\"test_1.mp3\""));
+    assert_eq!(
+        requests[2].0,
+        "PATCH /households/household/creativetonies/tonie HTTP/1.1"
+    );
+    let patch = serde_json::from_str::<serde_json::Value>(&requests[2].1)?;
+    assert_eq!(patch["chapters"][0]["id"], "c1");
+    assert_eq!(patch["chapters"][0]["seconds"], 119.5);
+    assert_eq!(patch["chapters"][1]["file"], "file1");
+    assert_eq!(patch["chapters"][1]["title"], "test_1");
+    assert_eq!(updated.chapters.len(), 1);
+    assert!(updated.chapters[0].transcoding);
+    return Ok(());
+}
+
+#[test]
+fn test_select_creative_tonie() -> Result<()> {
+    let tonies = vec![
+        creative_tonie("1", "Lotte"),
+        creative_tonie("2", "Paul"),
+        creative_tonie("3", "paul"),
+    ];
+
+    assert_eq!(select_creative_tonie(&tonies, Some("lotte"))?.id, "1");
+    assert_eq!(select_creative_tonie(&tonies, Some("3"))?.name, "paul");
+    assert!(select_creative_tonie(&tonies, Some("Paul")).is_err());
+    assert!(select_creative_tonie(&tonies, Some("Emma")).is_err());
+    assert!(select_creative_tonie(&tonies, None).is_err());
+    assert_eq!(select_creative_tonie(&tonies[..1], None)?.id, "1");
+    assert!(select_creative_tonie(&[], None).is_err());
+    return Ok(());
+}
+
+#[test]
+fn test_cloud_chapter_files() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let mp3_file = PathBuf::from(TEST_FILES_DIR).join(TEST_MP3_FILE);
+    let tonie_file = PathBuf::from(TEST_FILES_DIR).join(TEST_CHAPTERS_TONIE_FILE);
+
+    let chapter_files = cloud_chapter_files(
+        &[mp3_file.clone(), tonie_file],
+        temp_dir.path(),
+        &ConvertOptions::default(),
+    )?;
+
+    // MP3 files are uploaded as they are, the chapters of Tonie files are extracted
+    assert_eq!(chapter_files[0], (String::from("test_1"), mp3_file));
+    assert!(chapter_files.len() > 2);
+    for (_, path) in &chapter_files[1..] {
+        assert!(path.starts_with(temp_dir.path()));
+        assert_eq!(path.extension().unwrap(), "ogg");
+    }
+    return Ok(());
+}
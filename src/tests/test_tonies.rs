@@ -0,0 +1,120 @@
+use anyhow::Result;
+use audio2tonie::limits::Limits;
+use audio2tonie::tonies::{MatchKind, ToniesDatabase};
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+use crate::identify::{identifications_json, identify_tonie_files};
+
+const TEST_FILES_DIR: &str = env!("CARGO_MANIFEST_DIR");
+const TEST_TONIE_FILE: &str = "resources/test/test_1.taf";
+
+const TONIES_JSON: &str = r#"[
+    {
+        "no": "0",
+        "model": "10000123",
+        "audio_id": ["1500000000", 1600000000],
+        "hash": ["5A2D0EF510B6798CE0AE5DCD001E800993DDF24F", "0123456789abcdef0123456789abcdef01234567"],
+        "title": "Benjamin Blümchen - Folge 12",
+        "series": "Benjamin Blümchen",
+        "episodes": "Folge 12",
+        "tracks": ["Teil 1", "Teil 2"],
+        "release": "1500000000",
+        "language": "de-de",
+        "category": "Hörspiel",
+        "pic": "https://example.com/benjamin.png"
+    },
+    {
+        "no": "1",
+        "model": "10000456",
+        "audio_id": ["305419896"],
+        "hash": [],
+        "title": "Die Schule der magischen Tiere",
+        "series": "",
+        "episodes": "",
+        "tracks": null
+    }
+]"#;
+
+#[test]
+fn test_parse_tonies_database() -> Result<()> {
+    let database = ToniesDatabase::parse(TONIES_JSON)?;
+
+    assert_eq!(database.entries.len(), 2);
+    assert_eq!(database.entries[0].audio_ids, vec![1500000000, 1600000000]);
+    assert_eq!(
+        database.entries[0].hashes[0],
+        "5a2d0ef510b6798ce0ae5dcd001e800993ddf24f"
+    );
+    assert_eq!(
+        database.entries[0].display_name(),
+        "Benjamin Blümchen – Folge 12"
+    );
+    assert!(database.entries[1].tracks.is_empty());
+    assert_eq!(
+        database.entries[1].display_name(),
+        "Die Schule der magischen Tiere"
+    );
+    assert!(ToniesDatabase::parse("{}").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_identify_tonie() -> Result<()> {
+    let database = ToniesDatabase::parse(TONIES_JSON)?;
+
+    // The hash wins over an audio id of another entry
+    let (entry, kind) = database
+        .identify(305419896, "5a2d0ef510b6798ce0ae5dcd001e800993ddf24f")
+        .expect("The hash is known.");
+    assert_eq!(entry.model, "10000123");
+    assert_eq!(kind, MatchKind::Hash);
+
+    let (entry, kind) = database
+        .identify(1600000000, "ffffffffffffffffffffffffffffffffffffffff")
+        .expect("The audio id is known.");
+    assert_eq!(entry.model, "10000123");
+    assert_eq!(kind, MatchKind::AudioId);
+
+    assert!(database
+        .identify(42, "ffffffffffffffffffffffffffffffffffffffff")
+        .is_none());
+    Ok(())
+}
+
+#[test]
+fn test_identify_tonie_files() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let content_dir = temp_dir.path().join("CONTENT").join("5634121E");
+    std::fs::create_dir_all(&content_dir)?;
+    std::fs::copy(
+        Path::new(TEST_FILES_DIR).join(TEST_TONIE_FILE),
+        content_dir.join("500304E0"),
+    )?;
+    let database = ToniesDatabase::parse(TONIES_JSON)?;
+
+    let identifications = identify_tonie_files(
+        &[temp_dir.path().to_path_buf()],
+        &database,
+        &Limits::default(),
+    )?;
+
+    assert_eq!(identifications.len(), 1);
+    assert_eq!(
+        identifications[0].path,
+        PathBuf::from("CONTENT/5634121E/500304E0")
+    );
+    let report = identifications_json(&identifications);
+    assert_eq!(report[0]["tonie"]["match"], "hash");
+    assert_eq!(report[0]["tonie"]["series"], "Benjamin Blümchen");
+    assert_eq!(report[0]["tonie"]["episodes"], "Folge 12");
+
+    let identifications = identify_tonie_files(
+        &[PathBuf::from(TEST_FILES_DIR).join(TEST_TONIE_FILE)],
+        &ToniesDatabase::default(),
+        &Limits::default(),
+    )?;
+    assert!(identifications[0].tonie.is_none());
+    assert!(identifications_json(&identifications)[0]["tonie"].is_null());
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::Terminal;
+use tempfile::tempdir;
+
+use crate::tui::{draw, Action, Focus, TuiApp};
+use audio2tonie::convert::{ConvertOptions, TrackOrder};
+
+fn press(app: &mut TuiApp, code: KeyCode) -> Action {
+    return app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+}
+
+#[test]
+fn test_tui_builds_a_tonie() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let album_path = temp_dir.path().join("album");
+    std::fs::create_dir(&album_path)?;
+    for file_name in ["02 Song.mp3", "01 Intro.mp3", "cover.jpg"] {
+        std::fs::write(album_path.join(file_name), b"")?;
+    }
+    let options = ConvertOptions {
+        ffmpeg: "/nonexistent/ffmpeg".to_string(),
+        track_order: TrackOrder::Tags,
+        ..ConvertOptions::default()
+    };
+    let mut app = TuiApp::new(temp_dir.path(), &options)?;
+    let album_path = album_path.canonicalize()?;
+
+    // The first entry is the parent directory
+    assert_eq!(app.entries.len(), 2);
+    assert_eq!(press(&mut app, KeyCode::Char('c')), Action::Continue);
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(app.directory, album_path);
+    assert_eq!(
+        app.entries[1..],
+        [
+            album_path.join("01 Intro.mp3"),
+            album_path.join("02 Song.mp3")
+        ]
+    );
+
+    // Files without track numbers are added in the order of their names
+    press(&mut app, KeyCode::Char('a'));
+    press(&mut app, KeyCode::Tab);
+    assert_eq!(app.focus, Focus::Tracks);
+    press(&mut app, KeyCode::Char(']'));
+    assert_eq!(
+        app.tracks,
+        [
+            album_path.join("02 Song.mp3"),
+            album_path.join("01 Intro.mp3")
+        ]
+    );
+    assert_eq!(app.track_cursor, 1);
+    press(&mut app, KeyCode::Char('x'));
+    assert_eq!(app.tracks, [album_path.join("02 Song.mp3")]);
+
+    press(&mut app, KeyCode::Char('+'));
+    press(&mut app, KeyCode::Char('+'));
+    assert_eq!(app.bitrate, 160);
+    press(&mut app, KeyCode::Char('+'));
+    assert_eq!(app.bitrate, 160);
+    press(&mut app, KeyCode::Char('-'));
+    assert_eq!(app.convert_options().bitrate, 128);
+
+    // The title defaults to the name of the start directory
+    press(&mut app, KeyCode::Char('t'));
+    for _ in 0..app.title.chars().count() {
+        press(&mut app, KeyCode::Backspace);
+    }
+    for c in "Bed/time".chars() {
+        press(&mut app, KeyCode::Char(c));
+    }
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(app.title, "Bed/time");
+    assert_eq!(
+        app.output_path(temp_dir.path()),
+        temp_dir.path().join("Bed_time.taf")
+    );
+
+    let mut terminal = Terminal::new(TestBackend::new(120, 12))?;
+    terminal.draw(|frame| draw(frame, &app))?;
+    let screen = terminal
+        .backend()
+        .buffer()
+        .content()
+        .iter()
+        .map(|cell| cell.symbol())
+        .collect::<String>();
+    assert!(screen.contains("01 --:--:-- 02 Song.mp3"));
+    assert!(screen.contains("Title: Bed/time   Bitrate: 128 kbit/s"));
+
+    assert_eq!(press(&mut app, KeyCode::Char('c')), Action::Convert);
+    assert_eq!(press(&mut app, KeyCode::Char('q')), Action::Quit);
+    return Ok(());
+}
@@ -0,0 +1,148 @@
+use std::path::Path;
+
+use audio2tonie::serve::parse_multipart;
+use audio2tonie::utils::{
+    find_executable, find_executable_in, format_duration, format_timestamp, limit_path_length,
+    multipart_form, sanitize_file_name, MAX_PATH_LENGTH,
+};
+
+#[test]
+fn test_sanitize_file_name_reserved_characters() {
+    assert_eq!(
+        sanitize_file_name("Folge 1: Wer? Wie? Was*.ogg", false),
+        "Folge 1_ Wer_ Wie_ Was_.ogg"
+    );
+    assert_eq!(
+        sanitize_file_name("a/b\\c|d\"e<f>g", false),
+        "a_b_c_d_e_f_g"
+    );
+    assert_eq!(sanitize_file_name("Tab\there", false), "Tab_here");
+}
+
+#[test]
+fn test_sanitize_file_name_trailing_dots_and_spaces() {
+    assert_eq!(sanitize_file_name("Und dann... ", false), "Und dann");
+    assert_eq!(sanitize_file_name("...", false), "_");
+}
+
+#[test]
+fn test_sanitize_file_name_reserved_device_names() {
+    assert_eq!(sanitize_file_name("CON", false), "_CON");
+    assert_eq!(sanitize_file_name("nul.ogg", false), "_nul.ogg");
+    assert_eq!(sanitize_file_name("Console.ogg", false), "Console.ogg");
+}
+
+#[test]
+fn test_sanitize_file_name_long_names() {
+    let long_name = format!("{}.ogg", "ä".repeat(200));
+    let sanitized = sanitize_file_name(&long_name, false);
+
+    assert!(sanitized.len() <= 255);
+    assert!(sanitized.ends_with(".ogg"));
+}
+
+#[test]
+fn test_sanitize_file_name_transliterate() {
+    assert_eq!(
+        sanitize_file_name("Benjamin Blümchen – Die Straßenbahn.ogg", true),
+        "Benjamin Bluemchen _ Die Strassenbahn.ogg"
+    );
+    assert_eq!(
+        sanitize_file_name("Benjamin Blümchen.ogg", false),
+        "Benjamin Blümchen.ogg"
+    );
+}
+
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(0.0), "00:00:00");
+    assert_eq!(format_duration(252.4), "00:04:12");
+    assert_eq!(format_duration(3723.0), "01:02:03");
+}
+
+#[test]
+fn test_format_timestamp() {
+    assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+    assert_eq!(format_timestamp(951782400), "2000-02-29 00:00:00 UTC");
+    assert_eq!(format_timestamp(1700000000), "2023-11-14 22:13:20 UTC");
+}
+
+#[test]
+fn test_find_executable_in() -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let empty_dir = tempfile::tempdir()?;
+    let tools_dir = tempfile::tempdir()?;
+    let ffmpeg_path = tools_dir.path().join("ffmpeg");
+    std::fs::write(&ffmpeg_path, "#!/bin/sh\n")?;
+    // A file without the executable bit is skipped
+    std::fs::write(empty_dir.path().join("ffplay"), "")?;
+
+    let search_dirs = [
+        empty_dir.path().to_path_buf(),
+        tools_dir.path().to_path_buf(),
+    ];
+    assert_eq!(find_executable_in("ffmpeg", &search_dirs), None);
+    std::fs::set_permissions(&ffmpeg_path, std::fs::Permissions::from_mode(0o755))?;
+    assert_eq!(
+        find_executable_in("ffmpeg", &search_dirs),
+        Some(ffmpeg_path)
+    );
+    assert_eq!(find_executable_in("ffplay", &search_dirs), None);
+
+    // Paths are not searched
+    assert_eq!(
+        find_executable("/nonexistent/ffmpeg"),
+        std::path::PathBuf::from("/nonexistent/ffmpeg")
+    );
+    assert_eq!(
+        find_executable("nonexistent-tool"),
+        std::path::PathBuf::from("nonexistent-tool")
+    );
+    return Ok(());
+}
+
+#[test]
+fn test_multipart_form() -> anyhow::Result<()> {
+    // The content contains the first boundary candidate, so another boundary is chosen
+    let content = b"\r\n------audio2tonie-boundary-0--\r\n";
+    let (content_type, body) = multipart_form(&[("key", "value")], "Folge \"1\"\r\n.taf", content);
+
+    assert!(!content_type.ends_with("boundary-0"));
+    let parts = parse_multipart(&content_type, &body)?;
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[0].name, "key");
+    assert_eq!(parts[0].data, b"value");
+    assert_eq!(parts[1].name, "file");
+    assert_eq!(
+        parts[1].file_name.as_deref(),
+        Some("Folge %221%22%0D%0A.taf")
+    );
+    assert_eq!(parts[1].data, content);
+    return Ok(());
+}
+
+#[test]
+fn test_limit_path_length() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let short_path = temp_dir.path().join("Folge 1.taf");
+    assert_eq!(limit_path_length(&short_path), short_path);
+
+    // The file name alone fits, but not together with the directory
+    let long_path = temp_dir.path().join(format!("{}.taf", "ä".repeat(250)));
+    let limited_path = limit_path_length(&long_path);
+    assert_eq!(limited_path.parent(), Some(temp_dir.path()));
+    let file_name = limited_path.file_name().unwrap().to_str().unwrap();
+    assert!(file_name.starts_with("ää") && file_name.ends_with(".taf"));
+    assert_eq!(
+        limited_path.to_str().unwrap().encode_utf16().count() + ".part".len(),
+        MAX_PATH_LENGTH
+    );
+
+    // A directory that is too long on its own keeps a single character of the name
+    let deep_path = Path::new(&"/directory".repeat(30)).join("Folge 1.taf");
+    assert_eq!(
+        limit_path_length(&deep_path),
+        Path::new(&"/directory".repeat(30)).join("F.taf")
+    );
+}
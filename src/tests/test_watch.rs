@@ -0,0 +1,111 @@
+use anyhow::Result;
+use std::fs::File;
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+use audio2tonie::convert::ConvertOptions;
+use audio2tonie::watch::{
+    top_level_entry, watch_events, watch_output_path, Debouncer, WatchOptions,
+};
+use notify::event::CreateKind;
+use notify::{Event, EventKind};
+
+#[test]
+fn test_debouncer() {
+    let start = Instant::now();
+    let mut debouncer = Debouncer::new(Duration::from_secs(5));
+
+    debouncer.touch("/drop/b.mp3".into(), start);
+    debouncer.touch("/drop/a.mp3".into(), start + Duration::from_secs(1));
+    assert!(debouncer
+        .take_ready(start + Duration::from_secs(4))
+        .is_empty());
+
+    // Another change postpones the conversion
+    debouncer.touch("/drop/b.mp3".into(), start + Duration::from_secs(4));
+    assert_eq!(
+        debouncer.take_ready(start + Duration::from_secs(6)),
+        vec![Path::new("/drop/a.mp3")]
+    );
+    assert_eq!(
+        debouncer.take_ready(start + Duration::from_secs(10)),
+        vec![Path::new("/drop/b.mp3")]
+    );
+    assert!(debouncer
+        .take_ready(start + Duration::from_secs(20))
+        .is_empty());
+}
+
+#[test]
+fn test_top_level_entry() {
+    let drop = Path::new("/drop");
+
+    assert_eq!(
+        top_level_entry(drop, Path::new("/drop/story.mp3")),
+        Some(drop.join("story.mp3"))
+    );
+    assert_eq!(
+        top_level_entry(drop, Path::new("/drop/Album/01 Track.mp3")),
+        Some(drop.join("Album"))
+    );
+    assert_eq!(top_level_entry(drop, Path::new("/drop")), None);
+    assert_eq!(top_level_entry(drop, Path::new("/other/story.mp3")), None);
+}
+
+#[test]
+fn test_watch_output_path() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let drop = temp_dir.path().join("drop");
+    std::fs::create_dir_all(drop.join("Some Album"))?;
+    File::create(drop.join("Some Story.mp3"))?;
+    let output = Path::new("/tonies");
+
+    assert_eq!(
+        watch_output_path(&drop, &drop.join("Some Story.mp3"), output),
+        output.join("Some Story.taf")
+    );
+    assert_eq!(
+        watch_output_path(&drop, &drop.join("Some Album"), output),
+        output.join("Some Album.taf")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_watch_events_continues_after_watcher_error() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let drop_dir = temp_dir.path().join("drop");
+    std::fs::create_dir_all(&drop_dir)?;
+    File::create(drop_dir.join("story.mp3"))?;
+    let options = WatchOptions {
+        convert: ConvertOptions {
+            ffmpeg: temp_dir.path().join("ffmpeg").to_string_lossy().to_string(),
+            ..ConvertOptions::default()
+        },
+        debounce: Duration::ZERO,
+    };
+
+    let (sender, receiver) = channel();
+    sender.send(Err(notify::Error::generic("event queue overflow")))?;
+    sender.send(Ok(
+        Event::new(EventKind::Create(CreateKind::File)).add_path(drop_dir.join("story.mp3"))
+    ))?;
+    std::mem::drop(sender);
+
+    // The event after the error is still handled, watching only stops once the watcher is gone
+    let mut converted = vec![];
+    let result = watch_events(
+        &receiver,
+        &drop_dir,
+        &temp_dir.path().join("tonies"),
+        &options,
+        |entry, _| converted.push(entry.to_path_buf()),
+    );
+    assert!(result.is_err());
+    assert_eq!(converted, vec![drop_dir.join("story.mp3")]);
+
+    Ok(())
+}
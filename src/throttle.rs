@@ -0,0 +1,98 @@
+use anyhow::{anyhow, Result};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Wraps a reader or writer and limits its throughput to the given number of bytes per second,
+/// so long running conversions don't starve other processes of disk or network bandwidth.
+pub struct ThrottledIo<T> {
+    inner: T,
+    bytes_per_second: Option<u64>,
+    started: Instant,
+    transferred: u64,
+}
+
+impl<T> ThrottledIo<T> {
+    /// # Arguments
+    ///
+    /// * `inner` - The reader or writer to wrap.
+    /// * `bytes_per_second` - The maximum throughput. `None` disables throttling.
+    pub fn new(inner: T, bytes_per_second: Option<u64>) -> Self {
+        ThrottledIo {
+            inner,
+            bytes_per_second,
+            started: Instant::now(),
+            transferred: 0,
+        }
+    }
+
+    /// Returns the wrapped reader or writer.
+    pub fn into_inner(self) -> T {
+        return self.inner;
+    }
+
+    fn throttle(&mut self, bytes: usize) {
+        let Some(bytes_per_second) = self.bytes_per_second.filter(|rate| *rate > 0) else {
+            return;
+        };
+
+        self.transferred += bytes as u64;
+        let expected = Duration::from_secs_f64(self.transferred as f64 / bytes_per_second as f64);
+        let elapsed = self.started.elapsed();
+        if expected > elapsed {
+            sleep(expected - elapsed);
+        }
+    }
+}
+
+impl<T: Read> Read for ThrottledIo<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let bytes = self.inner.read(buf)?;
+        self.throttle(bytes);
+        return Ok(bytes);
+    }
+}
+
+impl<T: Write> Write for ThrottledIo<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let bytes = self.inner.write(buf)?;
+        self.throttle(bytes);
+        return Ok(bytes);
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+impl<T: Seek> Seek for ThrottledIo<T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        return self.inner.seek(pos);
+    }
+}
+
+/// Lowers the scheduling priority of the current process. Spawned ffmpeg processes inherit it.
+///
+/// # Arguments
+///
+/// * `niceness` - The niceness from -20 (highest priority) to 19 (lowest priority).
+#[cfg(unix)]
+pub fn set_process_priority(niceness: i32) -> Result<()> {
+    // SAFETY: setpriority only reads its integer arguments
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) };
+    if result != 0 {
+        return Err(anyhow!(
+            "Failed to set the process priority: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+
+    return Ok(());
+}
+
+#[cfg(not(unix))]
+pub fn set_process_priority(_niceness: i32) -> Result<()> {
+    return Err(anyhow!(
+        "Setting the process priority is only supported on Unix systems."
+    ));
+}
@@ -0,0 +1,306 @@
+//! A client for the official Tonie cloud behind my.tonies.com, which plays uploaded audio files on creative
+//! Tonies. The cloud transcodes the files itself, so they are uploaded as they are instead of as Tonie files.
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::utils::multipart_form;
+
+/// The OpenID Connect token endpoint of the Tonie cloud accounts.
+pub const TONIE_CLOUD_AUTH_URL: &str =
+    "https://login.tonies.com/auth/realms/tonies/protocol/openid-connect/token";
+/// The REST API of the Tonie cloud.
+pub const TONIE_CLOUD_API_URL: &str = "https://api.tonie.cloud/v2";
+/// The audio formats the Tonie cloud transcodes itself. Other files have to be converted before the upload.
+pub const TONIE_CLOUD_FILE_EXTENSIONS: [&str; 10] = [
+    "mp3", "aac", "m4a", "wav", "ogg", "opus", "flac", "wma", "aiff", "aif",
+];
+
+/// A chapter of a creative Tonie.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CloudChapter {
+    pub id: String,
+    pub title: String,
+    /// The id of the uploaded file.
+    pub file: String,
+    /// The duration in seconds, 0 while the file is transcoded.
+    pub seconds: f64,
+    /// Whether the cloud is still transcoding the file.
+    pub transcoding: bool,
+}
+
+/// A creative Tonie of the account.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreativeTonie {
+    pub id: String,
+    pub household_id: String,
+    pub name: String,
+    pub chapters: Vec<CloudChapter>,
+    /// The recording time left on the Tonie in seconds.
+    pub seconds_remaining: f64,
+}
+
+/// An authenticated connection to the Tonie cloud.
+#[derive(Clone, Debug)]
+pub struct TonieCloudClient {
+    api_url: String,
+    access_token: String,
+}
+
+impl TonieCloudClient {
+    /// # Arguments
+    ///
+    /// * `api_url` - The base URL of the API, usually [`TONIE_CLOUD_API_URL`].
+    /// * `access_token` - The access token of the account.
+    pub fn new(api_url: &str, access_token: &str) -> Self {
+        TonieCloudClient {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            access_token: access_token.to_string(),
+        }
+    }
+
+    /// Logs in with the credentials of a my.tonies.com account.
+    ///
+    /// # Arguments
+    ///
+    /// * `auth_url` - The token endpoint, usually [`TONIE_CLOUD_AUTH_URL`].
+    /// * `api_url` - The base URL of the API, usually [`TONIE_CLOUD_API_URL`].
+    /// * `username` - The email address of the account.
+    /// * `password` - The password of the account.
+    pub fn login(auth_url: &str, api_url: &str, username: &str, password: &str) -> Result<Self> {
+        let response = match ureq::post(auth_url).send_form(&[
+            ("grant_type", "password"),
+            ("client_id", "my-tonies"),
+            ("scope", "openid"),
+            ("username", username),
+            ("password", password),
+        ]) {
+            Ok(response) => response,
+            Err(ureq::Error::Status(400 | 401, _)) => {
+                return Err(anyhow!(
+                    "The Tonie cloud rejected the login of {}. Check the username and password.",
+                    username
+                ));
+            }
+            Err(error) => return Err(error).context("Failed to log in to the Tonie cloud"),
+        };
+        let token = serde_json::from_str::<Value>(&response.into_string()?)?;
+        let access_token = token["access_token"]
+            .as_str()
+            .ok_or_else(|| anyhow!("The Tonie cloud did not return an access token."))?;
+
+        return Ok(TonieCloudClient::new(api_url, access_token));
+    }
+
+    fn request(&self, method: &str, endpoint: &str) -> ureq::Request {
+        return ureq::request(method, &format!("{}{}", self.api_url, endpoint))
+            .set("Authorization", &format!("Bearer {}", self.access_token));
+    }
+
+    fn get_json(&self, endpoint: &str) -> Result<Value> {
+        let response = self
+            .request("GET", endpoint)
+            .call()
+            .with_context(|| format!("Failed to request {} from the Tonie cloud", endpoint))?;
+        return Ok(serde_json::from_str(&response.into_string()?)?);
+    }
+
+    /// Lists the creative Tonies of all households of the account.
+    pub fn creative_tonies(&self) -> Result<Vec<CreativeTonie>> {
+        let households = self.get_json("/households")?;
+        let households = households
+            .as_array()
+            .ok_or_else(|| anyhow!("The Tonie cloud did not return a household list."))?;
+
+        let mut tonies = vec![];
+        for household in households {
+            let household_id = household["id"]
+                .as_str()
+                .ok_or_else(|| anyhow!("The Tonie cloud returned a household without id."))?;
+            let creative_tonies =
+                self.get_json(&format!("/households/{}/creativetonies", household_id))?;
+            for tonie in creative_tonies.as_array().into_iter().flatten() {
+                tonies.push(parse_creative_tonie(tonie, household_id)?);
+            }
+        }
+
+        return Ok(tonies);
+    }
+
+    /// Uploads an audio file to the storage of the cloud and returns its file id, which can be added to a
+    /// creative Tonie as chapter.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The audio file, in one of the [`TONIE_CLOUD_FILE_EXTENSIONS`].
+    pub fn upload_file(&self, path: &Path) -> Result<String> {
+        let upload = self
+            .request("POST", "/file")
+            .set("Content-Type", "application/json")
+            .send_string("{}")
+            .context("Failed to request an upload from the Tonie cloud")?;
+        let upload = serde_json::from_str::<Value>(&upload.into_string()?)?;
+        let file_id = upload["fileId"]
+            .as_str()
+            .ok_or_else(|| anyhow!("The Tonie cloud did not return a file id."))?;
+        let url = upload["request"]["url"]
+            .as_str()
+            .ok_or_else(|| anyhow!("The Tonie cloud did not return an upload URL."))?;
+        let fields = upload["request"]["fields"]
+            .as_object()
+            .map(|fields| {
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str().unwrap_or_default()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let content = std::fs::read(path)?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let (content_type, body) = multipart_form(&fields, &file_name, &content);
+        // The upload URL is signed, it must not carry the access token of the API
+        ureq::post(url)
+            .set("Content-Type", &content_type)
+            .send_bytes(&body)
+            .with_context(|| format!("Failed to upload {}", path.display()))?;
+
+        return Ok(file_id.to_string());
+    }
+
+    /// Sets the chapters of a creative Tonie to its current chapters followed by the given uploaded files, or to
+    /// the uploaded files only. Returns the updated Tonie.
+    ///
+    /// # Arguments
+    ///
+    /// * `tonie` - The creative Tonie.
+    /// * `files` - The titles and file ids of the new chapters.
+    /// * `replace` - Remove the current chapters of the Tonie.
+    pub fn add_chapters(
+        &self,
+        tonie: &CreativeTonie,
+        files: &[(String, String)],
+        replace: bool,
+    ) -> Result<CreativeTonie> {
+        let current = match replace {
+            true => &[][..],
+            false => &tonie.chapters[..],
+        };
+        let chapters = current
+            .iter()
+            .map(|chapter| {
+                json!({
+                    "id": chapter.id,
+                    "title": chapter.title,
+                    "file": chapter.file,
+                    "seconds": chapter.seconds,
+                    "transcoding": chapter.transcoding,
+                })
+            })
+            .chain(files.iter().map(|(title, file_id)| {
+                json!({
+                    "id": file_id,
+                    "title": title,
+                    "file": file_id,
+                    "seconds": 0,
+                    "transcoding": false,
+                })
+            }))
+            .collect::<Vec<_>>();
+
+        let response = self
+            .request(
+                "PATCH",
+                &format!(
+                    "/households/{}/creativetonies/{}",
+                    tonie.household_id, tonie.id
+                ),
+            )
+            .set("Content-Type", "application/json")
+            .send_string(&json!({ "chapters": chapters }).to_string())
+            .with_context(|| format!("Failed to update the chapters of {}", tonie.name))?;
+        let updated = serde_json::from_str::<Value>(&response.into_string()?)?;
+
+        return parse_creative_tonie(&updated, &tonie.household_id);
+    }
+}
+
+/// Selects a creative Tonie by its id or by its name, ignoring the case. Without a selection the only creative
+/// Tonie of the account is used.
+///
+/// # Arguments
+///
+/// * `tonies` - The creative Tonies of the account.
+/// * `selection` - The id or name of the Tonie.
+pub fn select_creative_tonie<'a>(
+    tonies: &'a [CreativeTonie],
+    selection: Option<&str>,
+) -> Result<&'a CreativeTonie> {
+    let names = || {
+        tonies
+            .iter()
+            .map(|tonie| tonie.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let Some(selection) = selection else {
+        return match tonies {
+            [] => Err(anyhow!("The account does not have any creative Tonies.")),
+            [tonie] => Ok(tonie),
+            _ => Err(anyhow!(
+                "The account has several creative Tonies, select one with --tonie: {}",
+                names()
+            )),
+        };
+    };
+
+    if let Some(tonie) = tonies.iter().find(|tonie| tonie.id == selection) {
+        return Ok(tonie);
+    }
+    let matches = tonies
+        .iter()
+        .filter(|tonie| tonie.name.trim().eq_ignore_ascii_case(selection.trim()))
+        .collect::<Vec<_>>();
+    return match matches[..] {
+        [tonie] => Ok(tonie),
+        [] => Err(anyhow!(
+            "There is no creative Tonie named '{}'. The account has: {}",
+            selection,
+            names()
+        )),
+        _ => Err(anyhow!(
+            "Several creative Tonies are named '{}', select one by its id.",
+            selection
+        )),
+    };
+}
+
+fn parse_creative_tonie(tonie: &Value, household_id: &str) -> Result<CreativeTonie> {
+    let id = tonie["id"]
+        .as_str()
+        .ok_or_else(|| anyhow!("The Tonie cloud returned a creative Tonie without id."))?;
+    let chapters = tonie["chapters"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|chapter| CloudChapter {
+            id: chapter["id"].as_str().unwrap_or_default().to_string(),
+            title: chapter["title"].as_str().unwrap_or_default().to_string(),
+            file: chapter["file"].as_str().unwrap_or_default().to_string(),
+            seconds: chapter["seconds"].as_f64().unwrap_or_default(),
+            transcoding: chapter["transcoding"].as_bool().unwrap_or_default(),
+        })
+        .collect();
+
+    return Ok(CreativeTonie {
+        id: id.to_string(),
+        household_id: household_id.to_string(),
+        name: tonie["name"].as_str().unwrap_or_default().to_string(),
+        chapters,
+        seconds_remaining: tonie["secondsRemaining"].as_f64().unwrap_or_default(),
+    });
+}
@@ -0,0 +1,202 @@
+//! Identification of official Tonies with the community database `tonies.json`, which lists the audio ids and
+//! SHA1 hashes of the Tonie files of Boxine together with their series and episode titles. It has the format of
+//! the `tonies.custom.json` file of TeddyCloud.
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::download::is_url_input;
+use crate::utils::cache_dir;
+
+/// The release of the community database, which TeddyCloud uses as well.
+pub const TONIES_JSON_URL: &str =
+    "https://raw.githubusercontent.com/toniebox-reverse-engineering/tonies-json/release/tonies.json";
+
+/// An official Tonie of the database.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ToniesEntry {
+    /// The article number, e.g. `10000123`.
+    pub model: String,
+    pub series: String,
+    pub episodes: String,
+    pub title: String,
+    /// The language of the content, e.g. `de-de`.
+    pub language: String,
+    /// The audio ids of all known releases of the content.
+    pub audio_ids: Vec<u32>,
+    /// The SHA1 hashes of all known releases as lowercase hex strings, in the order of the audio ids.
+    pub hashes: Vec<String>,
+    pub tracks: Vec<String>,
+    /// The URL of the cover image.
+    pub pic: String,
+}
+
+impl ToniesEntry {
+    /// The series and the episode, e.g. `Benjamin Blümchen – Folge 12`, or the title if either is missing.
+    pub fn display_name(&self) -> String {
+        return match (self.series.trim(), self.episodes.trim()) {
+            ("", "") => self.title.clone(),
+            (series, "") => series.to_string(),
+            ("", episodes) => episodes.to_string(),
+            (series, episodes) => format!("{} – {}", series, episodes),
+        };
+    }
+}
+
+/// How a Tonie file was matched with an entry of the database.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatchKind {
+    /// The SHA1 hash of the audio data matches, so the content is identical.
+    Hash,
+    /// Only the audio id matches, e.g. for a file which was re-encoded with the original audio id.
+    AudioId,
+}
+
+impl MatchKind {
+    /// The identifier of the match in JSON reports.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            MatchKind::Hash => "hash",
+            MatchKind::AudioId => "audio_id",
+        };
+    }
+}
+
+/// The entries of a `tonies.json` database.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ToniesDatabase {
+    pub entries: Vec<ToniesEntry>,
+}
+
+impl ToniesDatabase {
+    /// Parses the content of a `tonies.json` file. Entries with unexpected fields are read as far as possible.
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The JSON array of the entries.
+    pub fn parse(json: &str) -> Result<Self> {
+        let Value::Array(entries) = serde_json::from_str::<Value>(json)? else {
+            return Err(anyhow!("The database is not a JSON array."));
+        };
+
+        let text = |entry: &Value, key: &str| match &entry[key] {
+            Value::String(text) => text.trim().to_string(),
+            Value::Number(number) => number.to_string(),
+            _ => String::new(),
+        };
+        let texts = |entry: &Value, key: &str| match &entry[key] {
+            Value::Array(values) => values
+                .iter()
+                .filter_map(|value| match value {
+                    Value::String(text) => Some(text.trim().to_string()),
+                    Value::Number(number) => Some(number.to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        let entries = entries
+            .iter()
+            .map(|entry| ToniesEntry {
+                model: text(entry, "model"),
+                series: text(entry, "series"),
+                episodes: text(entry, "episodes"),
+                title: text(entry, "title"),
+                language: text(entry, "language"),
+                audio_ids: texts(entry, "audio_id")
+                    .iter()
+                    .filter_map(|audio_id| audio_id.parse().ok())
+                    .collect(),
+                hashes: texts(entry, "hash")
+                    .iter()
+                    .map(|hash| hash.to_ascii_lowercase())
+                    .collect(),
+                tracks: texts(entry, "tracks"),
+                pic: text(entry, "pic"),
+            })
+            .collect();
+
+        return Ok(ToniesDatabase { entries });
+    }
+
+    /// Loads the database from a file or downloads it from a URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The path of a `tonies.json` file or an http or https URL.
+    pub fn load(source: &str) -> Result<Self> {
+        let json = match is_url_input(Path::new(source)) {
+            true => download_database(source)?,
+            false => std::fs::read_to_string(source)
+                .with_context(|| format!("Failed to read the database {}", source))?,
+        };
+        return ToniesDatabase::parse(&json)
+            .with_context(|| format!("Failed to parse the database {}", source));
+    }
+
+    /// Loads the cached copy of the community database, which is downloaded from [`TONIES_JSON_URL`] if there
+    /// is no copy yet or if `update` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `update` - Download the latest release even if there is a cached copy.
+    /// * `on_download` - Called with the URL before the download starts.
+    pub fn load_cached<F>(update: bool, on_download: F) -> Result<Self>
+    where
+        F: FnOnce(&str),
+    {
+        let cache_path = cached_database_path()
+            .ok_or_else(|| anyhow!("There is no cache directory to download tonies.json to."))?;
+        if update || !cache_path.is_file() {
+            on_download(TONIES_JSON_URL);
+            let json = download_database(TONIES_JSON_URL)?;
+            // A broken download must not replace a working copy
+            ToniesDatabase::parse(&json)?;
+            if let Some(cache_dir) = cache_path.parent() {
+                std::fs::create_dir_all(cache_dir)?;
+            }
+            std::fs::write(&cache_path, &json)?;
+        }
+
+        return ToniesDatabase::load(&cache_path.to_string_lossy());
+    }
+
+    /// Finds the entry of a Tonie file. A matching hash is preferred over a matching audio id, because official
+    /// Tonies share the audio ids of other content now and then.
+    ///
+    /// # Arguments
+    ///
+    /// * `audio_id` - The audio id of the header.
+    /// * `sha1_hash` - The SHA1 hash of the header as hex string.
+    pub fn identify(&self, audio_id: u32, sha1_hash: &str) -> Option<(&ToniesEntry, MatchKind)> {
+        let sha1_hash = sha1_hash.to_ascii_lowercase();
+        let by_hash = self
+            .entries
+            .iter()
+            .find(|entry| entry.hashes.contains(&sha1_hash))
+            .map(|entry| (entry, MatchKind::Hash));
+        return by_hash.or_else(|| {
+            self.entries
+                .iter()
+                .find(|entry| entry.audio_ids.contains(&audio_id))
+                .map(|entry| (entry, MatchKind::AudioId))
+        });
+    }
+}
+
+/// The path of the cached copy of the community database, e.g. `~/.cache/audio2tonie/tonies.json`.
+pub fn cached_database_path() -> Option<PathBuf> {
+    return cache_dir().map(|cache_dir| cache_dir.join("tonies.json"));
+}
+
+fn download_database(url: &str) -> Result<String> {
+    let response = ureq::get(url)
+        .call()
+        .with_context(|| format!("Failed to download the database {}", url))?;
+    // The database exceeds the size limit of `into_string`
+    let mut json = String::new();
+    response.into_reader().read_to_string(&mut json)?;
+    return Ok(json);
+}
@@ -0,0 +1,444 @@
+use anyhow::Result;
+use audio2tonie::convert::{
+    collect_input_files, convert_files_to_tonie, estimate_tonie_size, filter_input_files,
+    probe_duration, ConvertOptions,
+};
+use audio2tonie::utils::{format_duration, sanitize_file_name};
+use human_sort::compare;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+/// The bitrates in kbit/s offered by the bitrate setting.
+pub const BITRATES: [u32; 7] = [32, 48, 64, 80, 96, 128, 160];
+
+const HELP: &str = "Tab: switch list  Enter: open  Space: add/remove  a: add all  [ ]: move track  +/-: bitrate  t: title  c: convert  q: quit";
+
+/// The list which receives the keys.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Focus {
+    Browser,
+    Tracks,
+}
+
+/// What the terminal loop does after a key was handled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Continue,
+    Quit,
+    Convert,
+}
+
+/// The state of the interactive front end: the browsed directory, the selected tracks in chapter order and
+/// the settings of the Tonie file.
+pub struct TuiApp {
+    /// The browsed directory.
+    pub directory: PathBuf,
+    /// The parent directory, the subdirectories and the audio files of the browsed directory.
+    pub entries: Vec<PathBuf>,
+    pub browser_cursor: usize,
+    /// The selected audio files in chapter order.
+    pub tracks: Vec<PathBuf>,
+    pub track_cursor: usize,
+    pub focus: Focus,
+    /// The Opus bitrate in kbit/s.
+    pub bitrate: u32,
+    /// The title of the Tonie file, used as its file name.
+    pub title: String,
+    /// Whether keys are typed into the title.
+    pub editing_title: bool,
+    /// The last message for the status line, e.g. an error.
+    pub status: String,
+    options: ConvertOptions,
+    // The probed duration of every selected track, `None` if ffmpeg did not report it
+    durations: HashMap<PathBuf, Option<f64>>,
+}
+
+impl TuiApp {
+    /// Creates the front end browsing the given directory. The title defaults to the name of the directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory to start browsing in.
+    /// * `options` - Options controlling the conversion, e.g. the path to ffmpeg and the bitrate.
+    pub fn new(directory: &Path, options: &ConvertOptions) -> Result<Self> {
+        let directory = directory.canonicalize()?;
+        let title = directory
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut app = TuiApp {
+            directory: directory.clone(),
+            entries: vec![],
+            browser_cursor: 0,
+            tracks: vec![],
+            track_cursor: 0,
+            focus: Focus::Browser,
+            bitrate: options.bitrate,
+            title,
+            editing_title: false,
+            status: String::new(),
+            options: options.clone(),
+            durations: HashMap::new(),
+        };
+        app.open_directory(&directory)?;
+
+        return Ok(app);
+    }
+
+    /// Handles a key press and returns what the terminal loop does next.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The pressed key.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if self.editing_title {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => self.editing_title = false,
+                KeyCode::Backspace => {
+                    self.title.pop();
+                }
+                KeyCode::Char(c) => self.title.push(c),
+                _ => (),
+            }
+            return Action::Continue;
+        }
+
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        match (key.code, self.focus) {
+            (KeyCode::Char('q') | KeyCode::Esc, _) => return Action::Quit,
+            (KeyCode::Char('c'), _) => {
+                if self.tracks.is_empty() {
+                    self.status = "Select at least one track.".to_string();
+                    return Action::Continue;
+                }
+                return Action::Convert;
+            }
+            (KeyCode::Tab | KeyCode::BackTab, _) => {
+                self.focus = match self.focus {
+                    Focus::Browser => Focus::Tracks,
+                    Focus::Tracks => Focus::Browser,
+                };
+            }
+            (KeyCode::Char('t'), _) => self.editing_title = true,
+            (KeyCode::Char('+'), _) => self.bitrate = next_bitrate(self.bitrate, true),
+            (KeyCode::Char('-'), _) => self.bitrate = next_bitrate(self.bitrate, false),
+            (KeyCode::Char('a'), _) => {
+                let directory = self.directory.clone();
+                self.add_tracks(&directory);
+            }
+            (KeyCode::Up, Focus::Tracks) if shift => self.move_track(false),
+            (KeyCode::Down, Focus::Tracks) if shift => self.move_track(true),
+            (KeyCode::Char('['), Focus::Tracks) => self.move_track(false),
+            (KeyCode::Char(']'), Focus::Tracks) => self.move_track(true),
+            (KeyCode::Up | KeyCode::Char('k'), Focus::Browser) => {
+                self.browser_cursor = self.browser_cursor.saturating_sub(1);
+            }
+            (KeyCode::Down | KeyCode::Char('j'), Focus::Browser) => {
+                self.browser_cursor =
+                    (self.browser_cursor + 1).min(self.entries.len().saturating_sub(1));
+            }
+            (KeyCode::Up | KeyCode::Char('k'), Focus::Tracks) => {
+                self.track_cursor = self.track_cursor.saturating_sub(1);
+            }
+            (KeyCode::Down | KeyCode::Char('j'), Focus::Tracks) => {
+                self.track_cursor =
+                    (self.track_cursor + 1).min(self.tracks.len().saturating_sub(1));
+            }
+            (KeyCode::Enter | KeyCode::Right, Focus::Browser) => {
+                if let Some(entry) = self.entries.get(self.browser_cursor).cloned() {
+                    if entry.is_dir() {
+                        self.change_directory(&entry);
+                    }
+                }
+            }
+            (KeyCode::Backspace | KeyCode::Left, Focus::Browser) => {
+                if let Some(parent) = self.directory.parent().map(Path::to_path_buf) {
+                    self.change_directory(&parent);
+                }
+            }
+            (KeyCode::Char(' '), Focus::Browser) => {
+                let entry = self.entries.get(self.browser_cursor).cloned();
+                // The parent directory is only there to navigate
+                if let Some(entry) =
+                    entry.filter(|entry| Some(entry.as_path()) != self.directory.parent())
+                {
+                    match self.tracks.iter().position(|track| *track == entry) {
+                        Some(index) => self.remove_track(index),
+                        None => self.add_tracks(&entry),
+                    }
+                }
+            }
+            (KeyCode::Char(' ') | KeyCode::Delete | KeyCode::Char('x'), Focus::Tracks)
+                if self.track_cursor < self.tracks.len() =>
+            {
+                self.remove_track(self.track_cursor);
+            }
+            _ => (),
+        }
+
+        return Action::Continue;
+    }
+
+    /// The probed duration of a selected track in seconds.
+    ///
+    /// # Arguments
+    ///
+    /// * `track` - The path of the selected track.
+    pub fn duration(&self, track: &Path) -> Option<f64> {
+        return self.durations.get(track).copied().flatten();
+    }
+
+    /// The total duration of the selected tracks in seconds. Tracks without a known duration are left out.
+    pub fn total_duration(&self) -> f64 {
+        return self
+            .tracks
+            .iter()
+            .filter_map(|track| self.duration(track))
+            .sum();
+    }
+
+    /// The path of the Tonie file: the sanitized title with the `.taf` extension in the given directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_directory` - The directory for the Tonie file.
+    pub fn output_path(&self, output_directory: &Path) -> PathBuf {
+        let file_name = match sanitize_file_name(self.title.trim(), false) {
+            file_name if file_name.is_empty() => "500304E0".to_string(),
+            file_name => file_name,
+        };
+        return output_directory.join(format!("{}.taf", file_name));
+    }
+
+    /// The options for the conversion with the chosen bitrate.
+    pub fn convert_options(&self) -> ConvertOptions {
+        return ConvertOptions {
+            bitrate: self.bitrate,
+            ..self.options.clone()
+        };
+    }
+
+    fn open_directory(&mut self, directory: &Path) -> Result<()> {
+        let mut subdirectories = std::fs::read_dir(directory)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .filter(|path| {
+                !path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+            })
+            .collect::<Vec<_>>();
+        subdirectories.sort_by(|a, b| compare(&a.to_string_lossy(), &b.to_string_lossy()));
+        let audio_files = filter_input_files(&directory.to_path_buf(), self.options.any_extension)?;
+
+        self.entries = directory
+            .parent()
+            .map(Path::to_path_buf)
+            .into_iter()
+            .chain(subdirectories)
+            .chain(audio_files)
+            .collect();
+        self.directory = directory.to_path_buf();
+        self.browser_cursor = 0;
+        return Ok(());
+    }
+
+    fn change_directory(&mut self, directory: &Path) {
+        if let Err(error) = self.open_directory(directory) {
+            self.status = format!("Failed to open {}: {}", directory.display(), error);
+        }
+    }
+
+    // Adds a file or the audio files of a directory in the order of their track numbers
+    fn add_tracks(&mut self, path: &Path) {
+        let files = match collect_input_files(&[path.to_path_buf()], &self.options) {
+            Ok(files) => files,
+            Err(error) => {
+                self.status = format!("Failed to add {}: {}", path.display(), error);
+                return;
+            }
+        };
+        let new_files = files
+            .into_iter()
+            .filter(|file| !self.tracks.contains(file))
+            .collect::<Vec<_>>();
+        for file in &new_files {
+            if !self.durations.contains_key(file) {
+                let duration = probe_duration(file, &self.options.ffmpeg).ok().flatten();
+                self.durations.insert(file.clone(), duration);
+            }
+        }
+        self.status = format!("Added {} tracks.", new_files.len());
+        self.tracks.extend(new_files);
+    }
+
+    fn remove_track(&mut self, index: usize) {
+        self.tracks.remove(index);
+        self.track_cursor = self.track_cursor.min(self.tracks.len().saturating_sub(1));
+    }
+
+    fn move_track(&mut self, down: bool) {
+        let target = match down {
+            true => self.track_cursor + 1,
+            false => match self.track_cursor.checked_sub(1) {
+                Some(target) => target,
+                None => return,
+            },
+        };
+        if target < self.tracks.len() {
+            self.tracks.swap(self.track_cursor, target);
+            self.track_cursor = target;
+        }
+    }
+}
+
+// Steps to the next higher or lower offered bitrate, bitrates between the offered ones step to the neighbours
+fn next_bitrate(bitrate: u32, higher: bool) -> u32 {
+    let next = match higher {
+        true => BITRATES.iter().find(|&&offered| offered > bitrate),
+        false => BITRATES.iter().rev().find(|&&offered| offered < bitrate),
+    };
+    return next.copied().unwrap_or(bitrate);
+}
+
+/// Draws the directory browser, the selected tracks, the settings and the key help.
+///
+/// # Arguments
+///
+/// * `frame` - The frame of the terminal to draw into.
+/// * `app` - The state to draw.
+pub fn draw(frame: &mut Frame, app: &TuiApp) {
+    let [lists_area, settings_area, status_area, help_area] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(3),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [browser_area, tracks_area] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .areas(lists_area);
+
+    let focused_block = |title: String, focused: bool| {
+        let block = Block::bordered().title(title);
+        return match focused {
+            true => block.border_style(Style::new().cyan()),
+            false => block,
+        };
+    };
+
+    let parent = app.directory.parent();
+    let entries = app.entries.iter().map(|entry| {
+        let name = entry
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (marker, label) = match (Some(entry.as_path()) == parent, entry.is_dir()) {
+            (true, _) => (" ", "../".to_string()),
+            (false, true) => (" ", format!("{}/", name)),
+            (false, false) => match app.tracks.contains(entry) {
+                true => ("*", name),
+                false => (" ", name),
+            },
+        };
+        return ListItem::new(format!("{} {}", marker, label));
+    });
+    let browser = List::new(entries)
+        .block(focused_block(
+            app.directory.display().to_string(),
+            app.focus == Focus::Browser,
+        ))
+        .highlight_style(Style::new().reversed());
+    let mut browser_state = ListState::default().with_selected(Some(app.browser_cursor));
+    frame.render_stateful_widget(browser, browser_area, &mut browser_state);
+
+    let tracks = app.tracks.iter().enumerate().map(|(index, track)| {
+        let duration = app
+            .duration(track)
+            .map(format_duration)
+            .unwrap_or_else(|| "--:--:--".to_string());
+        let name = track
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        return ListItem::new(format!("{:02} {} {}", index + 1, duration, name));
+    });
+    let tracks = List::new(tracks)
+        .block(focused_block(
+            format!("Chapters ({})", app.tracks.len()),
+            app.focus == Focus::Tracks,
+        ))
+        .highlight_style(Style::new().reversed());
+    let mut tracks_state =
+        ListState::default().with_selected((!app.tracks.is_empty()).then_some(app.track_cursor));
+    frame.render_stateful_widget(tracks, tracks_area, &mut tracks_state);
+
+    let total_duration = app.total_duration();
+    let size = estimate_tonie_size(total_duration, app.bitrate) as f64 / 1024.0 / 1024.0;
+    let title = match app.editing_title {
+        true => format!("{}_", app.title),
+        false => app.title.clone(),
+    };
+    let settings = Line::from(format!(
+        "Title: {}   Bitrate: {} kbit/s   Duration: {}   Size: ~{:.1} MB",
+        title,
+        app.bitrate,
+        format_duration(total_duration),
+        size
+    ));
+    frame.render_widget(
+        Paragraph::new(settings).block(Block::bordered().title("Tonie")),
+        settings_area,
+    );
+    frame.render_widget(Paragraph::new(app.status.as_str()), status_area);
+    frame.render_widget(Paragraph::new(HELP.dark_gray()), help_area);
+}
+
+/// Runs the interactive front end until the user quits or starts the conversion. The conversion runs after the
+/// terminal is restored, so its progress is printed like for `convert`.
+///
+/// # Arguments
+///
+/// * `directory` - The directory to start browsing in.
+/// * `output_directory` - The directory for the Tonie file.
+/// * `options` - Options controlling the conversion, e.g. the path to ffmpeg.
+pub fn run_tui(directory: &Path, output_directory: &Path, options: &ConvertOptions) -> Result<()> {
+    let mut app = TuiApp::new(directory, options)?;
+
+    let mut terminal = ratatui::init();
+    let action = loop {
+        if let Err(error) = terminal.draw(|frame| draw(frame, &app)) {
+            break Err(error);
+        }
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match app.handle_key(key) {
+                Action::Continue => (),
+                action => break Ok(action),
+            },
+            Ok(_) => (),
+            Err(error) => break Err(error),
+        }
+    };
+    ratatui::restore();
+
+    if action? == Action::Quit {
+        return Ok(());
+    }
+    let output_path = app.output_path(output_directory);
+    let convert_options = ConvertOptions {
+        show_progress: std::io::stderr().is_terminal(),
+        ..app.convert_options()
+    };
+    convert_files_to_tonie(&app.tracks, &output_path, &convert_options)?;
+    println!("{}", output_path.display());
+
+    return Ok(());
+}
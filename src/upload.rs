@@ -0,0 +1,401 @@
+use anyhow::{anyhow, Context, Result};
+use audio2tonie::convert::{convert_to_tonie, count_chapters, filter_input_files, ConvertOptions};
+use audio2tonie::extract::{extract_tonie_to_opus, ExtractOptions};
+use audio2tonie::hooks::{ConversionMetadata, PostProcessor, TeddyCloudUpload};
+use audio2tonie::teddycloud::{LibraryFile, ServerTag};
+use audio2tonie::tonie_cloud::{CreativeTonie, TonieCloudClient, TONIE_CLOUD_FILE_EXTENSIONS};
+use audio2tonie::utils::{format_duration, sanitize_file_name, tool_command};
+use serde_json::{json, Value};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use toniefile::Toniefile;
+
+use crate::i18n::{Language, Message};
+
+/// Uploads a Tonie file to a TeddyCloud server. Audio files and directories are converted into a
+/// temporary Tonie file named after the input first.
+///
+/// # Arguments
+///
+/// * `input_file_path` - The path to a Tonie file, an audio file or a directory of audio files.
+/// * `upload` - The TeddyCloud server to upload to.
+/// * `options` - Options controlling the conversion of audio files.
+/// * `temp_root` - The directory for the temporary Tonie file.
+pub fn upload_to_teddycloud(
+    input_file_path: &PathBuf,
+    upload: &TeddyCloudUpload,
+    options: &ConvertOptions,
+    temp_root: &Path,
+) -> Result<()> {
+    let Ok(input_files) = filter_input_files(input_file_path, options.any_extension) else {
+        // Not a supported audio file, so it has to be a Tonie file already
+        let tonie_header =
+            Toniefile::parse_header(&mut File::open(input_file_path)?).map_err(|_| {
+                anyhow!("The input is neither a Tonie file nor a supported audio file.")
+            })?;
+
+        return upload.process(&ConversionMetadata {
+            output_path: input_file_path.to_path_buf(),
+            input_files: vec![],
+            chapters: tonie_header.track_page_nums.len(),
+        });
+    };
+    if input_files.is_empty() {
+        return Err(anyhow!("The directory does not contain any audio files."));
+    }
+
+    let temp_dir = temp_root.join(format!("audio2tonie-{}", std::process::id()));
+    std::fs::create_dir_all(&temp_dir)?;
+    let result = convert_and_upload(input_file_path, input_files, &temp_dir, upload, options);
+    std::fs::remove_dir_all(&temp_dir).ok();
+
+    return result;
+}
+
+fn convert_and_upload(
+    input_file_path: &PathBuf,
+    input_files: Vec<PathBuf>,
+    temp_dir: &Path,
+    upload: &TeddyCloudUpload,
+    options: &ConvertOptions,
+) -> Result<()> {
+    let name = input_file_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let output_path = temp_dir.join(format!("{}.taf", sanitize_file_name(&name, false)));
+    convert_to_tonie(input_file_path, &output_path, options)?;
+
+    return upload.process(&ConversionMetadata {
+        output_path,
+        chapters: count_chapters(&input_files, options)?,
+        input_files,
+    });
+}
+
+/// Uploads audio files to a creative Tonie of the Tonie cloud, one chapter per file. Files in a format the cloud
+/// does not accept are converted to MP3 first, and the chapters of Tonie files are extracted into Ogg files.
+/// Returns the updated Tonie.
+///
+/// # Arguments
+///
+/// * `inputs` - Audio files, directories of audio files or Tonie files, in chapter order.
+/// * `client` - The logged in Tonie cloud.
+/// * `tonie` - The creative Tonie to add the chapters to.
+/// * `replace` - Remove the current chapters of the Tonie.
+/// * `options` - Options controlling the selection and conversion of audio files.
+/// * `temp_root` - The directory for converted files.
+pub fn upload_to_tonie_cloud(
+    inputs: &[PathBuf],
+    client: &TonieCloudClient,
+    tonie: &CreativeTonie,
+    replace: bool,
+    options: &ConvertOptions,
+    temp_root: &Path,
+) -> Result<CreativeTonie> {
+    let temp_dir = temp_root.join(format!("audio2tonie-{}", std::process::id()));
+    std::fs::create_dir_all(&temp_dir)?;
+    let result = cloud_chapter_files(inputs, &temp_dir, options).and_then(|chapter_files| {
+        let mut files = vec![];
+        for (title, path) in chapter_files {
+            if options.show_progress {
+                eprintln!("Uploading {}", title);
+            }
+            files.push((title, client.upload_file(&path)?));
+        }
+        return client.add_chapters(tonie, &files, replace);
+    });
+    std::fs::remove_dir_all(&temp_dir).ok();
+
+    return result;
+}
+
+/// The chapter titles and the files to upload for the given inputs. Files the cloud accepts are uploaded as they
+/// are, other audio files are converted to MP3 and Tonie files are extracted into one Ogg file per chapter, all
+/// in the temporary directory.
+///
+/// # Arguments
+///
+/// * `inputs` - Audio files, directories of audio files or Tonie files, in chapter order.
+/// * `temp_dir` - The directory for converted files.
+/// * `options` - Options controlling the selection and conversion of audio files.
+pub fn cloud_chapter_files(
+    inputs: &[PathBuf],
+    temp_dir: &Path,
+    options: &ConvertOptions,
+) -> Result<Vec<(String, PathBuf)>> {
+    let title = |path: &Path| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+
+    let mut chapter_files = vec![];
+    for (index, input) in inputs.iter().enumerate() {
+        let Ok(input_files) = filter_input_files(input, options.any_extension) else {
+            // Not a supported audio file, so it has to be a Tonie file
+            let extract_options = ExtractOptions {
+                ffmpeg: options.ffmpeg.clone(),
+                ..Default::default()
+            };
+            let extract_dir = temp_dir.join(index.to_string());
+            std::fs::create_dir_all(&extract_dir)?;
+            let extracted = extract_tonie_to_opus(input, Some(extract_dir), &extract_options)
+                .with_context(|| {
+                    format!(
+                        "{} is neither a supported audio file nor a readable Tonie file",
+                        input.display()
+                    )
+                })?;
+            chapter_files.extend(extracted.into_iter().map(|path| (title(&path), path)));
+            continue;
+        };
+        if input_files.is_empty() {
+            return Err(anyhow!(
+                "The directory {} does not contain any audio files.",
+                input.display()
+            ));
+        }
+
+        for input_file in input_files {
+            let accepted = input_file
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+                .is_some_and(|extension| TONIE_CLOUD_FILE_EXTENSIONS.contains(&extension.as_str()));
+            if accepted {
+                chapter_files.push((title(&input_file), input_file));
+                continue;
+            }
+
+            let mp3_path = temp_dir.join(format!("{:03}.mp3", chapter_files.len() + 1));
+            convert_to_mp3(&input_file, &mp3_path, &options.ffmpeg)?;
+            chapter_files.push((title(&input_file), mp3_path));
+        }
+    }
+
+    return Ok(chapter_files);
+}
+
+fn convert_to_mp3(input_path: &Path, output_path: &Path, ffmpeg: &str) -> Result<()> {
+    let output = tool_command(ffmpeg)
+        .args(["-hide_banner", "-v", "error", "-i"])
+        .arg(input_path)
+        .args(["-vn", "-c:a", "libmp3lame", "-q:a", "2", "-y"])
+        .arg(output_path)
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to run {}", ffmpeg))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffmpeg failed to convert {}: {}",
+            input_path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    return Ok(());
+}
+
+/// Prints a table of the creative Tonies of a Tonie cloud account with their chapters and remaining time.
+///
+/// # Arguments
+///
+/// * `tonies` - The creative Tonies of the account.
+/// * `language` - The language of the table headers.
+/// * `json` - Print the Tonies as JSON array instead of a table.
+pub fn print_creative_tonies(
+    tonies: &[CreativeTonie],
+    language: Language,
+    json: bool,
+) -> Result<()> {
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&creative_tonies_json(tonies))?
+        );
+        return Ok(());
+    }
+
+    let name_width = tonies
+        .iter()
+        .map(|tonie| tonie.name.chars().count())
+        .chain([language.translate(Message::Name).chars().count()])
+        .max()
+        .unwrap_or_default();
+    println!(
+        "{:<name_width$} {:>8} {:>9} {}",
+        language.translate(Message::Name),
+        language.translate(Message::Chapters),
+        language.translate(Message::Remaining),
+        language.translate(Message::Id)
+    );
+    for tonie in tonies {
+        println!(
+            "{:<name_width$} {:>8} {:>9} {}",
+            tonie.name,
+            tonie.chapters.len(),
+            format_duration(tonie.seconds_remaining),
+            tonie.id
+        );
+    }
+
+    return Ok(());
+}
+
+/// The creative Tonies of a Tonie cloud account as JSON array.
+///
+/// # Arguments
+///
+/// * `tonies` - The creative Tonies of the account.
+pub fn creative_tonies_json(tonies: &[CreativeTonie]) -> Value {
+    return tonies
+        .iter()
+        .map(|tonie| {
+            let chapters = tonie
+                .chapters
+                .iter()
+                .map(|chapter| {
+                    json!({
+                        "title": chapter.title,
+                        "seconds": chapter.seconds,
+                        "transcoding": chapter.transcoding,
+                    })
+                })
+                .collect::<Vec<_>>();
+            json!({
+                "id": tonie.id,
+                "household_id": tonie.household_id,
+                "name": tonie.name,
+                "seconds_remaining": tonie.seconds_remaining,
+                "chapters": chapters,
+            })
+        })
+        .collect();
+}
+
+/// Prints a table of the files of a library directory of a TeddyCloud server. Directories end with a slash.
+///
+/// # Arguments
+///
+/// * `files` - The files of the directory.
+/// * `language` - The language of the table headers.
+/// * `json` - Print the files as JSON array instead of a table.
+pub fn print_library_files(files: &[LibraryFile], language: Language, json: bool) -> Result<()> {
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&library_files_json(files))?
+        );
+        return Ok(());
+    }
+
+    let names = files
+        .iter()
+        .map(|file| match file.is_dir {
+            true => format!("{}/", file.name),
+            false => file.name.clone(),
+        })
+        .collect::<Vec<_>>();
+    let name_width = names
+        .iter()
+        .map(|name| name.chars().count())
+        .chain([language.translate(Message::Name).chars().count()])
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "{:<name_width$} {:>9} {:>10} {}",
+        language.translate(Message::Name),
+        language.translate(Message::Size),
+        language.translate(Message::AudioId),
+        language.translate(Message::Title)
+    );
+    for (file, name) in files.iter().zip(&names) {
+        let size = match file.is_dir {
+            true => String::new(),
+            false => format!("{:.1} MB", file.size as f64 / 1_000_000.0),
+        };
+        println!(
+            "{:<name_width$} {:>9} {:>10} {}",
+            name,
+            size,
+            file.audio_id.map(|id| id.to_string()).unwrap_or_default(),
+            file.title.as_deref().unwrap_or_default()
+        );
+    }
+
+    return Ok(());
+}
+
+/// The files of a library directory as JSON array.
+///
+/// # Arguments
+///
+/// * `files` - The files of the directory.
+pub fn library_files_json(files: &[LibraryFile]) -> Value {
+    return files
+        .iter()
+        .map(|file| {
+            json!({
+                "name": file.name,
+                "is_dir": file.is_dir,
+                "size": file.size,
+                "audio_id": file.audio_id,
+                "title": file.title,
+            })
+        })
+        .collect();
+}
+
+/// Prints a table of the tags known to a TeddyCloud server with their assigned content.
+///
+/// # Arguments
+///
+/// * `tags` - The tags of the server.
+/// * `language` - The language of the table headers.
+/// * `json` - Print the tags as JSON array instead of a table.
+pub fn print_server_tags(tags: &[ServerTag], language: Language, json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(&server_tags_json(tags))?);
+        return Ok(());
+    }
+
+    println!(
+        "{:<23} {:<40} {}",
+        language.translate(Message::TagUid),
+        language.translate(Message::Source),
+        language.translate(Message::Title)
+    );
+    for tag in tags {
+        println!(
+            "{:<23} {:<40} {}",
+            tag.uid()
+                .map(|uid| uid.to_string())
+                .unwrap_or_else(|| tag.ruid.clone()),
+            tag.source,
+            tag.title.as_deref().unwrap_or_default()
+        );
+    }
+
+    return Ok(());
+}
+
+/// The tags of a TeddyCloud server as JSON array.
+///
+/// # Arguments
+///
+/// * `tags` - The tags of the server.
+pub fn server_tags_json(tags: &[ServerTag]) -> Value {
+    return tags
+        .iter()
+        .map(|tag| {
+            json!({
+                "uid": tag.uid().map(|uid| uid.to_string()),
+                "ruid": tag.ruid,
+                "source": tag.source,
+                "title": tag.title,
+            })
+        })
+        .collect();
+}
@@ -1,4 +1,11 @@
+use crate::limits::Limits;
+use crate::taf::header_length;
 use anyhow::Result;
+use std::env::consts::EXE_SUFFIX;
+use std::ffi::OsStr;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
 pub fn vec_u8_to_i16(vector: Vec<u8>) -> Result<Vec<i16>> {
     let vec_i16 = vector
@@ -8,3 +15,437 @@ pub fn vec_u8_to_i16(vector: Vec<u8>) -> Result<Vec<i16>> {
 
     return Ok(vec_i16);
 }
+
+/// Validates the input size and the announced header size of a Tonie file before anything is allocated for parsing it.
+/// The reader is rewound afterwards.
+///
+/// # Arguments
+///
+/// * `reader` - The Tonie file to validate.
+/// * `limits` - The resource limits to enforce.
+pub fn check_input_limits<R: Read + Seek>(reader: &mut R, limits: &Limits) -> Result<()> {
+    let total_bytes = reader.seek(std::io::SeekFrom::End(0))?;
+    limits.check_total_bytes(total_bytes)?;
+
+    reader.rewind()?;
+    let mut length_prefix = [0u8; 4];
+    reader.read_exact(&mut length_prefix)?;
+    limits.check_header_size(header_length(&length_prefix).unwrap_or_default())?;
+
+    reader.rewind()?;
+    return Ok(());
+}
+
+/// Formats a duration in seconds as `HH:MM:SS`.
+///
+/// # Arguments
+///
+/// * `seconds` - The duration in seconds.
+pub fn format_duration(seconds: f64) -> String {
+    let total_seconds = seconds.round() as u64;
+    return format!(
+        "{:02}:{:02}:{:02}",
+        total_seconds / 3600,
+        (total_seconds / 60) % 60,
+        total_seconds % 60
+    );
+}
+
+/// Formats a Unix timestamp as a UTC date and time, e.g. `2023-11-14 22:13:20 UTC`.
+///
+/// # Arguments
+///
+/// * `timestamp` - Seconds since the Unix epoch.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds_of_day = timestamp % 86400;
+
+    // Convert days since the epoch to a proleptic Gregorian calendar date
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    return format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        (seconds_of_day / 60) % 60,
+        seconds_of_day % 60
+    );
+}
+
+const RESERVED_CHARACTERS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+const RESERVED_DEVICE_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+const MAX_FILE_NAME_LENGTH: usize = 255;
+
+/// Makes a file name derived from tags or input names safe to use on Windows and SMB shares.
+/// Reserved and control characters are replaced, trailing dots and spaces are removed, reserved
+/// device names are prefixed and overly long names are shortened while keeping their extension.
+///
+/// # Arguments
+///
+/// * `file_name` - The file name without any directory components.
+/// * `transliterate` - Also replace non-ASCII characters with ASCII equivalents, e.g. "ä" with "ae".
+pub fn sanitize_file_name(file_name: &str, transliterate: bool) -> String {
+    let mut sanitized = String::with_capacity(file_name.len());
+    for character in file_name.chars() {
+        if RESERVED_CHARACTERS.contains(&character) || character.is_control() {
+            sanitized.push('_');
+        } else if transliterate && !character.is_ascii() {
+            sanitized.push_str(transliterate_character(character));
+        } else {
+            sanitized.push(character);
+        }
+    }
+
+    let mut sanitized = sanitized.trim_end_matches(['.', ' ']).to_string();
+
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    if RESERVED_DEVICE_NAMES
+        .iter()
+        .any(|device_name| stem.eq_ignore_ascii_case(device_name))
+    {
+        sanitized.insert(0, '_');
+    }
+
+    if sanitized.len() > MAX_FILE_NAME_LENGTH {
+        let extension = sanitized
+            .rfind('.')
+            .map(|index| sanitized[index..].to_string())
+            .filter(|extension| extension.len() < MAX_FILE_NAME_LENGTH / 2)
+            .unwrap_or_default();
+        let mut stem_length = MAX_FILE_NAME_LENGTH - extension.len();
+        while !sanitized.is_char_boundary(stem_length) {
+            stem_length -= 1;
+        }
+        sanitized = format!("{}{}", &sanitized[..stem_length], extension);
+    }
+
+    if sanitized.is_empty() {
+        return String::from("_");
+    }
+
+    return sanitized;
+}
+
+/// The longest path Windows accepts without long path support, `MAX_PATH` without the terminating null.
+pub const MAX_PATH_LENGTH: usize = 259;
+// Files are written with a `.part` suffix before they are renamed into place
+const PARTIAL_SUFFIX_LENGTH: usize = ".part".len();
+
+/// Shortens the file name of an output path derived from tags or input names, so the absolute path stays within
+/// [`MAX_PATH_LENGTH`] on Windows, even with the `.part` suffix of a file being written. The extension is kept
+/// and at least one character of the name remains, even if the directory alone is too long.
+///
+/// # Arguments
+///
+/// * `path` - The output path with a sanitized file name, see [`sanitize_file_name`].
+pub fn limit_path_length(path: &Path) -> PathBuf {
+    let Some(file_name) = path.file_name().and_then(OsStr::to_str) else {
+        return path.to_path_buf();
+    };
+    // Windows counts the length in UTF-16 code units
+    let absolute_path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let length = absolute_path.to_string_lossy().encode_utf16().count() + PARTIAL_SUFFIX_LENGTH;
+    if length <= MAX_PATH_LENGTH {
+        return path.to_path_buf();
+    }
+
+    let (stem, extension) = match file_name.rfind('.') {
+        Some(index) if index > 0 => file_name.split_at(index),
+        _ => (file_name, ""),
+    };
+    let stem_length = stem
+        .encode_utf16()
+        .count()
+        .saturating_sub(length - MAX_PATH_LENGTH)
+        .max(1);
+    let mut shortened = String::new();
+    let mut shortened_length = 0;
+    for character in stem.chars() {
+        shortened_length += character.len_utf16();
+        if shortened_length > stem_length {
+            break;
+        }
+        shortened.push(character);
+    }
+    let shortened = match shortened.trim_end_matches(['.', ' ']) {
+        "" => "_",
+        shortened => shortened,
+    };
+
+    return path.with_file_name(format!("{}{}", shortened, extension));
+}
+
+fn transliterate_character(character: char) -> &'static str {
+    return match character {
+        'ä' => "ae",
+        'ö' => "oe",
+        'ü' => "ue",
+        'Ä' => "Ae",
+        'Ö' => "Oe",
+        'Ü' => "Ue",
+        'ß' => "ss",
+        'à' | 'á' | 'â' | 'ã' | 'å' => "a",
+        'À' | 'Á' | 'Â' | 'Ã' | 'Å' => "A",
+        'æ' => "ae",
+        'Æ' => "Ae",
+        'ç' => "c",
+        'Ç' => "C",
+        'è' | 'é' | 'ê' | 'ë' => "e",
+        'È' | 'É' | 'Ê' | 'Ë' => "E",
+        'ì' | 'í' | 'î' | 'ï' => "i",
+        'Ì' | 'Í' | 'Î' | 'Ï' => "I",
+        'ñ' => "n",
+        'Ñ' => "N",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ø' => "o",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ø' => "O",
+        'œ' => "oe",
+        'Œ' => "Oe",
+        'ù' | 'ú' | 'û' => "u",
+        'Ù' | 'Ú' | 'Û' => "U",
+        'ý' | 'ÿ' => "y",
+        'Ý' => "Y",
+        _ => "_",
+    };
+}
+
+/// Finds an external tool like ffmpeg. A plain program name is searched in the `PATH`, next to the audio2tonie
+/// executable, in the [`tool_cache_dir`] and in common install locations, e.g. `C:\ffmpeg\bin` on Windows or
+/// `/opt/homebrew/bin` on macOS.
+/// Paths and programs which are not found are returned unchanged, so spawning them reports the original name.
+///
+/// # Arguments
+///
+/// * `program` - The name or path of the program, e.g. `ffmpeg`.
+pub fn find_executable(program: &str) -> PathBuf {
+    if program.contains(['/', '\\']) {
+        return PathBuf::from(program);
+    }
+    return find_executable_in(program, &executable_search_dirs())
+        .unwrap_or_else(|| PathBuf::from(program));
+}
+
+/// Finds a program in the given directories. On Windows the `.exe` extension is added to names without one.
+///
+/// # Arguments
+///
+/// * `program` - The name of the program, e.g. `ffmpeg`.
+/// * `search_dirs` - The directories to search in order.
+pub fn find_executable_in(program: &str, search_dirs: &[PathBuf]) -> Option<PathBuf> {
+    let mut file_names = vec![program.to_string()];
+    if !EXE_SUFFIX.is_empty() && Path::new(program).extension().is_none() {
+        file_names.insert(0, format!("{}{}", program, EXE_SUFFIX));
+    }
+
+    return search_dirs
+        .iter()
+        .flat_map(|search_dir| {
+            file_names
+                .iter()
+                .map(|file_name| search_dir.join(file_name))
+        })
+        .find(|path| is_executable(path));
+}
+
+// The PATH, the directory of the running executable, the tool cache and common install locations of ffmpeg
+fn executable_search_dirs() -> Vec<PathBuf> {
+    let mut search_dirs = std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default();
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        search_dirs.push(exe_dir);
+    }
+    // ffmpeg downloaded with `--auto-ffmpeg`
+    search_dirs.extend(tool_cache_dir());
+
+    if cfg!(windows) {
+        let env_dir = |name: &str, path: &str| {
+            std::env::var_os(name).map(|dir| PathBuf::from(dir).join(path))
+        };
+        search_dirs.extend(
+            [
+                env_dir("ProgramFiles", "ffmpeg\\bin"),
+                env_dir("LOCALAPPDATA", "Microsoft\\WinGet\\Links"),
+                env_dir("USERPROFILE", "scoop\\shims"),
+                env_dir("ProgramData", "chocolatey\\bin"),
+                Some(PathBuf::from("C:\\ffmpeg\\bin")),
+            ]
+            .into_iter()
+            .flatten(),
+        );
+    } else {
+        // GUI apps and services on macOS and NAS systems often run without the PATH of a login shell
+        search_dirs.extend(
+            [
+                "/opt/homebrew/bin",
+                "/usr/local/bin",
+                "/usr/bin",
+                "/snap/bin",
+                "/opt/bin",
+            ]
+            .map(PathBuf::from),
+        );
+    }
+
+    return search_dirs;
+}
+
+/// The directory for tools which audio2tonie downloads, e.g. `~/.cache/audio2tonie/bin` on Linux,
+/// `~/Library/Caches/audio2tonie/bin` on macOS and `%LOCALAPPDATA%\audio2tonie\bin` on Windows.
+pub fn tool_cache_dir() -> Option<PathBuf> {
+    return cache_dir().map(|cache_dir| cache_dir.join("bin"));
+}
+
+/// The directory for files which audio2tonie downloads and keeps, e.g. `~/.cache/audio2tonie` on Linux,
+/// `~/Library/Caches/audio2tonie` on macOS and `%LOCALAPPDATA%\audio2tonie` on Windows.
+pub fn cache_dir() -> Option<PathBuf> {
+    let env_dir = |name: &str| {
+        std::env::var_os(name)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    };
+    let cache_dir = if cfg!(windows) {
+        env_dir("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        env_dir("XDG_CACHE_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".cache")))
+    };
+    return cache_dir.map(|cache_dir| cache_dir.join("audio2tonie"));
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    return path
+        .metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0);
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    return path.is_file();
+}
+
+/// Creates the command for an external tool like ffmpeg or ffplay, found with [`find_executable`]. On Windows
+/// the tool runs without a console window, which would otherwise flash up for every spawned process.
+///
+/// # Arguments
+///
+/// * `program` - The name or path of the program, e.g. `ffmpeg`.
+pub fn tool_command(program: &str) -> Command {
+    #[allow(unused_mut)]
+    let mut command = Command::new(find_executable(program));
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    return command;
+}
+
+/// The free space in bytes available to the current user on the file system of a path, e.g. an SD card.
+/// `None` if it cannot be determined.
+///
+/// # Arguments
+///
+/// * `path` - A path on the file system.
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: statvfs only reads the path and fills the struct on success
+    let result = unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) };
+    if result != 0 {
+        return None;
+    }
+    // SAFETY: the struct was filled by the successful call
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)]
+    return Some(stat.f_bavail as u64 * stat.f_frsize as u64);
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    return None;
+}
+
+/// Builds a `multipart/form-data` body with the given text fields followed by the file in the field `file`.
+/// Returns the content type with the boundary and the body. The boundary is chosen so it does not occur in any
+/// of the parts.
+///
+/// # Arguments
+///
+/// * `fields` - The names and values of the text fields.
+/// * `file_name` - The file name of the uploaded file.
+/// * `content` - The content of the uploaded file.
+pub fn multipart_form(
+    fields: &[(&str, &str)],
+    file_name: &str,
+    content: &[u8],
+) -> (String, Vec<u8>) {
+    let occurs = |boundary: &str| {
+        let boundary = boundary.as_bytes();
+        return memchr::memmem::find(content, boundary).is_some()
+            || fields.iter().any(|(name, value)| {
+                memchr::memmem::find(name.as_bytes(), boundary).is_some()
+                    || memchr::memmem::find(value.as_bytes(), boundary).is_some()
+            });
+    };
+    let boundary = (0u64..)
+        .map(|attempt| format!("----audio2tonie-boundary-{attempt}"))
+        .find(|boundary| !occurs(boundary))
+        .expect("Some boundary does not occur in the body.");
+
+    let mut body = vec![];
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{value}\r\n",
+                escape_form_name(name)
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+            escape_form_name(file_name)
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+    return (format!("multipart/form-data; boundary={boundary}"), body);
+}
+
+// Percent-encodes the characters that would end the quoted name or the header line, like browsers do
+fn escape_form_name(name: &str) -> String {
+    return name
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+}
@@ -0,0 +1,224 @@
+//! Watches a drop folder and converts every audio file or directory that appears in it into a Tonie file.
+
+use anyhow::{anyhow, Result};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::convert::{
+    album_output_path, convert_to_tonie, filter_input_files, output_exists, ConvertOptions,
+    ExistingOutput,
+};
+
+/// The default time without changes before a new entry of the watched directory is converted.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(5);
+
+/// Options controlling how new entries of a watched directory are converted.
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    /// Options controlling the conversion of every entry.
+    pub convert: ConvertOptions,
+    /// The time without changes before an entry is converted, so files are not read while they are copied.
+    pub debounce: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            convert: ConvertOptions::default(),
+            debounce: DEFAULT_DEBOUNCE,
+        }
+    }
+}
+
+/// Collects changed entries of the watched directory until they did not change for the debounce time.
+#[derive(Debug)]
+pub struct Debouncer {
+    debounce: Duration,
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl Debouncer {
+    pub fn new(debounce: Duration) -> Self {
+        Debouncer {
+            debounce,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Records a change of an entry, which postpones its conversion.
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The changed entry.
+    /// * `now` - The time of the change.
+    pub fn touch(&mut self, entry: PathBuf, now: Instant) {
+        self.pending.insert(entry, now);
+    }
+
+    /// Removes and returns the entries which did not change for the debounce time, in the order of their names.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time.
+    pub fn take_ready(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut ready = self
+            .pending
+            .iter()
+            .filter(|(_, changed)| now.duration_since(**changed) >= self.debounce)
+            .map(|(entry, _)| entry.clone())
+            .collect::<Vec<_>>();
+        for entry in &ready {
+            self.pending.remove(entry);
+        }
+
+        ready.sort();
+        return ready;
+    }
+}
+
+/// Watches the input directory and converts every audio file or directory which appears in it into a Tonie file
+/// in the output directory. Entries present at the start are ignored. A failed conversion is reported to
+/// `on_converted` and does not stop watching. Entries whose Tonie file exists are ignored with
+/// [`ExistingOutput::Skip`]. Runs until the watcher stops.
+///
+/// # Arguments
+///
+/// * `input_directory` - The drop folder to watch.
+/// * `output_directory` - The directory for the Tonie files.
+/// * `options` - Options controlling the debouncing and the conversion.
+/// * `on_converted` - Called with every converted entry and the path of its Tonie file or the error.
+pub fn watch_directory<F>(
+    input_directory: &Path,
+    output_directory: &Path,
+    options: &WatchOptions,
+    on_converted: F,
+) -> Result<()>
+where
+    F: FnMut(&Path, Result<PathBuf>),
+{
+    std::fs::create_dir_all(output_directory)?;
+    // Events contain absolute paths, so both directories have to be absolute to find the changed entries
+    let input_directory = input_directory.canonicalize()?;
+    let output_directory = output_directory.canonicalize()?;
+
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&input_directory, RecursiveMode::Recursive)?;
+
+    return watch_events(
+        &receiver,
+        &input_directory,
+        &output_directory,
+        options,
+        on_converted,
+    );
+}
+
+/// Converts the entries of the watched directory named by the events of a watcher, see [`watch_directory`].
+/// Errors reported by the watcher, e.g. a lost event on an overflowing queue, are printed and do not stop
+/// watching. Runs until the watcher is dropped.
+///
+/// # Arguments
+///
+/// * `receiver` - The events of the watcher.
+/// * `input_directory` - The absolute path of the watched directory.
+/// * `output_directory` - The absolute path of the directory for the Tonie files.
+/// * `options` - Options controlling the debouncing and the conversion.
+/// * `on_converted` - Called with every converted entry and the path of its Tonie file or the error.
+pub fn watch_events<F>(
+    receiver: &Receiver<notify::Result<Event>>,
+    input_directory: &Path,
+    output_directory: &Path,
+    options: &WatchOptions,
+    mut on_converted: F,
+) -> Result<()>
+where
+    F: FnMut(&Path, Result<PathBuf>),
+{
+    let mut debouncer = Debouncer::new(options.debounce);
+    loop {
+        match receiver.recv_timeout(Duration::from_millis(500)) {
+            Ok(Err(error)) => {
+                eprintln!(
+                    "Warning: watching {} reported an error: {}",
+                    input_directory.display(),
+                    error
+                );
+            }
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Access(_)) {
+                    continue;
+                }
+                for path in &event.paths {
+                    // Tonie files written into a drop folder inside the output directory must not trigger again
+                    if path.starts_with(output_directory) {
+                        continue;
+                    }
+                    if let Some(entry) = top_level_entry(input_directory, path) {
+                        debouncer.touch(entry, Instant::now());
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("Stopped watching {}.", input_directory.display()));
+            }
+        }
+
+        for entry in debouncer.take_ready(Instant::now()) {
+            // Deleted entries and files which are no audio files, e.g. temporary files of a copy, are skipped
+            let has_audio_files = entry.exists()
+                && filter_input_files(&entry, options.convert.any_extension)
+                    .is_ok_and(|input_files| !input_files.is_empty());
+            if !has_audio_files {
+                continue;
+            }
+
+            let output_file_path = watch_output_path(input_directory, &entry, output_directory);
+            if options.convert.existing_output == ExistingOutput::Skip
+                && output_exists(&output_file_path)
+            {
+                continue;
+            }
+            let result = convert_to_tonie(&entry, &output_file_path, &options.convert)
+                .map(|_| output_file_path)
+                .map_err(anyhow::Error::from);
+            on_converted(&entry, result);
+        }
+    }
+}
+
+/// The output path of an entry of the watched directory: `story.mp3` becomes `story.taf` and the directory
+/// `album` becomes `album.taf`.
+///
+/// # Arguments
+///
+/// * `input_directory` - The watched directory.
+/// * `entry` - The file or directory in the watched directory.
+/// * `output_directory` - The directory for the Tonie files.
+pub fn watch_output_path(input_directory: &Path, entry: &Path, output_directory: &Path) -> PathBuf {
+    let name = match entry.is_dir() {
+        true => entry.to_path_buf(),
+        false => entry.with_extension(""),
+    };
+    return album_output_path(input_directory, &name, output_directory);
+}
+
+/// The entry directly below the watched directory that contains the changed path, so files copied into a new
+/// directory are converted together.
+///
+/// # Arguments
+///
+/// * `input_directory` - The watched directory.
+/// * `path` - The changed path.
+pub fn top_level_entry(input_directory: &Path, path: &Path) -> Option<PathBuf> {
+    let first_component = path
+        .strip_prefix(input_directory)
+        .ok()?
+        .components()
+        .next()?;
+    return Some(input_directory.join(first_component));
+}